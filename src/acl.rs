@@ -0,0 +1,474 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::common::{QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::payload;
+
+/// The access granted by an ACL rule.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum AccessLevel {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "write")]
+    Write,
+    /// Only available for keys, to list keys without reading their values.
+    #[serde(rename = "list")]
+    List,
+    #[serde(rename = "deny")]
+    Deny,
+}
+
+impl AccessLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            AccessLevel::Read => "read",
+            AccessLevel::Write => "write",
+            AccessLevel::List => "list",
+            AccessLevel::Deny => "deny",
+        }
+    }
+}
+
+/// A kind of resource which ACL rules are scoped to by name, either exactly or by prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AclResource {
+    /// Agents, by the name of their node.
+    Agent,
+    /// User events, by their name.
+    Event,
+    /// KV entries, by their key.
+    Key,
+    /// Nodes, by their name.
+    Node,
+    /// Prepared queries, by their name.
+    Query,
+    /// Services, by their name.
+    Service,
+    /// Sessions, by the name of their node.
+    Session,
+}
+
+impl AclResource {
+    fn as_str(self) -> &'static str {
+        match self {
+            AclResource::Agent => "agent",
+            AclResource::Event => "event",
+            AclResource::Key => "key",
+            AclResource::Node => "node",
+            AclResource::Query => "query",
+            AclResource::Service => "service",
+            AclResource::Session => "session",
+        }
+    }
+}
+
+/// Builder for the rules of an ACL policy.
+///
+/// The rules can be rendered as JSON or HCL, either of which Consul accepts as the
+/// [`rules`](AclPolicyRequest::rules) of a policy.  Names are escaped as needed, and a later rule
+/// for the same resource and name replaces an earlier one, so rules can be built up without
+/// worrying about producing an invalid or ambiguous policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AclRules {
+    // Rules scoped by name, keyed by their section, such as `key_prefix`, and then by name.
+    scoped: BTreeMap<String, BTreeMap<String, AccessLevel>>,
+    // Rules which apply to a whole area of the cluster, such as `operator`, keyed by that area.
+    global: BTreeMap<&'static str, AccessLevel>,
+}
+
+impl AclRules {
+    /// Creates an empty set of rules, which grants nothing.
+    pub fn new() -> AclRules {
+        AclRules::default()
+    }
+
+    /// Adds a rule for the resource with exactly the given name.
+    pub fn exact(mut self, resource: AclResource, name: &str, level: AccessLevel) -> AclRules {
+        self.scoped
+            .entry(resource.as_str().to_string())
+            .or_default()
+            .insert(name.to_string(), level);
+        self
+    }
+
+    /// Adds a rule for the resources whose name starts with the given prefix.
+    ///
+    /// An empty prefix matches all resources of the given kind.  When several rules match a
+    /// resource, Consul applies the one with the longest name or prefix, with exact rules winning
+    /// over prefix rules of the same length.
+    pub fn prefix(mut self, resource: AclResource, prefix: &str, level: AccessLevel) -> AclRules {
+        self.scoped
+            .entry(format!("{}_prefix", resource.as_str()))
+            .or_default()
+            .insert(prefix.to_string(), level);
+        self
+    }
+
+    /// Adds a rule for the KV entry with exactly the given key.
+    pub fn key(self, key: &str, level: AccessLevel) -> AclRules {
+        self.exact(AclResource::Key, key, level)
+    }
+
+    /// Adds a rule for the KV entries whose key starts with the given prefix.
+    pub fn key_prefix(self, prefix: &str, level: AccessLevel) -> AclRules {
+        self.prefix(AclResource::Key, prefix, level)
+    }
+
+    /// Adds a rule for the service with exactly the given name.
+    pub fn service(self, name: &str, level: AccessLevel) -> AclRules {
+        self.exact(AclResource::Service, name, level)
+    }
+
+    /// Adds a rule for the services whose name starts with the given prefix.
+    pub fn service_prefix(self, prefix: &str, level: AccessLevel) -> AclRules {
+        self.prefix(AclResource::Service, prefix, level)
+    }
+
+    /// Adds a rule for the node with exactly the given name.
+    pub fn node(self, name: &str, level: AccessLevel) -> AclRules {
+        self.exact(AclResource::Node, name, level)
+    }
+
+    /// Adds a rule for the nodes whose name starts with the given prefix.
+    pub fn node_prefix(self, prefix: &str, level: AccessLevel) -> AclRules {
+        self.prefix(AclResource::Node, prefix, level)
+    }
+
+    /// Sets the access to the ACL system itself, such as managing tokens and policies.
+    pub fn acl(self, level: AccessLevel) -> AclRules {
+        self.global("acl", level)
+    }
+
+    /// Sets the access to the gossip encryption keyring.
+    pub fn keyring(self, level: AccessLevel) -> AclRules {
+        self.global("keyring", level)
+    }
+
+    /// Sets the access to the configuration of the service mesh as a whole.
+    pub fn mesh(self, level: AccessLevel) -> AclRules {
+        self.global("mesh", level)
+    }
+
+    /// Sets the access to the operator API, such as the Raft configuration and Autopilot.
+    pub fn operator(self, level: AccessLevel) -> AclRules {
+        self.global("operator", level)
+    }
+
+    fn global(mut self, area: &'static str, level: AccessLevel) -> AclRules {
+        self.global.insert(area, level);
+        self
+    }
+
+    /// Renders the rules as JSON.
+    pub fn to_json(&self) -> String {
+        let mut rules = Map::new();
+        for (section, names) in &self.scoped {
+            let names = names
+                .iter()
+                .map(|(name, level)| (name.clone(), json!({ "policy": level.as_str() })))
+                .collect::<Map<_, _>>();
+            rules.insert(section.clone(), Value::Object(names));
+        }
+        for (area, level) in &self.global {
+            rules.insert(area.to_string(), json!(level.as_str()));
+        }
+        Value::Object(rules).to_string()
+    }
+
+    /// Renders the rules as HCL, which is how policies are usually written by hand.
+    pub fn to_hcl(&self) -> String {
+        let mut hcl = String::new();
+        for (section, names) in &self.scoped {
+            for (name, level) in names {
+                // HCL strings are escaped the same way as JSON strings.
+                let _ = writeln!(
+                    hcl,
+                    "{} {} {{\n  policy = \"{}\"\n}}",
+                    section,
+                    Value::from(name.as_str()),
+                    level.as_str()
+                );
+            }
+        }
+        for (area, level) in &self.global {
+            let _ = writeln!(hcl, "{} = \"{}\"", area, level.as_str());
+        }
+        hcl
+    }
+}
+
+/// An ACL policy to create or update.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AclPolicyRequest {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(
+        rename = "Description",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub description: String,
+    /// The rules of the policy, in HCL or JSON, such as rendered by [`AclRules`].
+    #[serde(rename = "Rules", default)]
+    pub rules: String,
+    /// The datacenters the policy applies in, or all of them if empty.
+    #[serde(rename = "Datacenters", default, skip_serializing_if = "Vec::is_empty")]
+    pub datacenters: Vec<String>,
+}
+
+/// An ACL policy, as returned by Consul.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AclPolicy {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Description", default)]
+    pub description: String,
+    /// The rules of the policy.
+    ///
+    /// This is empty when listing policies, which only returns their metadata.
+    #[serde(rename = "Rules", default)]
+    pub rules: String,
+    #[serde(rename = "Datacenters", default)]
+    pub datacenters: Vec<String>,
+    #[serde(rename = "Namespace", default)]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default)]
+    pub partition: Option<String>,
+    #[serde(rename = "Hash", default)]
+    pub hash: String,
+    #[serde(rename = "CreateIndex")]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex")]
+    pub modify_index: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// ACL operations.
+///
+/// This type can be used to interact with the "ACL" portion of the Consul API, such as managing
+/// policies.  All operations require a token with `acl:read` or `acl:write` permissions.
+#[derive(Clone, Debug)]
+pub struct Acl {
+    http_client: Arc<HttpClient>,
+}
+
+impl Acl {
+    /// Creates a new [`Acl`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Acl {
+        Acl { http_client }
+    }
+
+    /// Creates a policy, returning it as created.
+    pub async fn create_policy(
+        &self,
+        policy: &AclPolicyRequest,
+        options: Option<WriteOptions>,
+    ) -> Result<AclPolicy, Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "acl", "policy"],
+            options.as_ref(),
+            policy,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let parsed = self.http_client.parse_write_response(response).await?;
+        Ok(parsed)
+    }
+
+    /// Updates the policy with the given ID, replacing all of it, and returns it as updated.
+    pub async fn update_policy(
+        &self,
+        id: &str,
+        policy: &AclPolicyRequest,
+        options: Option<WriteOptions>,
+    ) -> Result<AclPolicy, Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "acl", "policy", id],
+            options.as_ref(),
+            policy,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let parsed = self.http_client.parse_write_response(response).await?;
+        Ok(parsed)
+    }
+
+    /// Gets the policy with the given ID.
+    ///
+    /// Returns `None` if the policy does not exist.
+    pub async fn get_policy(
+        &self,
+        id: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<AclPolicy>, QueryMetadata), Error> {
+        self.read_policy(&["v1", "acl", "policy", id], options)
+            .await
+    }
+
+    /// Gets the policy with the given name.
+    ///
+    /// Returns `None` if the policy does not exist.
+    pub async fn get_policy_by_name(
+        &self,
+        name: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<AclPolicy>, QueryMetadata), Error> {
+        self.read_policy(&["v1", "acl", "policy", "name", name], options)
+            .await
+    }
+
+    /// Deletes the policy with the given ID.
+    pub async fn delete_policy(
+        &self,
+        id: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let request = self.http_client.build_request(
+            "DELETE",
+            &["v1", "acl", "policy", id],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let deleted = self.http_client.parse_write_response(response).await?;
+        Ok(deleted)
+    }
+
+    /// Gets all policies, without their rules.
+    pub async fn list_policies(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<AclPolicy>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "acl", "policies"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    async fn read_policy(
+        &self,
+        url_parts: &[&str],
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<AclPolicy>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self
+            .http_client
+            .build_request("GET", url_parts, options.as_ref(), ())?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self
+            .http_client
+            .parse_optional_query_response(response)
+            .await?;
+        Ok((parsed, meta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_rules_render_as_empty_policies() {
+        assert_eq!(AclRules::new().to_json(), "{}");
+        assert_eq!(AclRules::new().to_hcl(), "");
+    }
+
+    #[test]
+    fn renders_scoped_and_global_rules_as_json() {
+        let rules = AclRules::new()
+            .key_prefix("app/", AccessLevel::Write)
+            .service("web", AccessLevel::Read)
+            .node_prefix("", AccessLevel::Read)
+            .operator(AccessLevel::Read);
+        let rendered: Value = serde_json::from_str(&rules.to_json()).unwrap();
+        assert_eq!(
+            rendered,
+            json!({
+                "key_prefix": { "app/": { "policy": "write" } },
+                "service": { "web": { "policy": "read" } },
+                "node_prefix": { "": { "policy": "read" } },
+                "operator": "read",
+            })
+        );
+    }
+
+    #[test]
+    fn renders_rules_as_hcl() {
+        let rules = AclRules::new()
+            .exact(AclResource::Session, "node-1", AccessLevel::Write)
+            .key("config", AccessLevel::Deny)
+            .acl(AccessLevel::Write);
+        assert_eq!(
+            rules.to_hcl(),
+            "key \"config\" {\n  policy = \"deny\"\n}\n\
+             session \"node-1\" {\n  policy = \"write\"\n}\n\
+             acl = \"write\"\n"
+        );
+    }
+
+    #[test]
+    fn escapes_names_in_hcl() {
+        let rules = AclRules::new().key("say \"hi\"\\now", AccessLevel::Read);
+        assert_eq!(
+            rules.to_hcl(),
+            "key \"say \\\"hi\\\"\\\\now\" {\n  policy = \"read\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn later_rules_replace_earlier_ones() {
+        let rules = AclRules::new()
+            .service("web", AccessLevel::Read)
+            .service("web", AccessLevel::Write)
+            .keyring(AccessLevel::Read)
+            .keyring(AccessLevel::Deny);
+        assert_eq!(
+            rules,
+            AclRules::new()
+                .service("web", AccessLevel::Write)
+                .keyring(AccessLevel::Deny)
+        );
+    }
+
+    #[test]
+    fn keeps_exact_and_prefix_rules_apart() {
+        let rules = AclRules::new()
+            .service("web", AccessLevel::Write)
+            .service_prefix("web", AccessLevel::Read);
+        let rendered: Value = serde_json::from_str(&rules.to_json()).unwrap();
+        assert_eq!(rendered["service"]["web"]["policy"], "write");
+        assert_eq!(rendered["service_prefix"]["web"]["policy"], "read");
+    }
+}
@@ -1,11 +1,30 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use serde::Deserialize;
+use futures::future::{self, Either};
+use hyper::body::Bytes;
 
-use crate::health::HealthCheckDefinition;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
 
-#[derive(Deserialize, Debug)]
+use crate::catalog::{ServiceAddress, TaggedAddresses};
+use crate::common::{go_duration, QueryMetadata, QueryOptions, WriteOptions};
+use crate::config_entries::MeshGatewayConfig;
+use crate::errors::Error;
+use crate::health::{CheckStatus, HealthCheckDefinition};
+use crate::http_client::HttpClient;
+use crate::payload;
+use crate::runtime::{delay_for, spawn};
+use crate::watch::Watch;
+
+/// The kind of a service, such as a regular service or one of the kinds of Connect proxies.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+#[non_exhaustive]
 pub enum AgentServiceKind {
+    #[default]
     #[serde(rename = "")]
     Default,
     #[serde(rename = "connect-proxy")]
@@ -18,15 +37,38 @@ pub enum AgentServiceKind {
     IngressGateway,
 }
 
-#[derive(Deserialize, Debug)]
+impl AgentServiceKind {
+    /// Whether or not this is the default kind, for a regular service.
+    pub fn is_default(&self) -> bool {
+        *self == AgentServiceKind::Default
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct AgentWeights {
     #[serde(rename = "Passing")]
     pub passing: u64,
     #[serde(rename = "Warning")]
     pub warning: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Default for AgentWeights {
+    fn default() -> AgentWeights {
+        // Matches the defaults used by Consul itself.
+        AgentWeights {
+            passing: 1,
+            warning: 1,
+            extra: HashMap::new(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct AgentCheck {
     #[serde(rename = "Node")]
     pub node: String,
@@ -50,11 +92,15 @@ pub struct AgentCheck {
     pub namespace: Option<String>,
     #[serde(rename = "Definition")]
     pub definition: HealthCheckDefinition,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
 pub struct AgentService {
-    #[serde(rename = "Kind")]
+    #[serde(rename = "Kind", default)]
     pub kind: AgentServiceKind,
     #[serde(rename = "ID")]
     pub id: String,
@@ -68,23 +114,611 @@ pub struct AgentService {
     pub port: u16,
     #[serde(rename = "Address")]
     pub address: String,
-    #[serde(rename = "TaggedAddresses")]
-    pub tagged_addresses: HashMap<String, String>,
+    #[serde(rename = "TaggedAddresses", default)]
+    pub tagged_addresses: TaggedAddresses<ServiceAddress>,
     #[serde(rename = "Weights")]
     pub weights: AgentWeights,
     #[serde(rename = "EnableTagOverride")]
     pub enable_tag_override: bool,
-    #[serde(rename = "CreateIndex")]
+    #[serde(rename = "CreateIndex", default)]
     pub create_index: u64,
-    #[serde(rename = "ModifyIndex")]
+    #[serde(rename = "ModifyIndex", default)]
     pub modify_index: u64,
-    #[serde(rename = "ContentHash")]
+    #[serde(rename = "ContentHash", default)]
     pub content_hash: String,
+    #[serde(rename = "Proxy", default)]
+    pub proxy: Option<AgentServiceConnectProxyConfig>,
     // TODO: implement this stuff, I'm too lazy to do it right now.
-    //#[serde(rename = "Proxy")]
-    //pub proxy: AgentServiceConnectProxyConfig,
     //#[serde(rename = "Connect")]
     //pub connect: AgentServiceConnect,
     #[serde(rename = "Namespace")]
     pub namespace: Option<String>,
+    #[serde(rename = "Partition", default)]
+    pub partition: Option<String>,
+    /// The cluster peer the instance is imported from, if any.
+    #[serde(rename = "PeerName", default)]
+    pub peer_name: Option<String>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The configuration of a Connect proxy service.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AgentServiceConnectProxyConfig {
+    #[serde(
+        rename = "DestinationServiceName",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub destination_service_name: String,
+    #[serde(
+        rename = "DestinationServiceID",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination_service_id: Option<String>,
+    #[serde(
+        rename = "LocalServiceAddress",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub local_service_address: Option<String>,
+    #[serde(
+        rename = "LocalServicePort",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub local_service_port: Option<u16>,
+    #[serde(rename = "Config", default, skip_serializing_if = "HashMap::is_empty")]
+    pub config: HashMap<String, Value>,
+    #[serde(rename = "Upstreams", default, skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<AgentServiceUpstream>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The kind of destination of an upstream.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub enum UpstreamDestinationType {
+    #[default]
+    #[serde(rename = "service", alias = "")]
+    Service,
+    #[serde(rename = "prepared_query")]
+    PreparedQuery,
+}
+
+/// An upstream of a Connect proxy, which the proxy listens for locally and forwards to.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AgentServiceUpstream {
+    #[serde(rename = "DestinationType", default)]
+    pub destination_type: UpstreamDestinationType,
+    #[serde(
+        rename = "DestinationNamespace",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination_namespace: Option<String>,
+    #[serde(
+        rename = "DestinationPartition",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination_partition: Option<String>,
+    /// The cluster peer the destination is imported from, if any.
+    #[serde(
+        rename = "DestinationPeer",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination_peer: Option<String>,
+    /// The name of the service or prepared query to connect to.
+    #[serde(rename = "DestinationName")]
+    pub destination_name: String,
+    /// The datacenter of the destination, defaulting to the local datacenter.
+    #[serde(
+        rename = "Datacenter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datacenter: Option<String>,
+    #[serde(
+        rename = "LocalBindAddress",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub local_bind_address: Option<String>,
+    #[serde(
+        rename = "LocalBindPort",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub local_bind_port: Option<u16>,
+    #[serde(rename = "Config", default, skip_serializing_if = "HashMap::is_empty")]
+    pub config: HashMap<String, Value>,
+    #[serde(
+        rename = "MeshGateway",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mesh_gateway: Option<MeshGatewayConfig>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// A service to register with the local agent.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AgentServiceRegistration {
+    #[serde(
+        rename = "Kind",
+        default,
+        skip_serializing_if = "AgentServiceKind::is_default"
+    )]
+    pub kind: AgentServiceKind,
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Tags", default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename = "Address", default, skip_serializing_if = "String::is_empty")]
+    pub address: String,
+    #[serde(rename = "Port", default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "EnableTagOverride", default)]
+    pub enable_tag_override: bool,
+    #[serde(rename = "Weights", default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<AgentServiceWeights>,
+    #[serde(rename = "Checks", default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<AgentServiceCheck>,
+    #[serde(rename = "Proxy", default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<AgentServiceConnectProxyConfig>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// The weights of a service to register with the local agent, used in DNS SRV responses.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AgentServiceWeights {
+    #[serde(rename = "Passing")]
+    pub passing: u64,
+    #[serde(rename = "Warning")]
+    pub warning: u64,
+}
+
+impl Default for AgentServiceWeights {
+    fn default() -> AgentServiceWeights {
+        // Matches the defaults used by Consul itself.
+        AgentServiceWeights {
+            passing: 1,
+            warning: 1,
+        }
+    }
+}
+
+/// A check to register alongside a service with the local agent.
+///
+/// Exactly one kind of check should be configured, such as `http` or `ttl`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct AgentServiceCheck {
+    #[serde(rename = "CheckID", default, skip_serializing_if = "Option::is_none")]
+    pub check_id: Option<String>,
+    #[serde(rename = "Name", default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(rename = "Notes", default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(rename = "Status", default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<CheckStatus>,
+    #[serde(rename = "Args", default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(rename = "HTTP", default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+    #[serde(rename = "Method", default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(rename = "Header", default, skip_serializing_if = "HashMap::is_empty")]
+    pub header: HashMap<String, Vec<String>>,
+    #[serde(rename = "Body", default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(rename = "TCP", default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+    #[serde(rename = "GRPC", default, skip_serializing_if = "Option::is_none")]
+    pub grpc: Option<String>,
+    #[serde(rename = "GRPCUseTLS", default)]
+    pub grpc_use_tls: bool,
+    #[serde(rename = "TLSSkipVerify", default)]
+    pub tls_skip_verify: bool,
+    #[serde(
+        rename = "Interval",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interval: Option<Duration>,
+    #[serde(
+        rename = "Timeout",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout: Option<Duration>,
+    #[serde(
+        rename = "TTL",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttl: Option<Duration>,
+    #[serde(
+        rename = "DeregisterCriticalServiceAfter",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deregister_critical_service_after: Option<Duration>,
+}
+
+/// A snapshot of the metrics of the local agent, from its in-memory sink.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AgentMetrics {
+    /// The start of the interval the metrics were aggregated over.
+    #[serde(rename = "Timestamp")]
+    pub timestamp: String,
+    #[serde(rename = "Gauges", default)]
+    pub gauges: Vec<AgentMetricsGauge>,
+    #[serde(rename = "Points", default)]
+    pub points: Vec<AgentMetricsPoint>,
+    #[serde(rename = "Counters", default)]
+    pub counters: Vec<AgentMetricsSample>,
+    #[serde(rename = "Samples", default)]
+    pub samples: Vec<AgentMetricsSample>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The value of a gauge in [`AgentMetrics`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AgentMetricsGauge {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Value")]
+    pub value: f64,
+    #[serde(rename = "Labels", default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// The points of a key in [`AgentMetrics`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AgentMetricsPoint {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Points", default)]
+    pub points: Vec<f64>,
+}
+
+/// The aggregated values of a counter or sample in [`AgentMetrics`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AgentMetricsSample {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Count")]
+    pub count: u64,
+    #[serde(rename = "Sum")]
+    pub sum: f64,
+    #[serde(rename = "Min")]
+    pub min: f64,
+    #[serde(rename = "Max")]
+    pub max: f64,
+    #[serde(rename = "Mean")]
+    pub mean: f64,
+    #[serde(rename = "Stddev")]
+    pub stddev: f64,
+    #[serde(rename = "Labels", default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// Agent operations.
+///
+/// This type can be used to interact with the "Agent" portion of the Consul API.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    http_client: Arc<HttpClient>,
+}
+
+impl Agent {
+    /// Creates a new [`Agent`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Agent {
+        Agent { http_client }
+    }
+
+    /// Gets the service with the specified ID registered on the local agent.
+    ///
+    /// This endpoint supports hash-based blocking, via [`Blocking::Hash`](crate::common::Blocking).
+    pub async fn get_service(
+        &self,
+        service_id: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(AgentService, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        get_service(&self.http_client, service_id, options.as_ref()).await
+    }
+
+    /// Gets the metrics of the local agent.
+    pub async fn metrics(&self, options: Option<QueryOptions>) -> Result<AgentMetrics, Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "agent", "metrics"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, _) = self.http_client.parse_query_response(response).await?;
+        Ok(parsed)
+    }
+
+    /// Gets the metrics of the local agent in the Prometheus exposition format.
+    ///
+    /// The text is returned exactly as sent by the agent, so that it can be proxied to a
+    /// Prometheus scraper without re-encoding.  This requires the agent to be configured with a
+    /// non-zero `telemetry.prometheus_retention_time`, otherwise the request fails.
+    pub async fn metrics_prometheus(&self, options: Option<QueryOptions>) -> Result<Bytes, Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request_with_params(
+            "GET",
+            &["v1", "agent", "metrics"],
+            vec![("format", Cow::Borrowed("prometheus"))],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_raw_response(response).await
+    }
+
+    /// Registers a service, and any checks for it, with the local agent.
+    ///
+    /// If a service with the same ID is already registered, it is replaced.
+    pub async fn register_service(
+        &self,
+        registration: &AgentServiceRegistration,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "agent", "service", "register"],
+            options.as_ref(),
+            registration,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_empty_response(response).await
+    }
+
+    /// Deregisters the service with the specified ID, and any checks for it, from the local agent.
+    pub async fn deregister_service(
+        &self,
+        service_id: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "agent", "service", "deregister", service_id],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_empty_response(response).await
+    }
+
+    /// Forces the node with the specified name into the `left` state.
+    ///
+    /// This is used to remove failed nodes, which would otherwise be reaped only after 72 hours.
+    /// If `prune` is set, the node is removed from the member list entirely, rather than being kept
+    /// as a tombstone, so that a new node can join with the same name straight away.  If `wan` is
+    /// set, the node is only removed from the WAN pool, which requires the name of the node to be
+    /// qualified with its datacenter, e.g. `server1.dc1`.
+    pub async fn force_leave(
+        &self,
+        node: &str,
+        prune: bool,
+        wan: bool,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        let mut params = Vec::new();
+        if prune {
+            params.push(("prune", Cow::Borrowed("")));
+        }
+        if wan {
+            params.push(("wan", Cow::Borrowed("")));
+        }
+        let request = self.http_client.build_request_with_params(
+            "PUT",
+            &["v1", "agent", "force-leave", node],
+            params,
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_empty_response(response).await
+    }
+
+    /// Updates the status of the TTL check with the specified ID.
+    pub async fn update_ttl(
+        &self,
+        check_id: &str,
+        status: CheckStatus,
+        output: Option<&str>,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        update_ttl(
+            &self.http_client,
+            check_id,
+            status,
+            output,
+            options.as_ref(),
+        )
+        .await
+    }
+
+    /// Spawns a background task which keeps the TTL check with the specified ID updated.
+    ///
+    /// The check is updated immediately, and then every `interval`, which should be comfortably
+    /// shorter than the TTL of the check.  The returned handle can be used to change the status
+    /// that is reported.  Failed updates are retried on the next heartbeat, and the task stops once
+    /// the client is shut down.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn spawn_ttl_heartbeat(&self, check_id: &str, interval: Duration) -> TtlHeartbeat {
+        let (tx, mut rx) = watch::channel((CheckStatus::Passing, None::<String>));
+        let check_id = check_id.to_string();
+        let http_client = self.http_client.clone();
+
+        spawn(async move {
+            // The first value from the channel is the initial status, which is sent immediately.
+            let mut current = match rx.recv().await {
+                Some(current) => current,
+                None => return,
+            };
+            loop {
+                let (status, output) = &current;
+                let _result =
+                    update_ttl(&http_client, &check_id, *status, output.as_deref(), None).await;
+                #[cfg(feature = "tracing")]
+                if let Err(e) = &_result {
+                    tracing::debug!(error = %e, check_id = %check_id, "TTL heartbeat failed");
+                }
+                if matches!(&_result, Err(e) if e.is_shutdown()) {
+                    break;
+                }
+
+                match future::select(Box::pin(rx.recv()), delay_for(interval)).await {
+                    Either::Left((Some(next), _)) => current = next,
+                    Either::Left((None, _)) => break,
+                    Either::Right(_) => {}
+                }
+            }
+        });
+
+        TtlHeartbeat { tx }
+    }
+
+    /// Gets a stream of changes to the service with the specified ID registered on the local
+    /// agent.
+    ///
+    /// Each item in the response stream represents the service after a change to it has occurred.
+    /// Unlike most watches, this is driven by the content hash of the service, as the agent does
+    /// not track indexes for local services.  The stream will terminate if any error is hit during
+    /// the background requests made to Consul.
+    pub fn watch_service(
+        &self,
+        service_id: &str,
+        options: Option<QueryOptions>,
+    ) -> Watch<AgentService> {
+        let options = self.http_client.watch_options(options);
+        let service_id = service_id.to_string();
+        let http_client = self.http_client.clone();
+
+        Watch::new(options, move |options| {
+            let service_id = service_id.clone();
+            let http_client = http_client.clone();
+            async move { get_service(&http_client, &service_id, Some(&options)).await }
+        })
+        .tracked(self.http_client.stats())
+    }
+}
+
+/// Request body for updating a TTL check.
+#[derive(Serialize)]
+struct TtlUpdate<'a> {
+    #[serde(rename = "Status")]
+    status: CheckStatus,
+    #[serde(rename = "Output", skip_serializing_if = "Option::is_none")]
+    output: Option<&'a str>,
+}
+
+/// Handle to a background task which keeps a TTL check updated, as created by
+/// [`Agent::spawn_ttl_heartbeat`].
+///
+/// The check is reported as passing by default.  The task stops when this handle is dropped, after
+/// which the check will go critical once its TTL expires.
+#[derive(Debug)]
+pub struct TtlHeartbeat {
+    tx: watch::Sender<(CheckStatus, Option<String>)>,
+}
+
+impl TtlHeartbeat {
+    /// Reports the check as passing, with the given output.
+    pub fn pass(&self, output: Option<&str>) {
+        self.set(CheckStatus::Passing, output);
+    }
+
+    /// Reports the check as warning, with the given output.
+    pub fn warn(&self, output: Option<&str>) {
+        self.set(CheckStatus::Warning, output);
+    }
+
+    /// Reports the check as critical, with the given output.
+    pub fn fail(&self, output: Option<&str>) {
+        self.set(CheckStatus::Critical, output);
+    }
+
+    /// Reports the check with the given status and output.
+    ///
+    /// The update is sent to the agent immediately, and then repeated on every heartbeat.
+    pub fn set(&self, status: CheckStatus, output: Option<&str>) {
+        let _ = self.tx.broadcast((status, output.map(|s| s.to_string())));
+    }
+}
+
+async fn update_ttl(
+    http_client: &HttpClient,
+    check_id: &str,
+    status: CheckStatus,
+    output: Option<&str>,
+    options: Option<&WriteOptions>,
+) -> Result<(), Error> {
+    let body = TtlUpdate { status, output };
+    let request = http_client.build_request(
+        "PUT",
+        &["v1", "agent", "check", "update", check_id],
+        options,
+        body,
+    )?;
+    let response = http_client.run_request(request, options).await?;
+    http_client.parse_empty_response(response).await?;
+    Ok(())
+}
+
+async fn get_service(
+    http_client: &HttpClient,
+    service_id: &str,
+    options: Option<&QueryOptions>,
+) -> Result<(AgentService, QueryMetadata), Error> {
+    let request =
+        http_client.build_request("GET", &["v1", "agent", "service", service_id], options, ())?;
+    let response = http_client.run_request(request, options).await?;
+    let (parsed, meta) = http_client.parse_query_response(response).await?;
+    Ok((parsed, meta))
 }
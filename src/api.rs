@@ -0,0 +1,216 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use hyper::body::Bytes;
+
+use crate::catalog::{
+    Catalog, CatalogDeregistration, CatalogNode, CatalogNodeServices, CatalogRegistration,
+    CatalogServiceNode,
+};
+use crate::common::{QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::health::{Health, ServiceEntry};
+use crate::kv::{Kv, KvPair};
+
+/// Boxed future returned by the operations of the API traits, such as [`CatalogApi`].
+///
+/// The future only borrows the subclient, and not any of the arguments of the operation, so that
+/// the traits can be mocked.
+pub type ApiFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// The operations of the catalog API.
+///
+/// Implemented by [`Catalog`], this allows application code to depend on `dyn CatalogApi` rather
+/// than the concrete subclient, so that a fake can be injected in tests.  See [`Catalog`] for the
+/// details of each operation.
+///
+/// When the `mockall` feature is enabled, a `MockCatalogApi` is generated for use in tests.
+#[cfg_attr(feature = "mockall", mockall::automock)]
+pub trait CatalogApi: Send + Sync {
+    /// Registers a node, and optionally a service on it, directly in the catalog.
+    fn register(
+        &self,
+        registration: &CatalogRegistration,
+        options: Option<WriteOptions>,
+    ) -> ApiFuture<'_, bool>;
+
+    /// Removes a node, or a service or check on it, directly from the catalog.
+    fn deregister(
+        &self,
+        deregistration: &CatalogDeregistration,
+        options: Option<WriteOptions>,
+    ) -> ApiFuture<'_, bool>;
+
+    /// Gets all nodes in the catalog.
+    fn get_nodes(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<CatalogNode>, QueryMetadata)>;
+
+    /// Gets the node with the specified name, and the services registered on it.
+    fn get_node_services(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<CatalogNodeServices>, QueryMetadata)>;
+
+    /// Gets the nodes running the specified service.
+    fn get_service_nodes(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<CatalogServiceNode>, QueryMetadata)>;
+}
+
+impl CatalogApi for Catalog {
+    fn register(
+        &self,
+        registration: &CatalogRegistration,
+        options: Option<WriteOptions>,
+    ) -> ApiFuture<'_, bool> {
+        let registration = registration.clone();
+        Box::pin(async move { Catalog::register(self, &registration, options).await })
+    }
+
+    fn deregister(
+        &self,
+        deregistration: &CatalogDeregistration,
+        options: Option<WriteOptions>,
+    ) -> ApiFuture<'_, bool> {
+        let deregistration = deregistration.clone();
+        Box::pin(async move { Catalog::deregister(self, &deregistration, options).await })
+    }
+
+    fn get_nodes(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<CatalogNode>, QueryMetadata)> {
+        Box::pin(Catalog::get_nodes(self, options))
+    }
+
+    fn get_node_services(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<CatalogNodeServices>, QueryMetadata)> {
+        let node = node.to_string();
+        Box::pin(async move { Catalog::get_node_services(self, &node, options).await })
+    }
+
+    fn get_service_nodes(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<CatalogServiceNode>, QueryMetadata)> {
+        let service = service.to_string();
+        Box::pin(async move { Catalog::get_service_nodes(self, &service, options).await })
+    }
+}
+
+/// The operations of the health API.
+///
+/// Implemented by [`Health`], this allows application code to depend on `dyn HealthApi` rather
+/// than the concrete subclient, so that a fake can be injected in tests.  See [`Health`] for the
+/// details of each operation.
+///
+/// When the `mockall` feature is enabled, a `MockHealthApi` is generated for use in tests.
+#[cfg_attr(feature = "mockall", mockall::automock)]
+pub trait HealthApi: Send + Sync {
+    /// Gets the instances of the specified service, along with their health checks.
+    fn get_service_nodes(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<ServiceEntry>, QueryMetadata)>;
+}
+
+impl HealthApi for Health {
+    fn get_service_nodes(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<ServiceEntry>, QueryMetadata)> {
+        let service = service.to_string();
+        Box::pin(
+            async move { Health::get_service_nodes(self, &service, passing_only, options).await },
+        )
+    }
+}
+
+/// The operations of the KV store API.
+///
+/// Implemented by [`Kv`], this allows application code to depend on `dyn KvApi` rather than the
+/// concrete subclient, so that a fake can be injected in tests.  See [`Kv`] for the details of
+/// each operation.
+///
+/// When the `mockall` feature is enabled, a `MockKvApi` is generated for use in tests.
+#[cfg_attr(feature = "mockall", mockall::automock)]
+pub trait KvApi: Send + Sync {
+    /// Gets the key/value pair for the given key.
+    fn get(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<KvPair>, QueryMetadata)>;
+
+    /// Gets the raw value of the given key.
+    fn get_raw(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<Bytes>, QueryMetadata)>;
+
+    /// Lists the key/value pairs for all keys with the given prefix.
+    fn list(
+        &self,
+        prefix: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<KvPair>, QueryMetadata)>;
+
+    /// Sets the value of the given key.
+    fn put(&self, key: &str, value: Bytes, options: Option<WriteOptions>) -> ApiFuture<'_, bool>;
+
+    /// Deletes the given key.
+    fn delete(&self, key: &str, options: Option<WriteOptions>) -> ApiFuture<'_, bool>;
+}
+
+impl KvApi for Kv {
+    fn get(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<KvPair>, QueryMetadata)> {
+        let key = key.to_string();
+        Box::pin(async move { Kv::get(self, &key, options).await })
+    }
+
+    fn get_raw(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Option<Bytes>, QueryMetadata)> {
+        let key = key.to_string();
+        Box::pin(async move { Kv::get_raw(self, &key, options).await })
+    }
+
+    fn list(
+        &self,
+        prefix: &str,
+        options: Option<QueryOptions>,
+    ) -> ApiFuture<'_, (Vec<KvPair>, QueryMetadata)> {
+        let prefix = prefix.to_string();
+        Box::pin(async move { Kv::list(self, &prefix, options).await })
+    }
+
+    fn put(&self, key: &str, value: Bytes, options: Option<WriteOptions>) -> ApiFuture<'_, bool> {
+        let key = key.to_string();
+        Box::pin(async move { Kv::put(self, &key, value, options).await })
+    }
+
+    fn delete(&self, key: &str, options: Option<WriteOptions>) -> ApiFuture<'_, bool> {
+        let key = key.to_string();
+        Box::pin(async move { Kv::delete(self, &key, options).await })
+    }
+}
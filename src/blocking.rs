@@ -0,0 +1,339 @@
+//! A blocking facade over [`Client`](crate::Client), for code which does not run in an async
+//! executor, such as CLI tools and build scripts.
+//!
+//! [`Client`] owns a small Tokio runtime, and every operation blocks the calling thread until the
+//! corresponding async operation completes on it.  Background work, such as cache refreshes and
+//! TTL heartbeats, keeps running on the runtime in between calls.
+//!
+//! Operations must not be called from within an async runtime, as blocking there would stall it.
+//! Operations which are not exposed directly can be run with [`Client::block_on`].
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use tokio::runtime::{Builder, Runtime as TokioRuntime};
+
+use crate::agent::AgentServiceRegistration;
+use crate::builder::ClientBuilder;
+use crate::catalog::{
+    CatalogDeregistration, CatalogNode, CatalogNodeServices, CatalogRegistration,
+    CatalogServiceNode,
+};
+use crate::common::{QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::health::ServiceEntry;
+use crate::kv::KvPair;
+
+/// The runtime operations are run on, shared between a client and its subclients.
+#[derive(Clone, Debug)]
+struct Runtime(Arc<Mutex<TokioRuntime>>);
+
+impl Runtime {
+    fn new() -> Result<Runtime, Error> {
+        let runtime = Builder::new()
+            .threaded_scheduler()
+            .core_threads(1)
+            .thread_name("async-consul-blocking")
+            .enable_all()
+            .build()
+            .map_err(Error::RuntimeFailed)?;
+        Ok(Runtime(Arc::new(Mutex::new(runtime))))
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.0
+            .lock()
+            .expect("blocking runtime lock poisoned")
+            .block_on(future)
+    }
+}
+
+/// Blocking client for interacting with the Consul API.
+///
+/// Cloning the client is cheap, and clones share the same runtime and underlying client.  Calls
+/// from multiple threads at the same time are run one after another.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Runtime,
+}
+
+impl Client {
+    /// Create a new [`Client`].
+    pub fn new(base_uri: &str) -> Result<Client, Error> {
+        Client::from_builder(crate::Client::builder().base_uri(base_uri))
+    }
+
+    /// Create a new [`Client`] configured from the environment.
+    ///
+    /// See [`ClientBuilder::from_env`] for the environment variables which are used.
+    pub fn from_env() -> Result<Client, Error> {
+        Client::from_builder(ClientBuilder::from_env()?)
+    }
+
+    /// Create a new [`Client`] from the given builder.
+    pub fn from_builder(builder: ClientBuilder) -> Result<Client, Error> {
+        let runtime = Runtime::new()?;
+        // The client is built on the runtime, in case anything it sets up needs one.
+        let inner = runtime.block_on(async { builder.build() })?;
+        Ok(Client { inner, runtime })
+    }
+
+    /// Runs the given future to completion on the runtime of this client, blocking until it
+    /// completes.
+    ///
+    /// This can be used for any operation of the async client which is not exposed directly:
+    ///
+    /// `client.block_on(client.inner().operator().autopilot_health(None))`
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Gets the async client that operations are run with.
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Gets an [`Agent`] object for working with the agent API.
+    pub fn agent(&self) -> Agent {
+        Agent {
+            inner: self.inner.agent(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Gets a [`Catalog`] object for working with the catalog API.
+    pub fn catalog(&self) -> Catalog {
+        Catalog {
+            inner: self.inner.catalog(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Gets a [`Health`] object for working with the health API.
+    pub fn health(&self) -> Health {
+        Health {
+            inner: self.inner.health(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Gets a [`Kv`] object for working with the KV store API.
+    pub fn kv(&self) -> Kv {
+        Kv {
+            inner: self.inner.kv(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Gets a [`Status`] object for working with the status API.
+    pub fn status(&self) -> Status {
+        Status {
+            inner: self.inner.status(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Shuts down this client, and all of its clones.
+    ///
+    /// See [`Client::shutdown`](crate::Client::shutdown) for what shutting down does.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        self.runtime.block_on(self.inner.shutdown(timeout))
+    }
+}
+
+/// Blocking agent operations.
+///
+/// See [`Agent`](crate::Agent) for the details of each operation.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    inner: crate::Agent,
+    runtime: Runtime,
+}
+
+impl Agent {
+    /// Registers a service, and any checks for it, with the local agent.
+    pub fn register_service(
+        &self,
+        registration: &AgentServiceRegistration,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.register_service(registration, options))
+    }
+
+    /// Deregisters the service with the specified ID, and any checks for it, from the local agent.
+    pub fn deregister_service(
+        &self,
+        service_id: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.deregister_service(service_id, options))
+    }
+}
+
+/// Blocking catalog operations.
+///
+/// See [`Catalog`](crate::Catalog) for the details of each operation.
+#[derive(Clone, Debug)]
+pub struct Catalog {
+    inner: crate::Catalog,
+    runtime: Runtime,
+}
+
+impl Catalog {
+    /// Registers a node, and optionally a service on it, directly in the catalog.
+    pub fn register(
+        &self,
+        registration: &CatalogRegistration,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        self.runtime
+            .block_on(self.inner.register(registration, options))
+    }
+
+    /// Removes a node, or a service or check on it, directly from the catalog.
+    pub fn deregister(
+        &self,
+        deregistration: &CatalogDeregistration,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        self.runtime
+            .block_on(self.inner.deregister(deregistration, options))
+    }
+
+    /// Gets the names of all known datacenters.
+    pub fn get_datacenters(&self) -> Result<Vec<String>, Error> {
+        self.runtime.block_on(self.inner.get_datacenters())
+    }
+
+    /// Gets all nodes in the catalog.
+    pub fn get_nodes(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<CatalogNode>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.get_nodes(options))
+    }
+
+    /// Gets the node with the specified name, and the services registered on it.
+    pub fn get_node_services(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<CatalogNodeServices>, QueryMetadata), Error> {
+        self.runtime
+            .block_on(self.inner.get_node_services(node, options))
+    }
+
+    /// Gets the nodes running the specified service.
+    pub fn get_service_nodes(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<CatalogServiceNode>, QueryMetadata), Error> {
+        self.runtime
+            .block_on(self.inner.get_service_nodes(service, options))
+    }
+}
+
+/// Blocking health operations.
+///
+/// See [`Health`](crate::Health) for the details of each operation.
+#[derive(Clone, Debug)]
+pub struct Health {
+    inner: crate::Health,
+    runtime: Runtime,
+}
+
+impl Health {
+    /// Gets the instances of the specified service, along with their health checks.
+    pub fn get_service_nodes(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMetadata), Error> {
+        self.runtime
+            .block_on(self.inner.get_service_nodes(service, passing_only, options))
+    }
+}
+
+/// Blocking KV store operations.
+///
+/// See [`Kv`](crate::Kv) for the details of each operation.
+#[derive(Clone, Debug)]
+pub struct Kv {
+    inner: crate::Kv,
+    runtime: Runtime,
+}
+
+impl Kv {
+    /// Gets the key/value pair for the given key.
+    pub fn get(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<KvPair>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.get(key, options))
+    }
+
+    /// Gets the value of the given key, exactly as stored.
+    pub fn get_raw(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<Bytes>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.get_raw(key, options))
+    }
+
+    /// Lists the key/value pairs for all keys with the given prefix.
+    pub fn list(
+        &self,
+        prefix: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<KvPair>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.list(prefix, options))
+    }
+
+    /// Sets the value of the given key.
+    pub fn put<V>(&self, key: &str, value: V, options: Option<WriteOptions>) -> Result<bool, Error>
+    where
+        V: Into<Bytes>,
+    {
+        self.runtime.block_on(self.inner.put(key, value, options))
+    }
+
+    /// Deletes the given key.
+    pub fn delete(&self, key: &str, options: Option<WriteOptions>) -> Result<bool, Error> {
+        self.runtime.block_on(self.inner.delete(key, options))
+    }
+}
+
+/// Blocking status operations.
+///
+/// See [`Status`](crate::Status) for the details of each operation.
+#[derive(Clone, Debug)]
+pub struct Status {
+    inner: crate::Status,
+    runtime: Runtime,
+}
+
+impl Status {
+    /// Gets the Raft address of the current leader of the cluster.
+    pub fn leader(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<String>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.leader(options))
+    }
+
+    /// Gets the Raft addresses of the voting servers in the cluster.
+    pub fn peers(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMetadata), Error> {
+        self.runtime.block_on(self.inner.peers(options))
+    }
+}
@@ -0,0 +1,680 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "native")]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::client::connect::Connect;
+use hyper::client::Client as HyperClient;
+use hyper::Body;
+use url::Url;
+
+use crate::cache::CacheSettings;
+use crate::circuit_breaker::CircuitBreakerSettings;
+use crate::common::QueryOptions;
+#[cfg(feature = "native")]
+use crate::connector::{Connector, IpPreference, TlsConfig};
+use crate::discovery::{Discovery, ServerDiscovery};
+use crate::endpoints::{Endpoints, DEFAULT_DISCOVERY_REFRESH_INTERVAL, DEFAULT_FAILOVER_COOLDOWN};
+use crate::errors::Error;
+#[cfg(feature = "native")]
+use crate::http_client::ConnectionSettings;
+use crate::http_client::{HttpClient, RequestDefaults, Transport};
+use crate::interceptor::{Interceptors, RequestInterceptor};
+use crate::recording::{Recording, RecordingMode};
+use crate::restart_budget::RestartBudgetSettings;
+use crate::token::{SharedTokenProvider, TokenProvider};
+use crate::Client;
+
+/// Default address of the Consul agent, matching the default used by the official Go client.
+const DEFAULT_BASE_URI: &str = "http://127.0.0.1:8500";
+
+/// Base URI used for requests when connecting over a Unix socket.
+#[cfg(feature = "native")]
+const UNIX_SOCKET_BASE_URI: &str = "http://localhost/";
+
+/// Builder for [`Client`].
+///
+/// Allows configuring defaults which are applied to every request made by the resulting client,
+/// such as the ACL token or datacenter.  Any values specified in the options given to an
+/// individual operation take precedence over these defaults.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    base_uris: Vec<String>,
+    failover_cooldown: Duration,
+    discovery: Option<Discovery>,
+    discovery_refresh_interval: Duration,
+    defaults: RequestDefaults,
+    #[cfg(feature = "native")]
+    tls_config: Option<TlsConfig>,
+    #[cfg(feature = "native")]
+    settings: ConnectionSettings,
+    interceptors: Interceptors,
+    cache: Option<CacheSettings>,
+    circuit_breaker: Option<CircuitBreakerSettings>,
+    recording: Option<RecordingMode>,
+    restart_budget: Option<RestartBudgetSettings>,
+    transport: Option<Transport>,
+}
+
+impl ClientBuilder {
+    /// Creates a new [`ClientBuilder`].
+    pub(crate) fn new() -> ClientBuilder {
+        ClientBuilder {
+            base_uris: vec![DEFAULT_BASE_URI.to_string()],
+            failover_cooldown: DEFAULT_FAILOVER_COOLDOWN,
+            discovery: None,
+            discovery_refresh_interval: DEFAULT_DISCOVERY_REFRESH_INTERVAL,
+            defaults: RequestDefaults::default(),
+            #[cfg(feature = "native")]
+            tls_config: None,
+            #[cfg(feature = "native")]
+            settings: ConnectionSettings::default(),
+            interceptors: Interceptors::default(),
+            cache: None,
+            circuit_breaker: None,
+            recording: None,
+            restart_budget: None,
+            transport: None,
+        }
+    }
+
+    /// Creates a new [`ClientBuilder`] configured from the environment.
+    ///
+    /// Uses the same environment variables, and the same defaults, as the official Go client:
+    ///
+    /// - `CONSUL_HTTP_ADDR`: address of the Consul endpoint, with or without a scheme, such as
+    ///   `127.0.0.1:8500`, `https://consul.example.com`, or `unix:///var/run/consul.sock`
+    /// - `CONSUL_HTTP_TOKEN`: default ACL token
+    /// - `CONSUL_HTTP_TOKEN_FILE`: file to read the default ACL token from, if `CONSUL_HTTP_TOKEN`
+    ///   is not set
+    /// - `CONSUL_HTTP_SSL`: whether or not to use HTTPS when no scheme is given in the address
+    /// - `CONSUL_HTTP_SSL_VERIFY`: whether or not to verify the server certificate
+    /// - `CONSUL_CACERT`: file containing the CA certificate(s) to verify the server certificate
+    /// - `CONSUL_CLIENT_CERT`/`CONSUL_CLIENT_KEY`: files containing the client certificate and key
+    ///   for mutual TLS
+    /// - `CONSUL_TLS_SERVER_NAME`: server name to use for SNI and certificate verification
+    /// - `CONSUL_NAMESPACE`: default namespace
+    pub fn from_env() -> Result<ClientBuilder, Error> {
+        let mut builder = ClientBuilder::new();
+
+        let use_tls = env_bool("CONSUL_HTTP_SSL").unwrap_or(false);
+        if let Some(addr) = env_string("CONSUL_HTTP_ADDR") {
+            let base_uri = if addr.contains("://") {
+                addr
+            } else if use_tls {
+                format!("https://{}", addr)
+            } else {
+                format!("http://{}", addr)
+            };
+            builder.base_uris = vec![base_uri];
+        } else if use_tls {
+            builder.base_uris = vec![DEFAULT_BASE_URI.replacen("http", "https", 1)];
+        }
+
+        if let Some(token) = env_string("CONSUL_HTTP_TOKEN") {
+            builder.defaults.token = Some(token);
+        } else if let Some(path) = env_string("CONSUL_HTTP_TOKEN_FILE") {
+            let token = String::from_utf8_lossy(&read_file(&path)?)
+                .trim()
+                .to_string();
+            builder.defaults.token = Some(token);
+        }
+
+        if let Some(namespace) = env_string("CONSUL_NAMESPACE") {
+            builder.defaults.namespace = Some(namespace);
+        }
+
+        #[cfg(feature = "native")]
+        builder.tls_config_from_env()?;
+
+        Ok(builder)
+    }
+
+    /// Sets the TLS configuration from the environment, if any of it is set.
+    #[cfg(feature = "native")]
+    fn tls_config_from_env(&mut self) -> Result<(), Error> {
+        let mut tls_config = TlsConfig::default();
+        let mut has_tls_config = false;
+        if let Some(path) = env_string("CONSUL_CACERT") {
+            tls_config.ca_cert_pem = Some(read_file(&path)?);
+            has_tls_config = true;
+        }
+        if let Some(path) = env_string("CONSUL_CLIENT_CERT") {
+            tls_config.client_cert_pem = Some(read_file(&path)?);
+            has_tls_config = true;
+        }
+        if let Some(path) = env_string("CONSUL_CLIENT_KEY") {
+            tls_config.client_key_pem = Some(read_file(&path)?);
+            has_tls_config = true;
+        }
+        if let Some(server_name) = env_string("CONSUL_TLS_SERVER_NAME") {
+            tls_config.server_name = Some(server_name);
+            has_tls_config = true;
+        }
+        if let Some(verify) = env_bool("CONSUL_HTTP_SSL_VERIFY") {
+            tls_config.insecure_skip_verify = !verify;
+            has_tls_config = true;
+        }
+        if has_tls_config {
+            self.tls_config = Some(tls_config);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the base URI of the Consul endpoint to connect to.
+    ///
+    /// Defaults to `http://127.0.0.1:8500`.
+    ///
+    /// A Unix socket can be used by specifying a URI such as `unix:///var/run/consul.sock`.
+    pub fn base_uri(mut self, base_uri: &str) -> ClientBuilder {
+        self.base_uris = vec![base_uri.to_string()];
+        self
+    }
+
+    /// Sets multiple base URIs of Consul endpoints to connect to.
+    ///
+    /// Requests are sent to one endpoint at a time, failing over to the next endpoint if the request
+    /// cannot connect or times out.  This is intended for talking directly to the servers of a
+    /// Consul cluster, rather than a local agent.  All endpoints are expected to expose the API at
+    /// the same path.
+    ///
+    /// Unix sockets cannot be combined with other endpoints.
+    pub fn base_uris<I, S>(mut self, base_uris: I) -> ClientBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.base_uris = base_uris
+            .into_iter()
+            .map(|s| s.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Sets how long an endpoint is avoided after a request to it fails.
+    ///
+    /// Once this period has elapsed, the endpoint is considered healthy again.  Defaults to 30
+    /// seconds.  Only relevant when multiple base URIs are configured.
+    pub fn failover_cooldown(mut self, cooldown: Duration) -> ClientBuilder {
+        self.failover_cooldown = cooldown;
+        self
+    }
+
+    /// Sets a source for discovering the Consul endpoints to connect to, such as [`SrvDiscovery`].
+    ///
+    /// Discovery is run before the first request, replacing the configured base URIs, and then
+    /// periodically refreshed.  If a refresh fails, the previously discovered endpoints continue to
+    /// be used.  The discovered endpoints are expected to expose the API at the same path as the
+    /// configured base URI.
+    ///
+    /// [`SrvDiscovery`]: crate::SrvDiscovery
+    pub fn discovery<D>(mut self, discovery: D) -> ClientBuilder
+    where
+        D: ServerDiscovery,
+    {
+        self.discovery = Some(Discovery(Box::new(discovery)));
+        self
+    }
+
+    /// Sets how often discovered endpoints are refreshed.
+    ///
+    /// Defaults to 60 seconds.  Only relevant when a discovery source is configured.
+    pub fn discovery_refresh_interval(mut self, interval: Duration) -> ClientBuilder {
+        self.discovery_refresh_interval = interval;
+        self
+    }
+
+    /// Sets the default ACL token to use for requests.
+    pub fn token(mut self, token: &str) -> ClientBuilder {
+        self.defaults.token = Some(token.to_string());
+        self
+    }
+
+    /// Sets a provider for the default ACL token to use for requests, such as
+    /// [`FileTokenProvider`].
+    ///
+    /// The provider is asked for a token before each request which does not specify its own token,
+    /// allowing tokens to be rotated without rebuilding the client.  Takes precedence over any
+    /// token set via [`token`](ClientBuilder::token).
+    ///
+    /// [`FileTokenProvider`]: crate::FileTokenProvider
+    pub fn token_provider<P>(mut self, provider: P) -> ClientBuilder
+    where
+        P: TokenProvider,
+    {
+        self.defaults.token_provider = Some(SharedTokenProvider(Arc::new(provider)));
+        self
+    }
+
+    /// Sets whether or not to send ACL tokens as `Authorization: Bearer <token>`, rather than via
+    /// the `X-Consul-Token` header.
+    ///
+    /// This is useful when talking to Consul through proxies which only pass through the
+    /// `Authorization` header.  Requires Consul 1.7 or newer.  Defaults to `false`.
+    pub fn bearer_auth(mut self, enabled: bool) -> ClientBuilder {
+        self.defaults.bearer_auth = enabled;
+        self
+    }
+
+    /// Sets the default options for query operations.
+    ///
+    /// The options given to an individual query operation are merged over these defaults, with any
+    /// value set for the operation taking precedence.  This allows settings such as the consistency
+    /// mode, or agent caching, to be configured once for all queries.  Flags, such as
+    /// [`QueryOptions::use_cache`], can be explicitly disabled by an individual operation, and an
+    /// operation which asks for a consistent read does not inherit agent caching or sorting.
+    ///
+    /// Settings configured directly on the builder, such as [`datacenter`](ClientBuilder::datacenter),
+    /// still apply when not set by either these defaults or the operation.
+    pub fn default_query_options(mut self, options: QueryOptions) -> ClientBuilder {
+        self.defaults.query_options = Some(options);
+        self
+    }
+
+    /// Sets whether or not to skip list entries which cannot be decoded.
+    ///
+    /// By default, a list response fails entirely if any of its entries does not match the expected
+    /// structure.  When enabled, such entries are skipped instead, so that, for example, one
+    /// malformed service registration does not prevent discovering all of the others.  Defaults to
+    /// `false`.
+    pub fn lenient_payloads(mut self, enabled: bool) -> ClientBuilder {
+        self.defaults.lenient_payloads = enabled;
+        self
+    }
+
+    /// Sets whether or not to reject responses with fields which are not known to this crate.
+    ///
+    /// By default, fields returned by Consul which are not modeled are kept in the `extra` field of
+    /// the types which capture them, or otherwise ignored.  When enabled, a response with any such
+    /// field fails to decode instead, with the path to the offending value, which is meant to catch
+    /// changes to the Consul API in CI rather than in production.  Fields ignored by types without
+    /// an `extra` field are still ignored.  Defaults to `false`.
+    ///
+    /// When lenient payloads are enabled as well, list entries with unknown fields are skipped.
+    pub fn strict_payloads(mut self, enabled: bool) -> ClientBuilder {
+        self.defaults.strict_payloads = enabled;
+        self
+    }
+
+    /// Sets whether or not to generate a correlation ID for each request.
+    ///
+    /// When enabled, requests which were not given a correlation ID via their options, such as
+    /// [`QueryOptions::request_id`], are sent with a randomly generated one in the `X-Request-ID`
+    /// header.  Either way, the correlation ID is included in the context of any error for the
+    /// request.  Defaults to `false`.
+    pub fn request_ids(mut self, enabled: bool) -> ClientBuilder {
+        self.defaults.generate_request_ids = enabled;
+        self
+    }
+
+    /// Sets whether or not reads which allow stale results must be monotonic.
+    ///
+    /// Reads which allow stale results, such as those using [`Consistency::Stale`], can be serviced
+    /// by any server, including one which is lagging behind and returns older data than was
+    /// already read, such as after a failover.  When enabled, the highest index seen for each
+    /// resource is tracked, and any stale read which returns a lower index for the same resource is
+    /// retried against the leader, so that reads never go back in time.  Defaults to `false`.
+    ///
+    /// [`Consistency::Stale`]: crate::common::Consistency::Stale
+    pub fn monotonic_reads(mut self, enabled: bool) -> ClientBuilder {
+        self.defaults.monotonic_reads = enabled;
+        self
+    }
+
+    /// Sets the duration after which a request is logged as being slow.
+    ///
+    /// Requests which take longer than this, other than blocking queries, are logged at the info
+    /// level.  This only has an effect when the `log` feature is enabled.  By default, requests are
+    /// never logged as being slow.
+    pub fn slow_request_threshold(mut self, threshold: Duration) -> ClientBuilder {
+        self.defaults.slow_request_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the default datacenter to execute requests against.
+    pub fn datacenter(mut self, datacenter: &str) -> ClientBuilder {
+        self.defaults.datacenter = Some(datacenter.to_string());
+        self
+    }
+
+    /// Sets the default namespace to execute requests against.
+    ///
+    /// NOTE: Namespaces are available only in Consul Enterprise.
+    pub fn namespace(mut self, namespace: &str) -> ClientBuilder {
+        self.defaults.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Sets the default overall timeout for requests.
+    ///
+    /// This timeout is not applied to blocking queries, as they are expected to take as long as
+    /// their configured blocking timeout to complete.
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.defaults.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the TLS configuration to use when connecting to Consul over HTTPS.
+    #[cfg(feature = "native")]
+    pub fn tls_config(mut self, tls_config: TlsConfig) -> ClientBuilder {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// Sets the maximum number of idle connections to keep open per host.
+    ///
+    /// Defaults to no limit.
+    #[cfg(feature = "native")]
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> ClientBuilder {
+        self.settings.pool_max_idle_per_host = Some(max_idle);
+        self
+    }
+
+    /// Sets how long idle connections are kept open before being closed.
+    ///
+    /// Defaults to 90 seconds.
+    #[cfg(feature = "native")]
+    pub fn pool_idle_timeout(mut self, idle_timeout: Duration) -> ClientBuilder {
+        self.settings.pool_idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Sets whether or not to only use HTTP/2 when talking to Consul.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "native")]
+    pub fn http2_only(mut self, enabled: bool) -> ClientBuilder {
+        self.settings.http2_only = enabled;
+        self
+    }
+
+    /// Sets the timeout for establishing new connections.
+    ///
+    /// Defaults to no timeout.
+    #[cfg(feature = "native")]
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.settings.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the interval for TCP keep-alive probes on connections.
+    ///
+    /// Defaults to TCP keep-alive being disabled.
+    #[cfg(feature = "native")]
+    pub fn tcp_keepalive(mut self, interval: Duration) -> ClientBuilder {
+        self.settings.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Sets which IP address families to connect to Consul over.
+    ///
+    /// See [`IpPreference`] for how connections are attempted when the host of an endpoint resolves
+    /// to both IPv4 and IPv6 addresses.  Addresses given as IP literals are always connected to as
+    /// they are.  Defaults to [`IpPreference::Resolver`].
+    #[cfg(feature = "native")]
+    pub fn ip_preference(mut self, preference: IpPreference) -> ClientBuilder {
+        self.settings.ip_preference = preference;
+        self
+    }
+
+    /// Sets how long to wait for a connection over the preferred IP address family before also
+    /// trying the other family.
+    ///
+    /// A timeout of zero tries both families at the same time.  Defaults to 300 milliseconds, as
+    /// recommended by RFC 8305.
+    #[cfg(feature = "native")]
+    pub fn happy_eyeballs_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.settings.happy_eyeballs_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the hyper client to send requests with, instead of constructing one.
+    ///
+    /// This allows reusing a client which is already tuned, such as with a custom resolver or a
+    /// connector which records metrics.  The client is used as-is, so the TLS configuration, and
+    /// the connection and pooling settings of this builder, are ignored.  Unix sockets cannot be
+    /// used, as they need a connector of their own.
+    pub fn hyper_client<C>(mut self, client: HyperClient<C, Body>) -> ClientBuilder
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        self.transport = Some(Transport::from_client(client));
+        self
+    }
+
+    /// Adds an interceptor which can observe and modify requests and responses.
+    ///
+    /// Interceptors are called in the order they are added.
+    pub fn interceptor<I>(mut self, interceptor: I) -> ClientBuilder
+    where
+        I: RequestInterceptor,
+    {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// Records every request, and the response it received, to the given file.
+    ///
+    /// Interactions are appended to the file, one per line, so that they can later be replayed
+    /// with [`replay_from`](ClientBuilder::replay_from), such as for golden-file integration
+    /// tests based on captures from a real Consul cluster.  Request headers, including ACL tokens,
+    /// are not recorded, but responses are recorded in full.
+    pub fn record_to<P: AsRef<Path>>(mut self, path: P) -> ClientBuilder {
+        self.recording = Some(RecordingMode::Record(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Replays the responses recorded in the given file, rather than sending requests to Consul.
+    ///
+    /// Requests are matched against the recording by their method, path, and query string.  If
+    /// the same request was recorded multiple times, its responses are replayed in order, with the
+    /// last one repeating once all others have been replayed.  Requests without a recorded
+    /// response fail with [`Error::NoRecordedResponse`].
+    pub fn replay_from<P: AsRef<Path>>(mut self, path: P) -> ClientBuilder {
+        self.recording = Some(RecordingMode::Replay(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Sets whether or not to cache query responses within the client.
+    ///
+    /// When enabled, the responses to queries are cached, keyed by their endpoint, options, and ACL
+    /// token.  Cached responses are served immediately, while being kept up-to-date with blocking
+    /// queries in the background.  This is similar to agent caching, via
+    /// [`QueryOptions::use_cache`], but also works when talking directly to Consul servers.  Only
+    /// endpoints which support blocking queries are cached, and blocking queries themselves always
+    /// bypass the cache.  Defaults to `false`.
+    pub fn cache(mut self, enabled: bool) -> ClientBuilder {
+        self.cache = if enabled {
+            Some(self.cache.unwrap_or_default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Sets how long a cached response can go without being successfully refreshed before it is no
+    /// longer served.
+    ///
+    /// Once a cached response is this stale, requests go to Consul again until the background
+    /// refresh succeeds.  Defaults to 60 seconds.  Enables caching if not already enabled.
+    pub fn cache_max_stale(mut self, max_stale: Duration) -> ClientBuilder {
+        self.cache
+            .get_or_insert_with(CacheSettings::default)
+            .max_stale = max_stale;
+        self
+    }
+
+    /// Sets how long a cached response is kept without being used.
+    ///
+    /// Cached responses which are not used for this long are evicted, stopping their background
+    /// refresh.  Defaults to 5 minutes.  Enables caching if not already enabled.
+    pub fn cache_idle_timeout(mut self, idle_timeout: Duration) -> ClientBuilder {
+        self.cache
+            .get_or_insert_with(CacheSettings::default)
+            .idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets whether or not to use a circuit breaker for requests.
+    ///
+    /// When enabled, the outcomes of recent requests are tracked, where connection errors, timeouts,
+    /// and server errors count as failures.  Once too many are failing, requests fail immediately
+    /// with [`Error::CircuitOpen`] for a period, rather than waiting on an unavailable endpoint,
+    /// after which a single request is let through to check whether it has recovered.  If
+    /// [caching](ClientBuilder::cache) is enabled, cached responses are served while the circuit
+    /// breaker is open, no matter how stale.  Defaults to `false`.
+    pub fn circuit_breaker(mut self, enabled: bool) -> ClientBuilder {
+        self.circuit_breaker = if enabled {
+            Some(self.circuit_breaker.unwrap_or_default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Sets the rate of failures, between 0 and 1, over the recent requests at which the circuit
+    /// breaker opens.
+    ///
+    /// Defaults to 0.5.  Enables the circuit breaker if not already enabled.
+    pub fn circuit_breaker_failure_rate(mut self, failure_rate: f64) -> ClientBuilder {
+        self.circuit_breaker
+            .get_or_insert_with(CircuitBreakerSettings::default)
+            .failure_rate = failure_rate;
+        self
+    }
+
+    /// Sets how long the circuit breaker stays open before letting a request through again.
+    ///
+    /// Defaults to 30 seconds.  Enables the circuit breaker if not already enabled.
+    pub fn circuit_breaker_open_duration(mut self, open_duration: Duration) -> ClientBuilder {
+        self.circuit_breaker
+            .get_or_insert_with(CircuitBreakerSettings::default)
+            .open_duration = open_duration;
+        self
+    }
+
+    /// Sets a budget for restarting failed watches, shared by all watches on the client.
+    ///
+    /// When many watches fail at the same time, such as when the agent restarts, their individual
+    /// backoff would still have them all reconnect at the same time.  With a budget, up to `burst`
+    /// watches may restart at once, after which restarts are spread out to one every `interval`,
+    /// so that the recovering agent is not overwhelmed.  This applies to the watches of a
+    /// [`WatchManager`](crate::WatchManager) and of a [`ConfigWatcher`](crate::ConfigWatcher).
+    ///
+    /// By default, there is no budget, and watches restart as soon as their backoff allows.
+    pub fn watch_restart_budget(mut self, burst: u32, interval: Duration) -> ClientBuilder {
+        self.restart_budget = Some(RestartBudgetSettings { burst, interval });
+        self
+    }
+
+    /// Builds the [`Client`].
+    pub fn build(self) -> Result<Client, Error> {
+        let mut base_uris = self
+            .base_uris
+            .iter()
+            .map(|s| Url::parse(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        if base_uris.is_empty() {
+            return Err(Error::InvalidEndpoints("no base URIs given"));
+        }
+
+        let is_unix = base_uris.iter().any(|u| u.scheme() == "unix");
+        let transport = match self.transport.clone() {
+            Some(_) if is_unix => {
+                return Err(Error::InvalidEndpoints(
+                    "Unix sockets cannot be used with a custom hyper client",
+                ));
+            }
+            Some(transport) => transport,
+            None => self.default_transport(&mut base_uris)?,
+        };
+        let mut endpoints = Endpoints::new(base_uris, self.failover_cooldown);
+        if let Some(discovery) = self.discovery {
+            endpoints = endpoints.with_discovery(discovery, self.discovery_refresh_interval);
+        }
+        let http_client = HttpClient::new(
+            endpoints,
+            transport,
+            self.defaults,
+            self.interceptors,
+            self.cache,
+            self.circuit_breaker,
+            self.recording.map(Recording::new).transpose()?,
+            self.restart_budget,
+        );
+
+        Ok(Client {
+            http_client: Arc::new(http_client),
+        })
+    }
+
+    /// Creates the transport to send requests with when no hyper client was given.
+    #[cfg(feature = "native")]
+    fn default_transport(&self, base_uris: &mut Vec<Url>) -> Result<Transport, Error> {
+        if !base_uris.iter().any(|u| u.scheme() == "unix") {
+            let connector = Connector::new(self.tls_config.as_ref(), &self.settings)?;
+            return Ok(Transport::new(connector, &self.settings));
+        }
+
+        if base_uris.len() > 1 || self.discovery.is_some() {
+            return Err(Error::InvalidEndpoints(
+                "Unix sockets cannot be combined with other endpoints",
+            ));
+        }
+
+        // Requests still need a valid HTTP URI, even though the host is never used to connect.
+        let path = PathBuf::from(base_uris[0].path());
+        *base_uris = vec![Url::parse(UNIX_SOCKET_BASE_URI)?];
+        Ok(Transport::new(Connector::unix(path)?, &self.settings))
+    }
+
+    /// Creates the transport to send requests with when no hyper client was given.
+    #[cfg(all(not(feature = "native"), feature = "wasm", target_arch = "wasm32"))]
+    fn default_transport(&self, _base_uris: &mut Vec<Url>) -> Result<Transport, Error> {
+        Ok(Transport::fetch())
+    }
+
+    /// Creates the transport to send requests with when no hyper client was given.
+    #[cfg(not(any(feature = "native", all(feature = "wasm", target_arch = "wasm32"))))]
+    fn default_transport(&self, _base_uris: &mut Vec<Url>) -> Result<Transport, Error> {
+        Err(Error::MissingTransport)
+    }
+
+    /// Builds the [`Client`], verifying that it can reach Consul.
+    ///
+    /// Queries the current cluster leader, which catches misconfigurations such as an incorrect
+    /// address or TLS settings at startup rather than on first use.  This also establishes a pooled
+    /// connection, so the first real request does not have to pay the cost of connecting, including
+    /// any TLS handshake.
+    ///
+    /// Returns [`Error::NoClusterLeader`] if Consul is reachable but its cluster has no leader.
+    pub async fn connect(self) -> Result<Client, Error> {
+        let client = self.build()?;
+        client.verify().await?;
+        Ok(client)
+    }
+}
+
+/// Gets the value of the given environment variable, if it is set and not empty.
+fn env_string(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+/// Gets the value of the given environment variable as a boolean, if it is set and valid.
+///
+/// Accepts the same values as Go's `strconv.ParseBool`.
+fn env_bool(key: &str) -> Option<bool> {
+    env_string(key).and_then(|v| match v.as_str() {
+        "1" | "t" | "T" | "true" | "TRUE" | "True" => Some(true),
+        "0" | "f" | "F" | "false" | "FALSE" | "False" => Some(false),
+        _ => None,
+    })
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| Error::InvalidConfigFile(Path::new(path).to_path_buf(), e))
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use hyper::http::uri::InvalidUri;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+use url::Url;
+
+use crate::common::{Blocking, QueryOptions, REQUEST_ID_HEADER};
+use crate::errors::{Error, ResponseError};
+use crate::http_client::{parse_retry_after, HttpClient};
+use crate::runtime::{delay_for, spawn, Instant};
+use crate::shutdown::Shutdown;
+
+/// Default for how long a cached response may go without being refreshed before it is no longer
+/// served.
+pub(crate) const DEFAULT_CACHE_MAX_STALE: Duration = Duration::from_secs(60);
+
+/// Default for how long a cached response is kept, and refreshed, without being used.
+pub(crate) const DEFAULT_CACHE_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+const MAX_REFRESH_WAIT: Duration = Duration::from_secs(300);
+const MIN_REFRESH_WAIT: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Settings for the in-process response cache.
+#[derive(Clone, Debug)]
+pub(crate) struct CacheSettings {
+    pub max_stale: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl Default for CacheSettings {
+    fn default() -> CacheSettings {
+        CacheSettings {
+            max_stale: DEFAULT_CACHE_MAX_STALE,
+            idle_timeout: DEFAULT_CACHE_IDLE_TIMEOUT,
+        }
+    }
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    index: u64,
+    refreshed: Instant,
+}
+
+struct Entry {
+    response: Mutex<CachedResponse>,
+    last_access: Mutex<Instant>,
+}
+
+type Entries = Arc<Mutex<HashMap<String, Arc<Entry>>>>;
+
+/// An in-process cache of query responses, kept up-to-date in the background.
+///
+/// Responses are keyed by their full request URI and ACL token.  Once a response is cached, a
+/// background task keeps it up-to-date with blocking queries, for as long as it keeps being used.
+/// Cached responses are only served while they have been refreshed recently enough, so a failing
+/// refresh eventually results in requests going to Consul again.  Refreshing stops once the client
+/// is shut down.
+///
+/// Refreshes are sent through the client like any other request, so they fail over between
+/// endpoints, pass through interceptors, the circuit breaker and statistics, and are replayed
+/// when replaying a recording.
+#[derive(Clone)]
+pub(crate) struct ResponseCache {
+    settings: CacheSettings,
+    entries: Entries,
+    shutdown: Arc<Shutdown>,
+}
+
+impl ResponseCache {
+    pub fn new(settings: CacheSettings, shutdown: Arc<Shutdown>) -> ResponseCache {
+        ResponseCache {
+            settings,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            shutdown,
+        }
+    }
+
+    /// Gets the key to cache the response to the given request under.
+    pub fn key(request: &Request<Body>) -> String {
+        let headers = request.headers();
+        format!(
+            "{} {:?} {:?}",
+            request.uri(),
+            headers.get("X-Consul-Token"),
+            headers.get(AUTHORIZATION)
+        )
+    }
+
+    /// Gets the cached response for the given key, if it is fresh enough to be served.
+    pub fn get(&self, key: &str) -> Option<Response<Body>> {
+        self.lookup(key, Some(self.settings.max_stale))
+    }
+
+    /// Gets the cached response for the given key, no matter how stale it is.
+    pub fn get_stale(&self, key: &str) -> Option<Response<Body>> {
+        self.lookup(key, None)
+    }
+
+    fn lookup(&self, key: &str, max_stale: Option<Duration>) -> Option<Response<Body>> {
+        let entry = self
+            .entries
+            .lock()
+            .expect("cache lock poisoned")
+            .get(key)?
+            .clone();
+        *entry.last_access.lock().expect("cache lock poisoned") = Instant::now();
+
+        let cached = entry.response.lock().expect("cache lock poisoned");
+        if max_stale.is_some_and(|max_stale| cached.refreshed.elapsed() > max_stale) {
+            return None;
+        }
+
+        let mut response = Response::new(Body::from(cached.body.clone()));
+        *response.status_mut() = cached.status;
+        *response.headers_mut() = cached.headers.clone();
+        Some(response)
+    }
+
+    /// Caches the given response to the given request, returning an equivalent response.
+    ///
+    /// Only successful responses which support blocking queries are cached.  For those, a
+    /// background task is started which keeps the response up-to-date via the given client.
+    pub async fn insert(
+        &self,
+        client: &HttpClient,
+        key: String,
+        uri: Uri,
+        request_headers: HeaderMap,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, Error> {
+        let index = consul_index(response.headers());
+        if response.status() != StatusCode::OK || index.is_none() {
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let now = Instant::now();
+        let entry = Arc::new(Entry {
+            response: Mutex::new(CachedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+                index: index.unwrap_or_default().max(1),
+                refreshed: now,
+            }),
+            last_access: Mutex::new(now),
+        });
+
+        let existing = self
+            .entries
+            .lock()
+            .expect("cache lock poisoned")
+            .insert(key.clone(), entry.clone());
+        // Only a single refresh task is needed per key, so if one is already running, such as for an
+        // entry which went stale, we replace its entry and let it pick up the new one.
+        if existing.is_none() {
+            let cache = self.clone();
+            let client = client.clone();
+            spawn(async move {
+                let shutdown = cache.shutdown.clone();
+                let refresh = cache.refresh(client, key, uri, request_headers);
+                future::select(refresh, Box::pin(shutdown.closed())).await;
+            });
+        }
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Keeps the entry for the given key up-to-date until it is evicted.
+    ///
+    /// The future is boxed, as refreshing runs requests through the client, which may in turn
+    /// insert into the cache, and the compiler can't otherwise tell that the future is `Send`.
+    fn refresh(
+        self,
+        client: HttpClient,
+        key: String,
+        uri: Uri,
+        headers: HeaderMap,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(self.run_refresh(client, key, uri, headers))
+    }
+
+    async fn run_refresh(self, client: HttpClient, key: String, uri: Uri, headers: HeaderMap) {
+        let wait = (self.settings.max_stale / 2).clamp(MIN_REFRESH_WAIT, MAX_REFRESH_WAIT);
+        let mut retry_delay = MIN_REFRESH_WAIT;
+
+        loop {
+            let entry = match self
+                .entries
+                .lock()
+                .expect("cache lock poisoned")
+                .get(&key)
+                .cloned()
+            {
+                Some(entry) => entry,
+                None => return,
+            };
+
+            let idle = entry
+                .last_access
+                .lock()
+                .expect("cache lock poisoned")
+                .elapsed();
+            if idle > self.settings.idle_timeout {
+                self.entries
+                    .lock()
+                    .expect("cache lock poisoned")
+                    .remove(&key);
+                return;
+            }
+
+            let index = entry.response.lock().expect("cache lock poisoned").index;
+            match query(&client, &uri, &headers, index, wait).await {
+                Ok(Some((response, body))) => {
+                    let mut cached = entry.response.lock().expect("cache lock poisoned");
+                    let next = consul_index(response.headers()).unwrap_or_default();
+                    // Like any blocking query, reset if the index goes backwards, and never block
+                    // on an index of zero, which would return immediately.
+                    cached.index = if next < cached.index { 1 } else { next.max(1) };
+                    cached.status = response.status();
+                    cached.headers = response.headers().clone();
+                    cached.body = body;
+                    cached.refreshed = Instant::now();
+                    retry_delay = MIN_REFRESH_WAIT;
+                }
+                Ok(None) => {
+                    delay_for(retry_delay).await;
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Err(e) => {
+                    // Never retry sooner than Consul asked us to.
+                    delay_for(e.retry_after().map_or(retry_delay, |d| d.max(retry_delay))).await;
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+fn consul_index(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("X-Consul-Index")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Runs a blocking query for the given request, returning the response if it was successful.
+///
+/// The request is sent to whichever endpoint is current at the time, rather than the one the
+/// response was originally cached from, so that refreshes follow failover.
+async fn query(
+    client: &HttpClient,
+    uri: &Uri,
+    headers: &HeaderMap,
+    index: u64,
+    wait: Duration,
+) -> Result<Option<(Response<()>, Bytes)>, Error> {
+    let mut url = Url::parse(&uri.to_string())?;
+    let pairs = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(k, _)| k != "index" && k != "wait")
+        .collect::<Vec<_>>();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("index", &index.to_string())
+        .append_pair("wait", &format!("{}ms", wait.as_millis()));
+    let uri = url
+        .as_str()
+        .parse()
+        .map_err(|e: InvalidUri| Error::InvalidRequest(e.into()))?;
+
+    let mut request = Request::new(Body::empty());
+    *request.uri_mut() = client.resolve_uri(&uri)?;
+    *request.headers_mut() = headers.clone();
+    // Each refresh is a request of its own, so gets its own request ID, if any.
+    request.headers_mut().remove(REQUEST_ID_HEADER);
+
+    let options = QueryOptions {
+        blocking: Some(Blocking::Index(index)),
+        blocking_timeout: Some(wait),
+        ..Default::default()
+    };
+    let response = client.run_request(request, Some(options)).await?;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ResponseError::RateLimited {
+            retry_after: parse_retry_after(response.headers()),
+            body: String::new(),
+        }
+        .into());
+    }
+    if response.status() != StatusCode::OK {
+        return Ok(None);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    Ok(Some((Response::from_parts(parts, ()), body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::sync::mpsc;
+
+    use crate::endpoints::Endpoints;
+    use crate::http_client::{RequestDefaults, Transport};
+    use crate::interceptor::Interceptors;
+
+    fn request() -> Request<Body> {
+        Request::get("http://127.0.0.1:8500/v1/catalog/services")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cached_reads_are_served_without_reaching_the_transport() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let (refreshes, mut refreshed) = mpsc::unbounded_channel();
+        let counted = reads.clone();
+        let transport = Transport::from_fn(move |request| {
+            // Blocking refreshes never complete, so the cached response stays as it was.
+            if request.uri().query().is_some_and(|q| q.contains("index=")) {
+                let _ = refreshes.send(request.uri().to_string());
+                return Box::pin(future::pending());
+            }
+            counted.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Ok(Response::builder()
+                    .header("X-Consul-Index", "5")
+                    .body(Body::from("{}"))
+                    .unwrap())
+            })
+        });
+        let endpoints = Endpoints::new(
+            vec![Url::parse("http://127.0.0.1:8500").unwrap()],
+            Duration::from_secs(30),
+        );
+        let client = HttpClient::new(
+            endpoints,
+            transport,
+            RequestDefaults::default(),
+            Interceptors::default(),
+            Some(CacheSettings::default()),
+            None,
+            None,
+            None,
+        );
+
+        for _ in 0..3 {
+            let response = client
+                .run_request(request(), None::<QueryOptions>)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(hyper::body::to_bytes(response).await.unwrap(), "{}");
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // The background refresh goes through the client, as a blocking query from the cached index.
+        let refresh = refreshed.recv().await.unwrap();
+        assert!(refresh.starts_with("http://127.0.0.1:8500/v1/catalog/services?"));
+        assert!(refresh.contains("index=5"));
+    }
+}
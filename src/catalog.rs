@@ -1,24 +1,39 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use async_stream::try_stream;
-use futures::stream::Stream;
-use serde::Deserialize;
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
 
-use crate::common::{Blocking, QueryMetadata, QueryOptions};
+use crate::agent::{AgentService, AgentServiceKind};
+use crate::common::{
+    CollectQueryParameters, CollectRequestHeaders, QueryMetadata, QueryOptions, WriteOptions,
+};
 use crate::errors::Error;
-use crate::health::HealthCheck;
+use crate::health::{HealthCheck, ServiceEntry};
 use crate::http_client::HttpClient;
+use crate::paging;
+use crate::payload;
+use crate::watch::{SharedWatch, Watch};
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct Weights {
     #[serde(rename = "Passing")]
     pub passing: u64,
     #[serde(rename = "Warning")]
     pub warning: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
 pub struct CatalogNode {
     #[serde(rename = "ID")]
     pub id: String,
@@ -28,25 +43,111 @@ pub struct CatalogNode {
     pub address: String,
     #[serde(rename = "Datacenter")]
     pub datacenter: String,
-    #[serde(rename = "TaggedAddresses")]
-    pub tagged_addresses: HashMap<String, String>,
+    #[serde(rename = "TaggedAddresses", default)]
+    pub tagged_addresses: TaggedAddresses<String>,
     #[serde(rename = "Meta")]
     pub meta: HashMap<String, String>,
     #[serde(rename = "CreateIndex")]
     pub create_index: u64,
     #[serde(rename = "ModifyIndex")]
     pub modify_index: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Addresses of a node or service, keyed by their tag.
+///
+/// Consul uses well-known tags for the addresses of a node or service on the LAN and WAN, which
+/// have dedicated accessors.  Any other tags, such as those added by users, can be accessed via
+/// [`get`](TaggedAddresses::get).
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct TaggedAddresses<A>(HashMap<String, A>);
+
+impl<A> TaggedAddresses<A> {
+    /// Gets the address with the given tag.
+    pub fn get(&self, tag: &str) -> Option<&A> {
+        self.0.get(tag)
+    }
+
+    /// Gets the LAN address.
+    pub fn lan(&self) -> Option<&A> {
+        self.get("lan")
+    }
+
+    /// Gets the IPv4 LAN address.
+    pub fn lan_ipv4(&self) -> Option<&A> {
+        self.get("lan_ipv4")
+    }
+
+    /// Gets the IPv6 LAN address.
+    pub fn lan_ipv6(&self) -> Option<&A> {
+        self.get("lan_ipv6")
+    }
+
+    /// Gets the WAN address.
+    pub fn wan(&self) -> Option<&A> {
+        self.get("wan")
+    }
+
+    /// Gets the IPv4 WAN address.
+    pub fn wan_ipv4(&self) -> Option<&A> {
+        self.get("wan_ipv4")
+    }
+
+    /// Gets the IPv6 WAN address.
+    pub fn wan_ipv6(&self) -> Option<&A> {
+        self.get("wan_ipv6")
+    }
+
+    /// Iterates over all of the tagged addresses.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &A)> {
+        self.0.iter().map(|(tag, addr)| (tag.as_str(), addr))
+    }
+
+    /// Gets the number of tagged addresses.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether or not there are no tagged addresses.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Consumes this value, returning the underlying map of tags to addresses.
+    pub fn into_inner(self) -> HashMap<String, A> {
+        self.0
+    }
+}
+
+impl<A> Default for TaggedAddresses<A> {
+    fn default() -> TaggedAddresses<A> {
+        TaggedAddresses(HashMap::new())
+    }
+}
+
+impl<A> From<HashMap<String, A>> for TaggedAddresses<A> {
+    fn from(addresses: HashMap<String, A>) -> TaggedAddresses<A> {
+        TaggedAddresses(addresses)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct ServiceAddress {
     #[serde(rename = "Address")]
     pub address: String,
     #[serde(rename = "Port")]
     pub port: u16,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
 pub struct CatalogServiceNode {
     #[serde(rename = "ID")]
     pub id: String,
@@ -56,8 +157,8 @@ pub struct CatalogServiceNode {
     pub address: String,
     #[serde(rename = "Datacenter")]
     pub datacenter: String,
-    #[serde(rename = "TaggedAddresses")]
-    pub tagged_addresses: HashMap<String, String>,
+    #[serde(rename = "TaggedAddresses", default)]
+    pub tagged_addresses: TaggedAddresses<String>,
     #[serde(rename = "NodeMeta")]
     pub node_meta: HashMap<String, String>,
     #[serde(rename = "ServiceID")]
@@ -67,7 +168,7 @@ pub struct CatalogServiceNode {
     #[serde(rename = "ServiceAddress")]
     pub service_address: String,
     #[serde(rename = "ServiceTaggedAddresses")]
-    pub service_tagged_addresses: Option<HashMap<String, ServiceAddress>>,
+    pub service_tagged_addresses: Option<TaggedAddresses<ServiceAddress>>,
     #[serde(rename = "ServiceTags")]
     pub service_tags: Vec<String>,
     #[serde(rename = "ServiceMeta")]
@@ -91,6 +192,379 @@ pub struct CatalogServiceNode {
     pub modify_index: u64,
     #[serde(rename = "Namespace")]
     pub namespace: Option<String>,
+    #[serde(rename = "Partition", default)]
+    pub partition: Option<String>,
+    /// The cluster peer the instance is imported from, if any.
+    #[serde(rename = "PeerName", default)]
+    pub peer_name: Option<String>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+impl CatalogServiceNode {
+    /// Gets the address to use to connect to the service.
+    ///
+    /// This is the service address, if one was registered, and otherwise the address of the node,
+    /// matching how Consul itself resolves the address of a service, such as for DNS queries.
+    ///
+    /// When the queried agent has address translation enabled, as indicated by
+    /// [`QueryMetadata::addr_translate_enabled`], Consul has already translated these addresses to
+    /// their WAN equivalents for services in remote datacenters.  Otherwise, callers in another
+    /// datacenter should use [`effective_wan_address`](CatalogServiceNode::effective_wan_address),
+    /// or [`address_for`](CatalogServiceNode::address_for) to pick between them.
+    pub fn effective_address(&self) -> &str {
+        if self.service_address.is_empty() {
+            &self.address
+        } else {
+            &self.service_address
+        }
+    }
+
+    /// Gets the address to use to connect to the service from another datacenter.
+    ///
+    /// This is the tagged WAN address of the service if one was registered, and otherwise the
+    /// tagged WAN address of the node, falling back to
+    /// [`effective_address`](CatalogServiceNode::effective_address).
+    pub fn effective_wan_address(&self) -> &str {
+        self.service_tagged_addresses
+            .as_ref()
+            .and_then(|addrs| addrs.wan())
+            .map(|addr| addr.address.as_str())
+            .filter(|addr| !addr.is_empty())
+            .or_else(|| {
+                self.tagged_addresses
+                    .wan()
+                    .map(|addr| addr.as_str())
+                    .filter(|addr| !addr.is_empty())
+            })
+            .unwrap_or_else(|| self.effective_address())
+    }
+
+    /// Gets the address to use to connect to the service from the given datacenter.
+    ///
+    /// This accounts for address translation: if the response was translated, as indicated by
+    /// [`QueryMetadata::addr_translate_enabled`] in `meta`, Consul has already picked the right
+    /// address and this is [`effective_address`](CatalogServiceNode::effective_address).
+    /// Otherwise, this is [`effective_wan_address`](CatalogServiceNode::effective_wan_address) if
+    /// the service is in a datacenter other than `local_datacenter`, and the LAN address if not.
+    pub fn address_for(&self, meta: &QueryMetadata, local_datacenter: &str) -> &str {
+        if meta.addr_translate_enabled || self.datacenter == local_datacenter {
+            self.effective_address()
+        } else {
+            self.effective_wan_address()
+        }
+    }
+
+    /// Gets the socket address to use to connect to the service.
+    ///
+    /// Combines [`effective_address`](CatalogServiceNode::effective_address) with the service
+    /// port.  Returns `None` if the address is not an IP address, such as a hostname.
+    pub fn service_socket_addr(&self) -> Option<SocketAddr> {
+        self.effective_address()
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, self.service_port))
+    }
+}
+
+/// A change to the nodes running a service, as produced by
+/// [`Catalog::watch_service_nodes_diff`].
+///
+/// Nodes are identified by the combination of their node name and service ID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServiceNodeEvent {
+    /// A node started running the service.
+    Added(CatalogServiceNode),
+    /// A node stopped running the service.
+    Removed(CatalogServiceNode),
+    /// The service on a node changed.
+    Updated(CatalogServiceNode),
+}
+
+/// The services registered on a node, as returned by [`Catalog::get_node_services`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CatalogNodeServices {
+    #[serde(rename = "Node")]
+    pub node: CatalogNode,
+    #[serde(rename = "Services", default)]
+    pub services: HashMap<String, AgentService>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The services registered on a node, as returned by [`Catalog::get_node_service_list`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CatalogNodeServiceList {
+    #[serde(rename = "Node")]
+    pub node: CatalogNode,
+    #[serde(rename = "Services", default)]
+    pub services: Vec<AgentService>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// A service in the catalog, as returned by [`Catalog::get_services_with_meta`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[non_exhaustive]
+pub struct CatalogServiceSummary {
+    /// The union of the tags of all instances of the service.
+    #[serde(rename = "Tags")]
+    pub tags: Vec<String>,
+    /// The union of the metadata of all instances of the service.
+    ///
+    /// Where instances have different values for the same key, the value of the first instance
+    /// returned by Consul is used.
+    #[serde(rename = "ServiceMeta")]
+    pub meta: HashMap<String, String>,
+}
+
+impl CatalogServiceSummary {
+    fn from_instances(
+        tags: Vec<String>,
+        instances: &[CatalogServiceNode],
+    ) -> CatalogServiceSummary {
+        let mut meta = HashMap::new();
+        for instance in instances {
+            for (key, value) in &instance.service_meta {
+                meta.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        CatalogServiceSummary { tags, meta }
+    }
+}
+
+/// A registration of a node, and optionally a service on it, directly in the catalog.
+///
+/// This is generally used to register external services, which do not run an agent.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CatalogRegistration {
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Node")]
+    pub node: String,
+    #[serde(rename = "Address")]
+    pub address: String,
+    #[serde(
+        rename = "Datacenter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datacenter: Option<String>,
+    #[serde(
+        rename = "TaggedAddresses",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub tagged_addresses: HashMap<String, String>,
+    #[serde(
+        rename = "NodeMeta",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub node_meta: HashMap<String, String>,
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<CatalogRegistrationService>,
+    #[serde(rename = "SkipNodeUpdate", default)]
+    pub skip_node_update: bool,
+}
+
+/// A service to register as part of a [`CatalogRegistration`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct CatalogRegistrationService {
+    #[serde(
+        rename = "Kind",
+        default,
+        skip_serializing_if = "AgentServiceKind::is_default"
+    )]
+    pub kind: AgentServiceKind,
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Service")]
+    pub service: String,
+    #[serde(rename = "Tags", default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(rename = "Address", default, skip_serializing_if = "String::is_empty")]
+    pub address: String,
+    #[serde(rename = "Port", default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+}
+
+/// A removal of a node, or a service or check on it, from the catalog.
+///
+/// This is created with one of [`node`](CatalogDeregistration::node),
+/// [`service`](CatalogDeregistration::service), or [`check`](CatalogDeregistration::check), so
+/// that removing an entire node is always explicit, rather than the result of forgetting to set
+/// the service or check to remove.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CatalogDeregistration {
+    #[serde(rename = "Node")]
+    node: String,
+    #[serde(
+        rename = "Datacenter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    datacenter: Option<String>,
+    #[serde(rename = "ServiceID", default, skip_serializing_if = "Option::is_none")]
+    service_id: Option<String>,
+    #[serde(rename = "CheckID", default, skip_serializing_if = "Option::is_none")]
+    check_id: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    namespace: Option<String>,
+}
+
+impl CatalogDeregistration {
+    /// Removes the node with the given name, along with all services and checks registered on it.
+    pub fn node(node: &str) -> CatalogDeregistration {
+        CatalogDeregistration {
+            node: node.to_string(),
+            datacenter: None,
+            service_id: None,
+            check_id: None,
+            namespace: None,
+        }
+    }
+
+    /// Removes only the service with the given ID, and its checks, from the given node.
+    pub fn service(node: &str, service_id: &str) -> CatalogDeregistration {
+        CatalogDeregistration {
+            service_id: Some(service_id.to_string()),
+            ..CatalogDeregistration::node(node)
+        }
+    }
+
+    /// Removes only the check with the given ID from the given node.
+    pub fn check(node: &str, check_id: &str) -> CatalogDeregistration {
+        CatalogDeregistration {
+            check_id: Some(check_id.to_string()),
+            ..CatalogDeregistration::node(node)
+        }
+    }
+
+    /// Sets the datacenter to remove from.
+    ///
+    /// By default, the datacenter of the agent being queried is used.
+    pub fn datacenter(mut self, datacenter: &str) -> CatalogDeregistration {
+        self.datacenter = Some(datacenter.to_string());
+        self
+    }
+
+    /// Sets the namespace of the service or check to remove.
+    ///
+    /// NOTE: Namespaces are available only in Consul Enterprise.
+    pub fn namespace(mut self, namespace: &str) -> CatalogDeregistration {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Gets the name of the node to remove from.
+    pub fn node_name(&self) -> &str {
+        &self.node
+    }
+
+    /// Gets the ID of the service to remove, if only a service is being removed.
+    pub fn service_id(&self) -> Option<&str> {
+        self.service_id.as_deref()
+    }
+
+    /// Gets the ID of the check to remove, if only a check is being removed.
+    pub fn check_id(&self) -> Option<&str> {
+        self.check_id.as_deref()
+    }
+
+    /// Whether or not this removes the entire node.
+    pub fn is_node(&self) -> bool {
+        self.service_id.is_none() && self.check_id.is_none()
+    }
+}
+
+/// The identity of a single instance of a service.
+///
+/// Service IDs are only unique within a node, and nodes are only unique within a partition, or a
+/// cluster peer they are imported from, so all of these make up the identity of an instance.  This
+/// is how instances are keyed wherever they are tracked across results, such as by
+/// [`Catalog::discover_service`] and [`CatalogSync`](crate::CatalogSync), and it can be built from
+/// any result describing an instance, so that keys from different sources can be compared.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstanceKey {
+    /// The name of the node the instance is running on.
+    pub node: String,
+    /// The ID of the service instance on the node.
+    pub service_id: String,
+    /// The namespace the instance is registered in, if any.
+    pub namespace: Option<String>,
+    /// The admin partition the instance is registered in, if any.
+    pub partition: Option<String>,
+    /// The cluster peer the instance is imported from, if any.
+    pub peer: Option<String>,
+}
+
+impl InstanceKey {
+    /// Creates a new [`InstanceKey`] for the given instance, in the default namespace and
+    /// partition of the local cluster.
+    pub fn new(node: &str, service_id: &str) -> InstanceKey {
+        InstanceKey {
+            node: node.to_string(),
+            service_id: service_id.to_string(),
+            namespace: None,
+            partition: None,
+            peer: None,
+        }
+    }
+}
+
+impl From<&CatalogServiceNode> for InstanceKey {
+    fn from(node: &CatalogServiceNode) -> InstanceKey {
+        InstanceKey {
+            node: node.node.clone(),
+            service_id: node.service_id.clone(),
+            namespace: node.namespace.clone(),
+            partition: node.partition.clone(),
+            peer: node.peer_name.clone(),
+        }
+    }
+}
+
+impl From<&ServiceEntry> for InstanceKey {
+    fn from(entry: &ServiceEntry) -> InstanceKey {
+        InstanceKey {
+            node: entry.node.node.clone(),
+            service_id: entry.service.id.clone(),
+            namespace: entry.service.namespace.clone(),
+            partition: entry.service.partition.clone(),
+            peer: entry.service.peer_name.clone(),
+        }
+    }
+}
+
+/// A change to a set of discovered service instances, as produced by
+/// [`Catalog::discover_service`].
+///
+/// This mirrors `tower::discover::Change`, which it can be converted into when the `tower`
+/// feature is enabled.  See also `Catalog::discover_service_tower`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<K, V> {
+    /// A new instance was discovered, or an existing instance changed and should be replaced.
+    Insert(K, V),
+    /// An existing instance was removed.
+    Remove(K),
+}
+
+#[cfg(feature = "tower")]
+impl<K, V> From<Change<K, V>> for tower::discover::Change<K, V> {
+    fn from(change: Change<K, V>) -> tower::discover::Change<K, V> {
+        match change {
+            Change::Insert(key, value) => tower::discover::Change::Insert(key, value),
+            Change::Remove(key) => tower::discover::Change::Remove(key),
+        }
+    }
 }
 
 /// Catalog operations.
@@ -107,14 +581,239 @@ impl Catalog {
         Catalog { http_client }
     }
 
+    /// Registers a node, and optionally a service on it, directly in the catalog.
+    pub async fn register(
+        &self,
+        registration: &CatalogRegistration,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "catalog", "register"],
+            options.as_ref(),
+            registration,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let registered = self.http_client.parse_write_response(response).await?;
+        Ok(registered)
+    }
+
+    /// Removes a node, or a service or check on it, directly from the catalog.
+    pub async fn deregister(
+        &self,
+        deregistration: &CatalogDeregistration,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let request = self.http_client.build_request(
+            "PUT",
+            &["v1", "catalog", "deregister"],
+            options.as_ref(),
+            deregistration,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let deregistered = self.http_client.parse_write_response(response).await?;
+        Ok(deregistered)
+    }
+
+    /// Gets the names of all known datacenters.
+    ///
+    /// Datacenters are sorted by their estimated round trip time from the datacenter of the agent
+    /// being queried, nearest first.
+    pub async fn get_datacenters(&self) -> Result<Vec<String>, Error> {
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "datacenters"],
+            None::<QueryOptions>,
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, None::<QueryOptions>)
+            .await?;
+        let (parsed, _) = self.http_client.parse_query_response(response).await?;
+        Ok(parsed)
+    }
+
+    /// Gets all nodes in the catalog.
+    pub async fn get_nodes(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<CatalogNode>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "nodes"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets the names of all services in the catalog, along with the tags of their instances.
+    ///
+    /// The tags of a service are the union of the tags of all of its instances.  The services can
+    /// be filtered on the server with [`QueryOptions::filtering`], with selectors such as
+    /// `ServiceMeta.<key>` and `ServiceTags`.
+    pub async fn get_services(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(HashMap<String, Vec<String>>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "services"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets all services in the catalog, along with their tags and metadata.
+    ///
+    /// The service list only carries the names and tags of services, so the metadata of each
+    /// service is collected from its instances, via
+    /// [`get_service_nodes`](Catalog::get_service_nodes), which takes one further request per
+    /// service.  Any filter in the options applies to both the service list and the instances, so
+    /// the metadata only comes from matching instances.  The returned metadata is that of the
+    /// service list.
+    pub async fn get_services_with_meta(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(HashMap<String, CatalogServiceSummary>, QueryMetadata), Error> {
+        let (services, meta) = self.get_services(options.clone()).await?;
+
+        // The instances are looked up as they are now, even when the service list was a blocking
+        // query.
+        let instance_options = options.map(|options| QueryOptions {
+            blocking: None,
+            blocking_timeout: None,
+            wait_jitter: None,
+            ..options
+        });
+        let summaries = services.into_iter().map(|(name, tags)| {
+            let instance_options = instance_options.clone();
+            async move {
+                let (instances, _) = self.get_service_nodes(&name, instance_options).await?;
+                let summary = CatalogServiceSummary::from_instances(tags, &instances);
+                Ok::<_, Error>((name, summary))
+            }
+        });
+        let summaries = future::try_join_all(summaries).await?;
+        Ok((summaries.into_iter().collect(), meta))
+    }
+
+    /// Gets a stream of changes to the services in the catalog.
+    ///
+    /// Each item in the response stream represents all services, along with the tags of their
+    /// instances, after a service has been added or removed, or its tags have changed.  The stream
+    /// will terminate if any error is hit during the background requests made to Consul.
+    pub fn watch_services(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Watch<HashMap<String, Vec<String>>> {
+        let options = self.http_client.watch_options(options);
+        let http_client = self.http_client.clone();
+
+        Watch::new(options, move |options| {
+            let http_client = http_client.clone();
+            async move {
+                let request = http_client.build_request(
+                    "GET",
+                    &["v1", "catalog", "services"],
+                    Some(&options),
+                    (),
+                )?;
+                let response = http_client.run_request(request, Some(&options)).await?;
+                let (parsed, meta) = http_client.parse_query_response(response).await?;
+                Ok((parsed, meta))
+            }
+        })
+        .tracked(self.http_client.stats())
+    }
+
+    /// Gets the node with the specified name, and the services registered on it.
+    ///
+    /// Returns `None` if the node does not exist.
+    pub async fn get_node_services(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<CatalogNodeServices>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "node", node],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        // Unknown nodes are returned as a successful `null`, rather than a 404.
+        let (parsed, meta) = self
+            .http_client
+            .parse_query_response::<Option<CatalogNodeServices>>(response)
+            .await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets the node with the specified name, and the services registered on it, as a list.
+    ///
+    /// Unlike [`get_node_services`](Catalog::get_node_services), the services are returned in a
+    /// list rather than keyed by ID, which allows them to be filtered on the server with
+    /// [`QueryOptions::filtering`], including on their metadata.  This requires Consul 1.7 or later.
+    ///
+    /// Returns `None` if the node does not exist.
+    pub async fn get_node_service_list(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<CatalogNodeServiceList>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "node-services", node],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        // Depending on the version, unknown nodes are returned as a 404 or as a successful `null`.
+        let (parsed, meta) = self
+            .http_client
+            .parse_optional_query_response::<Option<CatalogNodeServiceList>>(response)
+            .await?;
+        Ok((parsed.flatten(), meta))
+    }
+
     /// Gets the nodes running the specified service.
     pub async fn get_service_nodes(
         &self,
         service: &str,
         options: Option<QueryOptions>,
     ) -> Result<(Vec<CatalogServiceNode>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
         let request = self.http_client.build_request(
-            "get",
+            "GET",
             &["v1", "catalog", "service", service],
             options.as_ref(),
             (),
@@ -123,7 +822,89 @@ impl Catalog {
             .http_client
             .run_request(request, options.as_ref())
             .await?;
-        let (parsed, meta) = self.http_client.parse_query_response(response).await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets the nodes running the specified service, in pages.
+    ///
+    /// This is the catalog equivalent of
+    /// [`Health::get_service_nodes_paged`](crate::Health::get_service_nodes_paged), and splits the
+    /// nodes into pages the same way.  As there, each page is the result of a separate query, so
+    /// the nodes can change between pages, which can be detected by comparing the
+    /// [`last_index`](QueryMetadata::last_index) of each page.
+    pub fn get_service_nodes_paged(
+        &self,
+        service: &str,
+        pages: usize,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<(Vec<CatalogServiceNode>, QueryMetadata), Error>> {
+        let options = self.http_client.query_options(options);
+        let catalog = self.clone();
+        let service = service.to_string();
+
+        paging::paged(options, "Node", pages, move |options| {
+            let catalog = catalog.clone();
+            let service = service.clone();
+            async move { catalog.get_service_nodes(&service, Some(options)).await }
+        })
+    }
+
+    /// Gets the nodes running the specified service in every known datacenter.
+    ///
+    /// The datacenters are listed first, and then the service is queried in all of them
+    /// concurrently, with the given options.  Any datacenter set in the options is ignored.  The
+    /// results are keyed by datacenter, and include datacenters where the service has no nodes.
+    ///
+    /// If the query fails in any datacenter, the error is returned, with the failing datacenter
+    /// included in its [request context](Error::request_context).
+    pub async fn get_service_nodes_all_dcs(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<HashMap<String, Vec<CatalogServiceNode>>, Error> {
+        let datacenters = self.get_datacenters().await?;
+        let queries = datacenters.into_iter().map(|datacenter| {
+            let mut options = options.clone().unwrap_or_default();
+            options.datacenter = Some(datacenter.clone());
+            async move {
+                let (nodes, _) = self.get_service_nodes(service, Some(options)).await?;
+                Ok::<_, Error>((datacenter, nodes))
+            }
+        });
+        let results = future::try_join_all(queries).await?;
+        Ok(results.into_iter().collect())
+    }
+
+    /// Gets the nodes running the specified service, as a stream.
+    ///
+    /// Unlike [`get_service_nodes`](Catalog::get_service_nodes), which collects all nodes before
+    /// returning, each node is yielded as soon as it has been received and decoded.  This keeps
+    /// memory usage low when processing services with a very large number of nodes.  The metadata
+    /// of the response is available as soon as the response starts being received.
+    pub async fn stream_service_nodes(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<
+        (
+            impl Stream<Item = Result<CatalogServiceNode, Error>>,
+            QueryMetadata,
+        ),
+        Error,
+    > {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "catalog", "service", service],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_stream(response).await?;
         Ok((parsed, meta))
     }
 
@@ -132,31 +913,219 @@ impl Catalog {
     /// Each item in the response stream represents all nodes running in the service after a change
     /// to the service has occurred.  The stream will terminate if any error is hit during the
     /// background requests made to Consul.
+    ///
+    /// If `options` specifies blocking parameters, such as an index persisted from a previous
+    /// watch, the first query will block on them, rather than returning the current nodes
+    /// immediately.
     pub fn watch_service_nodes(
         &self,
         service: &str,
         options: Option<QueryOptions>,
-    ) -> impl Stream<Item = Result<(Vec<CatalogServiceNode>, QueryMetadata), Error>> {
+    ) -> Watch<Vec<CatalogServiceNode>> {
+        let options = self.http_client.watch_options(options);
         let service = service.to_string();
         let http_client = self.http_client.clone();
-        let mut options = options.or_else(|| Some(QueryOptions::default()));
 
-        let mut blocking: Option<Blocking> = None;
+        Watch::new(options, move |options| {
+            let service = service.clone();
+            let http_client = http_client.clone();
+            async move {
+                let request = http_client.build_request(
+                    "GET",
+                    &["v1", "catalog", "service", &service],
+                    Some(&options),
+                    (),
+                )?;
+                let response = http_client.run_request(request, Some(&options)).await?;
+                let (parsed, meta) = http_client.parse_list_query_response(response).await?;
+                Ok((parsed, meta))
+            }
+        })
+        .tracked(self.http_client.stats())
+    }
 
-        try_stream! {
-            loop {
-                // Override the blocking settings before every request.
-                let options = options.as_mut().map(|opts| { opts.blocking = blocking.take(); &*opts });
+    /// Gets a stream of individual changes to the nodes running the specified service.
+    ///
+    /// Consecutive results of [`watch_service_nodes`](Catalog::watch_service_nodes) are compared,
+    /// and an event is yielded for every node which was added, removed, or updated, where a node is
+    /// considered updated if its modify index has changed.  All nodes in the initial result are
+    /// yielded as added.  The stream will terminate if any error is hit during the background
+    /// requests made to Consul.
+    pub fn watch_service_nodes_diff(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<ServiceNodeEvent, Error>> {
+        let mut watch = self.watch_service_nodes(service, options);
 
-                let request = http_client.build_request("GET", &["v1", "catalog", "service", &service], options, ())?;
-                let response = http_client.run_request(request, options).await?;
-                let (parsed, meta) = http_client.parse_query_response(response).await?;
+        try_stream! {
+            let mut previous: HashMap<InstanceKey, CatalogServiceNode> = HashMap::new();
+            while let Some(result) = watch.next().await {
+                let (nodes, _) = result?;
 
-                // Override our blocking configuration based on the metadata from this response.
-                blocking = meta.as_blocking();
+                let mut current = HashMap::with_capacity(nodes.len());
+                let mut events = Vec::new();
+                for node in nodes {
+                    let key = InstanceKey::from(&node);
+                    match previous.remove(&key) {
+                        None => events.push(ServiceNodeEvent::Added(node.clone())),
+                        Some(old) if old.modify_index != node.modify_index => {
+                            events.push(ServiceNodeEvent::Updated(node.clone()))
+                        }
+                        Some(_) => {}
+                    }
+                    current.insert(key, node);
+                }
+                events.extend(previous.drain().map(|(_, node)| ServiceNodeEvent::Removed(node)));
+                previous = current;
 
-                yield (parsed, meta);
+                for event in events {
+                    yield event;
+                }
             }
         }
     }
+
+    /// Gets a stream of changes to the instances of the specified service, suitable for driving a
+    /// load balancer.
+    ///
+    /// Each instance is keyed by its [`InstanceKey`].  Instances which are added or updated
+    /// are yielded as [`Change::Insert`], replacing any previous value for the same key, and
+    /// instances which are removed are yielded as [`Change::Remove`].  The stream will terminate if
+    /// any error is hit during the background requests made to Consul.
+    pub fn discover_service(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<Change<InstanceKey, CatalogServiceNode>, Error>> {
+        self.watch_service_nodes_diff(service, options)
+            .map(|result| {
+                result.map(|event| match event {
+                    ServiceNodeEvent::Added(node) | ServiceNodeEvent::Updated(node) => {
+                        Change::Insert(InstanceKey::from(&node), node)
+                    }
+                    ServiceNodeEvent::Removed(node) => Change::Remove(InstanceKey::from(&node)),
+                })
+            })
+    }
+
+    /// Gets a [`tower::discover::Discover`] over the instances of the specified service, so that
+    /// `tower` load balancers can balance over them directly.
+    ///
+    /// This is [`discover_service`](Catalog::discover_service), with each instance which is added
+    /// or updated turned into a service by the given function, such as by creating a client for
+    /// its address.
+    #[cfg(feature = "tower")]
+    pub fn discover_service_tower<S, F>(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+        mut make_service: F,
+    ) -> impl tower::discover::Discover<Key = InstanceKey, Service = S, Error = Error> + Send + Unpin
+    where
+        S: Send + 'static,
+        F: FnMut(&CatalogServiceNode) -> S + Send + 'static,
+    {
+        let changes = self.discover_service(service, options).map(move |result| {
+            result.map(|change| match change {
+                Change::Insert(key, node) => {
+                    tower::discover::Change::Insert(key, make_service(&node))
+                }
+                Change::Remove(key) => tower::discover::Change::Remove(key),
+            })
+        });
+        Box::pin(changes)
+    }
+
+    /// Watches the nodes running the specified service, publishing the latest nodes into a
+    /// [`watch`] channel.
+    ///
+    /// The initial set of nodes is queried before this method returns, so the receiver always
+    /// holds a valid snapshot.  Afterwards, a background task drives the watch and updates the
+    /// channel with every change.  The task stops once all receivers have been dropped, or if any
+    /// error is hit during the background requests made to Consul, which closes the channel.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub async fn watch_service_nodes_channel(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<watch::Receiver<Vec<CatalogServiceNode>>, Error> {
+        let (rx, _) = self
+            .watch_service_nodes(service, options)
+            .spawn_channel()
+            .await?;
+        Ok(rx)
+    }
+
+    /// Watches the nodes running the specified service, sharing the underlying watch with any other
+    /// callers watching the same service with equivalent options.
+    ///
+    /// Behaves like [`watch_service_nodes_channel`](Catalog::watch_service_nodes_channel), except
+    /// that only a single blocking query is made per distinct service and options, regardless of
+    /// how many callers are watching, with the results fanned out to all of them.  Options are
+    /// considered equivalent if they result in the same request, ignoring the blocking index or
+    /// hash and the wait jitter, so watches with different blocking timeouts are kept apart.  The
+    /// shared watch stops once every [`SharedWatch`] for it has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub async fn watch_service_nodes_shared(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<SharedWatch<Vec<CatalogServiceNode>>, Error> {
+        let options = self.http_client.watch_options(options);
+        let mut key_options = options.clone().unwrap_or_default();
+        key_options.blocking = None;
+        // Each request picks its own jittered wait, so only the blocking timeout itself is part of
+        // the key.
+        key_options.wait_jitter = None;
+        let key = format!(
+            "catalog/service/{}?{:?}{:?}",
+            service,
+            CollectQueryParameters::as_pairs(&key_options),
+            CollectRequestHeaders::as_pairs(&key_options),
+        );
+
+        let watch = self.watch_service_nodes(service, options);
+        self.http_client
+            .watches()
+            .get_or_start(key, || watch.spawn_channel())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(meta: &[(&str, &str)]) -> CatalogServiceNode {
+        CatalogServiceNode {
+            service_meta: meta
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summary_merges_metadata_of_all_instances() {
+        let instances = vec![
+            instance(&[("version", "1"), ("zone", "east")]),
+            instance(&[("version", "2"), ("canary", "true")]),
+        ];
+        let summary = CatalogServiceSummary::from_instances(vec!["web".to_string()], &instances);
+        assert_eq!(summary.tags, vec!["web".to_string()]);
+        assert_eq!(summary.meta.len(), 3);
+        assert_eq!(summary.meta["version"], "1");
+        assert_eq!(summary.meta["zone"], "east");
+        assert_eq!(summary.meta["canary"], "true");
+    }
+
+    #[test]
+    fn summary_without_instances_has_no_metadata() {
+        let summary = CatalogServiceSummary::from_instances(Vec::new(), &[]);
+        assert_eq!(summary, CatalogServiceSummary::default());
+    }
 }
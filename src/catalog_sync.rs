@@ -0,0 +1,357 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::agent::AgentService;
+use crate::catalog::{
+    Catalog, CatalogDeregistration, CatalogRegistration, CatalogRegistrationService, InstanceKey,
+};
+use crate::common::{QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::Client;
+
+/// The node and service metadata key used to mark entries as owned by a [`CatalogSync`].
+pub const EXTERNAL_SOURCE_META_KEY: &str = "external-source";
+
+/// The outcome of a [`CatalogSync::sync`] run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// Services which were registered, either because they were new or because they changed.
+    pub registered: Vec<InstanceKey>,
+    /// Services which were deregistered because they are no longer desired.
+    pub deregistered: Vec<InstanceKey>,
+    /// Nodes which were deregistered because they no longer have any services.
+    pub deregistered_nodes: Vec<String>,
+}
+
+/// Converges the catalog to a desired set of external services.
+///
+/// Every node and service registered by a [`CatalogSync`] is tagged with the
+/// [`EXTERNAL_SOURCE_META_KEY`] metadata key, set to the owner given when creating it.  Only
+/// entries tagged with the same owner are ever deregistered, so multiple syncs, along with
+/// services registered by any other means, can safely share the catalog.
+#[derive(Clone, Debug)]
+pub struct CatalogSync {
+    catalog: Catalog,
+    owner: String,
+    write_options: Option<WriteOptions>,
+}
+
+impl CatalogSync {
+    /// Creates a new [`CatalogSync`] which manages the entries tagged with the given owner.
+    pub fn new(client: &Client, owner: &str) -> CatalogSync {
+        CatalogSync {
+            catalog: client.catalog(),
+            owner: owner.to_string(),
+            write_options: None,
+        }
+    }
+
+    /// Sets the options to use when registering and deregistering entries.
+    pub fn write_options(mut self, options: WriteOptions) -> CatalogSync {
+        self.write_options = Some(options);
+        self
+    }
+
+    /// Converges the catalog to the given registrations.
+    ///
+    /// Each registration describes a single service on an external node.  Desired services which
+    /// are missing or differ from what is registered are (re-)registered, and owned services which
+    /// are not desired are deregistered, along with their node if no services remain on it.
+    /// Registrations without a service are ignored.
+    pub async fn sync(&self, desired: Vec<CatalogRegistration>) -> Result<SyncReport, Error> {
+        let existing = self.owned_services().await?;
+        let plan = self.plan(desired, existing);
+
+        let mut report = SyncReport::default();
+        for (id, registration) in plan.register {
+            self.catalog
+                .register(&registration, self.write_options.clone())
+                .await?;
+            report.registered.push(id);
+        }
+        for id in plan.deregister {
+            let deregistration = CatalogDeregistration::service(&id.node, &id.service_id);
+            self.catalog
+                .deregister(&deregistration, self.write_options.clone())
+                .await?;
+            report.deregistered.push(id);
+        }
+        for node in plan.deregister_nodes {
+            let deregistration = CatalogDeregistration::node(&node);
+            self.catalog
+                .deregister(&deregistration, self.write_options.clone())
+                .await?;
+            report.deregistered_nodes.push(node);
+        }
+
+        Ok(report)
+    }
+
+    /// Works out the changes needed to converge the existing owned services to the desired ones.
+    fn plan(&self, desired: Vec<CatalogRegistration>, existing: OwnedServices) -> SyncPlan {
+        let mut plan = SyncPlan::default();
+
+        let mut wanted = HashSet::new();
+        for mut registration in desired {
+            let service = match registration.service.as_mut() {
+                Some(service) => service,
+                None => continue,
+            };
+            let service_id = service
+                .id
+                .clone()
+                .unwrap_or_else(|| service.service.clone());
+            service.id = Some(service_id.clone());
+            let id = InstanceKey::new(&registration.node, &service_id);
+            registration
+                .node_meta
+                .insert(EXTERNAL_SOURCE_META_KEY.to_string(), self.owner.clone());
+            service
+                .meta
+                .insert(EXTERNAL_SOURCE_META_KEY.to_string(), self.owner.clone());
+
+            let up_to_date = existing
+                .services
+                .get(&id)
+                .is_some_and(|current| matches(current, service))
+                && existing.node_addresses.get(&id.node) == Some(&registration.address);
+            if !up_to_date {
+                plan.register.push((id.clone(), registration));
+            }
+            wanted.insert(id);
+        }
+
+        let mut remaining = existing.service_counts;
+        for id in existing.services.into_keys() {
+            if wanted.contains(&id) {
+                continue;
+            }
+
+            if let Some(count) = remaining.get_mut(&id.node) {
+                *count -= 1;
+            }
+            plan.deregister.push(id);
+        }
+
+        let wanted_nodes = wanted.iter().map(|id| &id.node).collect::<HashSet<_>>();
+        for (node, count) in remaining {
+            if count == 0 && !wanted_nodes.contains(&node) {
+                plan.deregister_nodes.push(node);
+            }
+        }
+
+        plan
+    }
+
+    /// Gets the services currently registered on nodes owned by this sync.
+    async fn owned_services(&self) -> Result<OwnedServices, Error> {
+        let mut node_meta = HashMap::new();
+        node_meta.insert(EXTERNAL_SOURCE_META_KEY.to_string(), self.owner.clone());
+        let options = QueryOptions {
+            node_meta: Some(node_meta),
+            ..Default::default()
+        };
+        let (nodes, _) = self.catalog.get_nodes(Some(options)).await?;
+
+        let mut owned = OwnedServices::default();
+        for node in nodes {
+            let services = match self.catalog.get_node_services(&node.node, None).await?.0 {
+                Some(services) => services,
+                None => continue,
+            };
+
+            owned
+                .service_counts
+                .insert(node.node.clone(), services.services.len());
+            owned
+                .node_addresses
+                .insert(node.node.clone(), services.node.address.clone());
+            for service in services.services.into_values() {
+                if self.owns(&service.meta) {
+                    let id = InstanceKey::new(&node.node, &service.id);
+                    owned.services.insert(id, service);
+                }
+            }
+        }
+        Ok(owned)
+    }
+
+    fn owns(&self, meta: &HashMap<String, String>) -> bool {
+        meta.get(EXTERNAL_SOURCE_META_KEY) == Some(&self.owner)
+    }
+}
+
+/// The changes a [`CatalogSync::sync`] run makes to the catalog, in the order they are made.
+#[derive(Default)]
+struct SyncPlan {
+    register: Vec<(InstanceKey, CatalogRegistration)>,
+    deregister: Vec<InstanceKey>,
+    deregister_nodes: Vec<String>,
+}
+
+#[derive(Default)]
+struct OwnedServices {
+    services: HashMap<InstanceKey, AgentService>,
+    node_addresses: HashMap<String, String>,
+    service_counts: HashMap<String, usize>,
+}
+
+/// Whether or not the registered service matches the desired service.
+fn matches(current: &AgentService, desired: &CatalogRegistrationService) -> bool {
+    current.service == desired.service
+        && current.tags == desired.tags
+        && current.address == desired.address
+        && current.port == desired.port.unwrap_or(0)
+        && current.meta == desired.meta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync() -> CatalogSync {
+        let client = Client::new("http://127.0.0.1:8500").unwrap();
+        CatalogSync::new(&client, "sync")
+    }
+
+    fn registration(node: &str, service: &str, port: u16) -> CatalogRegistration {
+        CatalogRegistration {
+            node: node.to_string(),
+            address: format!("{}.example.com", node),
+            service: Some(CatalogRegistrationService {
+                service: service.to_string(),
+                port: Some(port),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn owned_meta() -> HashMap<String, String> {
+        let mut meta = HashMap::new();
+        meta.insert(EXTERNAL_SOURCE_META_KEY.to_string(), "sync".to_string());
+        meta
+    }
+
+    /// Creates the owned services as they would be read back after registering the given ones.
+    fn existing(registrations: &[CatalogRegistration]) -> OwnedServices {
+        let mut owned = OwnedServices::default();
+        for registration in registrations {
+            let service = registration.service.as_ref().unwrap();
+            let id = service
+                .id
+                .clone()
+                .unwrap_or_else(|| service.service.clone());
+            owned.services.insert(
+                InstanceKey::new(&registration.node, &id),
+                AgentService {
+                    id,
+                    service: service.service.clone(),
+                    port: service.port.unwrap_or(0),
+                    meta: owned_meta(),
+                    ..Default::default()
+                },
+            );
+            owned
+                .node_addresses
+                .insert(registration.node.clone(), registration.address.clone());
+            *owned
+                .service_counts
+                .entry(registration.node.clone())
+                .or_default() += 1;
+        }
+        owned
+    }
+
+    fn registered(plan: &SyncPlan) -> Vec<InstanceKey> {
+        plan.register.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    #[test]
+    fn registers_new_services_tagged_with_the_owner() {
+        let plan = sync().plan(
+            vec![registration("ext-1", "web", 80)],
+            OwnedServices::default(),
+        );
+        assert_eq!(registered(&plan), vec![InstanceKey::new("ext-1", "web")]);
+        assert!(plan.deregister.is_empty());
+        assert!(plan.deregister_nodes.is_empty());
+
+        let (_, registration) = &plan.register[0];
+        let service = registration.service.as_ref().unwrap();
+        assert_eq!(service.id.as_deref(), Some("web"));
+        assert_eq!(registration.node_meta, owned_meta());
+        assert_eq!(service.meta, owned_meta());
+    }
+
+    #[test]
+    fn leaves_up_to_date_services_alone() {
+        let desired = vec![registration("ext-1", "web", 80)];
+        let plan = sync().plan(desired.clone(), existing(&desired));
+        assert!(plan.register.is_empty());
+        assert!(plan.deregister.is_empty());
+        assert!(plan.deregister_nodes.is_empty());
+    }
+
+    #[test]
+    fn reregisters_changed_services_and_nodes() {
+        let current = vec![
+            registration("ext-1", "web", 80),
+            registration("ext-2", "api", 80),
+        ];
+        let mut moved = registration("ext-2", "api", 80);
+        moved.address = "10.0.0.2".to_string();
+        let desired = vec![registration("ext-1", "web", 8080), moved];
+
+        let plan = sync().plan(desired, existing(&current));
+        assert_eq!(
+            registered(&plan),
+            vec![
+                InstanceKey::new("ext-1", "web"),
+                InstanceKey::new("ext-2", "api"),
+            ]
+        );
+        assert!(plan.deregister.is_empty());
+    }
+
+    #[test]
+    fn deregisters_services_and_nodes_no_longer_desired() {
+        let current = vec![
+            registration("ext-1", "web", 80),
+            registration("ext-1", "api", 80),
+            registration("ext-2", "db", 5432),
+        ];
+        let plan = sync().plan(vec![registration("ext-1", "web", 80)], existing(&current));
+        assert!(plan.register.is_empty());
+
+        let mut deregistered = plan.deregister;
+        deregistered.sort();
+        assert_eq!(
+            deregistered,
+            vec![
+                InstanceKey::new("ext-1", "api"),
+                InstanceKey::new("ext-2", "db"),
+            ]
+        );
+        assert_eq!(plan.deregister_nodes, vec!["ext-2".to_string()]);
+    }
+
+    #[test]
+    fn keeps_nodes_with_services_from_other_sources() {
+        let current = vec![registration("ext-1", "web", 80)];
+        let mut owned = existing(&current);
+        // A service registered by other means, which is not returned as owned.
+        *owned.service_counts.get_mut("ext-1").unwrap() += 1;
+
+        let plan = sync().plan(Vec::new(), owned);
+        assert_eq!(plan.deregister, vec![InstanceKey::new("ext-1", "web")]);
+        assert!(plan.deregister_nodes.is_empty());
+    }
+
+    #[test]
+    fn ignores_registrations_without_a_service() {
+        let mut node_only = registration("ext-1", "web", 80);
+        node_only.service = None;
+        let plan = sync().plan(vec![node_only], OwnedServices::default());
+        assert!(plan.register.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::runtime::Instant;
+
+/// Default failure rate, over the recent requests, at which the circuit breaker opens.
+pub(crate) const DEFAULT_FAILURE_RATE: f64 = 0.5;
+
+/// Default for how long the circuit breaker stays open before letting a request through again.
+pub(crate) const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+/// Number of recent requests the failure rate is calculated over.
+const WINDOW_SIZE: usize = 20;
+
+/// Minimum number of recent requests before the failure rate is considered meaningful.
+const MIN_REQUESTS: usize = 10;
+
+/// Settings for the circuit breaker.
+#[derive(Clone, Debug)]
+pub(crate) struct CircuitBreakerSettings {
+    pub failure_rate: f64,
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> CircuitBreakerSettings {
+        CircuitBreakerSettings {
+            failure_rate: DEFAULT_FAILURE_RATE,
+            open_duration: DEFAULT_OPEN_DURATION,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    /// Requests are allowed, and their outcomes tracked.
+    Closed,
+    /// Requests are rejected until the given time.
+    Open { until: Instant },
+    /// A single probe request, with the given ID, has been allowed at the given time to check for
+    /// recovery.
+    HalfOpen { probe: u64, probe_started: Instant },
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    outcomes: VecDeque<bool>,
+    next_probe: u64,
+}
+
+/// Permission for a request to go through a [`CircuitBreaker`], to be handed back along with its
+/// outcome.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Permit {
+    probe: Option<u64>,
+}
+
+/// Tracks the outcomes of recent requests, rejecting requests while too many are failing.
+///
+/// Once the failure rate over the recent requests reaches the threshold, the breaker opens and
+/// rejects all requests for a period.  Afterwards, a single probe request is let through: if it
+/// succeeds, the breaker closes again, and otherwise, it reopens.  Only the outcome of the probe
+/// itself counts while half-open, so requests which were let through before the breaker opened, and
+/// finish afterwards, can't close or reopen it.  If the probe never reports its outcome, such as
+/// when its future is dropped, another probe is let through after the same period.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    settings: CircuitBreakerSettings,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: CircuitBreakerSettings) -> CircuitBreaker {
+        CircuitBreaker {
+            settings,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                outcomes: VecDeque::with_capacity(WINDOW_SIZE),
+                next_probe: 0,
+            }),
+        }
+    }
+
+    /// Gets permission for a request to go through right now, if it is allowed.
+    pub fn allow(&self) -> Option<Permit> {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        let now = Instant::now();
+        match inner.state {
+            State::Closed => Some(Permit { probe: None }),
+            State::Open { until } if now < until => None,
+            State::HalfOpen { probe_started, .. }
+                if now.duration_since(probe_started) < self.settings.open_duration =>
+            {
+                None
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                let probe = inner.next_probe;
+                inner.next_probe += 1;
+                inner.state = State::HalfOpen {
+                    probe,
+                    probe_started: now,
+                };
+                Some(Permit { probe: Some(probe) })
+            }
+        }
+    }
+
+    /// Records the outcome of a request which was allowed through with the given permit.
+    pub fn record(&self, permit: Permit, success: bool) {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        match inner.state {
+            // Only the current probe decides whether the breaker closes or reopens.
+            State::HalfOpen { probe, .. } if permit.probe != Some(probe) => {}
+            State::HalfOpen { .. } if success => {
+                inner.state = State::Closed;
+                inner.outcomes.clear();
+            }
+            State::HalfOpen { .. } => self.open(&mut inner),
+            // Requests which were already in flight when the breaker opened don't count.
+            State::Open { .. } => {}
+            State::Closed => {
+                if inner.outcomes.len() == WINDOW_SIZE {
+                    inner.outcomes.pop_front();
+                }
+                inner.outcomes.push_back(success);
+
+                let failures = inner.outcomes.iter().filter(|success| !**success).count();
+                let total = inner.outcomes.len();
+                if total >= MIN_REQUESTS
+                    && failures as f64 >= total as f64 * self.settings.failure_rate
+                {
+                    self.open(&mut inner);
+                }
+            }
+        }
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            open_for_ms = self.settings.open_duration.as_millis() as u64,
+            "too many Consul requests failing, opening circuit breaker"
+        );
+
+        inner.state = State::Open {
+            until: Instant::now() + self.settings.open_duration,
+        };
+        inner.outcomes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerSettings {
+            failure_rate: 0.5,
+            open_duration,
+        })
+    }
+
+    fn fail(breaker: &CircuitBreaker, times: usize) {
+        for _ in 0..times {
+            let permit = breaker.allow().expect("breaker must be closed");
+            breaker.record(permit, false);
+        }
+    }
+
+    fn open_briefly() -> CircuitBreaker {
+        let breaker = breaker(Duration::from_millis(20));
+        fail(&breaker, MIN_REQUESTS);
+        assert_eq!(breaker.allow(), None);
+        std::thread::sleep(Duration::from_millis(30));
+        breaker
+    }
+
+    #[test]
+    fn stays_closed_below_the_minimum_number_of_requests() {
+        let breaker = breaker(Duration::from_secs(60));
+        fail(&breaker, MIN_REQUESTS - 1);
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn stays_closed_below_the_failure_rate() {
+        let breaker = breaker(Duration::from_secs(60));
+        for i in 0..WINDOW_SIZE {
+            let permit = breaker.allow().unwrap();
+            // Just under half of the requests fail.
+            breaker.record(permit, i % 2 == 0 || i == 1);
+        }
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn opens_once_the_failure_rate_is_reached() {
+        let breaker = breaker(Duration::from_secs(60));
+        fail(&breaker, MIN_REQUESTS);
+        assert_eq!(breaker.allow(), None);
+    }
+
+    #[test]
+    fn lets_a_single_probe_through_once_the_open_duration_passes() {
+        let breaker = open_briefly();
+        assert!(breaker.allow().is_some());
+        assert_eq!(breaker.allow(), None);
+    }
+
+    #[test]
+    fn closes_when_the_probe_succeeds() {
+        let breaker = open_briefly();
+        let probe = breaker.allow().unwrap();
+        breaker.record(probe, true);
+        assert!(breaker.allow().is_some());
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn reopens_when_the_probe_fails() {
+        let breaker = open_briefly();
+        let probe = breaker.allow().unwrap();
+        breaker.record(probe, false);
+        assert_eq!(breaker.allow(), None);
+    }
+
+    #[test]
+    fn ignores_outcomes_of_other_requests_while_half_open() {
+        let breaker = breaker(Duration::from_millis(20));
+        let slow = breaker.allow().unwrap();
+        fail(&breaker, MIN_REQUESTS);
+        std::thread::sleep(Duration::from_millis(30));
+
+        let probe = breaker.allow().unwrap();
+        // A request let through before the breaker opened finishing now says nothing about recovery.
+        breaker.record(slow, true);
+        assert_eq!(breaker.allow(), None);
+
+        breaker.record(probe, true);
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn ignores_outcomes_of_earlier_probes() {
+        let breaker = open_briefly();
+        let stale = breaker.allow().unwrap();
+        // The first probe never reports back in time, so another one is let through.
+        std::thread::sleep(Duration::from_millis(30));
+        let probe = breaker.allow().unwrap();
+
+        breaker.record(stale, true);
+        assert_eq!(breaker.allow(), None);
+        breaker.record(probe, false);
+        assert_eq!(breaker.allow(), None);
+    }
+}
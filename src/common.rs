@@ -1,11 +1,31 @@
 //! Common types utilized throughout the crate.
 use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
 use std::time::Duration;
 
 use hyper::header::{HeaderMap, HeaderValue};
 
-use crate::errors::ResponseError;
+use crate::errors::{Error, ResponseError};
+
+/// Maximum relay factor supported by keyring operations.
+const MAX_RELAY_FACTOR: u8 = 5;
+
+/// Default amount of time for blocking queries to wait for changes.
+const DEFAULT_BLOCKING_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time allowed on top of the wait and Consul's jitter for a blocking query to make it back to us.
+const BLOCKING_TIMEOUT_MARGIN: Duration = Duration::from_secs(5);
+
+/// Gets the overall timeout for a blocking query with the given wait.
+///
+/// Consul adds up to one sixteenth of the wait as jitter, and the response still has to be
+/// processed and sent back after that, so the timeout allows for both.
+pub(crate) fn blocking_request_timeout(wait: Duration) -> Duration {
+    wait + wait / 16 + BLOCKING_TIMEOUT_MARGIN
+}
 
 /// The consistency of a given operation.
 ///
@@ -18,6 +38,16 @@ pub enum Consistency {
     /// Allows any non-leader Consul server to service this read, which trades lower latency and
     /// higher throughput for staleness.
     Stale,
+    /// Allows any non-leader Consul server to service this read, as long as its results are no
+    /// more stale than the given bound.
+    ///
+    /// The bound is sent to Consul, and the response is also checked against it: if the server
+    /// which serviced the read had not contacted the leader within the bound, or did not know of a
+    /// leader at all, the read is transparently retried against the leader.
+    StaleBounded {
+        /// The maximum amount of time since the server last contacted the leader.
+        max_stale: Duration,
+    },
 }
 
 /// The blocking behavior of a given operation.
@@ -36,6 +66,19 @@ pub enum Blocking {
     Hash(String),
 }
 
+/// The node to sort results by network latency from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Near {
+    /// Sort by latency from the node with the given name.
+    Node(String),
+    /// Sort by latency from the agent servicing the request.
+    Agent,
+    /// Sort by latency from the node with the given IP address.
+    ///
+    /// Only supported by prepared queries, and sent to Consul via the `X-Forwarded-For` header.
+    Ip(IpAddr),
+}
+
 /// An object that can be collected as a set of query parameters for a given Consul request.
 ///
 /// This provides an interface such that configuration types can be easily queried to generate
@@ -52,6 +95,19 @@ pub(crate) trait CollectRequestHeaders {
     fn as_pairs(&self) -> Vec<(&'static str, Cow<'static, str>)>;
 }
 
+/// Namespace which matches all namespaces, for querying across them in a single operation.
+///
+/// NOTE: Namespaces are available only in Consul Enterprise.
+pub const WILDCARD_NAMESPACE: &str = "*";
+
+/// Header used to send the correlation ID of a request.
+pub(crate) const REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+/// An object whose settings can be checked for invalid combinations before a request is sent.
+pub(crate) trait ValidateOptions {
+    fn validate(&self) -> Result<(), Error>;
+}
+
 /// An object that can express a timeout.
 ///
 /// Commonly used to derive a timeout value from a configuration object where there may be a single
@@ -59,9 +115,24 @@ pub(crate) trait CollectRequestHeaders {
 /// generically pass options when building requests so a timeout can be retrieved.
 pub(crate) trait AsTimeout {
     fn as_timeout(&self) -> Option<Duration>;
+
+    /// Whether or not the operation is a blocking query.
+    ///
+    /// Blocking queries are expected to run for as long as their blocking timeout, so any
+    /// client-wide default timeout should not be applied to them.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+
+    /// The maximum staleness allowed for the results of the operation, if bounded.
+    ///
+    /// Reads whose results are more stale than this are retried against the leader.
+    fn max_stale(&self) -> Option<Duration> {
+        None
+    }
 }
 
-impl<'a, T> CollectQueryParameters for &'a T
+impl<T> CollectQueryParameters for &T
 where
     T: CollectQueryParameters,
 {
@@ -86,6 +157,23 @@ where
     fn as_timeout(&self) -> Option<Duration> {
         AsTimeout::as_timeout(*self)
     }
+
+    fn is_blocking(&self) -> bool {
+        AsTimeout::is_blocking(*self)
+    }
+
+    fn max_stale(&self) -> Option<Duration> {
+        AsTimeout::max_stale(*self)
+    }
+}
+
+impl<'a, T> ValidateOptions for &'a T
+where
+    T: ValidateOptions + 'a,
+{
+    fn validate(&self) -> Result<(), Error> {
+        ValidateOptions::validate(*self)
+    }
 }
 
 impl<T> CollectQueryParameters for Option<T>
@@ -112,6 +200,18 @@ where
     }
 }
 
+impl<T> ValidateOptions for Option<T>
+where
+    T: ValidateOptions,
+{
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Some(inner) => ValidateOptions::validate(inner),
+            None => Ok(()),
+        }
+    }
+}
+
 impl<T> AsTimeout for Option<T>
 where
     T: AsTimeout,
@@ -122,6 +222,20 @@ where
             None => None,
         }
     }
+
+    fn is_blocking(&self) -> bool {
+        match self {
+            Some(inner) => AsTimeout::is_blocking(inner),
+            None => false,
+        }
+    }
+
+    fn max_stale(&self) -> Option<Duration> {
+        match self {
+            Some(inner) => AsTimeout::max_stale(inner),
+            None => None,
+        }
+    }
 }
 
 /// Options specific to write operations.
@@ -147,6 +261,84 @@ pub struct WriteOptions {
     pub relay_factor: Option<u8>,
     /// Timeout for this operation overall.
     pub timeout: Option<Duration>,
+    /// Correlation ID to send with this operation, in the `X-Request-ID` header.
+    ///
+    /// This is included in the context of any error for the operation, so that failures can be
+    /// matched up with the logs of the Consul agent, or of any proxy in front of it.
+    pub request_id: Option<String>,
+}
+
+impl WriteOptions {
+    /// Creates a [`WriteOptionsBuilder`] for constructing [`WriteOptions`].
+    pub fn builder() -> WriteOptionsBuilder {
+        WriteOptionsBuilder::default()
+    }
+
+    /// Checks these options for invalid settings.
+    ///
+    /// This is done automatically before sending a request, so that invalid options result in
+    /// [`Error::InvalidOptions`] rather than the request being rejected by Consul.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_relay_factor(self.relay_factor)
+    }
+}
+
+impl ValidateOptions for WriteOptions {
+    fn validate(&self) -> Result<(), Error> {
+        WriteOptions::validate(self)
+    }
+}
+
+/// Builder for [`WriteOptions`].
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptionsBuilder {
+    options: WriteOptions,
+}
+
+impl WriteOptionsBuilder {
+    /// Sets the namespace to execute this operation against.
+    pub fn namespace(mut self, namespace: &str) -> WriteOptionsBuilder {
+        self.options.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Sets the datacenter to execute this operation against.
+    pub fn datacenter(mut self, datacenter: &str) -> WriteOptionsBuilder {
+        self.options.datacenter = Some(datacenter.to_string());
+        self
+    }
+
+    /// Sets the token to use for this operation.
+    pub fn token(mut self, token: &str) -> WriteOptionsBuilder {
+        self.options.token = Some(token.to_string());
+        self
+    }
+
+    /// Sets the relay factor for keyring operations.
+    ///
+    /// Must be a value from 0 to 5 (inclusive).
+    pub fn relay_factor(mut self, relay_factor: u8) -> WriteOptionsBuilder {
+        self.options.relay_factor = Some(relay_factor);
+        self
+    }
+
+    /// Sets the timeout for this operation overall.
+    pub fn timeout(mut self, timeout: Duration) -> WriteOptionsBuilder {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the correlation ID to send with this operation.
+    pub fn request_id(mut self, request_id: &str) -> WriteOptionsBuilder {
+        self.options.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Builds the [`WriteOptions`].
+    pub fn build(self) -> Result<WriteOptions, Error> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
 }
 
 impl CollectQueryParameters for WriteOptions {
@@ -177,13 +369,17 @@ impl CollectRequestHeaders for WriteOptions {
             pairs.push(("X-Consul-Token", token.clone().into()));
         }
 
+        if let Some(request_id) = self.request_id.as_ref() {
+            pairs.push((REQUEST_ID_HEADER, request_id.clone().into()));
+        }
+
         pairs
     }
 }
 
 impl AsTimeout for WriteOptions {
     fn as_timeout(&self) -> Option<Duration> {
-        self.timeout.clone()
+        self.timeout
     }
 }
 
@@ -192,6 +388,9 @@ impl AsTimeout for WriteOptions {
 pub struct QueryOptions {
     /// Namespace to execute this operation against.
     ///
+    /// This can be set to [`WILDCARD_NAMESPACE`] to query across all namespaces, in which case
+    /// results may come from several namespaces and carry the namespace they belong to.
+    ///
     /// NOTE: Namespaces are available only in Consul Enterprise.
     pub namespace: Option<String>,
     /// Datacenter to execute this operation against.
@@ -200,6 +399,14 @@ pub struct QueryOptions {
     /// endpoint you connect to, whether you're connected directly to a Consul cluster or to an
     /// agent.
     pub datacenter: Option<String>,
+    /// Admin partition to execute this operation against.
+    ///
+    /// NOTE: Admin partitions are available only in Consul Enterprise.
+    pub partition: Option<String>,
+    /// Name of the cluster peer to query, for services imported via cluster peering.
+    ///
+    /// By default, operations query the local cluster.
+    pub peer: Option<String>,
     /// Token to use for this operation.
     ///
     /// By default, operations will use the agent's default token if talking to an agent, but will
@@ -211,18 +418,42 @@ pub struct QueryOptions {
     pub blocking: Option<Blocking>,
     /// Blocking timeout.
     ///
-    /// Only used if `blocking` is set.  This should be set lower than the overall `timeout` in
-    /// order to ensure that Consul hasa chance to complete the request and send back results.
+    /// Sent to Consul as the `wait` parameter.  This should be set lower than the overall `timeout`
+    /// in order to ensure that Consul has a chance to complete the request and send back results.
+    ///
+    /// This can be set without `blocking`, for endpoints which accept a wait time without an
+    /// index, in which case it is the only blocking parameter sent.
+    ///
+    /// Defaults to 5 minutes, matching the default used by Consul itself, when `blocking` is set.
+    /// If `timeout` is not set, the overall timeout for the request defaults to the blocking
+    /// timeout plus a grace period of one sixteenth of it, which is the maximum amount of jitter
+    /// Consul adds to the wait, plus five seconds for the response to make it back, so that
+    /// requests over dead connections do not hang forever.
     ///
-    /// If `blocking` is configured, and `timeout` is set, but `blocking_timeout` is not set, then a
-    /// timeout will be applied to the request overall, and callers may receive a timeout error
-    /// rather than a successful response from the server.
+    /// If `timeout` is also set, it must be greater than the blocking timeout, as otherwise the
+    /// request would always time out before Consul had a chance to respond.  For blocking queries,
+    /// it must also be at least as long as the timeout derived from the blocking timeout, or from
+    /// Consul's default wait when no blocking timeout is set.
     pub blocking_timeout: Option<Duration>,
+    /// Maximum amount of time to randomly shorten the blocking timeout by.
+    ///
+    /// Watches which were woken up at the same time, such as by a change to the index of a widely
+    /// watched resource, otherwise keep waking up, and reconnecting, at the same time whenever
+    /// their blocking timeout runs out.  With jitter, each query waits for somewhere between the
+    /// blocking timeout minus the jitter and the blocking timeout, which spreads them out over
+    /// time.  The jitter is capped at one sixteenth of the blocking timeout, and Consul applies its
+    /// own jitter of up to one sixteenth of the wait on top of this.  The overall timeout of the
+    /// request, when derived from the blocking timeout, is derived from the shortened wait.
+    ///
+    /// By default, no jitter is applied.
+    pub wait_jitter: Option<Duration>,
     /// Asks the agent to cache results locally.
     ///
     /// Users can refer to the Consul API documentation,
     /// [https://www.consul.io/api/features/caching.html], for more information on caching.
-    pub use_cache: bool,
+    ///
+    /// Left unset, the client's default query options decide, and caching is otherwise disabled.
+    pub use_cache: Option<bool>,
     /// Controls how old of a cached response this operation will accept from the agent.
     ///
     /// If there is a cached response older than this, the agent treats it as a miss and will
@@ -240,11 +471,9 @@ pub struct QueryOptions {
     pub cache_stale_if_error: Option<Duration>,
     /// Sorting based on network latency.
     ///
-    /// Should be the name of a node, which will sort of the results in order of lowest to highest
-    /// latency from the given node to each node in the results.
-    ///
-    /// Optionally, `_agent` can be specified to sort the results based on their latency to the agent.
-    pub near: Option<String>,
+    /// Sorts the results in order of lowest to highest latency from the given node to each node in
+    /// the results.
+    pub near: Option<Near>,
     /// Filter results to nodes that match the specified node metadata values.
     pub node_meta: Option<HashMap<String, String>>,
     /// Filter results to nodes that have a tag matching the specified tag.
@@ -259,13 +488,308 @@ pub struct QueryOptions {
     pub relay_factor: Option<u8>,
     /// Used in keyring list operations to force the keyring query to only hit local servers i.e. no
     /// WAN traffic.
-    pub local_only: bool,
+    ///
+    /// Left unset, the client's default query options decide, and this is otherwise disabled.
+    pub local_only: Option<bool>,
     /// Only include Connect-capable services/nodes in the response.
     ///
     /// Currently only affects prepared query operations.
-    pub connect: bool,
+    ///
+    /// Left unset, the client's default query options decide, and this is otherwise disabled.
+    pub connect: Option<bool>,
     /// Timeout for this operation overall.
     pub timeout: Option<Duration>,
+    /// Correlation ID to send with this operation, in the `X-Request-ID` header.
+    ///
+    /// This is included in the context of any error for the operation, so that failures can be
+    /// matched up with the logs of the Consul agent, or of any proxy in front of it.
+    pub request_id: Option<String>,
+}
+
+impl QueryOptions {
+    /// Merges these options over the given defaults.
+    ///
+    /// Any value set in these options takes precedence over the corresponding default, and node
+    /// metadata filters are combined.  When these options ask for a consistent read, the default
+    /// agent caching and sorting settings are not inherited, as neither can be used with one.
+    pub(crate) fn merged_over(self, defaults: &QueryOptions) -> QueryOptions {
+        let node_meta = match (defaults.node_meta.as_ref(), self.node_meta) {
+            (Some(defaults), Some(node_meta)) => {
+                let mut merged = defaults.clone();
+                merged.extend(node_meta);
+                Some(merged)
+            }
+            (defaults, node_meta) => node_meta.or_else(|| defaults.cloned()),
+        };
+        let consistent = self.consistency == Some(Consistency::Consistent);
+        let (default_use_cache, default_near) = if consistent {
+            (None, None)
+        } else {
+            (defaults.use_cache, defaults.near.clone())
+        };
+
+        QueryOptions {
+            namespace: self.namespace.or_else(|| defaults.namespace.clone()),
+            datacenter: self.datacenter.or_else(|| defaults.datacenter.clone()),
+            partition: self.partition.or_else(|| defaults.partition.clone()),
+            peer: self.peer.or_else(|| defaults.peer.clone()),
+            token: self.token.or_else(|| defaults.token.clone()),
+            consistency: self.consistency.or_else(|| defaults.consistency.clone()),
+            blocking: self.blocking.or_else(|| defaults.blocking.clone()),
+            blocking_timeout: self.blocking_timeout.or(defaults.blocking_timeout),
+            wait_jitter: self.wait_jitter.or(defaults.wait_jitter),
+            use_cache: self.use_cache.or(default_use_cache),
+            cache_max_age: self.cache_max_age.or(defaults.cache_max_age),
+            cache_stale_if_error: self.cache_stale_if_error.or(defaults.cache_stale_if_error),
+            near: self.near.or(default_near),
+            node_meta,
+            tag: self.tag.or_else(|| defaults.tag.clone()),
+            filtering: self.filtering.or_else(|| defaults.filtering.clone()),
+            relay_factor: self.relay_factor.or(defaults.relay_factor),
+            local_only: self.local_only.or(defaults.local_only),
+            connect: self.connect.or(defaults.connect),
+            timeout: self.timeout.or(defaults.timeout),
+            request_id: self.request_id.or_else(|| defaults.request_id.clone()),
+        }
+    }
+
+    /// Creates a [`QueryOptionsBuilder`] for constructing [`QueryOptions`].
+    pub fn builder() -> QueryOptionsBuilder {
+        QueryOptionsBuilder::default()
+    }
+
+    /// Checks these options for invalid settings, or combinations of settings.
+    ///
+    /// This is done automatically before sending a request, so that invalid options result in
+    /// [`Error::InvalidOptions`] rather than the request being rejected by Consul.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_relay_factor(self.relay_factor)?;
+
+        if let (Some(blocking_timeout), Some(timeout)) = (self.blocking_timeout, self.timeout) {
+            if blocking_timeout >= timeout {
+                return Err(Error::InvalidOptions(
+                    "blocking timeout must be less than the overall timeout",
+                ));
+            }
+        }
+
+        // Blocking queries wait for Consul's default wait time when no blocking timeout is given,
+        // and Consul adds its own jitter on top, so the overall timeout has to allow for both.
+        if let (Some(_), Some(timeout)) = (self.blocking.as_ref(), self.timeout) {
+            if timeout < blocking_request_timeout(self.blocking_wait()) {
+                return Err(Error::InvalidOptions(
+                    "overall timeout of a blocking query must allow for the blocking timeout and Consul's jitter",
+                ));
+            }
+        }
+
+        if let Some(Consistency::Consistent) = self.consistency {
+            if self.use_cache == Some(true) {
+                return Err(Error::InvalidOptions(
+                    "agent caching cannot be used with consistent reads",
+                ));
+            }
+            if self.near.is_some() {
+                return Err(Error::InvalidOptions(
+                    "sorting by distance cannot be used with consistent reads",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ValidateOptions for QueryOptions {
+    fn validate(&self) -> Result<(), Error> {
+        QueryOptions::validate(self)
+    }
+}
+
+/// Builder for [`QueryOptions`].
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptionsBuilder {
+    options: QueryOptions,
+}
+
+impl QueryOptionsBuilder {
+    /// Sets the namespace to execute this operation against.
+    pub fn namespace(mut self, namespace: &str) -> QueryOptionsBuilder {
+        self.options.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// Executes this operation against all namespaces.
+    ///
+    /// This is equivalent to setting the namespace to [`WILDCARD_NAMESPACE`].
+    pub fn all_namespaces(self) -> QueryOptionsBuilder {
+        self.namespace(WILDCARD_NAMESPACE)
+    }
+
+    /// Sets the datacenter to execute this operation against.
+    pub fn datacenter(mut self, datacenter: &str) -> QueryOptionsBuilder {
+        self.options.datacenter = Some(datacenter.to_string());
+        self
+    }
+
+    /// Sets the admin partition to execute this operation against.
+    pub fn partition(mut self, partition: &str) -> QueryOptionsBuilder {
+        self.options.partition = Some(partition.to_string());
+        self
+    }
+
+    /// Sets the cluster peer to query.
+    pub fn peer(mut self, peer: &str) -> QueryOptionsBuilder {
+        self.options.peer = Some(peer.to_string());
+        self
+    }
+
+    /// Sets the token to use for this operation.
+    pub fn token(mut self, token: &str) -> QueryOptionsBuilder {
+        self.options.token = Some(token.to_string());
+        self
+    }
+
+    /// Sets the consistency level for this operation.
+    pub fn consistency(mut self, consistency: Consistency) -> QueryOptionsBuilder {
+        self.options.consistency = Some(consistency);
+        self
+    }
+
+    /// Allows any Consul server to service this read.
+    ///
+    /// Shorthand for `consistency(Consistency::Stale)`.
+    pub fn stale(self) -> QueryOptionsBuilder {
+        self.consistency(Consistency::Stale)
+    }
+
+    /// Allows any Consul server to service this read, as long as its results are no more stale than
+    /// the given bound.
+    ///
+    /// Shorthand for `consistency(Consistency::StaleBounded { max_stale })`.
+    pub fn max_stale(self, max_stale: Duration) -> QueryOptionsBuilder {
+        self.consistency(Consistency::StaleBounded { max_stale })
+    }
+
+    /// Forces this read to be fully consistent.
+    ///
+    /// Shorthand for `consistency(Consistency::Consistent)`.
+    pub fn consistent(self) -> QueryOptionsBuilder {
+        self.consistency(Consistency::Consistent)
+    }
+
+    /// Sets the blocking configuration for this operation.
+    pub fn blocking(mut self, blocking: Blocking) -> QueryOptionsBuilder {
+        self.options.blocking = Some(blocking);
+        self
+    }
+
+    /// Sets the blocking timeout for this operation.
+    pub fn blocking_timeout(mut self, timeout: Duration) -> QueryOptionsBuilder {
+        self.options.blocking_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time to randomly shorten the blocking timeout by.
+    pub fn wait_jitter(mut self, jitter: Duration) -> QueryOptionsBuilder {
+        self.options.wait_jitter = Some(jitter);
+        self
+    }
+
+    /// Asks the agent to cache results locally.
+    pub fn use_cache(mut self, use_cache: bool) -> QueryOptionsBuilder {
+        self.options.use_cache = Some(use_cache);
+        self
+    }
+
+    /// Sets how old of a cached response this operation will accept from the agent.
+    pub fn cache_max_age(mut self, max_age: Duration) -> QueryOptionsBuilder {
+        self.options.cache_max_age = Some(max_age);
+        self
+    }
+
+    /// Sets how old of a cached response this operation will accept from the agent if refreshing
+    /// the value fails.
+    pub fn cache_stale_if_error(mut self, stale_if_error: Duration) -> QueryOptionsBuilder {
+        self.options.cache_stale_if_error = Some(stale_if_error);
+        self
+    }
+
+    /// Sorts the results by network latency from the given node.
+    pub fn near(mut self, near: Near) -> QueryOptionsBuilder {
+        self.options.near = Some(near);
+        self
+    }
+
+    /// Filters results to nodes that have the given node metadata value.
+    ///
+    /// Can be called multiple times to filter on multiple values.
+    pub fn node_meta(mut self, key: &str, value: &str) -> QueryOptionsBuilder {
+        self.options
+            .node_meta
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Filters results to nodes that have a tag matching the given tag.
+    pub fn tag(mut self, tag: &str) -> QueryOptionsBuilder {
+        self.options.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Filters results on the server with the given filter expression.
+    pub fn filter(mut self, filter: &str) -> QueryOptionsBuilder {
+        self.options.filtering = Some(filter.to_string());
+        self
+    }
+
+    /// Sets the relay factor for keyring operations.
+    ///
+    /// Must be a value from 0 to 5 (inclusive).
+    pub fn relay_factor(mut self, relay_factor: u8) -> QueryOptionsBuilder {
+        self.options.relay_factor = Some(relay_factor);
+        self
+    }
+
+    /// Forces keyring list operations to only hit local servers.
+    pub fn local_only(mut self, local_only: bool) -> QueryOptionsBuilder {
+        self.options.local_only = Some(local_only);
+        self
+    }
+
+    /// Only includes Connect-capable services/nodes in the response.
+    pub fn connect(mut self, connect: bool) -> QueryOptionsBuilder {
+        self.options.connect = Some(connect);
+        self
+    }
+
+    /// Sets the timeout for this operation overall.
+    pub fn timeout(mut self, timeout: Duration) -> QueryOptionsBuilder {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the correlation ID to send with this operation.
+    pub fn request_id(mut self, request_id: &str) -> QueryOptionsBuilder {
+        self.options.request_id = Some(request_id.to_string());
+        self
+    }
+
+    /// Builds the [`QueryOptions`].
+    pub fn build(self) -> Result<QueryOptions, Error> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+fn validate_relay_factor(relay_factor: Option<u8>) -> Result<(), Error> {
+    match relay_factor {
+        Some(relay_factor) if relay_factor > MAX_RELAY_FACTOR => Err(Error::InvalidOptions(
+            "relay factor must be between 0 and 5",
+        )),
+        _ => Ok(()),
+    }
 }
 
 impl CollectQueryParameters for QueryOptions {
@@ -280,10 +804,22 @@ impl CollectQueryParameters for QueryOptions {
             pairs.push(("dc", datacenter.clone().into()));
         }
 
+        if let Some(partition) = self.partition.as_ref() {
+            pairs.push(("partition", partition.clone().into()));
+        }
+
+        if let Some(peer) = self.peer.as_ref() {
+            pairs.push(("peer", peer.clone().into()));
+        }
+
         if let Some(consistency) = self.consistency.as_ref() {
             match consistency {
                 Consistency::Consistent => pairs.push(("consistent", "1".into())),
                 Consistency::Stale => pairs.push(("stale", "1".into())),
+                Consistency::StaleBounded { max_stale } => {
+                    pairs.push(("stale", "1".into()));
+                    pairs.push(("max_stale", go_duration::format(max_stale).into()));
+                }
             }
         }
 
@@ -295,21 +831,28 @@ impl CollectQueryParameters for QueryOptions {
                 }
                 Blocking::Hash(hash) => pairs.push(("hash", hash.clone().into())),
             }
+        }
 
-            // Apply the blocking timeout, if configured.
-            if let Some(timeout) = self.blocking_timeout {
-                let durs = format!("{}ms", timeout.as_millis());
-                pairs.push(("wait", durs.into()));
-            }
+        if self.blocking.is_some() || self.blocking_timeout.is_some() {
+            let durs = format!("{}ms", self.blocking_wait().as_millis());
+            pairs.push(("wait", durs.into()));
         }
 
         if let Some(near) = self.near.as_ref() {
-            pairs.push(("near", near.clone().into()));
+            let near = match near {
+                Near::Node(node) => node.clone(),
+                Near::Agent => "_agent".to_string(),
+                Near::Ip(_) => "_ip".to_string(),
+            };
+            pairs.push(("near", near.into()));
         }
 
         if let Some(nodemeta) = self.node_meta.as_ref() {
-            for (k, v) in nodemeta.iter() {
-                pairs.push(("node-meta[]", format!("{}:{}", k, v).into()))
+            // Sort the metadata so that the resulting query string is deterministic.
+            let mut nodemeta = nodemeta.iter().collect::<Vec<_>>();
+            nodemeta.sort();
+            for (k, v) in nodemeta {
+                pairs.push(("node-meta", format!("{}:{}", k, v).into()))
             }
         }
 
@@ -325,16 +868,16 @@ impl CollectQueryParameters for QueryOptions {
             pairs.push(("relay-factor", relay_factor.to_string().into()));
         }
 
-        if self.local_only {
+        if self.local_only == Some(true) {
             pairs.push(("local-only", "true".into()));
         }
 
-        if self.connect {
+        if self.connect == Some(true) {
             pairs.push(("connect", "true".into()));
         }
 
         // Can only send caching headers if enabled _and_ we aren't requesting fully consistency reads.
-        if self.use_cache
+        if self.use_cache == Some(true)
             && self
                 .consistency
                 .as_ref()
@@ -356,8 +899,17 @@ impl CollectRequestHeaders for QueryOptions {
             pairs.push(("X-Consul-Token", token.clone().into()));
         }
 
+        if let Some(request_id) = self.request_id.as_ref() {
+            pairs.push((REQUEST_ID_HEADER, request_id.clone().into()));
+        }
+
+        // Consul looks up the node to sort by from the forwarded address, when present.
+        if let Some(Near::Ip(addr)) = self.near.as_ref() {
+            pairs.push(("X-Forwarded-For", addr.to_string().into()));
+        }
+
         // Can only send caching headers if enabled _and_ we aren't requesting fully consistency reads.
-        if self.use_cache
+        if self.use_cache == Some(true)
             && self
                 .consistency
                 .as_ref()
@@ -388,14 +940,69 @@ impl CollectRequestHeaders for QueryOptions {
     }
 }
 
+impl QueryOptions {
+    /// Gets the blocking timeout to use, accounting for the default.
+    fn blocking_wait(&self) -> Duration {
+        self.blocking_timeout.unwrap_or(DEFAULT_BLOCKING_TIMEOUT)
+    }
+
+    /// Gets the blocking timeout shortened by a random amount of up to the wait jitter, if any.
+    ///
+    /// The jitter is capped at one sixteenth of the blocking timeout, matching the jitter Consul
+    /// adds itself, so that the wait never gets close to zero, which Consul would treat as asking
+    /// for its default wait instead.
+    fn jittered_wait(&self) -> Duration {
+        let wait = self.blocking_wait();
+        let jitter = match self.wait_jitter {
+            Some(jitter) if !jitter.is_zero() => jitter.min(wait / 16),
+            _ => return wait,
+        };
+
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+        wait - jitter.mul_f64(fraction)
+    }
+
+    /// Resolves the wait jitter, if any, into the blocking timeout for a single request.
+    ///
+    /// Both the wait sent to Consul and the overall timeout of the request are derived from the
+    /// blocking timeout, so the jitter has to be applied once, up front, for them to agree.  The
+    /// jitter is left set to zero, rather than cleared, so that the jitter from any default query
+    /// options is not applied a second time when these options are merged over them.
+    pub(crate) fn with_jittered_wait(mut self) -> QueryOptions {
+        let blocking = self.blocking.is_some() || self.blocking_timeout.is_some();
+        if blocking && self.wait_jitter.is_some() {
+            self.blocking_timeout = Some(self.jittered_wait());
+            self.wait_jitter = Some(Duration::ZERO);
+        }
+        self
+    }
+}
+
 impl AsTimeout for QueryOptions {
     fn as_timeout(&self) -> Option<Duration> {
+        if self.timeout.is_none() && (self.blocking.is_some() || self.blocking_timeout.is_some()) {
+            let wait = self.blocking_wait();
+            return Some(blocking_request_timeout(wait));
+        }
+
         self.timeout
     }
+
+    fn is_blocking(&self) -> bool {
+        self.blocking.is_some()
+    }
+
+    fn max_stale(&self) -> Option<Duration> {
+        match self.consistency {
+            Some(Consistency::StaleBounded { max_stale }) => Some(max_stale),
+            _ => None,
+        }
+    }
 }
 
 /// Metadata about the request returned from a query operation.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct QueryMetadata {
     /// The Consul index for the data in this response.
     ///
@@ -416,6 +1023,21 @@ pub struct QueryMetadata {
     pub cache_hit: bool,
     /// The age of the cache value, if served from cache.
     pub cache_age: Option<Duration>,
+    /// The backend used to serve this query, either `blocking-query` or `streaming`.
+    pub query_backend: Option<String>,
+    /// The consistency mode that was actually used to serve this query, such as `leader`,
+    /// `consistent`, or `stale`.
+    pub effective_consistency: Option<String>,
+    /// The default ACL policy of the cluster, either `allow` or `deny`.
+    ///
+    /// Only returned by certain endpoints, such as the ACL token self endpoint.
+    pub default_acl_policy: Option<String>,
+    /// Whether or not any results were omitted from this response due to ACL filtering.
+    ///
+    /// This distinguishes an empty result caused by the token lacking permissions, such as a
+    /// service which the token cannot read, from a result which is actually empty.  Older versions
+    /// of Consul never send this, so it is always `false` for them.
+    pub results_filtered_by_acls: bool,
 }
 
 impl QueryMetadata {
@@ -453,6 +1075,16 @@ impl QueryMetadata {
             }
         }
 
+        if let Some(contact_raw) = headers.get("X-Consul-LastContact") {
+            match contact_raw.to_str() {
+                Ok(contact_str) => match contact_str.parse::<u64>() {
+                    Ok(contact) => meta.last_contact = Duration::from_millis(contact),
+                    Err(_) => errors.push("X-Consul-LastContact"),
+                },
+                Err(_) => errors.push("X-Consul-LastContact"),
+            }
+        }
+
         if let Some(translate_raw) = headers.get("X-Consul-Translate-Addresses") {
             match translate_raw.to_str() {
                 Ok(translate_str) => {
@@ -485,6 +1117,40 @@ impl QueryMetadata {
             }
         }
 
+        if let Some(backend_raw) = headers.get("X-Consul-Query-Backend") {
+            match backend_raw.to_str() {
+                Ok(backend_str) => meta.query_backend = Some(backend_str.to_string()),
+                Err(_) => errors.push("X-Consul-Query-Backend"),
+            }
+        }
+
+        if let Some(consistency_raw) = headers.get("X-Consul-Effective-Consistency") {
+            match consistency_raw.to_str() {
+                Ok(consistency_str) => {
+                    meta.effective_consistency = Some(consistency_str.to_string())
+                }
+                Err(_) => errors.push("X-Consul-Effective-Consistency"),
+            }
+        }
+
+        if let Some(policy_raw) = headers.get("X-Consul-Default-ACL-Policy") {
+            match policy_raw.to_str() {
+                Ok(policy_str) => meta.default_acl_policy = Some(policy_str.to_string()),
+                Err(_) => errors.push("X-Consul-Default-ACL-Policy"),
+            }
+        }
+
+        if let Some(filtered_raw) = headers.get("X-Consul-Results-Filtered-By-ACLs") {
+            match filtered_raw.to_str() {
+                Ok(filtered_str) => {
+                    if filtered_str == "true" {
+                        meta.results_filtered_by_acls = true;
+                    }
+                }
+                Err(_) => errors.push("X-Consul-Results-Filtered-By-ACLs"),
+            }
+        }
+
         if !errors.is_empty() {
             Err(ResponseError::InvalidHeaders(errors))
         } else {
@@ -492,15 +1158,568 @@ impl QueryMetadata {
         }
     }
 
-    pub(crate) fn as_blocking(&self) -> Option<Blocking> {
+    /// Gets the blocking parameters to use to wait for changes after this response.
+    ///
+    /// Prefers the content hash, for endpoints which support hash-based blocking, and otherwise
+    /// uses the index.
+    pub fn as_blocking(&self) -> Option<Blocking> {
         if let Some(last_content_hash) = &self.last_content_hash {
             return Some(Blocking::Hash(last_content_hash.clone()));
         }
 
         if let Some(last_index) = &self.last_index {
-            return Some(Blocking::Index(last_index.clone()));
+            return Some(Blocking::Index(*last_index));
         }
 
         None
     }
+
+    /// Gets the options to use for the next query in a blocking loop.
+    ///
+    /// Returns `base` with its blocking parameters set based on this response, so that the next
+    /// query waits for changes after this response.
+    pub fn next_options(&self, mut base: QueryOptions) -> QueryOptions {
+        base.blocking = self.as_blocking();
+        base
+    }
+}
+
+/// Serde helpers for durations expressed as Go duration strings, e.g. `1m30s` or `500ms`.
+///
+/// Consul uses these for the durations in most payloads, such as the interval of a check or the
+/// timeouts of a config entry, so this can be used with `#[serde(with = "...")]` on the fields of
+/// user-defined payloads to handle them the same way as this crate does.  Use
+/// [`go_duration::option`] for optional fields.
+pub mod go_duration {
+    use std::time::Duration;
+
+    /// Formats a duration as a Go duration string.
+    ///
+    /// The largest unit which represents the duration exactly, out of seconds, milliseconds, and
+    /// nanoseconds, is used.
+    pub fn format(dur: &Duration) -> String {
+        if dur.subsec_nanos() == 0 {
+            format!("{}s", dur.as_secs())
+        } else if dur.subsec_nanos() % 1_000_000 == 0 {
+            format!("{}ms", dur.as_millis())
+        } else {
+            format!("{}ns", dur.as_nanos())
+        }
+    }
+
+    /// Parses a Go duration string, such as `1h2m3.5s`.
+    ///
+    /// A bare `0`, or an empty string, is parsed as a zero duration.
+    pub fn parse(s: &str) -> Result<Duration, String> {
+        let s = s.trim();
+        if s == "0" || s.is_empty() {
+            return Ok(Duration::from_secs(0));
+        }
+
+        let mut nanos = 0f64;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let num_len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .ok_or_else(|| format!("missing unit in duration '{}'", s))?;
+            let (num, tail) = rest.split_at(num_len);
+            let value = num
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number in duration '{}'", s))?;
+            let unit_len = tail
+                .find(|c: char| c.is_ascii_digit() || c == '.')
+                .unwrap_or(tail.len());
+            let (unit, tail) = tail.split_at(unit_len);
+            let multiplier = match unit {
+                "ns" => 1f64,
+                "us" | "µs" | "μs" => 1_000f64,
+                "ms" => 1_000_000f64,
+                "s" => 1_000_000_000f64,
+                "m" => 60_000_000_000f64,
+                "h" => 3_600_000_000_000f64,
+                _ => return Err(format!("unknown unit '{}' in duration '{}'", unit, s)),
+            };
+            nanos += value * multiplier;
+            rest = tail;
+        }
+
+        Ok(Duration::from_nanos(nanos as u64))
+    }
+
+    /// Serializes a duration as a Go duration string.
+    pub fn serialize<S>(dur: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format(dur))
+    }
+
+    /// Deserializes a duration from a Go duration string.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::{de::Error as _, Deserialize};
+
+        let raw = String::deserialize(deserializer)?;
+        parse(&raw).map_err(D::Error::custom)
+    }
+
+    /// Serde helpers for optional fields.
+    pub mod option {
+        use std::time::Duration;
+
+        use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+        /// Serializes an optional duration as a Go duration string.
+        pub fn serialize<S>(dur: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match dur {
+                Some(dur) => serializer.serialize_some(&super::format(dur)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        /// Deserializes an optional duration from a Go duration string.
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let raw = Option::<String>::deserialize(deserializer)?;
+            raw.map(|raw| super::parse(&raw).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+/// Serde helpers for durations expressed as an integer number of nanoseconds.
+///
+/// Consul uses these where Go durations are encoded without a custom format, such as in some
+/// agent payloads and in telemetry, so this can be used with `#[serde(with = "...")]` on the
+/// fields of user-defined payloads.  Use [`nanoseconds::option`] for optional fields.
+pub mod nanoseconds {
+    use std::convert::TryFrom;
+    use std::time::Duration;
+
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    /// Serializes a duration as an integer number of nanoseconds.
+    ///
+    /// Durations too long to represent as nanoseconds in an `i64`, as used by Go, are rejected.
+    pub fn serialize<S>(dur: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::Error as _;
+
+        let nanos = i64::try_from(dur.as_nanos())
+            .map_err(|_| S::Error::custom("duration too long to represent in nanoseconds"))?;
+        serializer.serialize_i64(nanos)
+    }
+
+    /// Deserializes a duration from an integer number of nanoseconds.
+    ///
+    /// Negative durations are rejected.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let nanos = i64::deserialize(deserializer)?;
+        u64::try_from(nanos)
+            .map(Duration::from_nanos)
+            .map_err(|_| D::Error::custom(format!("negative duration '{}ns'", nanos)))
+    }
+
+    /// Serde helpers for optional fields.
+    pub mod option {
+        use std::time::Duration;
+
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        #[derive(Serialize, Deserialize)]
+        struct Nanoseconds(#[serde(with = "super")] Duration);
+
+        /// Serializes an optional duration as an integer number of nanoseconds.
+        pub fn serialize<S>(dur: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            dur.map(Nanoseconds).serialize(serializer)
+        }
+
+        /// Deserializes an optional duration from an integer number of nanoseconds.
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let raw = Option::<Nanoseconds>::deserialize(deserializer)?;
+            Ok(raw.map(|Nanoseconds(dur)| dur))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn defaults() -> QueryOptions {
+        QueryOptions {
+            namespace: Some("default-ns".to_string()),
+            datacenter: Some("default-dc".to_string()),
+            partition: Some("default-partition".to_string()),
+            peer: Some("default-peer".to_string()),
+            token: Some("default-token".to_string()),
+            consistency: Some(Consistency::Stale),
+            blocking: Some(Blocking::Index(1)),
+            blocking_timeout: Some(Duration::from_secs(60)),
+            wait_jitter: Some(Duration::from_secs(1)),
+            use_cache: Some(true),
+            cache_max_age: Some(Duration::from_secs(10)),
+            cache_stale_if_error: Some(Duration::from_secs(20)),
+            near: Some(Near::Agent),
+            node_meta: Some(
+                vec![("rack".to_string(), "a".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            tag: Some("default-tag".to_string()),
+            filtering: Some("default-filter".to_string()),
+            relay_factor: Some(1),
+            local_only: Some(true),
+            connect: Some(true),
+            timeout: Some(Duration::from_secs(90)),
+            request_id: Some("default-id".to_string()),
+        }
+    }
+
+    #[test]
+    fn merged_over_inherits_unset_values() {
+        let merged = QueryOptions::default().merged_over(&defaults());
+        let defaults = defaults();
+
+        assert_eq!(merged.namespace, defaults.namespace);
+        assert_eq!(merged.datacenter, defaults.datacenter);
+        assert_eq!(merged.partition, defaults.partition);
+        assert_eq!(merged.peer, defaults.peer);
+        assert_eq!(merged.token, defaults.token);
+        assert_eq!(merged.consistency, defaults.consistency);
+        assert_eq!(merged.blocking, defaults.blocking);
+        assert_eq!(merged.blocking_timeout, defaults.blocking_timeout);
+        assert_eq!(merged.wait_jitter, defaults.wait_jitter);
+        assert_eq!(merged.use_cache, defaults.use_cache);
+        assert_eq!(merged.cache_max_age, defaults.cache_max_age);
+        assert_eq!(merged.cache_stale_if_error, defaults.cache_stale_if_error);
+        assert_eq!(merged.near, defaults.near);
+        assert_eq!(merged.node_meta, defaults.node_meta);
+        assert_eq!(merged.tag, defaults.tag);
+        assert_eq!(merged.filtering, defaults.filtering);
+        assert_eq!(merged.relay_factor, defaults.relay_factor);
+        assert_eq!(merged.local_only, defaults.local_only);
+        assert_eq!(merged.connect, defaults.connect);
+        assert_eq!(merged.timeout, defaults.timeout);
+        assert_eq!(merged.request_id, defaults.request_id);
+    }
+
+    #[test]
+    fn merged_over_prefers_call_level_values() {
+        let options = QueryOptions {
+            namespace: Some("ns".to_string()),
+            datacenter: Some("dc".to_string()),
+            partition: Some("partition".to_string()),
+            peer: Some("peer".to_string()),
+            token: Some("token".to_string()),
+            consistency: Some(Consistency::StaleBounded {
+                max_stale: Duration::from_secs(5),
+            }),
+            blocking: Some(Blocking::Hash("abc".to_string())),
+            blocking_timeout: Some(Duration::from_secs(30)),
+            wait_jitter: Some(Duration::from_secs(2)),
+            use_cache: Some(false),
+            cache_max_age: Some(Duration::from_secs(11)),
+            cache_stale_if_error: Some(Duration::from_secs(21)),
+            near: Some(Near::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))),
+            node_meta: None,
+            tag: Some("tag".to_string()),
+            filtering: Some("filter".to_string()),
+            relay_factor: Some(2),
+            local_only: Some(false),
+            connect: Some(false),
+            timeout: Some(Duration::from_secs(45)),
+            request_id: Some("id".to_string()),
+        };
+        let merged = options.clone().merged_over(&defaults());
+
+        assert_eq!(merged.namespace, options.namespace);
+        assert_eq!(merged.datacenter, options.datacenter);
+        assert_eq!(merged.partition, options.partition);
+        assert_eq!(merged.peer, options.peer);
+        assert_eq!(merged.token, options.token);
+        assert_eq!(merged.consistency, options.consistency);
+        assert_eq!(merged.blocking, options.blocking);
+        assert_eq!(merged.blocking_timeout, options.blocking_timeout);
+        assert_eq!(merged.wait_jitter, options.wait_jitter);
+        assert_eq!(merged.use_cache, Some(false));
+        assert_eq!(merged.cache_max_age, options.cache_max_age);
+        assert_eq!(merged.cache_stale_if_error, options.cache_stale_if_error);
+        assert_eq!(merged.near, options.near);
+        assert_eq!(merged.tag, options.tag);
+        assert_eq!(merged.filtering, options.filtering);
+        assert_eq!(merged.relay_factor, options.relay_factor);
+        assert_eq!(merged.local_only, Some(false));
+        assert_eq!(merged.connect, Some(false));
+        assert_eq!(merged.timeout, options.timeout);
+        assert_eq!(merged.request_id, options.request_id);
+    }
+
+    #[test]
+    fn merged_over_combines_node_meta() {
+        let options = QueryOptions::builder()
+            .node_meta("rack", "b")
+            .node_meta("zone", "east")
+            .build()
+            .unwrap();
+        let merged = options.merged_over(&defaults());
+
+        let node_meta = merged.node_meta.unwrap();
+        assert_eq!(node_meta.len(), 2);
+        assert_eq!(node_meta["rack"], "b");
+        assert_eq!(node_meta["zone"], "east");
+    }
+
+    #[test]
+    fn merged_over_drops_default_caching_and_sorting_for_consistent_reads() {
+        let options = QueryOptions::builder().consistent().build().unwrap();
+        let merged = options.merged_over(&defaults());
+
+        assert_eq!(merged.consistency, Some(Consistency::Consistent));
+        assert_eq!(merged.use_cache, None);
+        assert_eq!(merged.near, None);
+        assert!(merged.validate().is_ok());
+    }
+
+    fn invalid_reason(result: Result<QueryOptions, Error>) -> &'static str {
+        match result {
+            Err(Error::InvalidOptions(reason)) => reason,
+            other => panic!("expected invalid options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_relay_factor_out_of_range() {
+        assert!(QueryOptions::builder().relay_factor(5).build().is_ok());
+
+        let reason = invalid_reason(QueryOptions::builder().relay_factor(6).build());
+        assert_eq!(reason, "relay factor must be between 0 and 5");
+
+        let result = WriteOptions::builder().relay_factor(6).build();
+        assert!(matches!(result, Err(Error::InvalidOptions(_))));
+    }
+
+    #[test]
+    fn validate_rejects_blocking_timeout_not_below_timeout() {
+        let builder = QueryOptions::builder().timeout(Duration::from_secs(30));
+        assert!(builder
+            .clone()
+            .blocking_timeout(Duration::from_secs(29))
+            .build()
+            .is_ok());
+
+        let reason = invalid_reason(builder.blocking_timeout(Duration::from_secs(30)).build());
+        assert_eq!(
+            reason,
+            "blocking timeout must be less than the overall timeout"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_blocking_query_timeout_shorter_than_default_wait() {
+        let builder = QueryOptions::builder()
+            .blocking(Blocking::Index(10))
+            .timeout(Duration::from_secs(10));
+        let reason = invalid_reason(builder.clone().build());
+        assert_eq!(
+            reason,
+            "overall timeout of a blocking query must allow for the blocking timeout and Consul's jitter"
+        );
+
+        let wait = Duration::from_secs(5);
+        assert!(builder.clone().blocking_timeout(wait).build().is_err());
+        assert!(builder
+            .clone()
+            .blocking_timeout(wait)
+            .timeout(blocking_request_timeout(wait))
+            .build()
+            .is_ok());
+        assert!(builder
+            .timeout(blocking_request_timeout(DEFAULT_BLOCKING_TIMEOUT))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_caching_with_consistent_reads() {
+        let builder = QueryOptions::builder().consistent();
+        assert!(builder.clone().use_cache(false).build().is_ok());
+
+        let reason = invalid_reason(builder.use_cache(true).build());
+        assert_eq!(reason, "agent caching cannot be used with consistent reads");
+    }
+
+    #[test]
+    fn validate_rejects_sorting_with_consistent_reads() {
+        let reason = invalid_reason(
+            QueryOptions::builder()
+                .consistent()
+                .near(Near::Agent)
+                .build(),
+        );
+        assert_eq!(
+            reason,
+            "sorting by distance cannot be used with consistent reads"
+        );
+
+        assert!(QueryOptions::builder()
+            .stale()
+            .near(Near::Agent)
+            .use_cache(true)
+            .build()
+            .is_ok());
+    }
+
+    fn sent_wait(options: &QueryOptions) -> Option<String> {
+        CollectQueryParameters::as_pairs(options)
+            .into_iter()
+            .find(|(k, _)| *k == "wait")
+            .map(|(_, v)| v.into_owned())
+    }
+
+    #[test]
+    fn jittered_wait_is_capped_at_a_sixteenth_of_the_wait() {
+        let wait = Duration::from_secs(160);
+        let options = QueryOptions::builder()
+            .blocking_timeout(wait)
+            .wait_jitter(Duration::from_secs(600))
+            .build()
+            .unwrap();
+
+        for _ in 0..1000 {
+            let jittered = options.jittered_wait();
+            assert!(jittered <= wait);
+            assert!(jittered >= wait - wait / 16);
+        }
+    }
+
+    #[test]
+    fn jittered_wait_stays_within_the_jitter() {
+        let wait = Duration::from_secs(300);
+        let jitter = Duration::from_secs(3);
+        let options = QueryOptions::builder()
+            .blocking_timeout(wait)
+            .wait_jitter(jitter)
+            .build()
+            .unwrap();
+
+        for _ in 0..1000 {
+            let jittered = options.jittered_wait();
+            assert!(jittered <= wait);
+            assert!(jittered >= wait - jitter);
+        }
+    }
+
+    #[test]
+    fn jittered_wait_drives_both_wait_and_timeout() {
+        let options = QueryOptions::builder()
+            .blocking(Blocking::Index(42))
+            .wait_jitter(Duration::from_secs(10))
+            .build()
+            .unwrap()
+            .with_jittered_wait();
+
+        let wait = options.blocking_timeout.unwrap();
+        assert_eq!(options.wait_jitter, Some(Duration::ZERO));
+        assert_eq!(sent_wait(&options), Some(format!("{}ms", wait.as_millis())));
+        assert_eq!(options.as_timeout(), Some(blocking_request_timeout(wait)));
+
+        // Resolving again, or merging over defaults with jitter, must not shorten it any further.
+        let defaults = QueryOptions::builder()
+            .wait_jitter(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let merged = options.merged_over(&defaults).with_jittered_wait();
+        assert_eq!(merged.blocking_timeout, Some(wait));
+    }
+
+    #[test]
+    fn jittered_wait_is_not_sent_without_blocking() {
+        let options = QueryOptions::builder()
+            .wait_jitter(Duration::from_secs(10))
+            .build()
+            .unwrap()
+            .with_jittered_wait();
+
+        assert_eq!(options.blocking_timeout, None);
+        assert_eq!(sent_wait(&options), None);
+        assert_eq!(options.as_timeout(), None);
+    }
+
+    #[test]
+    fn node_meta_is_sent_as_repeated_sorted_pairs() {
+        let options = QueryOptions::builder()
+            .node_meta("zone", "east")
+            .node_meta("rack", "a")
+            .tag("primary")
+            .build()
+            .unwrap();
+
+        let pairs = CollectQueryParameters::as_pairs(&options);
+        let node_meta = pairs
+            .iter()
+            .filter(|(k, _)| *k == "node-meta")
+            .map(|(_, v)| v.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(node_meta, vec!["rack:a", "zone:east"]);
+        assert!(pairs.iter().any(|(k, v)| *k == "tag" && v == "primary"));
+    }
+
+    #[test]
+    fn go_duration_parses_compound_durations() {
+        let cases = [
+            ("1h2m3.5s", Duration::from_millis(3_723_500)),
+            ("1.5µs", Duration::from_nanos(1_500)),
+            ("1.5μs", Duration::from_nanos(1_500)),
+            ("2us", Duration::from_nanos(2_000)),
+            ("250ms", Duration::from_millis(250)),
+            ("10ns", Duration::from_nanos(10)),
+            ("90m", Duration::from_secs(5_400)),
+            ("0", Duration::from_secs(0)),
+            ("", Duration::from_secs(0)),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(go_duration::parse(input), Ok(*expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn go_duration_round_trips() {
+        for input in ["1h2m3.5s", "1.5µs", "30s", "1m30s", "500ms", "1ns"].iter() {
+            let parsed = go_duration::parse(input).unwrap();
+            let formatted = go_duration::format(&parsed);
+            assert_eq!(go_duration::parse(&formatted), Ok(parsed), "{}", input);
+        }
+
+        assert_eq!(go_duration::format(&Duration::from_secs(90)), "90s");
+        assert_eq!(go_duration::format(&Duration::from_millis(1_500)), "1500ms");
+        assert_eq!(go_duration::format(&Duration::from_nanos(1_500)), "1500ns");
+    }
+
+    #[test]
+    fn go_duration_rejects_invalid_durations() {
+        for input in ["10", "5d", "1..5s", "-1s", "s"].iter() {
+            assert!(go_duration::parse(input).is_err(), "{}", input);
+        }
+    }
 }
@@ -0,0 +1,858 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::{go_duration, QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+
+/// A centralized configuration entry.
+///
+/// Config entries are tagged by their `Kind` field.  Kinds which are not modeled by this crate are
+/// represented by [`ConfigEntry::Other`], which holds the raw JSON of the entry, so that newer
+/// versions of Consul can still be worked with.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "Kind")]
+#[allow(clippy::large_enum_variant)]
+pub enum ConfigEntry {
+    /// A `service-defaults` config entry.
+    #[serde(rename = "service-defaults")]
+    ServiceDefaults(ServiceConfigEntry),
+    /// A `proxy-defaults` config entry.
+    #[serde(rename = "proxy-defaults")]
+    ProxyDefaults(ProxyConfigEntry),
+    /// A `service-router` config entry.
+    #[serde(rename = "service-router")]
+    ServiceRouter(ServiceRouterConfigEntry),
+    /// A `service-splitter` config entry.
+    #[serde(rename = "service-splitter")]
+    ServiceSplitter(ServiceSplitterConfigEntry),
+    /// A `service-resolver` config entry.
+    #[serde(rename = "service-resolver")]
+    ServiceResolver(ServiceResolverConfigEntry),
+    /// An `ingress-gateway` config entry.
+    #[serde(rename = "ingress-gateway")]
+    IngressGateway(IngressGatewayConfigEntry),
+    /// A `terminating-gateway` config entry.
+    #[serde(rename = "terminating-gateway")]
+    TerminatingGateway(TerminatingGatewayConfigEntry),
+    /// A `mesh` config entry.
+    #[serde(rename = "mesh")]
+    Mesh(MeshConfigEntry),
+    /// An `exported-services` config entry.
+    #[serde(rename = "exported-services")]
+    ExportedServices(ExportedServicesConfigEntry),
+    /// A `sameness-group` config entry.
+    #[serde(rename = "sameness-group")]
+    SamenessGroup(SamenessGroupConfigEntry),
+    /// A config entry of a kind not modeled by this crate.
+    #[serde(untagged)]
+    Other(Value),
+}
+
+impl ConfigEntry {
+    /// Gets the kind of this config entry.
+    pub fn kind(&self) -> &str {
+        match self {
+            ConfigEntry::ServiceDefaults(_) => "service-defaults",
+            ConfigEntry::ProxyDefaults(_) => "proxy-defaults",
+            ConfigEntry::ServiceRouter(_) => "service-router",
+            ConfigEntry::ServiceSplitter(_) => "service-splitter",
+            ConfigEntry::ServiceResolver(_) => "service-resolver",
+            ConfigEntry::IngressGateway(_) => "ingress-gateway",
+            ConfigEntry::TerminatingGateway(_) => "terminating-gateway",
+            ConfigEntry::Mesh(_) => "mesh",
+            ConfigEntry::ExportedServices(_) => "exported-services",
+            ConfigEntry::SamenessGroup(_) => "sameness-group",
+            ConfigEntry::Other(value) => value.get("Kind").and_then(Value::as_str).unwrap_or(""),
+        }
+    }
+
+    /// Gets the name of this config entry.
+    ///
+    /// Some kinds, such as `mesh`, are singletons and have a fixed name.
+    pub fn name(&self) -> &str {
+        match self {
+            ConfigEntry::ServiceDefaults(entry) => &entry.name,
+            ConfigEntry::ProxyDefaults(entry) => &entry.name,
+            ConfigEntry::ServiceRouter(entry) => &entry.name,
+            ConfigEntry::ServiceSplitter(entry) => &entry.name,
+            ConfigEntry::ServiceResolver(entry) => &entry.name,
+            ConfigEntry::IngressGateway(entry) => &entry.name,
+            ConfigEntry::TerminatingGateway(entry) => &entry.name,
+            ConfigEntry::Mesh(_) => "mesh",
+            ConfigEntry::ExportedServices(entry) => &entry.name,
+            ConfigEntry::SamenessGroup(entry) => &entry.name,
+            ConfigEntry::Other(value) => value.get("Name").and_then(Value::as_str).unwrap_or(""),
+        }
+    }
+
+    /// Gets the index at which this config entry was created.
+    ///
+    /// This is `0` for entries which have not been read from Consul.
+    pub fn create_index(&self) -> u64 {
+        self.indexes().0
+    }
+
+    /// Gets the index at which this config entry was last modified.
+    ///
+    /// This is `0` for entries which have not been read from Consul, and is never sent when
+    /// applying entries.  Passing it as the `cas` of [`ConfigEntries::apply`] or
+    /// [`ConfigEntries::delete_cas`] makes the write fail if the entry was modified concurrently,
+    /// rather than clobbering the other change.
+    pub fn modify_index(&self) -> u64 {
+        self.indexes().1
+    }
+
+    /// Gets the create and modify indexes of this config entry.
+    fn indexes(&self) -> (u64, u64) {
+        match self {
+            ConfigEntry::ServiceDefaults(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::ProxyDefaults(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::ServiceRouter(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::ServiceSplitter(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::ServiceResolver(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::IngressGateway(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::TerminatingGateway(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::Mesh(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::ExportedServices(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::SamenessGroup(entry) => (entry.create_index, entry.modify_index),
+            ConfigEntry::Other(value) => {
+                let index = |field| value.get(field).and_then(Value::as_u64).unwrap_or(0);
+                (index("CreateIndex"), index("ModifyIndex"))
+            }
+        }
+    }
+}
+
+/// The mode used by mesh gateways when routing traffic to a given upstream.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum MeshGatewayMode {
+    #[default]
+    #[serde(rename = "")]
+    Default,
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "local")]
+    Local,
+    #[serde(rename = "remote")]
+    Remote,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct MeshGatewayConfig {
+    #[serde(rename = "Mode", default)]
+    pub mode: MeshGatewayMode,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ExposePath {
+    #[serde(rename = "ListenerPort", default)]
+    pub listener_port: u16,
+    #[serde(rename = "Path", default)]
+    pub path: String,
+    #[serde(rename = "LocalPathPort", default)]
+    pub local_path_port: u16,
+    #[serde(rename = "Protocol", default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ExposeConfig {
+    #[serde(rename = "Checks", default)]
+    pub checks: bool,
+    #[serde(rename = "Paths", default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<ExposePath>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Protocol", default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(rename = "Mode", default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(
+        rename = "MeshGateway",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mesh_gateway: Option<MeshGatewayConfig>,
+    #[serde(rename = "Expose", default, skip_serializing_if = "Option::is_none")]
+    pub expose: Option<ExposeConfig>,
+    #[serde(
+        rename = "ExternalSNI",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub external_sni: Option<String>,
+    #[serde(
+        rename = "UpstreamConfig",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub upstream_config: Option<Value>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ProxyConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Mode", default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(rename = "Config", default, skip_serializing_if = "HashMap::is_empty")]
+    pub config: HashMap<String, Value>,
+    #[serde(
+        rename = "MeshGateway",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mesh_gateway: Option<MeshGatewayConfig>,
+    #[serde(rename = "Expose", default, skip_serializing_if = "Option::is_none")]
+    pub expose: Option<ExposeConfig>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouteHttpMatchHeader {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Present", default)]
+    pub present: bool,
+    #[serde(rename = "Exact", default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<String>,
+    #[serde(rename = "Prefix", default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(rename = "Suffix", default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    #[serde(rename = "Regex", default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(rename = "Invert", default)]
+    pub invert: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouteHttpMatchQueryParam {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Present", default)]
+    pub present: bool,
+    #[serde(rename = "Exact", default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<String>,
+    #[serde(rename = "Regex", default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouteHttpMatch {
+    #[serde(rename = "PathExact", default, skip_serializing_if = "Option::is_none")]
+    pub path_exact: Option<String>,
+    #[serde(
+        rename = "PathPrefix",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub path_prefix: Option<String>,
+    #[serde(rename = "PathRegex", default, skip_serializing_if = "Option::is_none")]
+    pub path_regex: Option<String>,
+    #[serde(rename = "Header", default, skip_serializing_if = "Vec::is_empty")]
+    pub header: Vec<ServiceRouteHttpMatchHeader>,
+    #[serde(rename = "QueryParam", default, skip_serializing_if = "Vec::is_empty")]
+    pub query_param: Vec<ServiceRouteHttpMatchQueryParam>,
+    #[serde(rename = "Methods", default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouteMatch {
+    #[serde(rename = "HTTP", default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<ServiceRouteHttpMatch>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouteDestination {
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(
+        rename = "ServiceSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_subset: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(
+        rename = "PrefixRewrite",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub prefix_rewrite: Option<String>,
+    #[serde(
+        rename = "RequestTimeout",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "go_duration::option"
+    )]
+    pub request_timeout: Option<Duration>,
+    #[serde(rename = "NumRetries", default)]
+    pub num_retries: u32,
+    #[serde(rename = "RetryOnConnectFailure", default)]
+    pub retry_on_connect_failure: bool,
+    #[serde(
+        rename = "RetryOnStatusCodes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub retry_on_status_codes: Vec<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRoute {
+    #[serde(rename = "Match", default, skip_serializing_if = "Option::is_none")]
+    pub route_match: Option<ServiceRouteMatch>,
+    #[serde(
+        rename = "Destination",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub destination: Option<ServiceRouteDestination>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceRouterConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Routes", default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<ServiceRoute>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceSplit {
+    #[serde(rename = "Weight")]
+    pub weight: f32,
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(
+        rename = "ServiceSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_subset: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceSplitterConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Splits", default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<ServiceSplit>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceResolverSubset {
+    #[serde(rename = "Filter", default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    #[serde(rename = "OnlyPassing", default)]
+    pub only_passing: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceResolverRedirect {
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(
+        rename = "ServiceSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_subset: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(
+        rename = "Datacenter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datacenter: Option<String>,
+    #[serde(rename = "Peer", default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    #[serde(
+        rename = "SamenessGroup",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sameness_group: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceResolverFailoverTarget {
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(
+        rename = "ServiceSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_subset: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(
+        rename = "Datacenter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datacenter: Option<String>,
+    #[serde(rename = "Peer", default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceResolverFailover {
+    #[serde(rename = "Service", default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(
+        rename = "ServiceSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_subset: Option<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Datacenters", default, skip_serializing_if = "Vec::is_empty")]
+    pub datacenters: Vec<String>,
+    #[serde(rename = "Targets", default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ServiceResolverFailoverTarget>,
+    #[serde(
+        rename = "SamenessGroup",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sameness_group: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceResolverConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(
+        rename = "DefaultSubset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub default_subset: Option<String>,
+    #[serde(rename = "Subsets", default, skip_serializing_if = "HashMap::is_empty")]
+    pub subsets: HashMap<String, ServiceResolverSubset>,
+    #[serde(rename = "Redirect", default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<ServiceResolverRedirect>,
+    #[serde(
+        rename = "Failover",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub failover: HashMap<String, ServiceResolverFailover>,
+    #[serde(
+        rename = "ConnectTimeout",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "go_duration::option"
+    )]
+    pub connect_timeout: Option<Duration>,
+    #[serde(
+        rename = "RequestTimeout",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "go_duration::option"
+    )]
+    pub request_timeout: Option<Duration>,
+    #[serde(
+        rename = "LoadBalancer",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub load_balancer: Option<Value>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct GatewayTlsConfig {
+    #[serde(rename = "Enabled", default)]
+    pub enabled: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct IngressService {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Hosts", default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct IngressListener {
+    #[serde(rename = "Port")]
+    pub port: u16,
+    #[serde(rename = "Protocol", default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(rename = "Services", default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<IngressService>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct IngressGatewayConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "TLS", default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<GatewayTlsConfig>,
+    #[serde(rename = "Listeners", default, skip_serializing_if = "Vec::is_empty")]
+    pub listeners: Vec<IngressListener>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct LinkedService {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "CAFile", default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    #[serde(rename = "CertFile", default, skip_serializing_if = "Option::is_none")]
+    pub cert_file: Option<String>,
+    #[serde(rename = "KeyFile", default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+    #[serde(rename = "SNI", default, skip_serializing_if = "Option::is_none")]
+    pub sni: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct TerminatingGatewayConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Services", default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<LinkedService>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct TransparentProxyMeshConfig {
+    #[serde(rename = "MeshDestinationsOnly", default)]
+    pub mesh_destinations_only: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct MeshConfigEntry {
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(
+        rename = "TransparentProxy",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub transparent_proxy: Option<TransparentProxyMeshConfig>,
+    #[serde(rename = "AllowEnablingPermissiveMutualTLS", default)]
+    pub allow_enabling_permissive_mutual_tls: bool,
+    #[serde(rename = "TLS", default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Value>,
+    #[serde(rename = "HTTP", default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<Value>,
+    #[serde(rename = "Peering", default, skip_serializing_if = "Option::is_none")]
+    pub peering: Option<Value>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ServiceConsumer {
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Peer", default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    #[serde(
+        rename = "SamenessGroup",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sameness_group: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ExportedService {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Namespace", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "Consumers", default, skip_serializing_if = "Vec::is_empty")]
+    pub consumers: Vec<ServiceConsumer>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ExportedServicesConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Services", default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ExportedService>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct SamenessGroupMember {
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "Peer", default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct SamenessGroupConfigEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Partition", default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(rename = "DefaultForFailover", default)]
+    pub default_for_failover: bool,
+    #[serde(rename = "IncludeLocal", default)]
+    pub include_local: bool,
+    #[serde(rename = "Members", default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<SamenessGroupMember>,
+    #[serde(rename = "Meta", default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default, skip_serializing)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default, skip_serializing)]
+    pub modify_index: u64,
+}
+
+/// Config entry operations.
+///
+/// This type can be used to interact with the "Config" portion of the Consul API, which manages
+/// centralized configuration entries such as service defaults, proxy defaults, and the various
+/// service mesh routing/splitting/resolving entries.
+#[derive(Clone, Debug)]
+pub struct ConfigEntries {
+    http_client: Arc<HttpClient>,
+}
+
+impl ConfigEntries {
+    /// Creates a new [`ConfigEntries`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> ConfigEntries {
+        ConfigEntries { http_client }
+    }
+
+    /// Applies the given config entry, creating or updating it.
+    ///
+    /// If `cas` is specified, the entry will only be applied if its current modify index matches
+    /// the given value, such as the [`modify_index`](ConfigEntry::modify_index) of the entry as it
+    /// was read.  A value of `0` will only apply the entry if it does not already exist.
+    ///
+    /// Returns whether or not the entry was applied.
+    pub async fn apply(
+        &self,
+        entry: &ConfigEntry,
+        cas: Option<u64>,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let mut params = Vec::new();
+        if let Some(cas) = cas {
+            params.push(("cas", Cow::from(cas.to_string())));
+        }
+
+        let request = self.http_client.build_request_with_params(
+            "PUT",
+            &["v1", "config"],
+            params,
+            options.as_ref(),
+            entry,
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let applied = self.http_client.parse_write_response(response).await?;
+        Ok(applied)
+    }
+
+    /// Gets the config entry of the given kind and name.
+    pub async fn get(
+        &self,
+        kind: &str,
+        name: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(ConfigEntry, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "config", kind, name],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Lists all config entries of the given kind.
+    pub async fn list(
+        &self,
+        kind: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<ConfigEntry>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request =
+            self.http_client
+                .build_request("GET", &["v1", "config", kind], options.as_ref(), ())?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Deletes the config entry of the given kind and name.
+    pub async fn delete(
+        &self,
+        kind: &str,
+        name: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        let request = self.http_client.build_request(
+            "DELETE",
+            &["v1", "config", kind, name],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_empty_response(response).await?;
+        Ok(())
+    }
+
+    /// Deletes the config entry of the given kind and name, if its current modify index matches
+    /// the given value.
+    ///
+    /// Returns whether or not the entry was deleted.
+    pub async fn delete_cas(
+        &self,
+        kind: &str,
+        name: &str,
+        cas: u64,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let request = self.http_client.build_request_with_params(
+            "DELETE",
+            &["v1", "config", kind, name],
+            vec![("cas", Cow::from(cas.to_string()))],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let deleted = self.http_client.parse_write_response(response).await?;
+        Ok(deleted)
+    }
+}
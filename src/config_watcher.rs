@@ -0,0 +1,255 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+use crate::common::QueryOptions;
+use crate::errors::Error;
+use crate::kv::{Kv, KvPair};
+use crate::runtime::spawn;
+use crate::watch::{restarting, Backoff, RestartEvent, Watch, DEFAULT_RESTART_DELAY};
+use crate::Client;
+
+type Decoder = Arc<dyn Fn(&[u8]) -> Result<Value, String> + Send + Sync>;
+type Validator<T> = Arc<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+#[derive(Clone, Debug)]
+enum Source {
+    Key(String),
+    Prefix(String),
+}
+
+/// Watches configuration stored in the KV store, keeping a typed copy of it up-to-date.
+///
+/// The configuration can either be stored as a single value under one key, or spread across all
+/// of the keys under a prefix, in which case the keys are assembled into nested objects based on
+/// their path relative to the prefix.  For example, with a prefix of `app/`, the keys
+/// `app/http/port` and `app/name` are assembled into `{"http": {"port": ...}, "name": ...}`.
+///
+/// Values are decoded as JSON by default, with values that are not valid JSON treated as strings.
+/// Other formats, such as YAML, can be supported via [`decoder`](ConfigWatcher::decoder).
+///
+/// Whenever the configuration changes, it is decoded, deserialized, and validated, and only
+/// published if all of those succeed.  Otherwise, the previous configuration is kept.
+pub struct ConfigWatcher<T> {
+    kv: Kv,
+    source: Source,
+    options: Option<QueryOptions>,
+    decoder: Decoder,
+    validator: Option<Validator<T>>,
+    _config: PhantomData<fn() -> T>,
+}
+
+impl<T> ConfigWatcher<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    /// Creates a new [`ConfigWatcher`] for the configuration stored under the given key.
+    pub fn key(client: &Client, key: &str) -> ConfigWatcher<T> {
+        ConfigWatcher::new(client, Source::Key(key.to_string()))
+    }
+
+    /// Creates a new [`ConfigWatcher`] for the configuration stored across the keys under the given
+    /// prefix.
+    pub fn prefix(client: &Client, prefix: &str) -> ConfigWatcher<T> {
+        ConfigWatcher::new(client, Source::Prefix(prefix.to_string()))
+    }
+
+    fn new(client: &Client, source: Source) -> ConfigWatcher<T> {
+        ConfigWatcher {
+            kv: client.kv(),
+            source,
+            options: None,
+            decoder: Arc::new(decode_json),
+            validator: None,
+            _config: PhantomData,
+        }
+    }
+
+    /// Sets the options to use for the underlying queries.
+    pub fn options(mut self, options: QueryOptions) -> ConfigWatcher<T> {
+        self.options = Some(options);
+        self
+    }
+
+    /// Sets the decoder used to decode each value.
+    ///
+    /// Values are decoded into a generic [`Value`], which is then deserialized into the
+    /// configuration type.  Any format with a serde implementation can be used, such as YAML via
+    /// `serde_yaml::from_slice`.
+    pub fn decoder<F>(mut self, decoder: F) -> ConfigWatcher<T>
+    where
+        F: Fn(&[u8]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.decoder = Arc::new(decoder);
+        self
+    }
+
+    /// Sets a validator which the configuration must pass before it is published.
+    pub fn validate<F>(mut self, validator: F) -> ConfigWatcher<T>
+    where
+        F: Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Starts watching the configuration, publishing it into a [`watch`] channel.
+    ///
+    /// The initial configuration is loaded before this method returns, and an error is returned if
+    /// it does not exist or is invalid.  Afterwards, a background task keeps the configuration
+    /// up-to-date, restarting the underlying watch if it fails, until all receivers are dropped or
+    /// the client is shut down.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub async fn start(self) -> Result<watch::Receiver<Arc<T>>, Error> {
+        let this = Arc::new(self);
+        let start = {
+            let this = this.clone();
+            move |options| this.watch(options)
+        };
+        let http_client = this.kv.http_client().clone();
+        let backoff = Backoff::fixed(DEFAULT_RESTART_DELAY);
+        let mut events = Box::pin(restarting(
+            http_client,
+            this.options.clone(),
+            backoff,
+            start,
+        ));
+        let initial = match events.next().await {
+            Some(RestartEvent::Update(pairs, _)) => this.load(pairs)?,
+            Some(RestartEvent::Failed(e, _)) => return Err(e),
+            None => return Err(Error::WatchStopped),
+        };
+
+        let (mut tx, rx) = watch::channel(Arc::new(initial));
+        spawn(async move {
+            loop {
+                let next = match future::select(events.next(), Box::pin(tx.closed())).await {
+                    Either::Left((next, _)) => next,
+                    Either::Right(_) => break,
+                };
+                match next {
+                    Some(RestartEvent::Update(pairs, _)) => match this.load(pairs) {
+                        Ok(config) => {
+                            if tx.broadcast(Arc::new(config)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_e) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::warn!(error = %_e, "ignoring invalid configuration");
+                        }
+                    },
+                    Some(RestartEvent::Failed(..)) => {}
+                    // Watches end once the client is shut down.
+                    None => break,
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    fn watch(&self, options: Option<QueryOptions>) -> Watch<Vec<KvPair>> {
+        match &self.source {
+            Source::Key(key) => {
+                let options = self.kv.http_client().watch_options(options);
+                let kv = self.kv.clone();
+                let key = key.clone();
+                Watch::new(options, move |options| {
+                    let kv = kv.clone();
+                    let key = key.clone();
+                    async move {
+                        let (pair, meta) = kv.get(&key, Some(options)).await?;
+                        Ok((pair.into_iter().collect(), meta))
+                    }
+                })
+                .tracked(self.kv.http_client().stats())
+            }
+            Source::Prefix(prefix) => self.kv.watch_prefix(prefix, options),
+        }
+    }
+
+    /// Decodes, deserializes, and validates the configuration from the given key/value pairs.
+    fn load(&self, pairs: Vec<KvPair>) -> Result<T, Error> {
+        let value = match &self.source {
+            Source::Key(key) => {
+                let pair = pairs
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| Error::InvalidConfigValue(format!("key '{}' not found", key)))?;
+                self.decode(&pair)?
+            }
+            Source::Prefix(prefix) => {
+                let mut root = Map::new();
+                for pair in pairs {
+                    let relative = pair.key.strip_prefix(prefix.as_str()).unwrap_or(&pair.key);
+                    let parts = relative
+                        .split('/')
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>();
+                    // Keys ending in a slash are conventionally used as folders, and have no value.
+                    if parts.is_empty() || pair.key.ends_with('/') {
+                        continue;
+                    }
+                    let value = self.decode(&pair)?;
+                    insert_path(&mut root, &parts, value);
+                }
+                Value::Object(root)
+            }
+        };
+
+        let config = serde_json::from_value::<T>(value)
+            .map_err(|e| Error::InvalidConfigValue(e.to_string()))?;
+        if let Some(validator) = self.validator.as_ref() {
+            validator(&config).map_err(Error::InvalidConfigValue)?;
+        }
+        Ok(config)
+    }
+
+    fn decode(&self, pair: &KvPair) -> Result<Value, Error> {
+        let raw = pair.value.as_deref().unwrap_or_default();
+        (self.decoder)(raw).map_err(|e| {
+            Error::InvalidConfigValue(format!("failed to decode '{}': {}", pair.key, e))
+        })
+    }
+}
+
+impl<T> fmt::Debug for ConfigWatcher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigWatcher")
+            .field("source", &self.source)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+/// Decodes a value as JSON, treating values which are not valid JSON as strings.
+fn decode_json(raw: &[u8]) -> Result<Value, String> {
+    match serde_json::from_slice(raw) {
+        Ok(value) => Ok(value),
+        Err(_) => Ok(Value::String(String::from_utf8_lossy(raw).into_owned())),
+    }
+}
+
+/// Inserts the given value into nested objects based on the given path.
+fn insert_path(root: &mut Map<String, Value>, path: &[&str], value: Value) {
+    let (last, parents) = path.split_last().expect("path must not be empty");
+    let mut current = root;
+    for part in parents {
+        let entry = current
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !entry.is_object() {
+            *entry = Value::Object(Map::new());
+        }
+        current = entry.as_object_mut().expect("entry must be an object");
+    }
+    current.insert(last.to_string(), value);
+}
@@ -0,0 +1,317 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::{QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::payload;
+
+/// The action taken by an intention for connections which it matches.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum IntentionAction {
+    #[serde(rename = "allow")]
+    Allow,
+    #[serde(rename = "deny")]
+    Deny,
+}
+
+/// An intention, controlling which services may connect to which other services.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Intention {
+    #[serde(rename = "ID", default)]
+    pub id: String,
+    #[serde(rename = "Description", default)]
+    pub description: String,
+    #[serde(rename = "SourceNS", default)]
+    pub source_namespace: Option<String>,
+    #[serde(rename = "SourceName")]
+    pub source_name: String,
+    #[serde(rename = "SourcePartition", default)]
+    pub source_partition: Option<String>,
+    #[serde(rename = "SourcePeer", default)]
+    pub source_peer: Option<String>,
+    #[serde(rename = "DestinationNS", default)]
+    pub destination_namespace: Option<String>,
+    #[serde(rename = "DestinationName")]
+    pub destination_name: String,
+    #[serde(rename = "DestinationPartition", default)]
+    pub destination_partition: Option<String>,
+    #[serde(rename = "SourceType", default)]
+    pub source_type: String,
+    /// The action of the intention, if it is not based on L7 permissions.
+    #[serde(rename = "Action", default)]
+    pub action: Option<IntentionAction>,
+    /// The L7 permissions of the intention, if it is not based on a single action.
+    #[serde(rename = "Permissions", default)]
+    pub permissions: Vec<Value>,
+    /// The precedence of the intention, where intentions with a higher precedence are matched
+    /// first.
+    #[serde(rename = "Precedence", default)]
+    pub precedence: i64,
+    #[serde(rename = "Meta", default)]
+    pub meta: HashMap<String, String>,
+    #[serde(rename = "CreateIndex", default)]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex", default)]
+    pub modify_index: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Connect operations.
+///
+/// This type can be used to interact with the "Connect" portion of the Consul API, such as
+/// intentions.
+#[derive(Clone, Debug)]
+pub struct Connect {
+    http_client: Arc<HttpClient>,
+}
+
+impl Connect {
+    /// Creates a new [`Connect`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Connect {
+        Connect { http_client }
+    }
+
+    /// Gets all intentions.
+    ///
+    /// Large sets of intentions can be narrowed down on the server with
+    /// [`QueryOptions::filtering`], with selectors such as `SourceName`, `DestinationName` and
+    /// `Meta.<key>`, and scoped with [`QueryOptions::namespace`] and [`QueryOptions::partition`].
+    pub async fn list_intentions(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<Intention>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "connect", "intentions"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets the intentions which apply to connections to the specified service.
+    ///
+    /// This includes intentions with a wildcard destination, and the intentions are sorted by
+    /// precedence, highest first, which is the order in which they are evaluated.
+    pub async fn intentions_for_destination(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<Intention>, QueryMetadata), Error> {
+        self.match_intentions("destination", service, options).await
+    }
+
+    /// Gets the intentions which apply to connections from the specified service.
+    ///
+    /// This includes intentions with a wildcard source, and the intentions are sorted by
+    /// precedence, highest first, which is the order in which they are evaluated.
+    pub async fn intentions_for_source(
+        &self,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<Intention>, QueryMetadata), Error> {
+        self.match_intentions("source", service, options).await
+    }
+
+    /// Gets the intention with exactly the specified source and destination services.
+    ///
+    /// Returns `None` if there is no such intention.
+    pub async fn get_intention_exact(
+        &self,
+        source: &str,
+        destination: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<Intention>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request_with_params(
+            "GET",
+            &["v1", "connect", "intentions", "exact"],
+            vec![
+                ("source", Cow::Owned(source.to_string())),
+                ("destination", Cow::Owned(destination.to_string())),
+            ],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self
+            .http_client
+            .parse_optional_query_response(response)
+            .await?;
+        Ok((parsed, meta))
+    }
+
+    async fn match_intentions(
+        &self,
+        by: &'static str,
+        service: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<Intention>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request_with_params(
+            "GET",
+            &["v1", "connect", "intentions", "match"],
+            vec![
+                ("by", Cow::Borrowed(by)),
+                ("name", Cow::Owned(service.to_string())),
+            ],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        // The matches are keyed by the names that were asked for, which is only ever one here.
+        let (mut matches, meta) = self
+            .http_client
+            .parse_query_response::<HashMap<String, Vec<Intention>>>(response)
+            .await?;
+        Ok((matches.remove(service).unwrap_or_default(), meta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use hyper::{Body, Response, StatusCode};
+    use url::Url;
+
+    use crate::endpoints::Endpoints;
+    use crate::http_client::{RequestDefaults, Transport};
+    use crate::interceptor::Interceptors;
+
+    /// Creates a client which answers every request with the given response, and the URIs of
+    /// the requests it was sent.
+    fn responding_client(
+        status: StatusCode,
+        body: &'static str,
+    ) -> (Connect, Arc<Mutex<Vec<String>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let captured = sent.clone();
+        let transport = Transport::from_fn(move |request| {
+            captured.lock().unwrap().push(request.uri().to_string());
+            Box::pin(async move {
+                Ok(Response::builder()
+                    .status(status)
+                    .header("X-Consul-Index", "7")
+                    .body(Body::from(body))
+                    .unwrap())
+            })
+        });
+        let endpoints = Endpoints::new(
+            vec![Url::parse("http://127.0.0.1:8500").unwrap()],
+            Duration::from_secs(30),
+        );
+        let client = HttpClient::new(
+            endpoints,
+            transport,
+            RequestDefaults::default(),
+            Interceptors::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        (Connect::new(Arc::new(client)), sent)
+    }
+
+    const MATCHES: &str = r#"{
+        "web": [
+            { "SourceName": "api", "DestinationName": "web", "Action": "allow", "Precedence": 9 },
+            { "SourceName": "*", "DestinationName": "web", "Action": "deny", "Precedence": 8 },
+            { "SourceName": "*", "DestinationName": "*", "Permissions": [{ "Action": "allow" }], "Precedence": 5 }
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn matches_intentions_by_destination() {
+        let (connect, sent) = responding_client(StatusCode::OK, MATCHES);
+        let (intentions, meta) = connect
+            .intentions_for_destination("web", None)
+            .await
+            .unwrap();
+
+        let uri = sent.lock().unwrap().remove(0);
+        assert!(uri.contains("/v1/connect/intentions/match?"), "{}", uri);
+        assert!(uri.contains("by=destination&name=web"), "{}", uri);
+        assert_eq!(meta.last_index, Some(7));
+
+        let sources = intentions
+            .iter()
+            .map(|intention| intention.source_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sources, vec!["api", "*", "*"]);
+        assert_eq!(intentions[0].action, Some(IntentionAction::Allow));
+        assert_eq!(intentions[1].action, Some(IntentionAction::Deny));
+        assert_eq!(intentions[2].action, None);
+        assert_eq!(intentions[2].permissions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn matches_intentions_by_source() {
+        let (connect, sent) = responding_client(StatusCode::OK, r#"{ "api": [] }"#);
+        let (intentions, _) = connect.intentions_for_source("api", None).await.unwrap();
+        assert!(intentions.is_empty());
+
+        let uri = sent.lock().unwrap().remove(0);
+        assert!(uri.contains("by=source&name=api"), "{}", uri);
+    }
+
+    #[tokio::test]
+    async fn matches_nothing_for_services_missing_from_the_response() {
+        let (connect, _) = responding_client(StatusCode::OK, MATCHES);
+        let (intentions, _) = connect
+            .intentions_for_destination("db", None)
+            .await
+            .unwrap();
+        assert!(intentions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn gets_exact_intentions() {
+        let (connect, sent) = responding_client(
+            StatusCode::OK,
+            r#"{ "ID": "abc", "SourceName": "api", "DestinationName": "web", "Action": "allow" }"#,
+        );
+        let (intention, _) = connect
+            .get_intention_exact("api", "web", None)
+            .await
+            .unwrap();
+        assert_eq!(intention.unwrap().id, "abc");
+
+        let uri = sent.lock().unwrap().remove(0);
+        assert!(
+            uri.contains("/v1/connect/intentions/exact?source=api&destination=web"),
+            "{}",
+            uri
+        );
+
+        let (connect, _) = responding_client(StatusCode::NOT_FOUND, "");
+        let (intention, _) = connect
+            .get_intention_exact("api", "web", None)
+            .await
+            .unwrap();
+        assert_eq!(intention, None);
+    }
+}
@@ -0,0 +1,358 @@
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::client::connect::dns::{GaiResolver, Name};
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use hyper_tls::MaybeHttpsStream;
+use native_tls::{Certificate, Identity, TlsConnector as NativeTlsConnector};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tls::TlsConnector;
+
+use crate::errors::Error;
+use crate::http_client::ConnectionSettings;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// TLS configuration for connecting to Consul over HTTPS.
+///
+/// By default, the system's trust store is used to verify the server certificate, and no client
+/// certificate is presented.  Deployments using a private CA, or with `verify_incoming` enabled,
+/// will need to configure the CA and/or client identity accordingly.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// PEM-encoded CA certificate(s) used to verify the server certificate.
+    ///
+    /// These are used in addition to the system's trust store.
+    pub ca_cert_pem: Option<Vec<u8>>,
+    /// PEM-encoded client certificate to present to the server, for mutual TLS.
+    ///
+    /// Must be set along with `client_key_pem`.
+    pub client_cert_pem: Option<Vec<u8>>,
+    /// PEM-encoded PKCS #8 private key for the client certificate, for mutual TLS.
+    ///
+    /// Must be set along with `client_cert_pem`.
+    pub client_key_pem: Option<Vec<u8>>,
+    /// Server name to use for SNI and certificate verification.
+    ///
+    /// By default, the host of the base URI is used.  This is useful when connecting to Consul by
+    /// IP address, or via a name which differs from the one in the server certificate, such as
+    /// `server.dc1.consul`.
+    pub server_name: Option<String>,
+    /// Disables verification of the server certificate.
+    ///
+    /// This is dangerous, and should only be used for testing.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    fn build_connector(&self) -> Result<NativeTlsConnector, Error> {
+        let mut builder = NativeTlsConnector::builder();
+
+        if let Some(ca_cert_pem) = self.ca_cert_pem.as_ref() {
+            for cert in Certificate::stack_from_pem(ca_cert_pem).map_err(Error::InvalidTlsConfig)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+
+        match (self.client_cert_pem.as_ref(), self.client_key_pem.as_ref()) {
+            (Some(cert), Some(key)) => {
+                let identity = Identity::from_pkcs8(cert, key).map_err(Error::InvalidTlsConfig)?;
+                builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err(Error::IncompleteClientIdentity),
+        }
+
+        if self.insecure_skip_verify {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+
+        builder.build().map_err(Error::InvalidTlsConfig)
+    }
+}
+
+/// Which IP address families to connect to Consul over, when its host resolves to both IPv4 and
+/// IPv6 addresses.
+///
+/// Connections are attempted per [RFC 8305] ("Happy Eyeballs"): addresses of the preferred family
+/// are tried first, and if no connection has been established after the
+/// [Happy Eyeballs timeout](crate::ClientBuilder::happy_eyeballs_timeout), addresses of the other
+/// family are tried at the same time, with whichever connects first being used.  This avoids
+/// hanging until the connect timeout on a family which is broken on dual-stack hosts.
+///
+/// [RFC 8305]: https://tools.ietf.org/html/rfc8305
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Prefers the family of the first address returned by the resolver, which is usually IPv6 on
+    /// hosts with IPv6 connectivity.
+    #[default]
+    Resolver,
+    /// Prefers IPv4, falling back to IPv6.
+    PreferIpv4,
+    /// Prefers IPv6, falling back to IPv4.
+    PreferIpv6,
+    /// Only connects over IPv4.
+    Ipv4Only,
+    /// Only connects over IPv6.
+    Ipv6Only,
+}
+
+/// Resolves host names with the system resolver, ordering and filtering the addresses based on the
+/// configured [`IpPreference`].
+#[derive(Clone, Debug)]
+pub(crate) struct Resolver {
+    inner: GaiResolver,
+    preference: IpPreference,
+}
+
+impl Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<IpAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolving = self.inner.call(name);
+        let preference = self.preference;
+
+        Box::pin(async move {
+            let mut addrs = resolving.await?.collect::<Vec<_>>();
+            match preference {
+                IpPreference::Resolver => {}
+                // Sorting is stable, so the order within each family is kept.
+                IpPreference::PreferIpv4 => addrs.sort_by_key(IpAddr::is_ipv6),
+                IpPreference::PreferIpv6 => addrs.sort_by_key(IpAddr::is_ipv4),
+                IpPreference::Ipv4Only => addrs.retain(IpAddr::is_ipv4),
+                IpPreference::Ipv6Only => addrs.retain(IpAddr::is_ipv6),
+            }
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no addresses of an allowed IP family",
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+/// Connector used by [`HttpClient`](crate::http_client::HttpClient) to establish connections.
+///
+/// Supports both plain HTTP and HTTPS, using the configured TLS settings for the latter.  If a Unix
+/// socket path is configured, all connections are made to that socket instead, regardless of the
+/// request URI.
+#[derive(Clone)]
+pub(crate) struct Connector {
+    http: HttpConnector<Resolver>,
+    tls: TlsConnector,
+    server_name: Option<String>,
+    unix_socket: Option<PathBuf>,
+}
+
+impl Connector {
+    /// Creates a new [`Connector`] that connects to the given Unix socket.
+    pub fn unix(path: PathBuf) -> Result<Connector, Error> {
+        let mut connector = Connector::new(None, &ConnectionSettings::default())?;
+        connector.unix_socket = Some(path);
+        Ok(connector)
+    }
+
+    /// Creates a new [`Connector`].
+    pub fn new(
+        tls_config: Option<&TlsConfig>,
+        settings: &ConnectionSettings,
+    ) -> Result<Connector, Error> {
+        let resolver = Resolver {
+            inner: GaiResolver::new(),
+            preference: settings.ip_preference,
+        };
+        let mut http = HttpConnector::new_with_resolver(resolver);
+        http.enforce_http(false);
+        http.set_connect_timeout(settings.connect_timeout);
+        http.set_keepalive(settings.tcp_keepalive);
+        if let Some(timeout) = settings.happy_eyeballs_timeout {
+            http.set_happy_eyeballs_timeout(Some(timeout));
+        }
+
+        let default_config = TlsConfig::default();
+        let tls_config = tls_config.unwrap_or(&default_config);
+        let tls = tls_config.build_connector()?.into();
+
+        Ok(Connector {
+            http,
+            tls,
+            server_name: tls_config.server_name.clone(),
+            unix_socket: None,
+        })
+    }
+}
+
+impl std::fmt::Debug for Connector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connector")
+            .field("http", &self.http)
+            .field("server_name", &self.server_name)
+            .field("unix_socket", &self.unix_socket)
+            .finish()
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        if let Some(path) = self.unix_socket.clone() {
+            return Box::pin(
+                async move { Ok(Stream::Unix(unix::UnixStream::connect(&path).await?)) },
+            );
+        }
+
+        let is_https = dst.scheme_str() == Some("https");
+        let host = self.server_name.clone().unwrap_or_else(|| {
+            dst.host()
+                .unwrap_or("")
+                .trim_matches(|c| c == '[' || c == ']')
+                .to_string()
+        });
+        let connecting = self.http.call(dst);
+        let tls = self.tls.clone();
+
+        Box::pin(async move {
+            let tcp = connecting.await?;
+            if is_https {
+                let stream = tls.connect(&host, tcp).await?;
+                Ok(Stream::Tcp(MaybeHttpsStream::Https(stream)))
+            } else {
+                Ok(Stream::Tcp(MaybeHttpsStream::Http(tcp)))
+            }
+        })
+    }
+}
+
+/// A connection to Consul, either over TCP (with or without TLS) or over a Unix socket.
+pub(crate) enum Stream {
+    Tcp(MaybeHttpsStream<TcpStream>),
+    Unix(unix::UnixStream),
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Stream::Tcp(stream) => stream.connected(),
+            Stream::Unix(_) => Connected::new(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    pub(crate) use tokio::net::UnixStream;
+}
+
+#[cfg(not(unix))]
+mod unix {
+    use std::io;
+    use std::path::Path;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::{AsyncRead, AsyncWrite};
+
+    /// Placeholder for platforms without Unix socket support.
+    pub(crate) enum UnixStream {}
+
+    impl UnixStream {
+        pub async fn connect(_path: &Path) -> io::Result<UnixStream> {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Unix sockets are not supported on this platform",
+            ))
+        }
+    }
+
+    impl AsyncRead for UnixStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            match *self {}
+        }
+    }
+
+    impl AsyncWrite for UnixStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            match *self {}
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match *self {}
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match *self {}
+        }
+    }
+}
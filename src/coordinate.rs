@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::catalog::{CatalogNode, CatalogServiceNode};
+use crate::common::{QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::health::ServiceEntry;
+use crate::http_client::HttpClient;
+use crate::payload;
+
+/// A network coordinate, as computed by Serf, from which the round trip time between two nodes
+/// can be estimated.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct NetworkCoordinate {
+    #[serde(rename = "Vec", default)]
+    pub vec: Vec<f64>,
+    #[serde(rename = "Error", default)]
+    pub error: f64,
+    #[serde(rename = "Adjustment", default)]
+    pub adjustment: f64,
+    #[serde(rename = "Height", default)]
+    pub height: f64,
+}
+
+impl NetworkCoordinate {
+    /// Estimates the round trip time between this coordinate and the given one.
+    ///
+    /// Returns `None` if the coordinates are not comparable, such as when they come from
+    /// coordinate systems with a different number of dimensions.
+    pub fn rtt(&self, other: &NetworkCoordinate) -> Option<Duration> {
+        if self.vec.len() != other.vec.len() {
+            return None;
+        }
+
+        let distance = self
+            .vec
+            .iter()
+            .zip(&other.vec)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+            + self.height
+            + other.height;
+        // Adjustments only ever correct the estimate, so they are ignored if they would make it
+        // nonsensical, which matches how Serf computes it.
+        let adjusted = distance + self.adjustment + other.adjustment;
+        let seconds = if adjusted > 0.0 { adjusted } else { distance };
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+}
+
+/// The network coordinate of a node.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CoordinateEntry {
+    #[serde(rename = "Node")]
+    pub node: String,
+    /// The network segment of the node, as nodes only have comparable coordinates within a
+    /// segment.
+    #[serde(rename = "Segment", default)]
+    pub segment: String,
+    #[serde(rename = "Partition", default)]
+    pub partition: Option<String>,
+    #[serde(rename = "Coord")]
+    pub coord: NetworkCoordinate,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The network coordinates of the servers of a datacenter, in the WAN pool.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CoordinateDatacenterMap {
+    #[serde(rename = "Datacenter")]
+    pub datacenter: String,
+    #[serde(rename = "AreaID", default)]
+    pub area_id: String,
+    #[serde(rename = "Coordinates", default)]
+    pub coordinates: Vec<CoordinateEntry>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Results which are tied to a node, and so can be sorted by [`Coordinates::sort_by_rtt`].
+pub trait NodeName {
+    /// Gets the name of the node.
+    fn node_name(&self) -> &str;
+}
+
+impl NodeName for CatalogNode {
+    fn node_name(&self) -> &str {
+        &self.node
+    }
+}
+
+impl NodeName for CatalogServiceNode {
+    fn node_name(&self) -> &str {
+        &self.node
+    }
+}
+
+impl NodeName for ServiceEntry {
+    fn node_name(&self) -> &str {
+        &self.node.node
+    }
+}
+
+/// Coordinate operations.
+///
+/// This type can be used to interact with the "Coordinate" portion of the Consul API, which
+/// exposes the network coordinates used to estimate the round trip times between nodes.
+#[derive(Clone, Debug)]
+pub struct Coordinates {
+    http_client: Arc<HttpClient>,
+}
+
+impl Coordinates {
+    /// Creates a new [`Coordinates`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Coordinates {
+        Coordinates { http_client }
+    }
+
+    /// Gets the network coordinates of the servers of every datacenter, in the WAN pool.
+    pub async fn datacenters(&self) -> Result<Vec<CoordinateDatacenterMap>, Error> {
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "coordinate", "datacenters"],
+            None::<QueryOptions>,
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, None::<QueryOptions>)
+            .await?;
+        let (parsed, _) = self.http_client.parse_list_query_response(response).await?;
+        Ok(parsed)
+    }
+
+    /// Gets the network coordinates of all nodes, in the LAN pool of the datacenter.
+    pub async fn nodes(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<CoordinateEntry>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "coordinate", "nodes"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Gets the network coordinates of the specified node, one for each network segment it is in.
+    ///
+    /// Returns an empty list if the node does not exist, or has no coordinates yet.
+    pub async fn node(
+        &self,
+        node: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<CoordinateEntry>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "coordinate", "node", node],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self
+            .http_client
+            .parse_optional_query_response::<Vec<CoordinateEntry>>(response)
+            .await?;
+        Ok((parsed.unwrap_or_default(), meta))
+    }
+
+    /// Sorts the given results by the estimated round trip time from the specified node, nearest
+    /// first.
+    ///
+    /// This does what [`QueryOptions::near`] does on the server, for when it can't be used, such as
+    /// with results that were merged from several queries or come from elsewhere.  The network
+    /// coordinates of all nodes are fetched with the given options, which should target the
+    /// datacenter the results come from, as coordinates are only comparable within a datacenter.
+    ///
+    /// Results on nodes without a coordinate are placed last, and the order of results with the
+    /// same round trip time is kept.  If the specified node has no coordinate, the results are
+    /// returned as they were.
+    pub async fn sort_by_rtt<T: NodeName>(
+        &self,
+        nodes: Vec<T>,
+        from_node: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<T>, Error> {
+        let (entries, _) = self.nodes(options).await?;
+        Ok(sort_by_coordinates(nodes, from_node, &entries))
+    }
+}
+
+/// Sorts the given results by the estimated round trip time from the specified node, based on the
+/// given coordinates, as described in [`Coordinates::sort_by_rtt`].
+fn sort_by_coordinates<T: NodeName>(
+    mut nodes: Vec<T>,
+    from_node: &str,
+    entries: &[CoordinateEntry],
+) -> Vec<T> {
+    let origin = match entries.iter().find(|entry| entry.node == from_node) {
+        Some(origin) => origin,
+        None => return nodes,
+    };
+
+    // Nodes can have a coordinate in each segment they are in, and only those in the same segment
+    // as the origin can be compared with it.
+    let mut rtts = HashMap::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.segment == origin.segment)
+    {
+        if let Some(rtt) = origin.coord.rtt(&entry.coord) {
+            rtts.insert(entry.node.as_str(), rtt);
+        }
+    }
+    nodes.sort_by_key(|node| match rtts.get(node.node_name()) {
+        Some(rtt) => (false, *rtt),
+        None => (true, Duration::default()),
+    });
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinate(vec: &[f64], height: f64, adjustment: f64) -> NetworkCoordinate {
+        NetworkCoordinate {
+            vec: vec.to_vec(),
+            error: 0.0,
+            adjustment,
+            height,
+        }
+    }
+
+    fn entry(node: &str, segment: &str, x: f64) -> CoordinateEntry {
+        CoordinateEntry {
+            node: node.to_string(),
+            segment: segment.to_string(),
+            partition: None,
+            coord: coordinate(&[x, 0.0], 0.0, 0.0),
+            extra: HashMap::new(),
+        }
+    }
+
+    fn nodes(names: &[&str]) -> Vec<CatalogNode> {
+        names
+            .iter()
+            .map(|name| CatalogNode {
+                node: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn names(nodes: &[CatalogNode]) -> Vec<&str> {
+        nodes.iter().map(|node| node.node.as_str()).collect()
+    }
+
+    #[test]
+    fn rtt_is_the_distance_plus_heights() {
+        let a = coordinate(&[0.0, 0.0], 0.001, 0.0);
+        let b = coordinate(&[0.003, 0.004], 0.002, 0.0);
+        let rtt = a.rtt(&b).unwrap();
+        assert!((rtt.as_secs_f64() - 0.008).abs() < 1e-9);
+        assert_eq!(a.rtt(&b), b.rtt(&a));
+    }
+
+    #[test]
+    fn rtt_applies_adjustments_unless_they_make_it_negative() {
+        let a = coordinate(&[0.0], 0.0, 0.002);
+        let b = coordinate(&[0.01], 0.0, -0.001);
+        assert!((a.rtt(&b).unwrap().as_secs_f64() - 0.011).abs() < 1e-9);
+
+        let c = coordinate(&[0.01], 0.0, -0.1);
+        assert!((a.rtt(&c).unwrap().as_secs_f64() - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rtt_of_coordinates_with_different_dimensions_is_unknown() {
+        let a = coordinate(&[0.0, 0.0], 0.0, 0.0);
+        let b = coordinate(&[0.0, 0.0, 0.0], 0.0, 0.0);
+        assert_eq!(a.rtt(&b), None);
+    }
+
+    #[test]
+    fn sorts_nearest_first_with_unknown_nodes_last() {
+        let entries = vec![
+            entry("origin", "", 0.0),
+            entry("far", "", 0.5),
+            entry("near", "", 0.1),
+            entry("mid", "", 0.2),
+        ];
+        let sorted = sort_by_coordinates(
+            nodes(&["unknown-1", "far", "mid", "unknown-2", "near", "origin"]),
+            "origin",
+            &entries,
+        );
+        assert_eq!(
+            names(&sorted),
+            vec!["origin", "near", "mid", "far", "unknown-1", "unknown-2"]
+        );
+    }
+
+    #[test]
+    fn only_compares_coordinates_in_the_segment_of_the_origin() {
+        let entries = vec![
+            entry("origin", "alpha", 0.0),
+            entry("other-segment", "beta", 0.01),
+            entry("same-segment", "alpha", 0.2),
+        ];
+        let sorted = sort_by_coordinates(
+            nodes(&["other-segment", "same-segment"]),
+            "origin",
+            &entries,
+        );
+        assert_eq!(names(&sorted), vec!["same-segment", "other-segment"]);
+    }
+
+    #[test]
+    fn keeps_the_order_without_a_coordinate_for_the_origin() {
+        let entries = vec![entry("far", "", 0.5), entry("near", "", 0.1)];
+        let sorted = sort_by_coordinates(nodes(&["far", "near"]), "origin", &entries);
+        assert_eq!(names(&sorted), vec!["far", "near"]);
+    }
+}
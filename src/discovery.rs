@@ -0,0 +1,39 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use url::Url;
+
+use crate::errors::Error;
+
+/// Boxed future returned by [`ServerDiscovery::discover`].
+pub type DiscoveryFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Url>, Error>> + Send + 'a>>;
+
+/// A source of Consul endpoints.
+///
+/// When configured on a client, discovery is run before the first request, and then again
+/// whenever the configured refresh interval has elapsed, with the client failing over between
+/// the discovered endpoints as it would for a static list of base URIs.
+pub trait ServerDiscovery: Send + Sync + 'static {
+    /// Discovers the base URIs of the currently available Consul endpoints.
+    fn discover(&self) -> DiscoveryFuture<'_>;
+}
+
+impl<F> ServerDiscovery for F
+where
+    F: Fn() -> Result<Vec<Url>, Error> + Send + Sync + 'static,
+{
+    fn discover(&self) -> DiscoveryFuture<'_> {
+        let result = self();
+        Box::pin(async move { result })
+    }
+}
+
+/// Wrapper to allow storing a [`ServerDiscovery`] in types which derive `Debug`.
+pub(crate) struct Discovery(pub Box<dyn ServerDiscovery>);
+
+impl fmt::Debug for Discovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Discovery")
+    }
+}
@@ -0,0 +1,369 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use hyper::Uri;
+use tokio::sync::Mutex as AsyncMutex;
+use url::{Position, Url};
+
+use crate::discovery::Discovery;
+use crate::errors::Error;
+use crate::runtime::Instant;
+
+/// Default amount of time that an endpoint is avoided after a failure.
+pub(crate) const DEFAULT_FAILOVER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Default amount of time between refreshes of discovered endpoints.
+pub(crate) const DEFAULT_DISCOVERY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Endpoint {
+    uri: Url,
+    failed_at: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn new(uri: Url) -> Endpoint {
+        Endpoint {
+            uri,
+            failed_at: Mutex::new(None),
+        }
+    }
+
+    fn is_healthy(&self, cooldown: Duration) -> bool {
+        match *self.failed_at.lock().expect("endpoint lock poisoned") {
+            Some(failed_at) => failed_at.elapsed() >= cooldown,
+            None => true,
+        }
+    }
+}
+
+/// The set of Consul endpoints a client can send requests to.
+///
+/// Requests are sent to the current endpoint until it fails, at which point the next healthy
+/// endpoint becomes current.  Failed endpoints are avoided until a cooldown period has elapsed,
+/// after which they are considered healthy again.
+///
+/// If configured with a discovery source, the endpoints are replaced with the discovered ones
+/// before the first request, and then periodically refreshed.
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    endpoints: RwLock<Vec<Endpoint>>,
+    current: AtomicUsize,
+    cooldown: Duration,
+    discovery: Option<Discovery>,
+    refresh_interval: Duration,
+    refreshed_at: AsyncMutex<Option<Instant>>,
+}
+
+impl Endpoints {
+    /// Creates a new [`Endpoints`].
+    ///
+    /// `uris` must not be empty.
+    pub fn new(uris: Vec<Url>, cooldown: Duration) -> Endpoints {
+        assert!(!uris.is_empty(), "at least one endpoint is required");
+
+        Endpoints {
+            endpoints: RwLock::new(uris.into_iter().map(Endpoint::new).collect()),
+            current: AtomicUsize::new(0),
+            cooldown,
+            discovery: None,
+            refresh_interval: DEFAULT_DISCOVERY_REFRESH_INTERVAL,
+            refreshed_at: AsyncMutex::new(None),
+        }
+    }
+
+    /// Sets the discovery source used to refresh the endpoints, and how often to refresh them.
+    pub fn with_discovery(mut self, discovery: Discovery, refresh_interval: Duration) -> Endpoints {
+        self.discovery = Some(discovery);
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Whether or not the endpoints are refreshed from a discovery source.
+    pub fn is_discovered(&self) -> bool {
+        self.discovery.is_some()
+    }
+
+    /// Refreshes the endpoints from the discovery source, if they are due to be refreshed.
+    ///
+    /// If discovery fails after having previously succeeded, the existing endpoints are kept and
+    /// the error is otherwise ignored, so that an unavailable DNS server does not also make
+    /// Consul unavailable.  If discovery has never succeeded, the error is returned.
+    pub async fn refresh_if_stale(&self) -> Result<(), Error> {
+        let discovery = match self.discovery.as_ref() {
+            Some(discovery) => discovery,
+            None => return Ok(()),
+        };
+
+        let mut refreshed_at = self.refreshed_at.lock().await;
+        if let Some(last) = *refreshed_at {
+            if last.elapsed() < self.refresh_interval {
+                return Ok(());
+            }
+        }
+
+        let result = discovery.0.discover().await.and_then(|uris| {
+            if uris.is_empty() {
+                Err(Error::InvalidEndpoints("no Consul endpoints discovered"))
+            } else {
+                Ok(uris)
+            }
+        });
+        match result {
+            Ok(uris) => {
+                self.replace(uris);
+                *refreshed_at = Some(Instant::now());
+                Ok(())
+            }
+            Err(e) if refreshed_at.is_some() => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %e, "failed to refresh Consul endpoints");
+                #[cfg(not(feature = "tracing"))]
+                let _ = e;
+
+                // Avoid retrying discovery on every request while it is failing.
+                *refreshed_at = Some(Instant::now());
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the endpoints with the given ones.
+    ///
+    /// Endpoints which are still present keep their failure state, and the current endpoint stays
+    /// current if it is still present.
+    fn replace(&self, uris: Vec<Url>) {
+        let mut endpoints = self.endpoints.write().expect("endpoints lock poisoned");
+        let current_uri = endpoints
+            .get(self.current.load(Ordering::Relaxed) % endpoints.len())
+            .map(|e| e.uri.clone());
+
+        let mut previous = std::mem::take(&mut *endpoints);
+        for uri in uris {
+            let endpoint = match previous.iter().position(|e| e.uri == uri) {
+                Some(idx) => previous.swap_remove(idx),
+                None => Endpoint::new(uri),
+            };
+            endpoints.push(endpoint);
+        }
+
+        let current = current_uri
+            .and_then(|uri| endpoints.iter().position(|e| e.uri == uri))
+            .unwrap_or(0);
+        self.current.store(current, Ordering::Relaxed);
+    }
+
+    /// Gets the number of endpoints.
+    pub fn len(&self) -> usize {
+        self.endpoints
+            .read()
+            .expect("endpoints lock poisoned")
+            .len()
+    }
+
+    /// Gets the URI of the current endpoint.
+    pub fn current(&self) -> Url {
+        let endpoints = self.endpoints.read().expect("endpoints lock poisoned");
+        let current = self.current.load(Ordering::Relaxed) % endpoints.len();
+        endpoints[current].uri.clone()
+    }
+
+    /// Gets the order in which endpoints should be attempted for a request.
+    ///
+    /// The current endpoint is first, followed by any other healthy endpoints, followed by the
+    /// endpoints which have recently failed, as a last resort.
+    pub fn attempt_order(&self) -> Vec<(usize, Url)> {
+        let endpoints = self.endpoints.read().expect("endpoints lock poisoned");
+        let current = self.current.load(Ordering::Relaxed) % endpoints.len();
+
+        let mut healthy = Vec::new();
+        let mut unhealthy = Vec::new();
+        for offset in 0..endpoints.len() {
+            let idx = (current + offset) % endpoints.len();
+            let endpoint = &endpoints[idx];
+            if offset == 0 || endpoint.is_healthy(self.cooldown) {
+                healthy.push((idx, endpoint.uri.clone()));
+            } else {
+                unhealthy.push((idx, endpoint.uri.clone()));
+            }
+        }
+
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Marks the given endpoint as having succeeded, making it the current endpoint.
+    pub fn mark_success(&self, idx: usize) {
+        let endpoints = self.endpoints.read().expect("endpoints lock poisoned");
+        if let Some(endpoint) = endpoints.get(idx) {
+            *endpoint.failed_at.lock().expect("endpoint lock poisoned") = None;
+            self.current.store(idx, Ordering::Relaxed);
+        }
+    }
+
+    /// Marks the given endpoint as having failed.
+    pub fn mark_failure(&self, idx: usize) {
+        let endpoints = self.endpoints.read().expect("endpoints lock poisoned");
+        if let Some(endpoint) = endpoints.get(idx) {
+            *endpoint.failed_at.lock().expect("endpoint lock poisoned") = Some(Instant::now());
+        }
+    }
+}
+
+/// Rewrites the given URI to point at the given endpoint.
+///
+/// Only the scheme and authority are changed, as request paths are relative to the API root.
+pub(crate) fn rewrite_uri(uri: &Uri, endpoint: &Url) -> Uri {
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    let rewritten = format!(
+        "{}://{}{}",
+        endpoint.scheme(),
+        &endpoint[Position::BeforeHost..Position::AfterPort],
+        path_and_query
+    );
+
+    rewritten.parse().unwrap_or_else(|_| uri.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn urls(hosts: &[&str]) -> Vec<Url> {
+        hosts
+            .iter()
+            .map(|host| Url::parse(&format!("http://{}:8500", host)).unwrap())
+            .collect()
+    }
+
+    fn order(endpoints: &Endpoints) -> Vec<usize> {
+        endpoints
+            .attempt_order()
+            .into_iter()
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    #[test]
+    fn attempts_endpoints_in_order_from_the_current_one() {
+        let endpoints = Endpoints::new(urls(&["a", "b", "c"]), DEFAULT_FAILOVER_COOLDOWN);
+        assert_eq!(order(&endpoints), vec![0, 1, 2]);
+        assert_eq!(endpoints.current(), urls(&["a"])[0]);
+
+        endpoints.mark_success(1);
+        assert_eq!(order(&endpoints), vec![1, 2, 0]);
+        assert_eq!(endpoints.current(), urls(&["b"])[0]);
+    }
+
+    #[test]
+    fn attempts_failed_endpoints_last() {
+        let endpoints = Endpoints::new(urls(&["a", "b", "c"]), DEFAULT_FAILOVER_COOLDOWN);
+        endpoints.mark_failure(1);
+        assert_eq!(order(&endpoints), vec![0, 2, 1]);
+
+        // The current endpoint is always attempted first, until another one succeeds.
+        endpoints.mark_failure(0);
+        assert_eq!(order(&endpoints), vec![0, 2, 1]);
+        endpoints.mark_success(2);
+        assert_eq!(order(&endpoints), vec![2, 0, 1]);
+
+        endpoints.mark_success(1);
+        assert_eq!(order(&endpoints), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn failed_endpoints_are_healthy_again_after_the_cooldown() {
+        let endpoints = Endpoints::new(urls(&["a", "b", "c"]), Duration::from_millis(20));
+        endpoints.mark_failure(1);
+        assert_eq!(order(&endpoints), vec![0, 2, 1]);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(order(&endpoints), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn ignores_unknown_endpoints() {
+        let endpoints = Endpoints::new(urls(&["a", "b"]), DEFAULT_FAILOVER_COOLDOWN);
+        endpoints.mark_failure(5);
+        endpoints.mark_success(5);
+        assert_eq!(order(&endpoints), vec![0, 1]);
+    }
+
+    #[test]
+    fn replacing_keeps_the_current_endpoint_and_failures() {
+        let endpoints = Endpoints::new(urls(&["a", "b", "c"]), DEFAULT_FAILOVER_COOLDOWN);
+        endpoints.mark_success(1);
+        endpoints.mark_failure(2);
+
+        endpoints.replace(urls(&["c", "d", "b"]));
+        assert_eq!(endpoints.len(), 3);
+        assert_eq!(endpoints.current(), urls(&["b"])[0]);
+        assert_eq!(order(&endpoints), vec![2, 1, 0]);
+
+        endpoints.replace(urls(&["e"]));
+        assert_eq!(endpoints.current(), urls(&["e"])[0]);
+    }
+
+    #[tokio::test]
+    async fn refreshes_from_discovery_and_keeps_endpoints_when_it_fails() {
+        let responses = Arc::new(Mutex::new(vec![
+            Err(Error::InvalidEndpoints("unavailable")),
+            Ok(urls(&["a", "b"])),
+            Ok(Vec::new()),
+        ]));
+        let discovery = {
+            let responses = responses.clone();
+            move || responses.lock().unwrap().remove(0)
+        };
+        let endpoints = Endpoints::new(urls(&["seed"]), DEFAULT_FAILOVER_COOLDOWN)
+            .with_discovery(Discovery(Box::new(discovery)), Duration::from_millis(0));
+        assert!(endpoints.is_discovered());
+
+        // Without any previously discovered endpoints, failures are returned.
+        assert!(endpoints.refresh_if_stale().await.is_err());
+        assert_eq!(endpoints.current(), urls(&["seed"])[0]);
+
+        endpoints.refresh_if_stale().await.unwrap();
+        assert_eq!(order(&endpoints), vec![0, 1]);
+        assert_eq!(endpoints.current(), urls(&["a"])[0]);
+
+        // Discovering nothing is a failure, which keeps the existing endpoints.
+        endpoints.refresh_if_stale().await.unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert!(responses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_refreshes_once_the_interval_has_elapsed() {
+        let discovery = || Ok(urls(&["a"]));
+        let endpoints = Endpoints::new(urls(&["seed"]), DEFAULT_FAILOVER_COOLDOWN)
+            .with_discovery(Discovery(Box::new(discovery)), Duration::from_secs(60));
+
+        endpoints.refresh_if_stale().await.unwrap();
+        assert_eq!(endpoints.current(), urls(&["a"])[0]);
+
+        endpoints.replace(urls(&["b"]));
+        endpoints.refresh_if_stale().await.unwrap();
+        assert_eq!(endpoints.current(), urls(&["b"])[0]);
+    }
+
+    #[test]
+    fn rewrites_the_scheme_and_authority_of_uris() {
+        let endpoint = Url::parse("https://consul.example.com:8501/").unwrap();
+        let uri: Uri = "http://localhost:8500/v1/kv/key?recurse=true"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rewrite_uri(&uri, &endpoint),
+            "https://consul.example.com:8501/v1/kv/key?recurse=true"
+        );
+
+        let endpoint = Url::parse("http://10.0.0.1:8500").unwrap();
+        let uri: Uri = "http://localhost:8500/".parse().unwrap();
+        assert_eq!(rewrite_uri(&uri, &endpoint), "http://10.0.0.1:8500/");
+    }
+}
@@ -1,20 +1,120 @@
 use http::Error as HttpError;
 use hyper::{Error as HyperError, StatusCode};
+#[cfg(feature = "native")]
+use native_tls::Error as TlsError;
 use serde_json::Error as JsonError;
+use std::fmt;
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error as ThisError;
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
 use tokio::time::Elapsed;
 use url::ParseError as UrlParseError;
 
-fn strs_to_str(strs: &Vec<&'static str>) -> String {
+fn strs_to_str(strs: &[&'static str]) -> String {
     strs.join(", ")
 }
 
+fn status_to_str(status: &StatusCode, body: &str) -> String {
+    if body.is_empty() {
+        status.to_string()
+    } else {
+        format!("{} ({})", status, body)
+    }
+}
+
+/// Error returned when a request does not complete before its timeout.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq, Eq, ThisError)]
+#[error("deadline has elapsed")]
+pub struct Elapsed(());
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Elapsed {
+    pub(crate) fn new() -> Elapsed {
+        Elapsed(())
+    }
+}
+
+/// Details of the request an [`Error`] occurred for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequestContext {
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The path of the request, without any query parameters.
+    pub path: String,
+    /// The datacenter the request was sent to, if one was specified.
+    pub datacenter: Option<String>,
+    /// Whether or not the request was a blocking query.
+    pub blocking: bool,
+    /// The correlation ID sent with the request, if any.
+    pub request_id: Option<String>,
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)?;
+
+        let mut details = Vec::new();
+        if let Some(dc) = self.datacenter.as_ref() {
+            details.push(format!("dc={}", dc));
+        }
+        if self.blocking {
+            details.push("blocking".to_string());
+        }
+        if let Some(request_id) = self.request_id.as_ref() {
+            details.push(format!("request_id={}", request_id));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 /// High-level error for all operations.
 #[derive(ThisError, Debug)]
 pub enum Error {
     /// The Consul endpoint given to configure a client was invalid.
     #[error("failed to parse Consul endpoint: {0:?}")]
     InvalidConsulEndpoint(#[from] UrlParseError),
+    /// The set of Consul endpoints given to configure a client was invalid.
+    #[error("invalid Consul endpoints: {0}")]
+    InvalidEndpoints(&'static str),
+    /// The TLS configuration given to configure a client was invalid.
+    #[cfg(feature = "native")]
+    #[error("invalid TLS configuration: {0}")]
+    InvalidTlsConfig(TlsError),
+    /// A file referenced by the client configuration could not be read.
+    #[error("failed to read configuration file {0:?}: {1}")]
+    InvalidConfigFile(PathBuf, IoError),
+    /// A recording of requests, for recording or replaying them, could not be accessed.
+    #[error("failed to access recording {0:?}: {1}")]
+    RecordingFailed(PathBuf, IoError),
+    /// The runtime of a blocking client could not be started.
+    #[error("failed to start runtime: {0}")]
+    RuntimeFailed(IoError),
+    /// A snapshot could not be read, or is not a valid snapshot.
+    #[error("failed to read snapshot: {0}")]
+    InvalidSnapshot(IoError),
+    /// No response was recorded for a request being replayed.
+    #[error("no recorded response for {0}")]
+    NoRecordedResponse(String),
+    /// Discovering the Consul endpoints to send requests to failed.
+    #[error("failed to discover Consul endpoints: {0}")]
+    DiscoveryFailed(IoError),
+    /// A configuration value could not be decoded, deserialized, or validated.
+    #[error("invalid configuration value: {0}")]
+    InvalidConfigValue(String),
+    /// The options given for an operation were invalid.
+    #[error("invalid options: {0}")]
+    InvalidOptions(&'static str),
+    /// Only one of the client certificate or the client key was given when configuring TLS.
+    #[cfg(feature = "native")]
+    #[error("client certificate and client key must be specified together")]
+    IncompleteClientIdentity,
     /// JSON serialization error during building a request.
     #[error("failed to serialize request body to JSON: {0:?}")]
     InvalidRequestBody(JsonError),
@@ -24,20 +124,182 @@ pub enum Error {
     /// Error occurred during the sending of a request to Consul.
     #[error("request error: {0}")]
     RequestError(#[from] HyperError),
+    /// Error occurred during the sending of a request to Consul via the Fetch API.
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    #[error("fetch error: {0}")]
+    FetchError(#[from] reqwest::Error),
+    /// No transport is available to send requests to Consul with.
+    ///
+    /// This happens when building a client without a custom hyper client while neither the
+    /// `native` nor the `wasm` feature is enabled for the target.
+    #[error(
+        "no transport available: enable the `native` or `wasm` feature, or set a hyper client"
+    )]
+    MissingTransport,
     /// Request timed out.
     #[error("request timed out: {0}")]
     RequestTimedOut(#[from] Elapsed),
+    /// A blocking query timed out before Consul responded.
+    ///
+    /// Blocking queries wait for changes, so this is generally benign, and the query can simply be
+    /// run again with the same blocking parameters.  Watches do this automatically.
+    #[error("blocking query wait expired before Consul responded")]
+    BlockingWaitExpired,
+    /// The request was rejected without being sent, as too many recent requests have failed.
+    #[error("circuit breaker open: too many recent requests to Consul have failed")]
+    CircuitOpen,
+    /// The request was rejected, or cancelled, as the client is shutting down.
+    #[error("client has been shut down")]
+    ClientShutdown,
+    /// A watch stopped before producing any results.
+    #[error("watch stopped before producing any results")]
+    WatchStopped,
+    /// The Consul cluster does not currently have a leader.
+    #[error("Consul cluster has no leader")]
+    NoClusterLeader,
     /// Error occurred while parsing a response from Consul.
     #[error("unexpected response: {0}")]
     ResponseError(#[from] ResponseError),
+    /// An error occurred while making a request to Consul.
+    ///
+    /// Wraps the underlying error with the details of the request it occurred for.  Use
+    /// [`root`](Error::root) to get at the underlying error, such as for matching on it.
+    #[error("{context}: {source}")]
+    Request {
+        /// The request the error occurred for.
+        context: RequestContext,
+        /// The underlying error.
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Gets the underlying error, without any request context.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Request { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Gets the underlying error, without any request context.
+    pub fn into_root(self) -> Error {
+        match self {
+            Error::Request { source, .. } => source.into_root(),
+            e => e,
+        }
+    }
+
+    /// Gets the details of the request this error occurred for, if any.
+    pub fn request_context(&self) -> Option<&RequestContext> {
+        match self {
+            Error::Request { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Whether or not the operation may succeed if retried.
+    ///
+    /// This is the case for errors which are typically transient, such as connection failures,
+    /// timeouts, rate limiting, and server errors, but not for errors which will happen again
+    /// given the same request, such as invalid options or ACL denials.
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            Error::DiscoveryFailed(_)
+            | Error::RequestTimedOut(_)
+            | Error::BlockingWaitExpired
+            | Error::CircuitOpen => true,
+            Error::RequestError(e) => !e.is_user() && !e.is_parse(),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Error::FetchError(e) => !e.is_builder(),
+            Error::ResponseError(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Gets how long to wait before retrying the operation, if Consul specified it.
+    ///
+    /// This is set when Consul rate limits a request and returns a `Retry-After` header.  Retry
+    /// loops should wait at least this long before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self.root() {
+            Error::ResponseError(ResponseError::RateLimited { retry_after, .. }) => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether or not the operation failed due to timing out.
+    ///
+    /// This includes blocking queries whose wait expired, which can be distinguished via
+    /// [`is_blocking_wait_expired`](Error::is_blocking_wait_expired).
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self.root(),
+            Error::RequestTimedOut(_) | Error::BlockingWaitExpired
+        )
+    }
+
+    /// Whether or not the operation was a blocking query which timed out while waiting for
+    /// changes.
+    pub fn is_blocking_wait_expired(&self) -> bool {
+        matches!(self.root(), Error::BlockingWaitExpired)
+    }
+
+    /// Whether or not the operation was rejected, or cancelled, as the client is shutting down.
+    pub fn is_shutdown(&self) -> bool {
+        matches!(self.root(), Error::ClientShutdown)
+    }
+
+    /// Whether or not the operation was denied due to the ACL token lacking the necessary
+    /// permissions, or being invalid.
+    pub fn is_acl_denied(&self) -> bool {
+        matches!(
+            self.root(),
+            Error::ResponseError(ResponseError::AclDenied(_))
+        )
+    }
+
+    /// Attaches the given request context to this error, unless it already has one.
+    pub(crate) fn with_context(self, context: Option<RequestContext>) -> Error {
+        match (self, context) {
+            (e @ Error::Request { .. }, _) | (e, None) => e,
+            (e, Some(context)) => Error::Request {
+                context,
+                source: Box::new(e),
+            },
+        }
+    }
 }
 
 /// High-level error for responses.
 #[derive(ThisError, Debug)]
 pub enum ResponseError {
+    /// The request was denied due to the ACL token lacking the necessary permissions, or being
+    /// invalid.
+    #[error("permission denied: {0}")]
+    AclDenied(String),
+    /// The requested resource does not exist.
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// The request was rejected due to rate limiting.
+    ///
+    /// If Consul indicated how long to wait before retrying, it is provided as `retry_after`.
+    #[error("rate limited: {body}")]
+    RateLimited {
+        /// How long to wait before retrying the request, if specified by Consul.
+        retry_after: Option<Duration>,
+        /// The body of the response.
+        body: String,
+    },
+    /// Consul encountered an internal error while processing the request.
+    #[error("server error: {}", status_to_str(.0, .1))]
+    ServerError(StatusCode, String),
     /// The HTTP status code for the response was unexpected.
-    #[error("unexpected status code: {0}")]
-    UnexpectedStatus(StatusCode),
+    ///
+    /// Includes the body of the response, truncated to a reasonable length, which typically
+    /// contains the error message from Consul.
+    #[error("unexpected status code: {}", status_to_str(.0, .1))]
+    UnexpectedStatus(StatusCode, String),
     /// The response from Consul was missing expected headers or they were invalid.
     #[error("missing or invalid response headers: {}", strs_to_str(.0))]
     InvalidHeaders(Vec<&'static str>),
@@ -45,6 +307,32 @@ pub enum ResponseError {
     #[error("failed to consume response: {0}")]
     BodyConsumeFailure(#[from] HyperError),
     /// The response body was not JSON or did not match the expected JSON structure.
-    #[error("invalid JSON payload: {0}")]
-    InvalidPayload(#[from] JsonError),
+    ///
+    /// Includes the path within the payload at which decoding failed, such as
+    /// `$[3].ServiceWeights`, and an excerpt of the payload around that point.
+    #[error("invalid JSON payload at {path}: {source} (near `{excerpt}`)")]
+    InvalidPayload {
+        /// The underlying JSON error.
+        source: JsonError,
+        /// The path within the payload at which decoding failed.
+        path: String,
+        /// An excerpt of the payload around the point at which decoding failed.
+        excerpt: String,
+    },
+}
+
+impl ResponseError {
+    /// Whether or not this error is typically transient, such that retrying the request may
+    /// succeed.
+    ///
+    /// Rate limiting, server errors, and failures while reading the response are transient, while
+    /// all other errors will generally happen again given the same request.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ResponseError::RateLimited { .. }
+                | ResponseError::ServerError(..)
+                | ResponseError::BodyConsumeFailure(_)
+        )
+    }
 }
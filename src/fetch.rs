@@ -0,0 +1,44 @@
+//! Transport for WebAssembly, which sends requests via the Fetch API of the host.
+use hyper::{Body, Request, Response};
+use send_wrapper::SendWrapper;
+
+use crate::errors::Error;
+use crate::http_client::Transport;
+
+impl Transport {
+    /// Creates a new [`Transport`] which sends requests via the Fetch API.
+    ///
+    /// Responses are read in full before being handed back, as the Fetch API does not expose them
+    /// as a stream which can be sent between tasks.
+    pub fn fetch() -> Transport {
+        let client = reqwest::Client::new();
+        Transport::from_fn(move |request| {
+            let client = client.clone();
+            // Futures driven by the host can only be polled from the thread which created them,
+            // which is the only thread there is.
+            Box::pin(SendWrapper::new(send(client, request)))
+        })
+    }
+}
+
+async fn send(client: reqwest::Client, request: Request<Body>) -> Result<Response<Body>, Error> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+
+    let mut builder = client
+        .request(parts.method, parts.uri.to_string())
+        .headers(parts.headers);
+    if !body.is_empty() {
+        builder = builder.body(body.to_vec());
+    }
+    let response = builder.send().await?;
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+
+    let mut response = Response::new(Body::from(body.to_vec()));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use async_stream::try_stream;
+use futures::future::{self, Either};
+use futures::stream::{Stream, StreamExt};
+
+use crate::catalog::InstanceKey;
+use crate::common::QueryMetadata;
+use crate::errors::Error;
+use crate::health::{CheckStatus, ServiceEntry};
+use crate::runtime::{delay_until, Instant};
+use crate::watch::Watch;
+
+/// Damps changes to the health of the instances in the results of the given watch.
+///
+/// A change to the aggregated status of an instance is only published once the instance has had
+/// its new status for the given settle delay.  Until then, the instance is published as it was
+/// before the change, so an instance whose checks flap back and forth within the settle delay is
+/// never published as having changed status at all.  Instances which are added or removed, and
+/// changes which leave the status of an instance as it was, are published immediately.
+///
+/// The results of the watch must include all instances, regardless of health, so that instances
+/// going critical are seen as a change in status rather than as being removed.  If `passing_only`
+/// is set, instances are filtered based on their published status instead.
+pub(crate) fn damp(
+    mut watch: Watch<Vec<ServiceEntry>>,
+    settle: Duration,
+    passing_only: bool,
+) -> impl Stream<Item = Result<(Vec<ServiceEntry>, QueryMetadata), Error>> {
+    try_stream! {
+        let mut latest: Vec<ServiceEntry> = Vec::new();
+        let mut latest_meta = QueryMetadata::default();
+        let mut published: HashMap<InstanceKey, ServiceEntry> = HashMap::new();
+        let mut pending: HashMap<InstanceKey, Instant> = HashMap::new();
+        let mut last_view: Option<Vec<ServiceEntry>> = None;
+
+        loop {
+            let settled_at = pending.values().min().copied();
+            let next = match settled_at {
+                Some(at) => match future::select(watch.next(), delay_until(at)).await {
+                    Either::Left((next, _)) => Some(next),
+                    Either::Right(_) => None,
+                },
+                None => Some(watch.next().await),
+            };
+
+            match next {
+                Some(Some(result)) => {
+                    let (entries, meta) = result?;
+                    let now = Instant::now();
+                    let mut current = HashSet::with_capacity(entries.len());
+                    for entry in &entries {
+                        let key = InstanceKey::from(entry);
+                        let changed = published
+                            .get(&key)
+                            .is_some_and(|old| old.aggregated_status() != entry.aggregated_status());
+                        if changed {
+                            pending.entry(key.clone()).or_insert(now + settle);
+                        } else {
+                            pending.remove(&key);
+                            published.insert(key.clone(), entry.clone());
+                        }
+                        current.insert(key);
+                    }
+                    published.retain(|key, _| current.contains(key));
+                    pending.retain(|key, _| current.contains(key));
+
+                    latest = entries;
+                    latest_meta = meta;
+                }
+                Some(None) => break,
+                // The settle delay of at least one instance has passed.
+                None => {
+                    let now = Instant::now();
+                    let settled = pending
+                        .iter()
+                        .filter(|(_, at)| **at <= now)
+                        .map(|(key, _)| key.clone())
+                        .collect::<Vec<_>>();
+                    for key in settled {
+                        pending.remove(&key);
+                        let entry = latest.iter().find(|entry| InstanceKey::from(*entry) == key);
+                        if let Some(entry) = entry {
+                            published.insert(key, entry.clone());
+                        }
+                    }
+                }
+            }
+
+            let view = latest
+                .iter()
+                .filter_map(|entry| published.get(&InstanceKey::from(entry)))
+                .filter(|entry| !passing_only || entry.aggregated_status() == CheckStatus::Passing)
+                .cloned()
+                .collect::<Vec<_>>();
+            if last_view.as_ref() != Some(&view) {
+                last_view = Some(view.clone());
+                yield (view, latest_meta.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use tokio::time::timeout;
+
+    use crate::health::HealthCheck;
+
+    const SETTLE: Duration = Duration::from_millis(50);
+
+    fn entry(id: &str, status: &str) -> ServiceEntry {
+        let mut entry = ServiceEntry::default();
+        entry.node.node = "node-1".to_string();
+        entry.service.id = id.to_string();
+        entry.checks = vec![HealthCheck {
+            status: status.to_string(),
+            ..Default::default()
+        }];
+        entry
+    }
+
+    /// Creates a watch which returns each of the given results in turn, and then blocks forever.
+    fn watch(results: Vec<Vec<ServiceEntry>>) -> Watch<Vec<ServiceEntry>> {
+        let results = Arc::new(Mutex::new(results.into_iter().collect::<VecDeque<_>>()));
+        let mut index = 0;
+        Watch::new(None, move |_| {
+            index += 1;
+            let next = results.lock().unwrap().pop_front();
+            let meta = QueryMetadata {
+                last_index: Some(index),
+                ..Default::default()
+            };
+            let result: Pin<Box<dyn Future<Output = _> + Send>> = match next {
+                Some(entries) => Box::pin(future::ready(Ok((entries, meta)))),
+                None => Box::pin(future::pending()),
+            };
+            result
+        })
+    }
+
+    async fn next<S>(stream: &mut S) -> Option<Vec<ServiceEntry>>
+    where
+        S: Stream<Item = Result<(Vec<ServiceEntry>, QueryMetadata), Error>> + Unpin,
+    {
+        timeout(SETTLE * 4, stream.next())
+            .await
+            .ok()
+            .map(|result| result.unwrap().unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn flaps_within_the_settle_delay_are_never_published() {
+        let watch = watch(vec![
+            vec![entry("a", "passing")],
+            vec![entry("a", "critical")],
+            vec![entry("a", "passing")],
+        ]);
+        let mut damped = Box::pin(damp(watch, SETTLE, false));
+
+        assert_eq!(next(&mut damped).await, Some(vec![entry("a", "passing")]));
+        assert_eq!(next(&mut damped).await, None);
+    }
+
+    #[tokio::test]
+    async fn changes_are_published_once_settled() {
+        let watch = watch(vec![
+            vec![entry("a", "passing")],
+            vec![entry("a", "critical")],
+        ]);
+        let mut damped = Box::pin(damp(watch, SETTLE, false));
+
+        assert_eq!(next(&mut damped).await, Some(vec![entry("a", "passing")]));
+        let started = Instant::now();
+        assert_eq!(next(&mut damped).await, Some(vec![entry("a", "critical")]));
+        assert!(started.elapsed() >= SETTLE);
+    }
+
+    #[tokio::test]
+    async fn added_and_removed_instances_are_published_immediately() {
+        let watch = watch(vec![
+            vec![entry("a", "passing")],
+            vec![entry("a", "passing"), entry("b", "critical")],
+            vec![entry("b", "critical")],
+        ]);
+        let mut damped = Box::pin(damp(watch, SETTLE, false));
+
+        assert_eq!(next(&mut damped).await, Some(vec![entry("a", "passing")]));
+        let started = Instant::now();
+        assert_eq!(
+            next(&mut damped).await,
+            Some(vec![entry("a", "passing"), entry("b", "critical")])
+        );
+        assert_eq!(next(&mut damped).await, Some(vec![entry("b", "critical")]));
+        assert!(started.elapsed() < SETTLE);
+    }
+
+    #[tokio::test]
+    async fn passing_only_filters_on_the_published_status() {
+        let watch = watch(vec![
+            vec![entry("a", "passing"), entry("b", "passing")],
+            vec![entry("a", "warning"), entry("b", "passing")],
+        ]);
+        let mut damped = Box::pin(damp(watch, SETTLE, true));
+
+        assert_eq!(
+            next(&mut damped).await,
+            Some(vec![entry("a", "passing"), entry("b", "passing")])
+        );
+        // Until the warning settles, the instance is still published as passing.
+        assert_eq!(next(&mut damped).await, Some(vec![entry("b", "passing")]));
+    }
+}
@@ -1,9 +1,38 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 
-use serde::Deserialize;
+use futures::stream::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Deserialize, Debug)]
+use crate::agent::AgentService;
+use crate::catalog::CatalogNode;
+use crate::common::{go_duration, QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::flap_damping;
+use crate::http_client::HttpClient;
+use crate::paging;
+use crate::payload;
+use crate::resolver::{combined_weight, WeightedInstance};
+use crate::service_set::{watch_service_set, ServiceSelector, ServiceSetEvent};
+use crate::watch::Watch;
+
+/// The status of a health check.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    #[serde(rename = "passing")]
+    Passing,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "critical")]
+    Critical,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
 pub struct HealthCheck {
     #[serde(rename = "Node")]
     pub node: String,
@@ -33,14 +62,18 @@ pub struct HealthCheck {
     pub create_index: u64,
     #[serde(rename = "ModifyIndex")]
     pub modify_index: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
 pub struct HealthCheckDefinition {
     #[serde(rename = "HTTP")]
     pub http: String,
     #[serde(rename = "Header")]
-    pub header: HashMap<String, String>,
+    pub header: Option<HashMap<String, Vec<String>>>,
     #[serde(rename = "Method")]
     pub method: String,
     #[serde(rename = "Body")]
@@ -49,10 +82,268 @@ pub struct HealthCheckDefinition {
     pub tls_skip_verify: bool,
     #[serde(rename = "TCP")]
     pub tcp: String,
-    #[serde(rename = "Interval")]
+    #[serde(rename = "Interval", with = "go_duration", default)]
     pub interval: Duration,
-    #[serde(rename = "Timeout")]
+    #[serde(rename = "Timeout", with = "go_duration", default)]
     pub timeout: Duration,
-    #[serde(rename = "DeregisterCriticalServiceAfterDuration")]
+    #[serde(
+        rename = "DeregisterCriticalServiceAfter",
+        with = "go_duration",
+        default
+    )]
     pub deregister_critical_svc_after: Duration,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// An instance of a service, along with its node and health checks, as returned by the health
+/// API.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ServiceEntry {
+    #[serde(rename = "Node")]
+    pub node: CatalogNode,
+    #[serde(rename = "Service")]
+    pub service: AgentService,
+    /// The checks of the node as a whole, and of this service.
+    #[serde(rename = "Checks", default)]
+    pub checks: Vec<HealthCheck>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ServiceEntry {
+    /// Gets the overall status of the instance, which is the worst status of all of its checks.
+    ///
+    /// Instances without any checks are considered passing.  Checks in an unknown status are
+    /// ignored.  Maintenance mode is represented by a critical check, so instances in maintenance
+    /// are critical.
+    pub fn aggregated_status(&self) -> CheckStatus {
+        let mut status = CheckStatus::Passing;
+        for check in &self.checks {
+            match check.status.as_str() {
+                "critical" => return CheckStatus::Critical,
+                "warning" => status = CheckStatus::Warning,
+                _ => {}
+            }
+        }
+        status
+    }
+
+    /// Gets the address to use to connect to the service.
+    ///
+    /// This is the service address, if one was registered, and otherwise the address of the node,
+    /// matching how Consul itself resolves the address of a service, such as for DNS queries.
+    pub fn effective_address(&self) -> &str {
+        if self.service.address.is_empty() {
+            &self.node.address
+        } else {
+            &self.service.address
+        }
+    }
+
+    /// Gets the address to use to connect to the service from another datacenter.
+    ///
+    /// This is the tagged WAN address of the service if one was registered, and otherwise the
+    /// tagged WAN address of the node, falling back to
+    /// [`effective_address`](ServiceEntry::effective_address).
+    pub fn effective_wan_address(&self) -> &str {
+        self.service
+            .tagged_addresses
+            .wan()
+            .map(|addr| addr.address.as_str())
+            .filter(|addr| !addr.is_empty())
+            .or_else(|| {
+                self.node
+                    .tagged_addresses
+                    .wan()
+                    .map(|addr| addr.as_str())
+                    .filter(|addr| !addr.is_empty())
+            })
+            .unwrap_or_else(|| self.effective_address())
+    }
+
+    /// Gets the address to use to connect to the service from the given datacenter.
+    ///
+    /// This accounts for address translation: if the response was translated, as indicated by
+    /// [`QueryMetadata::addr_translate_enabled`] in `meta`, Consul has already picked the right
+    /// address and this is [`effective_address`](ServiceEntry::effective_address).  Otherwise,
+    /// this is [`effective_wan_address`](ServiceEntry::effective_wan_address) if the service is
+    /// in a datacenter other than `local_datacenter`, and the LAN address if not.
+    pub fn address_for(&self, meta: &QueryMetadata, local_datacenter: &str) -> &str {
+        if meta.addr_translate_enabled || self.node.datacenter == local_datacenter {
+            self.effective_address()
+        } else {
+            self.effective_wan_address()
+        }
+    }
+
+    /// Gets the socket address to use to connect to the service.
+    ///
+    /// Combines [`effective_address`](ServiceEntry::effective_address) with the service port.
+    /// Returns `None` if the address is not an IP address, such as a hostname.
+    pub fn service_socket_addr(&self) -> Option<SocketAddr> {
+        self.effective_address()
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, self.service.port))
+    }
+}
+
+impl WeightedInstance for ServiceEntry {
+    fn weight(&self) -> u64 {
+        let weights = &self.service.weights;
+        let statuses = self.checks.iter().map(|check| check.status.as_str());
+        combined_weight(statuses, weights.passing, weights.warning)
+    }
+}
+
+/// Health operations.
+///
+/// This type can be used to interact with the "Health" portion of the Consul API.
+#[derive(Clone, Debug)]
+pub struct Health {
+    http_client: Arc<HttpClient>,
+}
+
+impl Health {
+    /// Creates a new [`Health`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Health {
+        Health { http_client }
+    }
+
+    /// Gets the instances of the specified service, along with their health checks.
+    ///
+    /// If `passing_only` is `true`, only instances whose checks are all passing are returned.
+    pub async fn get_service_nodes(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<ServiceEntry>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        get_service_nodes(&self.http_client, service, passing_only, options.as_ref()).await
+    }
+
+    /// Gets the instances of the specified service, along with their health checks, in pages.
+    ///
+    /// This is meant for services with so many instances that holding all of them at once is a
+    /// burden.  The instances are split into roughly `pages` pages based on the first character of
+    /// the name of their node, using a server-side filter combined with any filter already set in
+    /// the options, and each page is queried only once the previous one has been consumed.
+    ///
+    /// Each page is the result of a separate query, so the instances can change between pages,
+    /// which can be detected by comparing the [`last_index`](QueryMetadata::last_index) of each
+    /// page.  Consul only serves the current instances, rather than those as of an earlier index,
+    /// so the pages can't be pinned to a single snapshot, and callers which need one should page
+    /// through again whenever the index moved.  An instance always falls in the same page, though,
+    /// so no instance is ever missed or repeated unless it is added or removed while paging.
+    pub fn get_service_nodes_paged(
+        &self,
+        service: &str,
+        passing_only: bool,
+        pages: usize,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<(Vec<ServiceEntry>, QueryMetadata), Error>> {
+        let options = self.http_client.query_options(options);
+        let service = service.to_string();
+        let http_client = self.http_client.clone();
+
+        paging::paged(options, "Node.Node", pages, move |options| {
+            let service = service.clone();
+            let http_client = http_client.clone();
+            async move { get_service_nodes(&http_client, &service, passing_only, Some(&options)).await }
+        })
+    }
+
+    /// Gets a stream of changes to the instances of the specified service.
+    ///
+    /// Each item in the response stream represents all instances of the service after a change to
+    /// any of them, including to their health, has occurred.  The stream will terminate if any
+    /// error is hit during the background requests made to Consul.
+    pub fn watch_service_nodes(
+        &self,
+        service: &str,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> Watch<Vec<ServiceEntry>> {
+        let options = self.http_client.watch_options(options);
+        let service = service.to_string();
+        let http_client = self.http_client.clone();
+
+        Watch::new(options, move |options| {
+            let service = service.clone();
+            let http_client = http_client.clone();
+            async move { get_service_nodes(&http_client, &service, passing_only, Some(&options)).await }
+        })
+        .tracked(self.http_client.stats())
+    }
+
+    /// Gets a stream of changes to the instances of the specified service, with changes to their
+    /// health damped.
+    ///
+    /// Behaves like [`watch_service_nodes`](Health::watch_service_nodes), except that a change to
+    /// the [aggregated status](ServiceEntry::aggregated_status) of an instance is only reflected
+    /// once the instance has kept its new status for `settle`.  Until then, the instance is
+    /// included as it was before the change, so checks which flap back and forth do not cause churn
+    /// in downstream load balancers.  Instances which are added or removed, and changes which do
+    /// not affect the status of an instance, are reflected immediately.
+    ///
+    /// All instances are queried regardless of `passing_only`, which is applied to the damped
+    /// statuses instead, so that instances going critical are damped rather than disappearing
+    /// straight away.  The stream will terminate if any error is hit during the background
+    /// requests made to Consul.
+    pub fn watch_service_nodes_damped(
+        &self,
+        service: &str,
+        passing_only: bool,
+        settle: Duration,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<(Vec<ServiceEntry>, QueryMetadata), Error>> {
+        let watch = self.watch_service_nodes(service, false, options);
+        flap_damping::damp(watch, settle, passing_only)
+    }
+
+    /// Gets a stream of changes to the instances of every service matching the given selector.
+    ///
+    /// The services in the catalog are watched, and the instances of every service which matches
+    /// are watched as it appears, like with [`watch_service_nodes`](Health::watch_service_nodes).
+    /// Watches are stopped, and [`ServiceSetEvent::Removed`] yielded, for services which disappear
+    /// or stop matching.  This is meant for gateways and routers which discover their upstreams
+    /// dynamically.  The stream will terminate if any error is hit during the background requests
+    /// made to Consul, for the catalog or for any of the watched services.
+    ///
+    /// The given options are used for all queries.
+    pub fn watch_service_set(
+        &self,
+        selector: ServiceSelector,
+        passing_only: bool,
+        options: Option<QueryOptions>,
+    ) -> impl Stream<Item = Result<ServiceSetEvent, Error>> {
+        watch_service_set(self.http_client.clone(), selector, passing_only, options)
+    }
+}
+
+async fn get_service_nodes(
+    http_client: &HttpClient,
+    service: &str,
+    passing_only: bool,
+    options: Option<&QueryOptions>,
+) -> Result<(Vec<ServiceEntry>, QueryMetadata), Error> {
+    let mut params = Vec::new();
+    if passing_only {
+        params.push(("passing", Cow::Borrowed("")));
+    }
+    let request = http_client.build_request_with_params(
+        "GET",
+        &["v1", "health", "service", service],
+        params,
+        options,
+        (),
+    )?;
+    let response = http_client.run_request(request, options).await?;
+    let (parsed, meta) = http_client.parse_list_query_response(response).await?;
+    Ok((parsed, meta))
 }
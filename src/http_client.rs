@@ -1,30 +1,245 @@
-use hyper::client::{Client as HyperClient, HttpConnector};
-use hyper::header::{HeaderName, HeaderValue};
-use hyper::{Body, Request, Response};
-use hyper_tls::HttpsConnector;
+use async_stream::try_stream;
+use futures::future::{self, Either};
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use hyper::body::Bytes;
+use hyper::client::connect::Connect;
+use hyper::client::Client as HyperClient;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CACHE_CONTROL, RETRY_AFTER,
+};
+use hyper::http::uri::InvalidUri;
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::time::timeout;
-use url::Url;
+use url::form_urlencoded;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::common::{AsTimeout, CollectQueryParameters, CollectRequestHeaders, QueryMetadata};
-use crate::errors::{Error, ResponseError};
+use crate::cache::{CacheSettings, ResponseCache};
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerSettings};
+use crate::common::{
+    AsTimeout, CollectQueryParameters, CollectRequestHeaders, QueryMetadata, QueryOptions,
+    ValidateOptions, REQUEST_ID_HEADER,
+};
+#[cfg(feature = "native")]
+use crate::connector::{Connector, IpPreference};
+use crate::endpoints::{rewrite_uri, Endpoints};
+use crate::errors::{Error, RequestContext, ResponseError};
+use crate::index_tracker::IndexTracker;
+use crate::interceptor::Interceptors;
+use crate::payload;
+use crate::recording::{self, Recording};
+use crate::restart_budget::{RestartBudget, RestartBudgetSettings};
+use crate::runtime::{system_now, timeout, Instant};
+use crate::shutdown::Shutdown;
+use crate::stats::StatsRecorder;
+use crate::token::SharedTokenProvider;
+use crate::watch::WatchRegistry;
+
+/// A stream of the entries of a list response, decoded as they are received.
+pub(crate) type ListStream<T> = Pin<Box<dyn Stream<Item = Result<T, Error>> + Send>>;
+
+/// Defaults applied to every request unless overridden by the options for a given operation.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestDefaults {
+    pub token: Option<String>,
+    pub datacenter: Option<String>,
+    pub namespace: Option<String>,
+    pub timeout: Option<Duration>,
+    pub bearer_auth: bool,
+    pub token_provider: Option<SharedTokenProvider>,
+    pub query_options: Option<QueryOptions>,
+    pub lenient_payloads: bool,
+    pub strict_payloads: bool,
+    pub generate_request_ids: bool,
+    pub monotonic_reads: bool,
+    pub slow_request_threshold: Option<Duration>,
+}
+
+/// Settings controlling how connections to Consul are established and reused.
+#[cfg(feature = "native")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectionSettings {
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<Duration>,
+    pub http2_only: bool,
+    pub connect_timeout: Option<Duration>,
+    pub tcp_keepalive: Option<Duration>,
+    pub ip_preference: IpPreference,
+    pub happy_eyeballs_timeout: Option<Duration>,
+}
+
+pub(crate) type TransportFuture =
+    Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send>>;
+
+/// The client used to send requests, with its type erased, so that users can supply their own
+/// hyper client, and so that requests can be sent via the Fetch API on WebAssembly.
+#[derive(Clone)]
+pub(crate) struct Transport(Arc<dyn Fn(Request<Body>) -> TransportFuture + Send + Sync>);
+
+impl Transport {
+    /// Creates a new [`Transport`] using the given connector.
+    #[cfg(feature = "native")]
+    pub fn new(connector: Connector, settings: &ConnectionSettings) -> Transport {
+        let mut builder = HyperClient::builder();
+        if let Some(max_idle) = settings.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = settings.pool_idle_timeout {
+            builder.pool_idle_timeout(idle_timeout);
+        }
+        builder.http2_only(settings.http2_only);
+        Transport::from_client(builder.build(connector))
+    }
+
+    /// Creates a new [`Transport`] from an existing hyper client.
+    pub fn from_client<C>(client: HyperClient<C, Body>) -> Transport
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        Transport::from_fn(move |request| {
+            let response = client.request(request);
+            Box::pin(async move { Ok(response.await?) })
+        })
+    }
+
+    /// Creates a new [`Transport`] which sends requests with the given function.
+    pub fn from_fn<F>(send: F) -> Transport
+    where
+        F: Fn(Request<Body>) -> TransportFuture + Send + Sync + 'static,
+    {
+        Transport(Arc::new(send))
+    }
+
+    /// Sends the given request.
+    pub fn request(&self, request: Request<Body>) -> TransportFuture {
+        (self.0)(request)
+    }
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Transport")
+    }
+}
 
 #[derive(Clone, Debug)]
 pub(crate) struct HttpClient {
-    client: HyperClient<HttpsConnector<HttpConnector>, Body>,
-    base_uri: Url,
+    client: Transport,
+    endpoints: Arc<Endpoints>,
+    defaults: RequestDefaults,
+    interceptors: Interceptors,
+    watches: WatchRegistry,
+    cache: Option<ResponseCache>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    stats: Arc<StatsRecorder>,
+    recording: Option<Arc<Recording>>,
+    restart_budget: Option<Arc<RestartBudget>>,
+    indexes: Arc<IndexTracker>,
+    shutdown: Arc<Shutdown>,
 }
 
 impl HttpClient {
     /// Creates a new [`HttpClient`].
-    pub fn new(base_uri: Url) -> HttpClient {
-        let connector = HttpsConnector::new();
-        let client = HyperClient::builder().build(connector);
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        endpoints: Endpoints,
+        client: Transport,
+        defaults: RequestDefaults,
+        interceptors: Interceptors,
+        cache: Option<CacheSettings>,
+        circuit_breaker: Option<CircuitBreakerSettings>,
+        recording: Option<Recording>,
+        restart_budget: Option<RestartBudgetSettings>,
+    ) -> HttpClient {
+        let shutdown = Arc::new(Shutdown::default());
+        let cache = cache.map(|settings| ResponseCache::new(settings, shutdown.clone()));
+        let indexes = Arc::new(IndexTracker::new(defaults.monotonic_reads));
 
-        HttpClient { client, base_uri }
+        HttpClient {
+            client,
+            endpoints: Arc::new(endpoints),
+            defaults,
+            interceptors,
+            watches: WatchRegistry::default(),
+            cache,
+            circuit_breaker: circuit_breaker
+                .map(|settings| Arc::new(CircuitBreaker::new(settings))),
+            stats: Arc::new(StatsRecorder::default()),
+            recording: recording.map(Arc::new),
+            restart_budget: restart_budget.map(|settings| Arc::new(RestartBudget::new(settings))),
+            indexes,
+            shutdown,
+        }
+    }
+
+    /// Merges the given options for a query operation over the default query options, if any, and
+    /// resolves their wait jitter for a single request.
+    pub fn query_options(&self, options: Option<QueryOptions>) -> Option<QueryOptions> {
+        self.watch_options(options)
+            .map(QueryOptions::with_jittered_wait)
+    }
+
+    /// Merges the given options for a watch over the default query options, if any.
+    ///
+    /// Unlike [`query_options`](HttpClient::query_options), the wait jitter is left unresolved, as
+    /// watches resolve it anew for each of their requests.
+    pub fn watch_options(&self, options: Option<QueryOptions>) -> Option<QueryOptions> {
+        match (options, self.defaults.query_options.as_ref()) {
+            (Some(options), Some(defaults)) => Some(options.merged_over(defaults)),
+            (None, Some(defaults)) => Some(defaults.clone()),
+            (options, None) => options,
+        }
+    }
+
+    /// Waits until a failed watch is allowed to restart, according to the restart budget, if any.
+    pub async fn acquire_restart(&self) {
+        if let Some(budget) = self.restart_budget.as_ref() {
+            budget.acquire().await;
+        }
+    }
+
+    /// Gets the registry of shared watches running on this client.
+    pub fn watches(&self) -> &WatchRegistry {
+        &self.watches
+    }
+
+    /// Gets the highest Consul index seen in any response to this client, if any.
+    pub fn last_index(&self) -> Option<u64> {
+        self.indexes.last_index()
+    }
+
+    /// Gets the statistics recorded for this client.
+    pub fn stats(&self) -> &Arc<StatsRecorder> {
+        &self.stats
+    }
+
+    /// Whether or not this client has started shutting down.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown.is_closed()
+    }
+
+    /// Stops accepting new requests, cancels blocking queries, and waits for up to the given
+    /// timeout for other requests in flight to finish.
+    pub async fn shutdown(&self, wait: Duration) -> bool {
+        self.shutdown.close(wait).await
+    }
+
+    /// Resolves a path-only URI against the base URI.
+    pub fn resolve_uri(&self, uri: &Uri) -> Result<Uri, Error> {
+        let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        let resolved = self.endpoints.current().join(path_and_query)?;
+        resolved
+            .as_str()
+            .parse()
+            .map_err(|e: InvalidUri| Error::InvalidRequest(e.into()))
     }
 
     pub fn build_request<I, O, B>(
@@ -37,40 +252,76 @@ impl HttpClient {
     where
         I: IntoIterator,
         I::Item: AsRef<str>,
-        O: CollectQueryParameters + CollectRequestHeaders,
+        O: CollectQueryParameters + CollectRequestHeaders + ValidateOptions,
+        B: Serialize,
+    {
+        self.build_request_with_params(method, url_parts, Vec::new(), options, body)
+    }
+
+    /// Builds a request with additional, operation-specific query parameters.
+    ///
+    /// Operation-specific parameters are applied after those derived from `options`, and so will
+    /// override them if they share the same name.
+    pub fn build_request_with_params<I, O, B>(
+        &self,
+        method: &str,
+        url_parts: I,
+        params: Vec<(&'static str, Cow<'static, str>)>,
+        options: Option<O>,
+        body: B,
+    ) -> Result<Request<Body>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: CollectQueryParameters + CollectRequestHeaders + ValidateOptions,
         B: Serialize,
     {
-        let mut new_path = self.base_uri.clone();
+        let body = serialize_body(&body)?;
+        self.build_raw_request(method, url_parts, params, options, body)
+    }
+
+    /// Builds a request with a raw body, rather than one serialized as JSON.
+    pub fn build_raw_request<I, O>(
+        &self,
+        method: &str,
+        url_parts: I,
+        params: Vec<(&'static str, Cow<'static, str>)>,
+        options: Option<O>,
+        body: Body,
+    ) -> Result<Request<Body>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        O: CollectQueryParameters + CollectRequestHeaders + ValidateOptions,
+    {
+        // Catch invalid options up front, rather than Consul rejecting the request.
+        options.validate()?;
+
+        let mut new_path = self.endpoints.current();
         new_path
             .path_segments_mut()
             .expect("URL not in suitable format for extending")
             .extend(url_parts);
 
         let pairs = CollectQueryParameters::as_pairs(&options);
-        if !pairs.is_empty() {
-            // we need to add query params but also make sure that we handle overrides
-            // correctly so that per-operation things can be tweaked.  since query params
-            // aren't represented as a map, but instead of a single value that is append-only,
-            // we'll have to figure out a good way to handle this.  probably just take existing,
-            // make a hashmap out of it, upsert our per-operation pairs, and then reassemble?
-            let parse = new_path.query_pairs();
-            let mut existing = parse
+        if !pairs.is_empty() || !params.is_empty() {
+            // Any query parameters already present on the base URI are overridden by those from
+            // the options, which are in turn overridden by the operation-specific parameters.  A
+            // key given by a later layer replaces all values for that key from earlier layers, but
+            // repeated keys within a layer, such as `node-meta`, are all kept, in order.
+            let existing = new_path
+                .query_pairs()
                 .into_owned()
-                .into_iter()
-                .map(|(k, v)| (k.into(), v.into()))
-                .collect::<HashMap<Cow<'static, str>, Cow<'static, str>>>();
+                .map(|(k, v)| (Cow::Owned(k), Cow::Owned(v)))
+                .collect::<Vec<_>>();
+            let mut merged = merge_query_pairs(Vec::new(), existing);
+            merged = merge_query_pairs(merged, to_owned_pairs(pairs));
+            merged = merge_query_pairs(merged, to_owned_pairs(params));
 
-            for (k, v) in pairs.into_iter() {
-                existing.insert(k.into(), v);
-            }
-
-            new_path.query_pairs_mut().clear().extend_pairs(existing);
+            new_path.query_pairs_mut().clear().extend_pairs(merged);
         }
 
         let headers = CollectRequestHeaders::as_pairs(&options);
-        let serialized = serde_json::to_vec(&body).map_err(Error::InvalidRequestBody)?;
-        let body = Body::from(serialized);
-
         let mut req = Request::builder()
             .method(method)
             .uri(new_path.to_string())
@@ -88,44 +339,989 @@ impl HttpClient {
             })
             .collect::<Vec<_>>();
         req.headers_mut().extend(headers);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(method = %req.method(), uri = %req.uri(), "built Consul request");
+        #[cfg(feature = "log")]
+        log::debug!("built Consul request: {} {}", req.method(), req.uri());
+
         Ok(req)
     }
 
     pub async fn run_request<O>(
         &self,
-        request: Request<Body>,
+        mut request: Request<Body>,
         options: Option<O>,
     ) -> Result<Response<Body>, Error>
     where
         O: AsTimeout,
     {
-        let timeout_dur = options.as_timeout();
+        self.apply_defaults(&mut request).await?;
+        if self.defaults.generate_request_ids && !request.headers().contains_key(REQUEST_ID_HEADER)
+        {
+            let value = HeaderValue::from_str(&generate_request_id())
+                .expect("generated request IDs must be valid headers");
+            request.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
 
-        if let Some(dur) = timeout_dur {
-            let result = timeout(dur, self.client.request(request)).await?;
-            Ok(result?)
+        // Keep track of what the request was, so that any errors, including those from parsing the
+        // response later on, can say which request they were for.
+        let context = request_context(&request, options.is_blocking());
+        let _guard = self
+            .shutdown
+            .request_started()
+            .map_err(|e| e.with_context(Some(context.clone())))?;
+        let max_stale = options.max_stale();
+        // Indexes are only comparable between responses for the same resource in the same
+        // datacenter, so that is what they are tracked by when reads must be monotonic.
+        let index_key = if self.indexes.is_monotonic() {
+            let datacenter = context.datacenter.as_deref().unwrap_or("");
+            Some(format!("{}?dc={}", context.path, datacenter))
         } else {
-            Ok(self.client.request(request).await?)
+            None
+        };
+        let monotonic = index_key.is_some() && allows_stale(&request);
+        let leader_request = if max_stale.is_some() || monotonic {
+            Some(leader_request(&request))
+        } else {
+            None
+        };
+        #[cfg(feature = "log")]
+        let start = Instant::now();
+        let result = self.execute_request(request, &options).await;
+        #[cfg(feature = "log")]
+        log_failure(&context, &result);
+        let mut response = result.map_err(|e| e.with_context(Some(context.clone())))?;
+
+        // Retry reads which turned out to be more stale than allowed, or older than what was already
+        // seen when reads must be monotonic, against the leader.
+        if let Some(leader_request) = leader_request {
+            let stale = max_stale.is_some_and(|max_stale| too_stale(&response, max_stale))
+                || (monotonic && self.indexes.is_behind(index_key.as_deref(), &response));
+            if stale {
+                #[cfg(feature = "log")]
+                log::warn!(
+                    "retrying stale Consul request against the leader: {}",
+                    context
+                );
+                let _ = hyper::body::to_bytes(response.into_body()).await;
+                let result = self.execute_request(leader_request, &options).await;
+                #[cfg(feature = "log")]
+                log_failure(&context, &result);
+                response = result.map_err(|e| e.with_context(Some(context.clone())))?;
+            }
         }
+
+        // Blocking queries are expected to take as long as their wait time, so they are never
+        // considered slow.
+        #[cfg(feature = "log")]
+        if let Some(threshold) = self.defaults.slow_request_threshold {
+            let elapsed = start.elapsed();
+            if !context.blocking && elapsed > threshold {
+                log::info!("slow Consul request: {} took {:?}", context, elapsed);
+            }
+        }
+
+        self.indexes.observe(index_key.as_deref(), &response);
+        response.extensions_mut().insert(context);
+        Ok(response)
+    }
+
+    async fn execute_request<O>(
+        &self,
+        mut request: Request<Body>,
+        options: O,
+    ) -> Result<Response<Body>, Error>
+    where
+        O: AsTimeout,
+    {
+        let timeout_dur = options.as_timeout().or_else(|| {
+            if options.is_blocking() {
+                None
+            } else {
+                self.defaults.timeout
+            }
+        });
+
+        self.interceptors.on_request(&mut request);
+
+        // Blocking queries are already waiting on changes themselves, so there's no point caching
+        // them, and anything other than a read can't be cached at all.
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| request.method() == Method::GET && !options.is_blocking());
+        let cache_request = match cache {
+            Some(cache) => {
+                let key = ResponseCache::key(&request);
+                if let Some(response) = cache.get(&key) {
+                    self.interceptors.on_response(&response);
+                    return Ok(response);
+                }
+                Some((key, request.uri().clone(), request.headers().clone()))
+            }
+            None => None,
+        };
+
+        let permit = match self.circuit_breaker.as_ref().map(|breaker| breaker.allow()) {
+            Some(None) => {
+                // Fall back to anything we have cached, however stale, rather than failing outright.
+                let cached = cache
+                    .zip(cache_request.as_ref())
+                    .and_then(|(cache, (key, _, _))| cache.get_stale(key));
+                if let Some(response) = cached {
+                    self.interceptors.on_response(&response);
+                    return Ok(response);
+                }
+                self.stats.circuit_open();
+                return Err(Error::CircuitOpen);
+            }
+            Some(permit) => permit,
+            None => None,
+        };
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(&request);
+        let start = Instant::now();
+        let in_flight = self.stats.request_started();
+
+        let result = self.send_request(request, timeout_dur, options.is_blocking());
+        #[cfg(feature = "tracing")]
+        let result = tracing::Instrument::instrument(result, span.clone());
+        // Blocking queries can take minutes to finish, so they are cancelled rather than waited on
+        // when shutting down.
+        let result = if options.is_blocking() {
+            match future::select(Box::pin(result), Box::pin(self.shutdown.closed())).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(Error::ClientShutdown),
+            }
+        } else {
+            result.await
+        };
+        // Blocking queries are expected to sometimes run out the clock, which isn't a failure.
+        let result = result.map_err(|e| match e {
+            Error::RequestTimedOut(_) if options.is_blocking() => Error::BlockingWaitExpired,
+            e => e,
+        });
+        drop(in_flight);
+        self.stats
+            .request_finished(&result, start.elapsed(), options.is_blocking());
+
+        #[cfg(feature = "tracing")]
+        {
+            span.record("duration_ms", start.elapsed().as_millis() as u64);
+            match &result {
+                Ok(response) => {
+                    span.record("status", response.status().as_u16());
+                }
+                Err(e) => {
+                    let _enter = span.enter();
+                    tracing::debug!(error = %e, "Consul request failed");
+                }
+            }
+        }
+
+        if let (Some(breaker), Some(permit)) = (self.circuit_breaker.as_ref(), permit) {
+            let failed = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(Error::RequestError(_)) | Err(Error::RequestTimedOut(_)) => true,
+                #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+                Err(Error::FetchError(_)) => true,
+                Err(_) => false,
+            };
+            breaker.record(permit, !failed);
+        }
+
+        let mut response = result?;
+        if let (Some(cache), Some((key, uri, headers))) = (cache, cache_request) {
+            response = cache.insert(self, key, uri, headers, response).await?;
+        }
+        self.interceptors.on_response(&response);
+        Ok(response)
+    }
+
+    /// Applies the client's default datacenter, namespace and token to the given request.
+    ///
+    /// This is done for every request as it is run, whether it was built from the options of an
+    /// operation or given as is, such as via [`HttpService`](crate::HttpService).  Anything the
+    /// request already specifies itself is left untouched.
+    async fn apply_defaults(&self, request: &mut Request<Body>) -> Result<(), Error> {
+        self.apply_default_params(request)?;
+        self.apply_token(request).await
+    }
+
+    /// Adds the default datacenter and namespace to the query of the given request.
+    fn apply_default_params(&self, request: &mut Request<Body>) -> Result<(), Error> {
+        let query = request.uri().query().unwrap_or("");
+        let present = form_urlencoded::parse(query.as_bytes())
+            .map(|(k, _)| k.into_owned())
+            .collect::<Vec<_>>();
+        let mut serializer = form_urlencoded::Serializer::new(query.to_string());
+        let mut changed = false;
+        for (key, default) in [
+            ("dc", self.defaults.datacenter.as_ref()),
+            ("ns", self.defaults.namespace.as_ref()),
+        ] {
+            if let Some(value) = default.filter(|_| !present.iter().any(|k| k == key)) {
+                serializer.append_pair(key, value);
+                changed = true;
+            }
+        }
+        if changed {
+            let path_and_query = format!("{}?{}", request.uri().path(), serializer.finish());
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = Some(
+                path_and_query
+                    .parse()
+                    .map_err(|e: InvalidUri| Error::InvalidRequest(e.into()))?,
+            );
+            *request.uri_mut() =
+                Uri::from_parts(parts).map_err(|e| Error::InvalidRequest(e.into()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the token of the given request, using the default token if it doesn't carry its own.
+    ///
+    /// The default token comes from the configured token provider if any.  Tokens given via the
+    /// `X-Consul-Token` header, such as from the options of an operation, are moved to the
+    /// `Authorization` header when bearer authentication is enabled, while requests which already
+    /// carry an `Authorization` header are left untouched.
+    async fn apply_token(&self, request: &mut Request<Body>) -> Result<(), Error> {
+        let token = match request.headers_mut().remove("X-Consul-Token") {
+            Some(token) => token.as_bytes().to_vec(),
+            None if request.headers().contains_key(AUTHORIZATION) => return Ok(()),
+            None => {
+                let token = match self.defaults.token_provider.as_ref() {
+                    Some(provider) => provider.0.token().await?,
+                    None => self.defaults.token.clone(),
+                };
+                match token {
+                    Some(token) => token.into_bytes(),
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        let (name, value) = if self.defaults.bearer_auth {
+            (AUTHORIZATION, [b"Bearer ".as_ref(), &token].concat())
+        } else {
+            (HeaderName::from_static("x-consul-token"), token)
+        };
+        let value = HeaderValue::from_bytes(&value).map_err(|e| Error::InvalidRequest(e.into()))?;
+        request.headers_mut().insert(name, value);
+
+        Ok(())
+    }
+
+    /// Sends the given request, failing over to other endpoints if necessary.
+    ///
+    /// If the request fails to connect, or times out, it is retried against the next endpoint,
+    /// until either the request succeeds or all endpoints have been tried.  Any other errors, such
+    /// as the request failing after being sent, are returned immediately.
+    ///
+    /// Blocking queries which time out are not retried, as they are expected to sometimes run out
+    /// the clock, and that says nothing about the health of the endpoint.
+    async fn send_request(
+        &self,
+        request: Request<Body>,
+        timeout_dur: Option<Duration>,
+        blocking: bool,
+    ) -> Result<Response<Body>, Error> {
+        self.endpoints.refresh_if_stale().await?;
+
+        if self.endpoints.len() == 1 && !self.endpoints.is_discovered() {
+            return self.send_request_once(request, timeout_dur).await;
+        }
+
+        // We may need to send the request multiple times, so buffer the body up front.
+        let (parts, body) = request.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+
+        let mut last_error = None;
+        let attempts = self.endpoints.attempt_order();
+        #[cfg(feature = "log")]
+        let last = attempts.last().map(|(idx, _)| *idx);
+        for (idx, endpoint) in attempts {
+            let mut request = Request::new(Body::from(body.clone()));
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = rewrite_uri(&parts.uri, &endpoint);
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+
+            match self.send_request_once(request, timeout_dur).await {
+                Ok(response) => {
+                    self.endpoints.mark_success(idx);
+                    return Ok(response);
+                }
+                Err(e) if is_failover_error(&e, blocking) => {
+                    #[cfg(feature = "log")]
+                    if Some(idx) != last {
+                        log::warn!(
+                            "retrying Consul request {} {} after {} failed: {}",
+                            parts.method,
+                            parts.uri.path(),
+                            endpoint,
+                            e
+                        );
+                    }
+                    self.endpoints.mark_failure(idx);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.expect("at least one endpoint must be attempted"))
+    }
+
+    async fn send_request_once(
+        &self,
+        request: Request<Body>,
+        timeout_dur: Option<Duration>,
+    ) -> Result<Response<Body>, Error> {
+        let recorded = match self.recording.as_ref() {
+            Some(recording) => {
+                if let Some(response) = recording.replay(&request) {
+                    return response;
+                }
+                Some((
+                    recording,
+                    request.method().to_string(),
+                    recording::path_and_query(&request),
+                ))
+            }
+            None => None,
+        };
+
+        let mut response = if let Some(dur) = timeout_dur {
+            timeout(dur, self.client.request(request)).await??
+        } else {
+            self.client.request(request).await?
+        };
+
+        if let Some((recording, method, uri)) = recorded {
+            response = recording.record(&method, uri, response).await?;
+        }
+        Ok(response)
     }
 
     pub async fn parse_query_response<T>(
         &self,
         response: Response<Body>,
-    ) -> Result<(T, QueryMetadata), ResponseError>
+    ) -> Result<(T, QueryMetadata), Error>
     where
         T: DeserializeOwned,
     {
-        let status = response.status();
-        if !status.is_success() {
-            return Err(ResponseError::UnexpectedStatus(status));
+        let context = response.extensions().get::<RequestContext>().cloned();
+        parse_query_response(response, self.defaults.strict_payloads)
+            .await
+            .map_err(|e| Error::from(e).with_context(context))
+    }
+
+    /// Parses the response of a query operation which returns a list.
+    ///
+    /// Entries are decoded as they are received, rather than after buffering the entire response.
+    /// If lenient payloads are enabled, entries which cannot be decoded are skipped rather than
+    /// failing the entire response.
+    pub async fn parse_list_query_response<T>(
+        &self,
+        response: Response<Body>,
+    ) -> Result<(Vec<T>, QueryMetadata), Error>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let (entries, meta) = self.parse_list_query_stream(response).await?;
+        let entries = entries.try_collect().await?;
+        Ok((entries, meta))
+    }
+
+    /// Parses the response of a query operation which returns a list, incrementally.
+    ///
+    /// Rather than buffering the entire response, each entry of the list is decoded, and yielded
+    /// from the returned stream, as soon as it has been received.  If lenient payloads are enabled,
+    /// entries which cannot be decoded are skipped.
+    pub async fn parse_list_query_stream<T>(
+        &self,
+        response: Response<Body>,
+    ) -> Result<(ListStream<T>, QueryMetadata), Error>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        let with_context = move |e: ResponseError| Error::from(e).with_context(context.clone());
+
+        let response = check_status(response).await.map_err(&with_context)?;
+        let meta = QueryMetadata::from_headers(response.headers()).map_err(&with_context)?;
+        let lenient = self.defaults.lenient_payloads;
+        let strict = self.defaults.strict_payloads;
+
+        let mut body = response.into_body();
+        let stream = try_stream! {
+            let mut splitter = payload::ArraySplitter::default();
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk
+                    .map_err(ResponseError::BodyConsumeFailure)
+                    .map_err(&with_context)?;
+                for (index, element) in splitter.push(&chunk).map_err(&with_context)? {
+                    match payload::decode_element(index, &element, strict) {
+                        Ok(entry) => yield entry,
+                        Err(_) if lenient => {}
+                        Err(e) => Err(with_context(e))?,
+                    }
+                }
+            }
+            splitter.finish().map_err(&with_context)?;
+        };
+
+        Ok((Box::pin(stream), meta))
+    }
+
+    /// Parses the response of a query operation which returns a raw value, rather than JSON.
+    ///
+    /// The body is returned as-is.  If the resource was not found, `None` is returned along with
+    /// the metadata of the response.
+    pub async fn parse_raw_query_response(
+        &self,
+        response: Response<Body>,
+    ) -> Result<(Option<Bytes>, QueryMetadata), Error> {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        let result = async {
+            let not_found = response.status() == StatusCode::NOT_FOUND;
+            let response = if not_found {
+                response
+            } else {
+                check_status(response).await?
+            };
+            let meta = QueryMetadata::from_headers(response.headers())?;
+            let data = hyper::body::to_bytes(response.into_body()).await?;
+            Ok((if not_found { None } else { Some(data) }, meta))
+        };
+        result
+            .await
+            .map_err(|e: ResponseError| Error::from(e).with_context(context))
+    }
+
+    /// Parses the response of a query operation for a resource which may not exist.
+    ///
+    /// If the resource was not found, `None` is returned along with the metadata of the response,
+    /// which can still be used to block until the resource is created.
+    pub async fn parse_optional_query_response<T>(
+        &self,
+        response: Response<Body>,
+    ) -> Result<(Option<T>, QueryMetadata), Error>
+    where
+        T: DeserializeOwned,
+    {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        if response.status() == StatusCode::NOT_FOUND {
+            let result = async {
+                let meta = QueryMetadata::from_headers(response.headers())?;
+                let _ = hyper::body::to_bytes(response.into_body()).await?;
+                Ok((None, meta))
+            };
+            return result
+                .await
+                .map_err(|e: ResponseError| Error::from(e).with_context(context));
         }
 
-        let meta = QueryMetadata::from_headers(response.headers())?;
+        let (parsed, meta) = self.parse_query_response(response).await?;
+        Ok((Some(parsed), meta))
+    }
+
+    pub async fn parse_write_response<T>(&self, response: Response<Body>) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        parse_write_response(response, self.defaults.strict_payloads)
+            .await
+            .map_err(|e| Error::from(e).with_context(context))
+    }
+
+    pub async fn parse_empty_response(&self, response: Response<Body>) -> Result<(), Error> {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        parse_empty_response(response)
+            .await
+            .map_err(|e| Error::from(e).with_context(context))
+    }
+
+    /// Parses the response of an operation whose body is not JSON, returning the body as-is.
+    pub async fn parse_raw_response(&self, response: Response<Body>) -> Result<Bytes, Error> {
+        let context = response.extensions().get::<RequestContext>().cloned();
+        parse_raw_response(response)
+            .await
+            .map_err(|e| Error::from(e).with_context(context))
+    }
+}
+
+/// Gets the details of the given request to attach to any errors for it.
+fn request_context(request: &Request<Body>, blocking: bool) -> RequestContext {
+    let datacenter = request.uri().query().and_then(|query| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == "dc")
+            .map(|(_, v)| v.into_owned())
+    });
+
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    RequestContext {
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        datacenter,
+        blocking,
+        request_id,
+    }
+}
+
+/// Builds a copy of the given read which does not allow stale results, so that it is serviced by
+/// the leader.
+///
+/// Agent caching is disabled as well, as the agent could otherwise serve the same stale results
+/// from its cache.
+fn leader_request(request: &Request<Body>) -> Request<Body> {
+    let mut path_and_query = request.uri().path().to_string();
+    if let Some(query) = request.uri().query() {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(
+                form_urlencoded::parse(query.as_bytes())
+                    .filter(|(k, _)| k != "stale" && k != "max_stale" && k != "cached"),
+            )
+            .finish();
+        if !query.is_empty() {
+            path_and_query.push('?');
+            path_and_query.push_str(&query);
+        }
+    }
+
+    let mut uri = request.uri().clone().into_parts();
+    uri.path_and_query = Some(
+        path_and_query
+            .parse()
+            .expect("path and query of a valid URI must be valid"),
+    );
+    let mut leader_request = Request::new(Body::empty());
+    *leader_request.method_mut() = request.method().clone();
+    *leader_request.uri_mut() = Uri::from_parts(uri).expect("parts of a valid URI must be valid");
+    *leader_request.headers_mut() = request.headers().clone();
+    leader_request.headers_mut().remove(CACHE_CONTROL);
+    leader_request
+}
+
+/// Whether or not the given request allows stale results, and may be serviced by any server.
+fn allows_stale(request: &Request<Body>) -> bool {
+    request
+        .uri()
+        .query()
+        .is_some_and(|query| form_urlencoded::parse(query.as_bytes()).any(|(k, _)| k == "stale"))
+}
+
+/// Whether or not the given response is more stale than the given bound.
+///
+/// Responses from servers which do not know of a leader are always considered too stale.
+/// Responses without the staleness headers, such as those from agent endpoints, never are.
+fn too_stale(response: &Response<Body>, max_stale: Duration) -> bool {
+    if !response.status().is_success() {
+        return false;
+    }
+
+    let headers = response.headers();
+    let known_leader = headers
+        .get("X-Consul-KnownLeader")
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v == "true");
+    let last_contact = headers
+        .get("X-Consul-LastContact")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_millis);
+    !known_leader || last_contact.is_some_and(|contact| contact > max_stale)
+}
+
+/// Generates a new, random, correlation ID for a request.
+fn generate_request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    format!(
+        "{:016x}{:016x}",
+        hasher.finish(),
+        RandomState::new().build_hasher().finish()
+    )
+}
+
+async fn parse_query_response<T>(
+    response: Response<Body>,
+    strict: bool,
+) -> Result<(T, QueryMetadata), ResponseError>
+where
+    T: DeserializeOwned,
+{
+    let response = check_status(response).await?;
+
+    let meta = QueryMetadata::from_headers(response.headers())?;
+
+    let body = response.into_body();
+    let data = hyper::body::to_bytes(body).await?;
+
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        index = ?meta.last_index,
+        body_len = data.len(),
+        "parsing Consul query response"
+    );
+
+    let parsed = payload::decode(&data, strict)?;
+    Ok((parsed, meta))
+}
+
+async fn parse_write_response<T>(response: Response<Body>, strict: bool) -> Result<T, ResponseError>
+where
+    T: DeserializeOwned,
+{
+    let response = check_status(response).await?;
+
+    let body = response.into_body();
+    let data = hyper::body::to_bytes(body).await?;
+    let parsed = payload::decode(&data, strict)?;
+    Ok(parsed)
+}
+
+async fn parse_empty_response(response: Response<Body>) -> Result<(), ResponseError> {
+    let response = check_status(response).await?;
+
+    // Drain the body so that the connection can be reused.
+    let body = response.into_body();
+    let _ = hyper::body::to_bytes(body).await?;
+    Ok(())
+}
+
+async fn parse_raw_response(response: Response<Body>) -> Result<Bytes, ResponseError> {
+    let response = check_status(response).await?;
+
+    let body = response.into_body();
+    let data = hyper::body::to_bytes(body).await?;
+    Ok(data)
+}
+
+/// Serializes the given value as the body of a request.
+///
+/// Values which serialize to `null`, such as `()` or `None`, are treated as the request having no
+/// payload at all, and result in an empty body rather than a literal `null`.
+fn serialize_body<B: Serialize>(body: &B) -> Result<Body, Error> {
+    let serialized = serde_json::to_vec(body).map_err(Error::InvalidRequestBody)?;
+    if serialized == b"null" {
+        Ok(Body::empty())
+    } else {
+        Ok(Body::from(serialized))
+    }
+}
+
+/// Converts query pairs with static keys into pairs with owned keys.
+fn to_owned_pairs(
+    pairs: Vec<(&'static str, Cow<'static, str>)>,
+) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+    pairs
+        .into_iter()
+        .map(|(k, v)| (Cow::Borrowed(k), v))
+        .collect()
+}
+
+/// Merges a layer of query pairs into the given query pairs.
+///
+/// All existing values for any key present in `layer` are removed, and then the pairs from
+/// `layer` are appended in order.
+fn merge_query_pairs(
+    mut pairs: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+    layer: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+    pairs.retain(|(k, _)| !layer.iter().any(|(lk, _)| lk == k));
+    pairs.extend(layer);
+    pairs
+}
+
+/// Whether or not the given error warrants trying the request against another endpoint.
+fn is_failover_error(e: &Error, blocking: bool) -> bool {
+    match e {
+        Error::RequestError(e) => e.is_connect(),
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        Error::FetchError(e) => e.is_request(),
+        Error::RequestTimedOut(_) => !blocking,
+        _ => false,
+    }
+}
+
+/// Maximum number of bytes of a response body to include in an error.
+const MAX_ERROR_BODY_LEN: usize = 4096;
+
+/// Checks that a response has a successful status code.
+///
+/// If the status code is not successful, the body of the response is read and included in the
+/// resulting error, as Consul generally returns a message in the body explaining the failure.
+/// Common failure modes are mapped to their own error variants so that they can be easily handled.
+async fn check_status(response: Response<Body>) -> Result<Response<Body>, ResponseError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = parse_retry_after(response.headers());
+
+    let body = match hyper::body::to_bytes(response.into_body()).await {
+        Ok(data) => {
+            let len = data.len().min(MAX_ERROR_BODY_LEN);
+            String::from_utf8_lossy(&data[..len]).trim().to_string()
+        }
+        Err(_) => String::new(),
+    };
+
+    let error = match status {
+        StatusCode::FORBIDDEN => ResponseError::AclDenied(body),
+        StatusCode::NOT_FOUND => ResponseError::NotFound(body),
+        StatusCode::TOO_MANY_REQUESTS => ResponseError::RateLimited { retry_after, body },
+        status if status.is_server_error() => ResponseError::ServerError(status, body),
+        status => ResponseError::UnexpectedStatus(status, body),
+    };
+
+    Err(error)
+}
+
+/// Parses the `Retry-After` header, which can either be a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let at = httpdate::parse_http_date(value).ok()?;
+            // A date in the past means the request can be retried right away.
+            Some(
+                at.duration_since(system_now())
+                    .unwrap_or(Duration::from_secs(0)),
+            )
+        }
+    }
+}
+
+/// Logs the given result of a request if it failed.
+///
+/// Blocking queries running out the clock, requests rejected by an open circuit breaker, and
+/// requests rejected while shutting down, are expected, so they are not logged as failures.
+#[cfg(feature = "log")]
+fn log_failure(context: &RequestContext, result: &Result<Response<Body>, Error>) {
+    match result {
+        Ok(_)
+        | Err(Error::BlockingWaitExpired)
+        | Err(Error::CircuitOpen)
+        | Err(Error::ClientShutdown) => {}
+        Err(e) => log::error!("Consul request failed: {}: {}", context, e),
+    }
+}
+
+/// Creates the span used to trace a given request.
+#[cfg(feature = "tracing")]
+fn request_span(request: &Request<Body>) -> tracing::Span {
+    let mut datacenter = None;
+    let mut index = None;
+    if let Some(query) = request.uri().query() {
+        for (k, v) in url::form_urlencoded::parse(query.as_bytes()) {
+            match k.as_ref() {
+                "dc" => datacenter = Some(v.into_owned()),
+                "index" => index = Some(v.into_owned()),
+                _ => {}
+            }
+        }
+    }
+
+    tracing::debug_span!(
+        "consul_request",
+        method = %request.method(),
+        path = request.uri().path(),
+        dc = datacenter.as_deref(),
+        index = index.as_deref(),
+        request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok()),
+        status = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use url::Url;
+
+    /// Creates a client with the given defaults, whose requests are captured rather than sent.
+    fn capturing_client(defaults: RequestDefaults) -> (HttpClient, Arc<Mutex<Vec<Request<()>>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let captured = sent.clone();
+        let transport = Transport::from_fn(move |request| {
+            let (parts, _) = request.into_parts();
+            captured
+                .lock()
+                .unwrap()
+                .push(Request::from_parts(parts, ()));
+            Box::pin(async { Ok(Response::new(Body::empty())) })
+        });
+        let endpoints = Endpoints::new(
+            vec![Url::parse("http://127.0.0.1:8500").unwrap()],
+            Duration::from_secs(30),
+        );
+        let client = HttpClient::new(
+            endpoints,
+            transport,
+            defaults,
+            Interceptors::default(),
+            None,
+            None,
+            None,
+            None,
+        );
+        (client, sent)
+    }
+
+    fn defaults() -> RequestDefaults {
+        RequestDefaults {
+            token: Some("default-token".to_string()),
+            datacenter: Some("dc1".to_string()),
+            namespace: Some("team".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn defaults_apply_to_raw_requests() {
+        let (client, sent) = capturing_client(defaults());
+        let request = Request::get("http://127.0.0.1:8500/v1/catalog/services?dc=dc2")
+            .body(Body::empty())
+            .unwrap();
+        client
+            .run_request(request, None::<&QueryOptions>)
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].uri().query(), Some("dc=dc2&ns=team"));
+        assert_eq!(sent[0].headers()["X-Consul-Token"], "default-token");
+    }
+
+    #[tokio::test]
+    async fn defaults_apply_to_built_requests_unless_overridden() {
+        let (client, sent) = capturing_client(defaults());
+        let options = QueryOptions {
+            namespace: Some("other".to_string()),
+            token: Some("own-token".to_string()),
+            ..Default::default()
+        };
+        let request = client
+            .build_request("GET", &["v1", "catalog", "services"], Some(&options), ())
+            .unwrap();
+        client.run_request(request, Some(&options)).await.unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].uri().query(), Some("ns=other&dc=dc1"));
+        assert_eq!(sent[0].headers()["X-Consul-Token"], "own-token");
+    }
+
+    #[tokio::test]
+    async fn bearer_auth_applies_to_default_and_given_tokens() {
+        let (client, sent) = capturing_client(RequestDefaults {
+            bearer_auth: true,
+            ..defaults()
+        });
+        let options = QueryOptions {
+            token: Some("own-token".to_string()),
+            ..Default::default()
+        };
+        let request = client
+            .build_request("GET", &["v1", "agent", "self"], Some(&options), ())
+            .unwrap();
+        client.run_request(request, Some(&options)).await.unwrap();
+
+        let request = Request::get("/v1/agent/self").body(Body::empty()).unwrap();
+        client
+            .run_request(request, None::<&QueryOptions>)
+            .await
+            .unwrap();
+
+        let request = Request::get("/v1/agent/self")
+            .header(AUTHORIZATION, "Basic abc")
+            .body(Body::empty())
+            .unwrap();
+        client
+            .run_request(request, None::<&QueryOptions>)
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        let authorization = sent
+            .iter()
+            .map(|request| {
+                assert!(!request.headers().contains_key("X-Consul-Token"));
+                request.headers()[AUTHORIZATION].to_str().unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            authorization,
+            vec!["Bearer own-token", "Bearer default-token", "Basic abc"]
+        );
+    }
+
+    fn pairs(
+        pairs: &[(&'static str, &'static str)],
+    ) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (Cow::Borrowed(*k), Cow::Borrowed(*v)))
+            .collect()
+    }
+
+    #[test]
+    fn merge_query_pairs_keeps_repeated_keys_in_order() {
+        let merged = merge_query_pairs(
+            Vec::new(),
+            pairs(&[
+                ("node-meta", "rack:a"),
+                ("tag", "x"),
+                ("node-meta", "zone:east"),
+            ]),
+        );
+        assert_eq!(
+            merged,
+            pairs(&[
+                ("node-meta", "rack:a"),
+                ("tag", "x"),
+                ("node-meta", "zone:east")
+            ])
+        );
+    }
+
+    #[test]
+    fn merge_query_pairs_replaces_all_values_of_overridden_keys() {
+        let base = pairs(&[
+            ("node-meta", "rack:a"),
+            ("dc", "dc1"),
+            ("node-meta", "zone:east"),
+        ]);
+        let merged = merge_query_pairs(base, pairs(&[("node-meta", "rack:b"), ("stale", "1")]));
+        assert_eq!(
+            merged,
+            pairs(&[("dc", "dc1"), ("node-meta", "rack:b"), ("stale", "1")])
+        );
+    }
 
-        let body = response.into_body();
-        let data = hyper::body::to_bytes(body).await?;
-        let parsed: T = serde_json::from_slice(&data)?;
-        Ok((parsed, meta))
+    #[test]
+    fn merge_query_pairs_keeps_pairs_not_in_layer() {
+        let base = pairs(&[("dc", "dc1"), ("ns", "team")]);
+        assert_eq!(merge_query_pairs(base.clone(), Vec::new()), base);
     }
 }
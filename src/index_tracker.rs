@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use hyper::{Body, Response};
+
+/// Tracks the Consul indexes seen in the responses to a client.
+///
+/// The highest index seen across all responses gives a rough idea of how far along the Raft log a
+/// client has observed the cluster.  Indexes are only comparable between responses for the same
+/// resource, though, as each endpoint reports the index of the last change to the data it covers,
+/// so the highest index is also tracked per resource when reads are required to be monotonic.
+#[derive(Debug)]
+pub(crate) struct IndexTracker {
+    monotonic: bool,
+    last_index: AtomicU64,
+    // The highest index seen for each resource, only tracked when reads must be monotonic.
+    resources: Mutex<HashMap<String, u64>>,
+}
+
+impl IndexTracker {
+    pub fn new(monotonic: bool) -> IndexTracker {
+        IndexTracker {
+            monotonic,
+            last_index: AtomicU64::new(0),
+            resources: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether or not reads must be monotonic.
+    pub fn is_monotonic(&self) -> bool {
+        self.monotonic
+    }
+
+    /// Gets the highest index seen in any response, if any.
+    pub fn last_index(&self) -> Option<u64> {
+        match self.last_index.load(Ordering::Relaxed) {
+            0 => None,
+            index => Some(index),
+        }
+    }
+
+    /// Whether or not the given response for the given resource has a lower index than a previous
+    /// response for the same resource, meaning it must have come from a server which is lagging
+    /// behind.
+    pub fn is_behind(&self, resource: Option<&str>, response: &Response<Body>) -> bool {
+        match (resource, response_index(response)) {
+            (Some(resource), Some(index)) => self
+                .resources
+                .lock()
+                .expect("index tracker lock poisoned")
+                .get(resource)
+                .is_some_and(|seen| index < *seen),
+            _ => false,
+        }
+    }
+
+    /// Records the index of the given response, for the given resource if it is tracked.
+    pub fn observe(&self, resource: Option<&str>, response: &Response<Body>) {
+        let index = match response_index(response) {
+            Some(index) => index,
+            None => return,
+        };
+        self.last_index.fetch_max(index, Ordering::Relaxed);
+
+        if let Some(resource) = resource {
+            let mut resources = self.resources.lock().expect("index tracker lock poisoned");
+            let seen = resources.entry(resource.to_string()).or_insert(0);
+            *seen = std::cmp::max(*seen, index);
+        }
+    }
+}
+
+/// Gets the index of the given response, if it is successful and carries one.
+fn response_index(response: &Response<Body>) -> Option<u64> {
+    if !response.status().is_success() {
+        return None;
+    }
+    response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
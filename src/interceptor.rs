@@ -0,0 +1,55 @@
+use std::fmt;
+use std::sync::Arc;
+
+use hyper::{Body, Request, Response};
+
+/// Hook for observing and modifying the requests made by a [`Client`](crate::Client).
+///
+/// Interceptors are called, in the order they were registered, just before a request is sent and
+/// just after its response headers are received.  This can be used to add custom authentication
+/// headers, audit logging, or to attach request IDs, without needing to modify the client itself.
+pub trait RequestInterceptor: Send + Sync + 'static {
+    /// Called before the given request is sent.
+    ///
+    /// The request can be modified freely, such as to add headers.
+    fn on_request(&self, _request: &mut Request<Body>) {}
+
+    /// Called after the response for a request has been received.
+    ///
+    /// Only the response headers are available at this point, as the body is consumed afterwards
+    /// when the response is parsed.
+    fn on_response(&self, _response: &Response<Body>) {}
+}
+
+/// The set of interceptors registered on a client.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Vec<Arc<dyn RequestInterceptor>>);
+
+impl Interceptors {
+    pub fn push<I>(&mut self, interceptor: I)
+    where
+        I: RequestInterceptor,
+    {
+        self.0.push(Arc::new(interceptor));
+    }
+
+    pub fn on_request(&self, request: &mut Request<Body>) {
+        for interceptor in self.0.iter() {
+            interceptor.on_request(request);
+        }
+    }
+
+    pub fn on_response(&self, response: &Response<Body>) {
+        for interceptor in self.0.iter() {
+            interceptor.on_response(response);
+        }
+    }
+}
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interceptors")
+            .field("count", &self.0.len())
+            .finish()
+    }
+}
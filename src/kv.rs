@@ -0,0 +1,320 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use hyper::body::Bytes;
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::watch::Watch;
+
+/// A key/value pair stored in the KV store.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct KvPair {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "CreateIndex")]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex")]
+    pub modify_index: u64,
+    #[serde(rename = "LockIndex")]
+    pub lock_index: u64,
+    #[serde(rename = "Flags")]
+    pub flags: u64,
+    #[serde(rename = "Value", with = "base64", default)]
+    pub value: Option<Bytes>,
+    #[serde(rename = "Session", default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+}
+
+/// KV store operations.
+///
+/// This type can be used to interact with the "KV Store" portion of the Consul API.
+#[derive(Clone, Debug)]
+pub struct Kv {
+    http_client: Arc<HttpClient>,
+}
+
+impl Kv {
+    /// Creates a new [`Kv`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Kv {
+        Kv { http_client }
+    }
+
+    pub(crate) fn http_client(&self) -> &Arc<HttpClient> {
+        &self.http_client
+    }
+
+    /// Gets the key/value pair for the given key.
+    ///
+    /// Returns `None` if the key does not exist.
+    pub async fn get(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<KvPair>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        get(&self.http_client, key, options.as_ref()).await
+    }
+
+    /// Gets the raw value of the given key.
+    ///
+    /// Unlike [`get`](Kv::get), the value is returned exactly as stored, directly from the
+    /// response, without the overhead of Consul encoding it or the key's metadata.  Returns `None`
+    /// if the key does not exist.
+    pub async fn get_raw(
+        &self,
+        key: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<Bytes>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let params = vec![("raw", Cow::Borrowed(""))];
+        let request = self.http_client.build_request_with_params(
+            "GET",
+            key_path(key),
+            params,
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (value, meta) = self.http_client.parse_raw_query_response(response).await?;
+        Ok((value, meta))
+    }
+
+    /// Lists the key/value pairs for all keys with the given prefix.
+    pub async fn list(
+        &self,
+        prefix: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<KvPair>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        list(&self.http_client, prefix, options.as_ref()).await
+    }
+
+    /// Sets the value of the given key.
+    ///
+    /// Returns whether or not the value was written.
+    ///
+    /// The value can be anything convertible into [`Bytes`], such as a `Vec<u8>` or `String`, which
+    /// is sent without being copied.
+    pub async fn put<V>(
+        &self,
+        key: &str,
+        value: V,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error>
+    where
+        V: Into<Bytes>,
+    {
+        let request = self.http_client.build_raw_request(
+            "PUT",
+            key_path(key),
+            Vec::new(),
+            options.as_ref(),
+            Body::from(value.into()),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let written = self.http_client.parse_write_response(response).await?;
+        Ok(written)
+    }
+
+    /// Writes the given value to the given key, tied to the given session.
+    ///
+    /// The key is locked by the session, so that it is deleted or released together with the
+    /// session, based on the behavior of the session.  Combined with a session from
+    /// [`Session::create_ephemeral`](crate::Session::create_ephemeral), this can be used for
+    /// presence or heartbeat keys which disappear when the process holding them dies.
+    ///
+    /// Returns whether or not the key was written, which is `false` if it is held by another
+    /// session.
+    pub async fn put_ephemeral<V>(
+        &self,
+        key: &str,
+        value: V,
+        session: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error>
+    where
+        V: Into<Bytes>,
+    {
+        let request = self.http_client.build_raw_request(
+            "PUT",
+            key_path(key),
+            vec![("acquire", Cow::Owned(session.to_string()))],
+            options.as_ref(),
+            Body::from(value.into()),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let written = self.http_client.parse_write_response(response).await?;
+        Ok(written)
+    }
+
+    /// Deletes the given key.
+    ///
+    /// Returns whether or not the key was deleted.
+    pub async fn delete(&self, key: &str, options: Option<WriteOptions>) -> Result<bool, Error> {
+        let request =
+            self.http_client
+                .build_request("DELETE", key_path(key), options.as_ref(), ())?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let deleted = self.http_client.parse_write_response(response).await?;
+        Ok(deleted)
+    }
+
+    /// Gets a stream of changes to the given key.
+    ///
+    /// Each item in the response stream represents the key/value pair after a change to it has
+    /// occurred, or `None` if the key does not exist.  The stream will terminate if any error is
+    /// hit during the background requests made to Consul.
+    pub fn watch_key(&self, key: &str, options: Option<QueryOptions>) -> Watch<Option<KvPair>> {
+        let options = self.http_client.watch_options(options);
+        let key = key.to_string();
+        let http_client = self.http_client.clone();
+
+        Watch::new(options, move |options| {
+            let key = key.clone();
+            let http_client = http_client.clone();
+            async move { get(&http_client, &key, Some(&options)).await }
+        })
+        .tracked(self.http_client.stats())
+    }
+
+    /// Gets a stream of changes to the keys with the given prefix.
+    ///
+    /// Each item in the response stream represents all key/value pairs with the prefix after a
+    /// change to any of them has occurred.  The stream will terminate if any error is hit during the
+    /// background requests made to Consul.
+    pub fn watch_prefix(&self, prefix: &str, options: Option<QueryOptions>) -> Watch<Vec<KvPair>> {
+        let options = self.http_client.watch_options(options);
+        let prefix = prefix.to_string();
+        let http_client = self.http_client.clone();
+
+        Watch::new(options, move |options| {
+            let prefix = prefix.clone();
+            let http_client = http_client.clone();
+            async move { list(&http_client, &prefix, Some(&options)).await }
+        })
+        .tracked(self.http_client.stats())
+    }
+}
+
+/// Gets the URL path segments for the given key.
+///
+/// Keys are hierarchical, so each part of the key becomes its own segment rather than having its
+/// slashes escaped.
+fn key_path(key: &str) -> Vec<&str> {
+    let mut parts = vec!["v1", "kv"];
+    parts.extend(key.split('/'));
+    parts
+}
+
+async fn get(
+    http_client: &HttpClient,
+    key: &str,
+    options: Option<&QueryOptions>,
+) -> Result<(Option<KvPair>, QueryMetadata), Error> {
+    let request = http_client.build_request("GET", key_path(key), options, ())?;
+    let response = http_client.run_request(request, options).await?;
+    let (parsed, meta) = http_client
+        .parse_optional_query_response::<Vec<KvPair>>(response)
+        .await?;
+    Ok((parsed.and_then(|pairs| pairs.into_iter().next()), meta))
+}
+
+async fn list(
+    http_client: &HttpClient,
+    prefix: &str,
+    options: Option<&QueryOptions>,
+) -> Result<(Vec<KvPair>, QueryMetadata), Error> {
+    let params = vec![("recurse", Cow::Borrowed(""))];
+    let request =
+        http_client.build_request_with_params("GET", key_path(prefix), params, options, ())?;
+    let response = http_client.run_request(request, options).await?;
+    let (parsed, meta) = http_client.parse_optional_query_response(response).await?;
+    Ok((parsed.unwrap_or_default(), meta))
+}
+
+/// Serde helpers for the base64-encoded values returned by the KV store.
+pub(crate) mod base64 {
+    use ::base64::engine::general_purpose::STANDARD;
+    use ::base64::Engine as _;
+    use hyper::body::Bytes;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn encode(data: &[u8]) -> String {
+        STANDARD.encode(data)
+    }
+
+    pub(crate) fn decode(encoded: &str) -> Result<Vec<u8>, String> {
+        STANDARD.decode(encoded).map_err(|e| e.to_string())
+    }
+
+    pub(crate) fn serialize<S>(value: &Option<Bytes>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(value) => serializer.serialize_some(&encode(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Bytes>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Option::<String>::deserialize(deserializer)?;
+        raw.map(|raw| decode(&raw).map(Bytes::from).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(value: &str) -> String {
+        format!(
+            r#"{{"Key":"foo","CreateIndex":1,"ModifyIndex":2,"LockIndex":0,"Flags":0,"Value":{}}}"#,
+            value
+        )
+    }
+
+    #[test]
+    fn decodes_values() {
+        let parsed: KvPair = serde_json::from_str(&pair(r#""aGVsbG8=""#)).unwrap();
+        assert_eq!(parsed.value, Some(Bytes::from_static(b"hello")));
+
+        let parsed: KvPair = serde_json::from_str(&pair("null")).unwrap();
+        assert_eq!(parsed.value, None);
+    }
+
+    #[test]
+    fn round_trips_values() {
+        for value in [&b""[..], b"a", b"ab", b"abc", b"\x00\xff\x10binary"] {
+            let encoded = base64::encode(value);
+            assert_eq!(base64::decode(&encoded).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for value in [r#""aGV=sbG8""#, r#""aGVsbG8""#, r#""aGVs*G8=""#] {
+            assert!(serde_json::from_str::<KvPair>(&pair(value)).is_err());
+        }
+    }
+}
@@ -1,18 +1,123 @@
 //! A Tokio-based asynchronous client for the Consul API.
 #![deny(missing_debug_implementations)]
 use std::sync::Arc;
-use url::Url;
+use std::time::Duration;
 
+use hyper::client::connect::Connect as HyperConnect;
+use hyper::client::Client as HyperClient;
+use hyper::Body;
+
+mod acl;
 mod agent;
+mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+mod cache;
 mod catalog;
+mod catalog_sync;
+mod circuit_breaker;
 pub mod common;
+mod config_entries;
+mod config_watcher;
+mod connect;
+#[cfg(feature = "native")]
+mod connector;
+mod coordinate;
+mod discovery;
+mod endpoints;
 mod errors;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod fetch;
+mod flap_damping;
 mod health;
 mod http_client;
+mod index_tracker;
+mod interceptor;
+mod kv;
+mod operator;
+mod paging;
+mod payload;
+mod recording;
+mod render;
+mod resolver;
+mod restart_budget;
+mod runtime;
+mod service;
+mod service_definition;
+mod service_set;
+mod session;
+mod shutdown;
+mod snapshot;
+#[cfg(feature = "native")]
+mod srv_discovery;
+mod stats;
+mod status;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod token;
+mod watch;
+mod watch_manager;
 
-pub use self::catalog::{Catalog, CatalogServiceNode};
+pub use self::acl::{AccessLevel, Acl, AclPolicy, AclPolicyRequest, AclResource, AclRules};
+pub use self::agent::{
+    Agent, AgentCheck, AgentMetrics, AgentMetricsGauge, AgentMetricsPoint, AgentMetricsSample,
+    AgentService, AgentServiceCheck, AgentServiceConnectProxyConfig, AgentServiceKind,
+    AgentServiceRegistration, AgentServiceUpstream, AgentServiceWeights, AgentWeights,
+    TtlHeartbeat, UpstreamDestinationType,
+};
+pub use self::api::{ApiFuture, CatalogApi, HealthApi, KvApi};
+#[cfg(feature = "mockall")]
+pub use self::api::{MockCatalogApi, MockHealthApi, MockKvApi};
+pub use self::builder::ClientBuilder;
+pub use self::catalog::{
+    Catalog, CatalogDeregistration, CatalogNode, CatalogNodeServiceList, CatalogNodeServices,
+    CatalogRegistration, CatalogRegistrationService, CatalogServiceNode, CatalogServiceSummary,
+    Change, InstanceKey, ServiceAddress, ServiceNodeEvent, TaggedAddresses, Weights,
+};
+pub use self::catalog_sync::{CatalogSync, SyncReport, EXTERNAL_SOURCE_META_KEY};
+pub use self::config_entries::{
+    ConfigEntries, ConfigEntry, ExportedService, ExportedServicesConfigEntry, ExposeConfig,
+    ExposePath, GatewayTlsConfig, IngressGatewayConfigEntry, IngressListener, IngressService,
+    LinkedService, MeshConfigEntry, MeshGatewayConfig, MeshGatewayMode, ProxyConfigEntry,
+    SamenessGroupConfigEntry, SamenessGroupMember, ServiceConfigEntry, ServiceConsumer,
+    ServiceResolverConfigEntry, ServiceResolverFailover, ServiceResolverFailoverTarget,
+    ServiceResolverRedirect, ServiceResolverSubset, ServiceRoute, ServiceRouteDestination,
+    ServiceRouteHttpMatch, ServiceRouteHttpMatchHeader, ServiceRouteHttpMatchQueryParam,
+    ServiceRouteMatch, ServiceRouterConfigEntry, ServiceSplit, ServiceSplitterConfigEntry,
+    TerminatingGatewayConfigEntry, TransparentProxyMeshConfig,
+};
+pub use self::config_watcher::ConfigWatcher;
+pub use self::connect::{Connect, Intention, IntentionAction};
+#[cfg(feature = "native")]
+pub use self::connector::{IpPreference, TlsConfig};
+pub use self::coordinate::{
+    CoordinateDatacenterMap, CoordinateEntry, Coordinates, NetworkCoordinate, NodeName,
+};
+pub use self::discovery::{DiscoveryFuture, ServerDiscovery};
 pub use self::errors::*;
+pub use self::health::{CheckStatus, Health, HealthCheck, HealthCheckDefinition, ServiceEntry};
 use self::http_client::HttpClient;
+pub use self::interceptor::RequestInterceptor;
+pub use self::kv::{Kv, KvPair};
+pub use self::operator::{AutopilotHealth, AutopilotServerHealth, KeyringResponse, Operator};
+pub use self::render::{RenderSnapshot, Renderer};
+pub use self::resolver::{
+    Nearest, PickStrategy, Random, RoundRobin, ServiceResolver, Weighted, WeightedDistribution,
+    WeightedInstance,
+};
+pub use self::service::HttpService;
+pub use self::service_set::{ServiceSelector, ServiceSetEvent};
+pub use self::session::{EphemeralSession, Session, SessionBehavior, SessionEntry, SessionRequest};
+pub use self::snapshot::SnapshotMeta;
+#[cfg(feature = "native")]
+pub use self::srv_discovery::SrvDiscovery;
+pub use self::stats::{ClientStats, ErrorCounts, WatchStats};
+pub use self::status::{ClusterHealth, Status};
+pub use self::token::{FileTokenProvider, TokenFuture, TokenProvider};
+pub use self::watch::{SharedWatch, Watch};
+pub use self::watch_manager::{WatchEvent, WatchManager};
+pub use hyper::body::Bytes;
 
 /// High-level client for interacting with the Consul API.
 ///
@@ -27,16 +132,167 @@ pub struct Client {
 impl Client {
     /// Create a new [`Client`].
     pub fn new(base_uri: &str) -> Result<Client, Error> {
-        let base_uri = Url::parse(base_uri)?;
-        let http_client = HttpClient::new(base_uri);
+        Client::builder().base_uri(base_uri).build()
+    }
 
-        Ok(Client {
-            http_client: Arc::new(http_client),
-        })
+    /// Create a new [`Client`] which sends requests with the given hyper client.
+    ///
+    /// See [`ClientBuilder::hyper_client`] for how the hyper client is used.
+    pub fn with_hyper_client<C>(
+        client: HyperClient<C, Body>,
+        base_uri: &str,
+    ) -> Result<Client, Error>
+    where
+        C: HyperConnect + Clone + Send + Sync + 'static,
+    {
+        Client::builder()
+            .base_uri(base_uri)
+            .hyper_client(client)
+            .build()
+    }
+
+    /// Create a new [`Client`], verifying that it can reach Consul.
+    ///
+    /// See [`ClientBuilder::connect`] for what is verified.
+    pub async fn connect(base_uri: &str) -> Result<Client, Error> {
+        Client::builder().base_uri(base_uri).connect().await
+    }
+
+    /// Create a new [`Client`] configured from the environment.
+    ///
+    /// See [`ClientBuilder::from_env`] for the environment variables which are used.
+    pub fn from_env() -> Result<Client, Error> {
+        ClientBuilder::from_env()?.build()
+    }
+
+    /// Creates a [`ClientBuilder`] for configuring a new [`Client`].
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Gets an [`Acl`] object for working with the ACL API.
+    pub fn acl(&self) -> Acl {
+        Acl::new(self.http_client.clone())
+    }
+
+    /// Gets an [`Agent`] object for working with the agent API.
+    pub fn agent(&self) -> Agent {
+        Agent::new(self.http_client.clone())
     }
 
     /// Gets a [`Catalog`] object for working with the catalog API.
     pub fn catalog(&self) -> Catalog {
         Catalog::new(self.http_client.clone())
     }
+
+    /// Gets a [`ConfigEntries`] object for working with the config entry API.
+    pub fn config_entries(&self) -> ConfigEntries {
+        ConfigEntries::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Connect`] object for working with the Connect API.
+    ///
+    /// This is not named `connect`, as that is used for connecting to Consul.
+    pub fn connect_api(&self) -> Connect {
+        Connect::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Coordinates`] object for working with the network coordinate API.
+    pub fn coordinates(&self) -> Coordinates {
+        Coordinates::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Health`] object for working with the health API.
+    pub fn health(&self) -> Health {
+        Health::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Kv`] object for working with the KV store API.
+    pub fn kv(&self) -> Kv {
+        Kv::new(self.http_client.clone())
+    }
+
+    /// Gets an [`Operator`] object for working with the operator API.
+    pub fn operator(&self) -> Operator {
+        Operator::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Session`] object for working with the session API.
+    pub fn session(&self) -> Session {
+        Session::new(self.http_client.clone())
+    }
+
+    /// Gets a [`Status`] object for working with the status API.
+    pub fn status(&self) -> Status {
+        Status::new(self.http_client.clone())
+    }
+
+    /// Gets an [`HttpService`] for making raw HTTP requests to Consul.
+    pub fn http_service(&self) -> HttpService {
+        HttpService::new(self.http_client.clone())
+    }
+
+    /// Gets the highest Consul index seen in any response to this client, or any of its clones.
+    ///
+    /// This gives a rough idea of how far along the Raft log of the cluster this client has
+    /// observed, across all of the data it has read.  Returns `None` if no response carried an
+    /// index yet.
+    pub fn last_index(&self) -> Option<u64> {
+        self.http_client.last_index()
+    }
+
+    /// Gets a snapshot of the statistics of this client, such as the number of requests made.
+    ///
+    /// Statistics are shared between all clones of this client.
+    pub fn stats(&self) -> ClientStats {
+        self.http_client.stats().snapshot()
+    }
+
+    /// Shuts down this client, and all of its clones.
+    ///
+    /// New requests are rejected with [`Error::ClientShutdown`], and blocking queries, including
+    /// those of running watches, are cancelled, which ends the watches.  Other requests which are
+    /// already in flight are waited on, for up to the given timeout, so that writes are not
+    /// abandoned halfway through.
+    ///
+    /// Returns `true` if all requests in flight finished before the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.http_client.shutdown(timeout).await
+    }
+
+    /// Gets a summary of the health of the Consul cluster, combining its leader, its peers, and
+    /// the health of its servers as tracked by Autopilot.
+    ///
+    /// This is intended for readiness checks of services which cannot function without Consul.
+    /// Failing to read the Autopilot health, such as due to the token lacking `operator:read`
+    /// permissions, is not an error, but failing to reach Consul at all is.
+    pub async fn cluster_health(&self) -> Result<ClusterHealth, Error> {
+        let status = self.status();
+        let (leader, _) = status.leader(None).await?;
+        let (peers, _) = status.peers(None).await?;
+        // Autopilot can only report on the servers while there is a leader.
+        let autopilot = match leader {
+            Some(_) => self
+                .operator()
+                .autopilot_health(None)
+                .await
+                .map(|(health, _)| health)
+                .ok(),
+            None => None,
+        };
+
+        Ok(ClusterHealth {
+            leader,
+            peers,
+            autopilot,
+        })
+    }
+
+    /// Checks that Consul is reachable, and that its cluster has a leader.
+    pub(crate) async fn verify(&self) -> Result<(), Error> {
+        match self.status().leader(None).await? {
+            (Some(_), _) => Ok(()),
+            (None, _) => Err(Error::NoClusterLeader),
+        }
+    }
 }
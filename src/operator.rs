@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::{go_duration, QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::payload;
+
+/// The health of the servers in a cluster, as tracked by Autopilot.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AutopilotHealth {
+    /// Whether or not all of the servers are healthy.
+    #[serde(rename = "Healthy")]
+    pub healthy: bool,
+    /// How many servers can fail without the cluster losing its leader.
+    #[serde(rename = "FailureTolerance")]
+    pub failure_tolerance: u64,
+    #[serde(rename = "Servers", default)]
+    pub servers: Vec<AutopilotServerHealth>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The health of a single server, as tracked by Autopilot.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AutopilotServerHealth {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Address")]
+    pub address: String,
+    #[serde(rename = "SerfStatus")]
+    pub serf_status: String,
+    #[serde(rename = "Version")]
+    pub version: String,
+    #[serde(rename = "Leader")]
+    pub leader: bool,
+    #[serde(rename = "LastContact", with = "go_duration", default)]
+    pub last_contact: Duration,
+    #[serde(rename = "LastTerm")]
+    pub last_term: u64,
+    #[serde(rename = "LastIndex")]
+    pub last_index: u64,
+    #[serde(rename = "Healthy")]
+    pub healthy: bool,
+    #[serde(rename = "Voter")]
+    pub voter: bool,
+    #[serde(rename = "StableSince")]
+    pub stable_since: String,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The state of the gossip encryption keyring of one gossip pool, as reported by its members.
+///
+/// Keyring operations are fanned out to every member of every pool, so some members can fail or
+/// disagree with the rest: [`num_nodes`](KeyringResponse::num_nodes) is the number of members that
+/// responded, and comparing it with the count of a key shows whether every member has it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct KeyringResponse {
+    /// Whether or not this is the WAN pool of the servers, rather than the LAN pool of a
+    /// datacenter.
+    #[serde(rename = "WAN", default)]
+    pub wan: bool,
+    #[serde(rename = "Datacenter", default)]
+    pub datacenter: String,
+    /// The network segment of the pool, if it is a LAN pool restricted to one.
+    #[serde(rename = "Segment", default)]
+    pub segment: Option<String>,
+    #[serde(rename = "Partition", default)]
+    pub partition: Option<String>,
+    /// The keys installed in the pool, along with the number of members which have each of them.
+    #[serde(rename = "Keys", default)]
+    pub keys: HashMap<String, u64>,
+    /// The keys used to encrypt messages in the pool, along with the number of members using each
+    /// of them.
+    ///
+    /// Only reported by Consul 1.13 and later.
+    #[serde(rename = "PrimaryKeys", default)]
+    pub primary_keys: HashMap<String, u64>,
+    /// Number of members of the pool which responded.
+    #[serde(rename = "NumNodes", default)]
+    pub num_nodes: u64,
+    /// Errors reported by members of the pool, keyed by the name of the member.
+    #[serde(rename = "Messages", default)]
+    pub messages: HashMap<String, String>,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+impl KeyringResponse {
+    /// Whether or not the given key is installed on every member of the pool which responded.
+    pub fn is_installed_everywhere(&self, key: &str) -> bool {
+        self.keys.get(key) == Some(&self.num_nodes)
+    }
+
+    /// Whether or not any member of the pool reported an error.
+    pub fn has_errors(&self) -> bool {
+        !self.messages.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct KeyringRequest<'a> {
+    #[serde(rename = "Key")]
+    key: &'a str,
+}
+
+/// Operator operations.
+///
+/// This type can be used to interact with the "Operator" portion of the Consul API, which manages
+/// the Raft cluster, Autopilot and the gossip encryption keyring.
+#[derive(Clone, Debug)]
+pub struct Operator {
+    http_client: Arc<HttpClient>,
+}
+
+impl Operator {
+    /// Creates a new [`Operator`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Operator {
+        Operator { http_client }
+    }
+
+    /// Gets the health of the servers in the cluster, as tracked by Autopilot.
+    ///
+    /// Requires a token with `operator:read` permissions.
+    pub async fn autopilot_health(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(AutopilotHealth, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "operator", "autopilot", "health"],
+            options.as_ref(),
+            (),
+        )?;
+        let mut response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        // Consul answers with a 429 when the cluster is unhealthy, but still reports its health.
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            *response.status_mut() = StatusCode::OK;
+        }
+        let (parsed, meta) = self.http_client.parse_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Transfers Raft leadership to another server.
+    ///
+    /// If `id` is given, leadership is transferred to the server with that node ID, such as the
+    /// [`id`](AutopilotServerHealth::id) of a server reported by Autopilot.  Otherwise, Consul
+    /// picks the most up-to-date voter.  This is meant to move leadership off a server before it
+    /// is restarted for maintenance, rather than waiting for an election after it is gone.
+    ///
+    /// Returns whether or not leadership was transferred.  Requires a token with `operator:write`
+    /// permissions, and Consul 1.17 or later.
+    pub async fn raft_transfer_leader(
+        &self,
+        id: Option<&str>,
+        options: Option<WriteOptions>,
+    ) -> Result<bool, Error> {
+        let mut params = Vec::new();
+        if let Some(id) = id {
+            params.push(("id", Cow::Owned(id.to_string())));
+        }
+        let request = self.http_client.build_request_with_params(
+            "POST",
+            &["v1", "operator", "raft", "transfer-leader"],
+            params,
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let result: TransferLeaderResult = self.http_client.parse_write_response(response).await?;
+        Ok(result.success)
+    }
+
+    /// Lists the gossip encryption keys installed in each gossip pool.
+    ///
+    /// There is one response for the WAN pool of the servers, and one for the LAN pool of each
+    /// datacenter, or of each segment or partition.  The WAN pool can be skipped with
+    /// [`QueryOptions::local_only`].  Members which failed to respond are reported in the
+    /// [`messages`](KeyringResponse::messages) of their pool rather than failing the whole
+    /// operation.
+    ///
+    /// Requires a token with `keyring:read` permissions.
+    pub async fn keyring_list(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<KeyringResponse>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "operator", "keyring"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_list_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+
+    /// Installs a new gossip encryption key on every member of every gossip pool.
+    ///
+    /// The key is not used for encryption until it is made primary with
+    /// [`keyring_use`](Operator::keyring_use), so it should be installed everywhere first, which
+    /// can be checked with [`keyring_list`](Operator::keyring_list).
+    ///
+    /// Requires a token with `keyring:write` permissions.
+    pub async fn keyring_install(
+        &self,
+        key: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        self.keyring_write("POST", key, options).await
+    }
+
+    /// Makes the given gossip encryption key, which must already be installed, the primary key used
+    /// to encrypt messages.
+    ///
+    /// Requires a token with `keyring:write` permissions.
+    pub async fn keyring_use(&self, key: &str, options: Option<WriteOptions>) -> Result<(), Error> {
+        self.keyring_write("PUT", key, options).await
+    }
+
+    /// Removes the given gossip encryption key, which must not be the primary key, from every
+    /// member of every gossip pool.
+    ///
+    /// Requires a token with `keyring:write` permissions.
+    pub async fn keyring_remove(
+        &self,
+        key: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        self.keyring_write("DELETE", key, options).await
+    }
+
+    async fn keyring_write(
+        &self,
+        method: &'static str,
+        key: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<(), Error> {
+        let request = self.http_client.build_request(
+            method,
+            &["v1", "operator", "keyring"],
+            options.as_ref(),
+            KeyringRequest { key },
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        self.http_client.parse_empty_response(response).await
+    }
+}
+
+#[derive(Deserialize)]
+struct TransferLeaderResult {
+    #[serde(rename = "Success", default)]
+    success: bool,
+}
@@ -0,0 +1,154 @@
+use std::future::Future;
+
+use async_stream::try_stream;
+use futures::stream::Stream;
+
+use crate::common::{QueryMetadata, QueryOptions};
+use crate::errors::Error;
+
+/// Characters node names are expected to start with, in the order they are split into pages.
+///
+/// Node names are usually hostnames, which are lowercase.  Node names starting with any other
+/// character are included in the last page.
+const NODE_NAME_START: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Runs the given query once per page, splitting the results into pages based on the first
+/// character of the node name given by `selector`.
+///
+/// Each page is selected with a filter expression, combined with any filter already set in the
+/// options, so the pages are disjoint, cover all results, and a result always falls in the same
+/// page no matter what other results there are.
+///
+/// Each page is a separate query, with its own metadata, and Consul can only serve the current
+/// state of the results rather than their state as of an earlier index, so the pages can't be
+/// pinned to a single snapshot.  Callers which need one can compare the
+/// [`last_index`](QueryMetadata::last_index) of each page to that of the first page, and page
+/// through again if it moved.
+pub(crate) fn paged<T, F, Fut>(
+    options: Option<QueryOptions>,
+    selector: &'static str,
+    pages: usize,
+    mut query: F,
+) -> impl Stream<Item = Result<(Vec<T>, QueryMetadata), Error>>
+where
+    F: FnMut(QueryOptions) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, QueryMetadata), Error>>,
+{
+    let options = options.unwrap_or_default();
+    let filters = page_filters(selector, pages, options.filtering.as_deref());
+
+    try_stream! {
+        for filter in filters {
+            let mut options = options.clone();
+            options.filtering = filter;
+            yield query(options).await?;
+        }
+    }
+}
+
+fn page_filters(selector: &str, pages: usize, filter: Option<&str>) -> Vec<Option<String>> {
+    let chars = NODE_NAME_START.chars().collect::<Vec<_>>();
+    let pages = pages.clamp(1, chars.len());
+    if pages == 1 {
+        return vec![filter.map(str::to_string)];
+    }
+
+    (0..pages)
+        .map(|page| {
+            let start = page * chars.len() / pages;
+            let end = (page + 1) * chars.len() / pages;
+            let class = chars[start..end].iter().collect::<String>();
+            let mut expr = format!("{} matches \"^[{}]\"", selector, class);
+            if page == pages - 1 {
+                expr = format!(
+                    "{} or {} not matches \"^[{}]\"",
+                    expr, selector, NODE_NAME_START
+                );
+            }
+            Some(match filter {
+                Some(filter) => format!("({}) and ({})", filter, expr),
+                None => expr,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn paged_yields_each_page_with_its_own_metadata() {
+        use futures::stream::TryStreamExt;
+
+        let mut index = 0;
+        let pages = paged(None, "Node", 2, move |options| {
+            index += 1;
+            let meta = QueryMetadata {
+                last_index: Some(index),
+                ..Default::default()
+            };
+            async move { Ok((vec![options.filtering.unwrap()], meta)) }
+        });
+        let pages = pages.try_collect::<Vec<_>>().await.unwrap();
+
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].0[0].contains("^[0123456789abcdefgh]"));
+        assert_eq!(pages[0].1.last_index, Some(1));
+        assert!(pages[1].0[0].contains("^[ijklmnopqrstuvwxyz]"));
+        assert_eq!(pages[1].1.last_index, Some(2));
+    }
+
+    #[test]
+    fn page_filters_split_node_names_into_character_classes() {
+        let filters = page_filters("Node", 3, None);
+        assert_eq!(
+            filters,
+            vec![
+                Some("Node matches \"^[0123456789ab]\"".to_string()),
+                Some("Node matches \"^[cdefghijklmn]\"".to_string()),
+                Some(
+                    "Node matches \"^[opqrstuvwxyz]\" or Node not matches \"^[0123456789abcdefghijklmnopqrstuvwxyz]\""
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn page_filters_combine_with_the_given_filter() {
+        let filters = page_filters("Node.Node", 2, Some("Service.Tags contains \"web\""));
+        assert_eq!(filters.len(), 2);
+        assert_eq!(
+            filters[0].as_deref(),
+            Some(
+                "(Service.Tags contains \"web\") and (Node.Node matches \"^[0123456789abcdefgh]\")"
+            )
+        );
+        assert!(filters[1].as_deref().unwrap().starts_with(
+            "(Service.Tags contains \"web\") and (Node.Node matches \"^[ijklmnopqrstuvwxyz]\" or "
+        ));
+    }
+
+    #[test]
+    fn page_filters_with_a_single_page_only_use_the_given_filter() {
+        assert_eq!(page_filters("Node", 1, None), vec![None]);
+        assert_eq!(page_filters("Node", 0, None), vec![None]);
+        assert_eq!(
+            page_filters("Node", 1, Some("Node == \"a\"")),
+            vec![Some("Node == \"a\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn page_filters_are_capped_at_one_page_per_character() {
+        let filters = page_filters("Node", 100, None);
+        assert_eq!(filters.len(), NODE_NAME_START.len());
+        assert_eq!(filters[0].as_deref(), Some("Node matches \"^[0]\""));
+        assert_eq!(filters[10].as_deref(), Some("Node matches \"^[a]\""));
+        assert_eq!(
+            filters[35].as_deref(),
+            Some("Node matches \"^[z]\" or Node not matches \"^[0123456789abcdefghijklmnopqrstuvwxyz]\"")
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Error as JsonError, Value};
+
+use crate::errors::ResponseError;
+
+/// Maximum number of bytes of the payload to include on either side of an error.
+const EXCERPT_CONTEXT_LEN: usize = 64;
+
+thread_local! {
+    /// Whether or not the payload currently being decoded on this thread must not have unknown
+    /// fields.
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Decodes the given JSON payload.
+///
+/// If `strict` is set, the payload fails to decode if it has any fields which would otherwise be
+/// captured in the `extra` field of a type.
+pub(crate) fn decode<T>(data: &[u8], strict: bool) -> Result<T, ResponseError>
+where
+    T: DeserializeOwned,
+{
+    let previous = STRICT.with(|s| s.replace(strict));
+    let result = serde_json::from_slice(data);
+    STRICT.with(|s| s.set(previous));
+    result.map_err(|e| invalid_payload(data, e))
+}
+
+/// Deserializes the fields of a type which are not otherwise captured by it, rejecting them if the
+/// payload is being decoded strictly.
+///
+/// This is meant to be used on the flattened `extra` field of types, as serde does not support
+/// denying unknown fields alongside a flattened field.
+pub(crate) fn extra<'de, D>(deserializer: D) -> Result<HashMap<String, Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let extra = HashMap::<String, Value>::deserialize(deserializer)?;
+    if !extra.is_empty() && STRICT.with(Cell::get) {
+        let mut fields = extra.keys().map(String::as_str).collect::<Vec<_>>();
+        fields.sort_unstable();
+        return Err(D::Error::custom(format!(
+            "unknown fields: {}",
+            fields.join(", ")
+        )));
+    }
+    Ok(extra)
+}
+
+/// Builds the error for a payload which failed to decode.
+fn invalid_payload(data: &[u8], source: JsonError) -> ResponseError {
+    let offset = error_offset(data, &source);
+    let start = offset.saturating_sub(EXCERPT_CONTEXT_LEN);
+    let end = (offset + EXCERPT_CONTEXT_LEN).min(data.len());
+    let excerpt = String::from_utf8_lossy(&data[start..end]).into_owned();
+
+    ResponseError::InvalidPayload {
+        path: json_path(&data[..offset]),
+        excerpt,
+        source,
+    }
+}
+
+/// Gets the byte offset into the payload at which the given error occurred.
+fn error_offset(data: &[u8], error: &JsonError) -> usize {
+    // Lines and columns are both 1-based, and a line of 0 means there is no position at all.
+    if error.line() == 0 {
+        return 0;
+    }
+
+    let line_start = data
+        .split_inclusive(|b| *b == b'\n')
+        .take(error.line() - 1)
+        .map(|line| line.len())
+        .sum::<usize>();
+    (line_start + error.column().saturating_sub(1)).min(data.len())
+}
+
+enum Frame {
+    Object { key: Option<String>, in_key: bool },
+    Array { index: usize },
+}
+
+/// Gets the JSON path to the value being parsed at the end of the given, partial, payload.
+///
+/// Paths are of the form `$.Services[3].Node`, with `$` being the root of the payload.
+fn json_path(partial: &[u8]) -> String {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut current = Vec::new();
+
+    for &b in partial {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+                if let Some(Frame::Object { key, in_key }) = stack.last_mut() {
+                    if *in_key {
+                        *key = Some(String::from_utf8_lossy(&current).into_owned());
+                        *in_key = false;
+                    }
+                }
+                continue;
+            }
+            current.push(b);
+            continue;
+        }
+
+        match b {
+            b'"' => {
+                in_string = true;
+                current.clear();
+            }
+            b'{' => stack.push(Frame::Object {
+                key: None,
+                in_key: true,
+            }),
+            b'[' => stack.push(Frame::Array { index: 0 }),
+            b'}' | b']' => {
+                stack.pop();
+            }
+            b',' => match stack.last_mut() {
+                Some(Frame::Array { index }) => *index += 1,
+                Some(Frame::Object { in_key, .. }) => *in_key = true,
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    let mut path = String::from("$");
+    for frame in stack {
+        match frame {
+            Frame::Object { key: Some(key), .. } => {
+                path.push('.');
+                path.push_str(&key);
+            }
+            Frame::Object { key: None, .. } => {}
+            Frame::Array { index } => path.push_str(&format!("[{}]", index)),
+        }
+    }
+    path
+}
+
+/// Splits a JSON array into its elements, incrementally, as the payload is received.
+///
+/// Only the bytes of the element currently being received are buffered, so large lists can be
+/// decoded one element at a time without ever holding the entire payload in memory.
+#[derive(Debug, Default)]
+pub(crate) struct ArraySplitter {
+    current: Vec<u8>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    finished: bool,
+    index: usize,
+}
+
+impl ArraySplitter {
+    /// Feeds the next chunk of the payload, returning the raw bytes of every element completed by
+    /// it, along with their index in the array.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<(usize, Vec<u8>)>, ResponseError> {
+        let mut elements = Vec::new();
+        for &b in chunk {
+            if self.finished {
+                if !b.is_ascii_whitespace() {
+                    return Err(self.unexpected(b));
+                }
+                continue;
+            }
+
+            if !self.started {
+                // A `null` payload is treated as an empty list, so buffer it to check at the end.
+                // Anything else before the array starts means the payload is not an array at all.
+                match b {
+                    b'[' if self.current.is_empty() => {
+                        self.started = true;
+                        self.depth = 1;
+                    }
+                    b if b.is_ascii_whitespace() => {}
+                    b => {
+                        self.current.push(b);
+                        if !b"null".starts_with(&self.current) {
+                            return Err(invalid_payload(
+                                &self.current,
+                                serde::de::Error::custom("expected a JSON array"),
+                            ));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if self.in_string {
+                self.current.push(b);
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match b {
+                b',' | b']' if self.depth == 1 => {
+                    if !self.current.is_empty() {
+                        elements.push((self.index, std::mem::take(&mut self.current)));
+                        self.index += 1;
+                    }
+                    if b == b']' {
+                        self.depth = 0;
+                        self.finished = true;
+                    }
+                }
+                b if b.is_ascii_whitespace() && self.depth == 1 => {}
+                b => {
+                    match b {
+                        b'"' => self.in_string = true,
+                        b'{' | b'[' => self.depth += 1,
+                        b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    self.current.push(b);
+                }
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Finishes splitting the payload, checking that it was complete.
+    pub fn finish(self) -> Result<(), ResponseError> {
+        if self.finished || (!self.started && self.current == b"null") {
+            return Ok(());
+        }
+
+        // Let the JSON parser describe exactly what is wrong with the payload.
+        match serde_json::from_slice::<Value>(&self.current) {
+            Err(e) => Err(invalid_payload(&self.current, e)),
+            Ok(_) => Err(invalid_payload(
+                &self.current,
+                serde::de::Error::custom("expected a JSON array"),
+            )),
+        }
+    }
+
+    fn unexpected(&self, b: u8) -> ResponseError {
+        let data = [b];
+        invalid_payload(
+            &data,
+            serde::de::Error::custom("trailing characters after JSON array"),
+        )
+    }
+}
+
+/// Decodes a single element of a JSON array, as split by [`ArraySplitter`].
+pub(crate) fn decode_element<T>(index: usize, data: &[u8], strict: bool) -> Result<T, ResponseError>
+where
+    T: DeserializeOwned,
+{
+    decode(data, strict).map_err(|e| match e {
+        ResponseError::InvalidPayload {
+            source,
+            path,
+            excerpt,
+        } => ResponseError::InvalidPayload {
+            source,
+            path: format!("$[{}]{}", index, &path[1..]),
+            excerpt,
+        },
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits the given payload, feeding it in chunks of the given size.
+    fn split(payload: &str, chunk_size: usize) -> Result<Vec<(usize, String)>, ResponseError> {
+        let mut splitter = ArraySplitter::default();
+        let mut elements = Vec::new();
+        for chunk in payload.as_bytes().chunks(chunk_size) {
+            elements.extend(splitter.push(chunk)?);
+        }
+        splitter.finish()?;
+        Ok(elements
+            .into_iter()
+            .map(|(index, data)| (index, String::from_utf8(data).unwrap()))
+            .collect())
+    }
+
+    fn elements(elements: &[&str]) -> Vec<(usize, String)> {
+        elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| (index, element.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_elements_regardless_of_chunk_boundaries() {
+        let payload = r#" [ {"a": [1, 2], "b": {"c": null}}, "x,]", 3 ,[[]] ] "#;
+        let expected = elements(&[
+            r#"{"a": [1, 2], "b": {"c": null}}"#,
+            r#""x,]""#,
+            "3",
+            "[[]]",
+        ]);
+        for chunk_size in 1..=payload.len() {
+            assert_eq!(split(payload, chunk_size).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn keeps_escaped_quotes_within_strings() {
+        let payload = r#"["a\"],[", "b\\", "c"]"#;
+        assert_eq!(
+            split(payload, 3).unwrap(),
+            elements(&[r#""a\"],[""#, r#""b\\""#, r#""c""#])
+        );
+    }
+
+    #[test]
+    fn treats_empty_arrays_and_null_as_empty() {
+        assert_eq!(split("[]", 1).unwrap(), Vec::new());
+        assert_eq!(split(" [ ] ", 2).unwrap(), Vec::new());
+        assert_eq!(split("null", 1).unwrap(), Vec::new());
+        assert_eq!(split(" null\n", 3).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_payloads_which_are_not_arrays() {
+        for payload in &[r#"{"a": 1}"#, "nul", "nulll", "1", r#""[]""#] {
+            assert!(
+                matches!(split(payload, 1), Err(ResponseError::InvalidPayload { .. })),
+                "{} must be rejected",
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_characters() {
+        assert!(split("[1] 2", 1).is_err());
+        assert!(split("[1]]", 4).is_err());
+        assert_eq!(split("[1] \n", 1).unwrap(), elements(&["1"]));
+    }
+
+    #[test]
+    fn rejects_incomplete_arrays() {
+        assert!(split("[1, 2", 2).is_err());
+        assert!(split("[1,", 2).is_err());
+        assert!(split("", 1).is_err());
+    }
+
+    #[test]
+    fn decode_element_reports_the_index_in_the_path() {
+        let error = decode_element::<HashMap<String, u64>>(4, br#"{"a": "x"}"#, false).unwrap_err();
+        match error {
+            ResponseError::InvalidPayload { path, .. } => assert_eq!(path, "$[4].a"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert_eq!(decode_element::<u64>(0, b"7", false).unwrap(), 7);
+    }
+}
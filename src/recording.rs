@@ -0,0 +1,362 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Error;
+use crate::kv::base64;
+
+/// How requests should be recorded or replayed.
+#[derive(Clone, Debug)]
+pub(crate) enum RecordingMode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A recorded request, and the response it received.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Interaction {
+    method: String,
+    uri: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+/// Records requests and their responses to disk, or replays previously recorded responses.
+///
+/// Recordings are stored one interaction per line, as JSON.  Requests are identified by their
+/// method and path, including the query string, but not the host, so recordings can be replayed
+/// regardless of which endpoint they were recorded against.  Request headers, and so ACL tokens,
+/// are never recorded.
+pub(crate) enum Recording {
+    Record {
+        path: PathBuf,
+        file: Mutex<File>,
+    },
+    Replay {
+        path: PathBuf,
+        interactions: Mutex<HashMap<String, VecDeque<Interaction>>>,
+    },
+}
+
+impl Recording {
+    pub fn new(mode: RecordingMode) -> Result<Recording, Error> {
+        match mode {
+            RecordingMode::Record(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| Error::RecordingFailed(path.clone(), e))?;
+                Ok(Recording::Record {
+                    path,
+                    file: Mutex::new(file),
+                })
+            }
+            RecordingMode::Replay(path) => {
+                let file =
+                    File::open(&path).map_err(|e| Error::RecordingFailed(path.clone(), e))?;
+                let mut interactions = HashMap::<_, VecDeque<_>>::new();
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(|e| Error::RecordingFailed(path.clone(), e))?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let interaction: Interaction = serde_json::from_str(&line)
+                        .map_err(|e| Error::RecordingFailed(path.clone(), e.into()))?;
+                    interactions
+                        .entry(key(&interaction.method, &interaction.uri))
+                        .or_default()
+                        .push_back(interaction);
+                }
+                Ok(Recording::Replay {
+                    path,
+                    interactions: Mutex::new(interactions),
+                })
+            }
+        }
+    }
+
+    /// Gets the recorded response for the given request, if replaying.
+    ///
+    /// Responses for the same request are replayed in the order they were recorded, with the last
+    /// one being repeated once all others have been replayed.
+    pub fn replay(&self, request: &Request<Body>) -> Option<Result<Response<Body>, Error>> {
+        let (path, interactions) = match self {
+            Recording::Record { .. } => return None,
+            Recording::Replay { path, interactions } => (path, interactions),
+        };
+
+        let uri = path_and_query(request);
+        let mut interactions = interactions.lock().expect("recording lock poisoned");
+        let queue = match interactions.get_mut(&key(request.method().as_str(), &uri)) {
+            Some(queue) if !queue.is_empty() => queue,
+            _ => {
+                return Some(Err(Error::NoRecordedResponse(format!(
+                    "{} {}",
+                    request.method(),
+                    uri
+                ))))
+            }
+        };
+        let interaction = if queue.len() > 1 {
+            queue.pop_front().expect("queue must not be empty")
+        } else {
+            queue[0].clone()
+        };
+        Some(to_response(path, interaction))
+    }
+
+    /// Records the given response to the given request, if recording, returning an equivalent
+    /// response.
+    pub async fn record(
+        &self,
+        method: &str,
+        uri: String,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, Error> {
+        let (path, file) = match self {
+            Recording::Record { path, file } => (path, file),
+            Recording::Replay { .. } => return Ok(response),
+        };
+
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let (text, encoded) = match std::str::from_utf8(&body) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (None, Some(base64::encode(&body))),
+        };
+        let interaction = Interaction {
+            method: method.to_string(),
+            uri,
+            status: parts.status.as_u16(),
+            headers: parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (name.to_string(), value.to_string()))
+                })
+                .collect(),
+            body: text,
+            body_base64: encoded,
+        };
+
+        let mut line = serde_json::to_string(&interaction)
+            .map_err(|e| Error::RecordingFailed(path.clone(), e.into()))?;
+        line.push('\n');
+        file.lock()
+            .expect("recording lock poisoned")
+            .write_all(line.as_bytes())
+            .map_err(|e| Error::RecordingFailed(path.clone(), e))?;
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+}
+
+impl fmt::Debug for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recording::Record { path, .. } => f.debug_tuple("Record").field(path).finish(),
+            Recording::Replay { path, .. } => f.debug_tuple("Replay").field(path).finish(),
+        }
+    }
+}
+
+/// Gets the path and query of the given request, which identify it within a recording.
+pub(crate) fn path_and_query(request: &Request<Body>) -> String {
+    request
+        .uri()
+        .path_and_query()
+        .map_or("/", |pq| pq.as_str())
+        .to_string()
+}
+
+fn key(method: &str, uri: &str) -> String {
+    format!("{} {}", method, uri)
+}
+
+fn to_response(path: &Path, interaction: Interaction) -> Result<Response<Body>, Error> {
+    let invalid = |reason: &str| {
+        Error::RecordingFailed(
+            path.to_path_buf(),
+            std::io::Error::new(std::io::ErrorKind::InvalidData, reason.to_string()),
+        )
+    };
+
+    let body = match (interaction.body, interaction.body_base64) {
+        (_, Some(encoded)) => base64::decode(&encoded).map_err(|e| invalid(&e))?,
+        (Some(text), None) => text.into_bytes(),
+        (None, None) => Vec::new(),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() =
+        StatusCode::from_u16(interaction.status).map_err(|_| invalid("invalid status code"))?;
+    for (name, value) in interaction.headers {
+        let name =
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid("invalid header"))?;
+        let value = HeaderValue::from_str(&value).map_err(|_| invalid("invalid header"))?;
+        response.headers_mut().append(name, value);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A recording file which is removed once the test is done with it.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> TempPath {
+            let path = std::env::temp_dir().join(format!(
+                "async-consul-recording-{}-{}.jsonl",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            TempPath(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn request(method: &str, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn response(status: u16, index: &str, body: impl Into<Body>) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .header("X-Consul-Index", index)
+            .body(body.into())
+            .unwrap()
+    }
+
+    async fn record(recording: &Recording, uri: &str, response: Response<Body>) {
+        let recorded = recording
+            .record("GET", uri.to_string(), response)
+            .await
+            .unwrap();
+        let _ = hyper::body::to_bytes(recorded.into_body()).await.unwrap();
+    }
+
+    async fn replay(recording: &Recording, uri: &str) -> (StatusCode, String, Vec<u8>) {
+        let response = recording
+            .replay(&request("GET", uri))
+            .expect("should be replaying")
+            .unwrap();
+        let status = response.status();
+        let index = response.headers()["X-Consul-Index"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, index, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_responses_regardless_of_host() {
+        let path = TempPath::new("round-trip");
+        let recorder = Recording::new(RecordingMode::Record(path.0.clone())).unwrap();
+        assert!(recorder.replay(&request("GET", "/v1/kv/key")).is_none());
+        record(&recorder, "/v1/kv/key", response(200, "3", "[]")).await;
+        record(
+            &recorder,
+            "/v1/kv/binary",
+            response(200, "4", vec![0xff, 0x00]),
+        )
+        .await;
+        record(
+            &recorder,
+            "/v1/kv/missing",
+            response(404, "5", Body::empty()),
+        )
+        .await;
+        drop(recorder);
+
+        let player = Recording::new(RecordingMode::Replay(path.0.clone())).unwrap();
+        assert_eq!(
+            replay(&player, "http://10.0.0.1:8500/v1/kv/key").await,
+            (StatusCode::OK, "3".to_string(), b"[]".to_vec())
+        );
+        assert_eq!(
+            replay(&player, "/v1/kv/binary").await,
+            (StatusCode::OK, "4".to_string(), vec![0xff, 0x00])
+        );
+        assert_eq!(
+            replay(&player, "/v1/kv/missing").await,
+            (StatusCode::NOT_FOUND, "5".to_string(), Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_responses_in_order_repeating_the_last() {
+        let path = TempPath::new("order");
+        let recorder = Recording::new(RecordingMode::Record(path.0.clone())).unwrap();
+        record(&recorder, "/v1/kv/key", response(200, "1", "first")).await;
+        record(&recorder, "/v1/kv/key", response(200, "2", "second")).await;
+        drop(recorder);
+
+        let player = Recording::new(RecordingMode::Replay(path.0.clone())).unwrap();
+        for expected in &["1", "2", "2"] {
+            let (_, index, _) = replay(&player, "/v1/kv/key").await;
+            assert_eq!(&index, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_requests_which_were_not_recorded() {
+        let path = TempPath::new("unrecorded");
+        let recorder = Recording::new(RecordingMode::Record(path.0.clone())).unwrap();
+        record(&recorder, "/v1/kv/key", response(200, "1", "[]")).await;
+        drop(recorder);
+
+        let player = Recording::new(RecordingMode::Replay(path.0.clone())).unwrap();
+        for (method, uri) in &[("GET", "/v1/kv/other"), ("PUT", "/v1/kv/key")] {
+            match player.replay(&request(method, uri)) {
+                Some(Err(Error::NoRecordedResponse(request))) => {
+                    assert_eq!(request, format!("{} {}", method, uri))
+                }
+                other => panic!("unexpected result: {:?}", other.map(|r| r.is_ok())),
+            }
+        }
+    }
+
+    #[test]
+    fn fails_to_replay_missing_or_invalid_recordings() {
+        let path = TempPath::new("invalid");
+        match Recording::new(RecordingMode::Replay(path.0.clone())) {
+            Err(Error::RecordingFailed(failed, _)) => assert_eq!(failed, path.0),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        std::fs::write(&path.0, "not json\n").unwrap();
+        match Recording::new(RecordingMode::Replay(path.0.clone())) {
+            Err(Error::RecordingFailed(failed, _)) => assert_eq!(failed, path.0),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::stream::{self, Stream, StreamExt};
+
+use crate::common::QueryOptions;
+use crate::health::ServiceEntry;
+use crate::http_client::HttpClient;
+use crate::kv::KvPair;
+use crate::runtime::{delay_until, Instant};
+use crate::watch::{self, Backoff, RestartEvent, Watch, DEFAULT_RESTART_DELAY};
+use crate::Client;
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Update> + Send>>;
+
+/// A change to one of the inputs of a [`Renderer`].
+enum Update {
+    Key(String, Option<KvPair>),
+    Prefix(String, Vec<KvPair>),
+    Service(String, Vec<ServiceEntry>),
+}
+
+/// The current state of all of the inputs of a [`Renderer`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderSnapshot {
+    keys: BTreeMap<String, Option<KvPair>>,
+    prefixes: BTreeMap<String, Vec<KvPair>>,
+    services: BTreeMap<String, Vec<ServiceEntry>>,
+}
+
+impl RenderSnapshot {
+    /// Gets the key/value pair for the given key, or `None` if the key does not exist or is not an
+    /// input of the renderer.
+    pub fn key(&self, key: &str) -> Option<&KvPair> {
+        self.keys.get(key).and_then(Option::as_ref)
+    }
+
+    /// Gets the value of the given key, or `None` if the key does not exist, has no value, or is
+    /// not an input of the renderer.
+    pub fn value(&self, key: &str) -> Option<&[u8]> {
+        self.key(key).and_then(|pair| pair.value.as_deref())
+    }
+
+    /// Gets the key/value pairs for all keys with the given prefix.
+    ///
+    /// Returns an empty list if the prefix is not an input of the renderer.
+    pub fn prefix(&self, prefix: &str) -> &[KvPair] {
+        self.prefixes.get(prefix).map_or(&[], Vec::as_slice)
+    }
+
+    /// Gets the instances of the specified service, along with their health checks.
+    ///
+    /// Returns an empty list if the service is not an input of the renderer.
+    pub fn service(&self, service: &str) -> &[ServiceEntry] {
+        self.services.get(service).map_or(&[], Vec::as_slice)
+    }
+
+    /// Applies the given update, returning whether or not anything changed.
+    fn apply(&mut self, update: Update) -> bool {
+        fn replace<V: PartialEq>(map: &mut BTreeMap<String, V>, name: String, value: V) -> bool {
+            if map.get(&name) == Some(&value) {
+                return false;
+            }
+            map.insert(name, value);
+            true
+        }
+
+        match update {
+            Update::Key(key, pair) => replace(&mut self.keys, key, pair),
+            Update::Prefix(prefix, pairs) => replace(&mut self.prefixes, prefix, pairs),
+            Update::Service(service, entries) => replace(&mut self.services, service, entries),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len() + self.prefixes.len() + self.services.len()
+    }
+}
+
+/// Renders output, such as a configuration file, from a set of KV keys and services, whenever any
+/// of them change.
+///
+/// This covers the common pattern of [consul-template]: each input is watched, and a callback is
+/// invoked with a [`RenderSnapshot`] of all of them once they have all been loaded, and then again
+/// after every change.  Changes can be debounced, so that a burst of changes, such as during a
+/// deployment, results in a single render rather than one per change.
+///
+/// Inputs whose watch fails keep their last known state while the watch is restarted, so a
+/// failure never results in a render with missing data.
+///
+/// [consul-template]: https://github.com/hashicorp/consul-template
+pub struct Renderer {
+    client: Client,
+    keys: BTreeSet<String>,
+    prefixes: BTreeSet<String>,
+    services: BTreeMap<String, bool>,
+    options: Option<QueryOptions>,
+    debounce_min: Duration,
+    debounce_max: Duration,
+}
+
+impl Renderer {
+    /// Creates a new [`Renderer`] without any inputs.
+    pub fn new(client: &Client) -> Renderer {
+        Renderer {
+            client: client.clone(),
+            keys: BTreeSet::new(),
+            prefixes: BTreeSet::new(),
+            services: BTreeMap::new(),
+            options: None,
+            debounce_min: Duration::from_secs(0),
+            debounce_max: Duration::from_secs(0),
+        }
+    }
+
+    /// Adds the given key as an input.
+    pub fn key(mut self, key: &str) -> Renderer {
+        self.keys.insert(key.to_string());
+        self
+    }
+
+    /// Adds the keys with the given prefix as an input.
+    pub fn prefix(mut self, prefix: &str) -> Renderer {
+        self.prefixes.insert(prefix.to_string());
+        self
+    }
+
+    /// Adds the instances of the specified service as an input.
+    ///
+    /// If `passing_only` is set, only instances with all of their checks passing are included.
+    /// Adding the same service again replaces it.
+    pub fn service(mut self, service: &str, passing_only: bool) -> Renderer {
+        self.services.insert(service.to_string(), passing_only);
+        self
+    }
+
+    /// Sets the options to use for the underlying queries.
+    pub fn options(mut self, options: QueryOptions) -> Renderer {
+        self.options = Some(options);
+        self
+    }
+
+    /// Sets how changes are debounced.
+    ///
+    /// After a change, rendering waits until there have been no further changes for `min`, but
+    /// never for longer than `max` after the first change, so that a steady stream of changes
+    /// still results in regular renders.  By default, there is no debouncing, and every change is
+    /// rendered straight away.
+    pub fn debounce(mut self, min: Duration, max: Duration) -> Renderer {
+        self.debounce_min = min;
+        self.debounce_max = max.max(min);
+        self
+    }
+
+    /// Watches the inputs, invoking the given callback with a snapshot of them whenever they
+    /// change.
+    ///
+    /// The callback is first invoked as soon as all inputs have been loaded, without debouncing.
+    /// This runs until the client is [shut down](Client::shutdown), and must be called from within
+    /// a Tokio runtime.
+    pub async fn run<F>(self, mut render: F)
+    where
+        F: FnMut(&RenderSnapshot),
+    {
+        let inputs = self.keys.len() + self.prefixes.len() + self.services.len();
+        let mut updates = stream::select_all(self.streams());
+        let mut snapshot = RenderSnapshot::default();
+        if inputs == 0 {
+            render(&snapshot);
+            return;
+        }
+
+        // When the first change which has not been rendered yet happened, and when the latest did.
+        // The initial render happens as soon as all inputs are loaded, without being debounced.
+        let mut pending: Option<(Instant, Instant)> = None;
+        let mut rendered = false;
+        loop {
+            let render_at = pending
+                .filter(|_| snapshot.len() == inputs)
+                .map(|(first, last)| {
+                    if rendered {
+                        std::cmp::min(last + self.debounce_min, first + self.debounce_max)
+                    } else {
+                        last
+                    }
+                });
+            let next = match render_at {
+                Some(at) => match future::select(updates.next(), delay_until(at)).await {
+                    Either::Left((next, _)) => Some(next),
+                    Either::Right(_) => None,
+                },
+                None => Some(updates.next().await),
+            };
+
+            match next {
+                Some(Some(update)) => {
+                    if snapshot.apply(update) {
+                        let now = Instant::now();
+                        let first = pending.map_or(now, |(first, _)| first);
+                        pending = Some((first, now));
+                    }
+                }
+                // Watches only end once the client is shut down.
+                Some(None) => break,
+                None => {
+                    render(&snapshot);
+                    pending = None;
+                    rendered = true;
+                }
+            }
+        }
+    }
+
+    fn streams(&self) -> Vec<UpdateStream> {
+        let mut streams = Vec::new();
+        for key in &self.keys {
+            let kv = self.client.kv();
+            let name = key.clone();
+            let key = key.clone();
+            let watch = restarting(
+                self.client.http_client.clone(),
+                self.options.clone(),
+                move |opts| kv.watch_key(&key, opts),
+            );
+            streams.push(Box::pin(watch.map(move |pair| Update::Key(name.clone(), pair))) as _);
+        }
+        for prefix in &self.prefixes {
+            let kv = self.client.kv();
+            let name = prefix.clone();
+            let prefix = prefix.clone();
+            let watch = restarting(
+                self.client.http_client.clone(),
+                self.options.clone(),
+                move |opts| kv.watch_prefix(&prefix, opts),
+            );
+            streams
+                .push(Box::pin(watch.map(move |pairs| Update::Prefix(name.clone(), pairs))) as _);
+        }
+        for (service, passing_only) in &self.services {
+            let health = self.client.health();
+            let name = service.clone();
+            let service = service.clone();
+            let passing_only = *passing_only;
+            let watch = restarting(
+                self.client.http_client.clone(),
+                self.options.clone(),
+                move |opts| health.watch_service_nodes(&service, passing_only, opts),
+            );
+            streams.push(
+                Box::pin(watch.map(move |entries| Update::Service(name.clone(), entries))) as _,
+            );
+        }
+        streams
+    }
+}
+
+impl std::fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Renderer")
+            .field("keys", &self.keys)
+            .field("prefixes", &self.prefixes)
+            .field("services", &self.services)
+            .field("options", &self.options)
+            .field("debounce_min", &self.debounce_min)
+            .field("debounce_max", &self.debounce_max)
+            .finish()
+    }
+}
+
+/// Runs the watch created by the given function, restarting it whenever it fails, until the client
+/// is shut down.
+fn restarting<T, F>(
+    http_client: Arc<HttpClient>,
+    options: Option<QueryOptions>,
+    start: F,
+) -> impl Stream<Item = T>
+where
+    F: Fn(Option<QueryOptions>) -> Watch<T>,
+{
+    let backoff = Backoff::fixed(DEFAULT_RESTART_DELAY);
+    watch::restarting(http_client, options, backoff, start).filter_map(|event| {
+        future::ready(match event {
+            RestartEvent::Update(value, _) => Some(value),
+            RestartEvent::Failed(_e, _) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %_e, "render input watch failed");
+                None
+            }
+        })
+    })
+}
@@ -0,0 +1,488 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use tokio::sync::watch;
+
+use crate::catalog::CatalogServiceNode;
+use crate::common::{Near, QueryOptions};
+use crate::errors::Error;
+use crate::health::ServiceEntry;
+use crate::runtime::spawn;
+use crate::watch::{restarting, Backoff, RestartEvent, DEFAULT_RESTART_DELAY};
+use crate::Client;
+
+/// A strategy for picking one instance out of the instances of a service.
+///
+/// Strategies are given the current instances, in the order returned by Consul, and return the
+/// index of the instance to use.  Any closure of the form `Fn(&[ServiceEntry]) -> Option<usize>`
+/// can be used as a strategy.
+pub trait PickStrategy: Send + Sync {
+    /// Picks an instance, returning its index within `instances`.
+    ///
+    /// Returns `None` if no instance could be picked.  `instances` is never empty.
+    fn pick(&self, instances: &[ServiceEntry]) -> Option<usize>;
+
+    /// Whether or not this strategy needs the instances to be sorted by distance from the agent.
+    fn sort_by_distance(&self) -> bool {
+        false
+    }
+}
+
+impl<F> PickStrategy for F
+where
+    F: Fn(&[ServiceEntry]) -> Option<usize> + Send + Sync,
+{
+    fn pick(&self, instances: &[ServiceEntry]) -> Option<usize> {
+        self(instances)
+    }
+}
+
+/// Picks instances in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl PickStrategy for RoundRobin {
+    fn pick(&self, instances: &[ServiceEntry]) -> Option<usize> {
+        Some(self.next.fetch_add(1, Ordering::Relaxed) % instances.len())
+    }
+}
+
+/// Picks instances at random.
+#[derive(Debug, Default)]
+pub struct Random {
+    counter: AtomicUsize,
+}
+
+impl Random {
+    fn next(&self) -> u64 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(self.counter.fetch_add(1, Ordering::Relaxed));
+        hasher.finish()
+    }
+}
+
+impl PickStrategy for Random {
+    fn pick(&self, instances: &[ServiceEntry]) -> Option<usize> {
+        Some((self.next() % instances.len() as u64) as usize)
+    }
+}
+
+/// Picks instances at random, in proportion to their [weight](WeightedInstance::weight).
+///
+/// Instances with a weight of 0, such as those with critical checks, are never picked.
+#[derive(Debug, Default)]
+pub struct Weighted {
+    random: Random,
+}
+
+impl PickStrategy for Weighted {
+    fn pick(&self, instances: &[ServiceEntry]) -> Option<usize> {
+        WeightedDistribution::new(instances).sample(self.random.next())
+    }
+}
+
+/// Picks the instance nearest to the agent, based on network coordinates.
+///
+/// Consul sorts the instances by estimated round trip time when asked to, so this strategy causes
+/// the resolver to query with [`Near::Agent`], unless another [`Near`] is already set, and picks
+/// the first instance.
+#[derive(Debug, Default)]
+pub struct Nearest;
+
+impl PickStrategy for Nearest {
+    fn pick(&self, _instances: &[ServiceEntry]) -> Option<usize> {
+        Some(0)
+    }
+
+    fn sort_by_distance(&self) -> bool {
+        true
+    }
+}
+
+/// An instance of a service which can be weighted for load balancing.
+pub trait WeightedInstance {
+    /// Gets the weight of this instance.
+    ///
+    /// This is the passing weight of the instance if all of its checks are passing, the warning
+    /// weight if any are warning, and 0 if any are critical.  Instances without weights use
+    /// Consul's default of 1 for both.
+    fn weight(&self) -> u64;
+}
+
+impl WeightedInstance for CatalogServiceNode {
+    fn weight(&self) -> u64 {
+        let (passing, warning) = self
+            .service_weights
+            .as_ref()
+            .map_or((1, 1), |weights| (weights.passing, weights.warning));
+
+        // Only the checks for this service, and those for the node as a whole, apply.
+        let statuses = self
+            .checks
+            .iter()
+            .flatten()
+            .filter(|check| check.service_id.is_empty() || check.service_id == self.service_id)
+            .map(|check| check.status.as_str());
+        combined_weight(statuses, passing, warning)
+    }
+}
+
+/// Gets the weight for an instance with checks in the given statuses.
+pub(crate) fn combined_weight<'a, I>(statuses: I, passing: u64, warning: u64) -> u64
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut weight = passing;
+    for status in statuses {
+        match status {
+            "critical" => return 0,
+            "warning" => weight = warning,
+            _ => {}
+        }
+    }
+    weight
+}
+
+/// A weighted distribution over a set of instances.
+///
+/// Built from the [weights](WeightedInstance::weight) of each instance, this can be used to pick
+/// instances in proportion to their weight, either via [`Weighted`] or directly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WeightedDistribution {
+    cumulative: Vec<u64>,
+}
+
+impl WeightedDistribution {
+    /// Creates a new [`WeightedDistribution`] over the given instances.
+    pub fn new<I>(instances: &[I]) -> WeightedDistribution
+    where
+        I: WeightedInstance,
+    {
+        let mut total = 0;
+        let cumulative = instances
+            .iter()
+            .map(|instance| {
+                total += instance.weight();
+                total
+            })
+            .collect();
+        WeightedDistribution { cumulative }
+    }
+
+    /// Gets the total weight of all instances.
+    pub fn total_weight(&self) -> u64 {
+        self.cumulative.last().copied().unwrap_or(0)
+    }
+
+    /// Gets the weight of the instance at the given index.
+    pub fn weight(&self, index: usize) -> Option<u64> {
+        let upper = *self.cumulative.get(index)?;
+        let lower = index.checked_sub(1).map_or(0, |prev| self.cumulative[prev]);
+        Some(upper - lower)
+    }
+
+    /// Gets the index of the instance selected by the given random value.
+    ///
+    /// The value is reduced modulo the total weight, so any uniformly distributed value selects
+    /// each instance with a probability proportional to its weight.  Returns `None` if the total
+    /// weight is 0.
+    pub fn sample(&self, value: u64) -> Option<usize> {
+        let total = self.total_weight();
+        if total == 0 {
+            return None;
+        }
+
+        let target = value % total;
+        Some(self.cumulative.partition_point(|&upper| upper <= target))
+    }
+}
+
+/// Resolves a service to one of its healthy instances.
+///
+/// A resolver watches the instances of a service which have all of their checks passing in the
+/// background, and picks one of the current instances on demand using its [`PickStrategy`].  If
+/// the watch fails, it is restarted where it left off, with the last known instances being used in
+/// the meantime.  The background watch stops when the resolver, and all of its clones, are dropped,
+/// or when the client is shut down.
+#[derive(Clone)]
+pub struct ServiceResolver {
+    instances: watch::Receiver<Vec<ServiceEntry>>,
+    strategy: Arc<dyn PickStrategy>,
+}
+
+impl ServiceResolver {
+    /// Creates a new [`ServiceResolver`] for the given service.
+    ///
+    /// The initial instances are queried before this method returns.  This must be called from
+    /// within a Tokio runtime.
+    pub async fn new<S>(
+        client: &Client,
+        service: &str,
+        strategy: S,
+        options: Option<QueryOptions>,
+    ) -> Result<ServiceResolver, Error>
+    where
+        S: PickStrategy + 'static,
+    {
+        let options = if strategy.sort_by_distance() {
+            let mut options = options.unwrap_or_default();
+            options.near.get_or_insert(Near::Agent);
+            Some(options)
+        } else {
+            options
+        };
+
+        let health = client.health();
+        let service = service.to_string();
+        let backoff = Backoff::fixed(DEFAULT_RESTART_DELAY);
+        let mut events = Box::pin(restarting(
+            client.http_client.clone(),
+            options,
+            backoff,
+            move |options| health.watch_service_nodes(&service, true, options),
+        ));
+        let initial = match events.next().await {
+            Some(RestartEvent::Update(instances, _)) => instances,
+            Some(RestartEvent::Failed(e, _)) => return Err(e),
+            None => return Err(Error::WatchStopped),
+        };
+
+        let (mut tx, instances) = watch::channel(initial);
+        spawn(async move {
+            loop {
+                let next = match future::select(events.next(), Box::pin(tx.closed())).await {
+                    Either::Left((next, _)) => next,
+                    Either::Right(_) => break,
+                };
+                match next {
+                    Some(RestartEvent::Update(instances, _)) => {
+                        if tx.broadcast(instances).is_err() {
+                            break;
+                        }
+                    }
+                    Some(RestartEvent::Failed(..)) => {}
+                    // Watches end once the client is shut down.
+                    None => break,
+                }
+            }
+        });
+
+        Ok(ServiceResolver {
+            instances,
+            strategy: Arc::new(strategy),
+        })
+    }
+
+    /// Picks an instance of the service.
+    ///
+    /// Returns `None` if the service currently has no healthy instances, or if the strategy could
+    /// not pick one.
+    pub fn pick(&self) -> Option<ServiceEntry> {
+        let instances = self.instances.borrow();
+        if instances.is_empty() {
+            return None;
+        }
+
+        self.strategy
+            .pick(&instances)
+            .and_then(|i| instances.get(i))
+            .cloned()
+    }
+
+    /// Picks an instance of the service, returning the socket address to connect to it on.
+    ///
+    /// Returns `None` under the same conditions as [`pick`](ServiceResolver::pick), or if the
+    /// picked instance does not have an IP address.
+    pub fn pick_addr(&self) -> Option<SocketAddr> {
+        self.pick()
+            .and_then(|instance| instance.service_socket_addr())
+    }
+
+    /// Gets the current healthy instances of the service.
+    pub fn instances(&self) -> Vec<ServiceEntry> {
+        self.instances.borrow().clone()
+    }
+}
+
+impl fmt::Debug for ServiceResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceResolver")
+            .field("instances", &self.instances.borrow().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::catalog::Weights;
+    use crate::health::HealthCheck;
+
+    fn weighted(passing: u64, warning: u64, statuses: &[&str]) -> ServiceEntry {
+        let mut instance = ServiceEntry::default();
+        instance.service.weights.passing = passing;
+        instance.service.weights.warning = warning;
+        instance.checks = statuses
+            .iter()
+            .map(|status| HealthCheck {
+                status: status.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        instance
+    }
+
+    fn instances(count: usize) -> Vec<ServiceEntry> {
+        (0..count)
+            .map(|i| {
+                let mut instance = ServiceEntry::default();
+                instance.service.id = format!("web-{}", i);
+                instance
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_robin_picks_instances_in_turn() {
+        let instances = instances(3);
+        let strategy = RoundRobin::default();
+        let picks = (0..7)
+            .map(|_| strategy.pick(&instances).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn random_picks_instances_within_range() {
+        let instances = instances(4);
+        let strategy = Random::default();
+        let mut seen = [false; 4];
+        for _ in 0..1000 {
+            seen[strategy.pick(&instances).unwrap()] = true;
+        }
+        assert_eq!(seen, [true; 4]);
+    }
+
+    #[test]
+    fn nearest_picks_the_first_instance_sorted_by_distance() {
+        let strategy = Nearest;
+        assert!(strategy.sort_by_distance());
+        assert_eq!(strategy.pick(&instances(3)), Some(0));
+        assert!(!RoundRobin::default().sort_by_distance());
+    }
+
+    #[test]
+    fn closures_can_be_used_as_strategies() {
+        let last = |instances: &[ServiceEntry]| Some(instances.len() - 1);
+        assert_eq!(last.pick(&instances(3)), Some(2));
+        assert!(!last.sort_by_distance());
+    }
+
+    #[test]
+    fn combined_weight_uses_the_worst_status() {
+        assert_eq!(combined_weight(Vec::new(), 10, 1), 10);
+        assert_eq!(combined_weight(vec!["passing", "passing"], 10, 1), 10);
+        assert_eq!(combined_weight(vec!["passing", "warning"], 10, 1), 1);
+        assert_eq!(combined_weight(vec!["warning", "critical"], 10, 1), 0);
+        assert_eq!(combined_weight(vec!["critical", "warning"], 10, 1), 0);
+        assert_eq!(combined_weight(vec!["unknown"], 10, 1), 10);
+    }
+
+    #[test]
+    fn service_entry_weight_follows_its_checks() {
+        assert_eq!(weighted(5, 2, &[]).weight(), 5);
+        assert_eq!(weighted(5, 2, &["passing", "warning"]).weight(), 2);
+        assert_eq!(weighted(5, 2, &["critical"]).weight(), 0);
+        assert_eq!(ServiceEntry::default().weight(), 1);
+    }
+
+    #[test]
+    fn catalog_node_weight_only_counts_its_own_and_node_checks() {
+        let check = |service_id: &str, status: &str| HealthCheck {
+            service_id: service_id.to_string(),
+            status: status.to_string(),
+            ..Default::default()
+        };
+        let mut node = CatalogServiceNode {
+            service_id: "web-1".to_string(),
+            service_weights: Some(Weights {
+                passing: 4,
+                warning: 2,
+                extra: HashMap::new(),
+            }),
+            checks: Some(vec![check("web-1", "passing"), check("db-1", "critical")]),
+            ..Default::default()
+        };
+        assert_eq!(node.weight(), 4);
+
+        node.checks = Some(vec![check("", "warning")]);
+        assert_eq!(node.weight(), 2);
+
+        node.service_weights = None;
+        node.checks = None;
+        assert_eq!(node.weight(), 1);
+    }
+
+    #[test]
+    fn distribution_tracks_weights() {
+        let instances = vec![
+            weighted(3, 1, &[]),
+            weighted(3, 1, &["critical"]),
+            weighted(5, 1, &[]),
+        ];
+        let distribution = WeightedDistribution::new(&instances);
+        assert_eq!(distribution.total_weight(), 8);
+        assert_eq!(distribution.weight(0), Some(3));
+        assert_eq!(distribution.weight(1), Some(0));
+        assert_eq!(distribution.weight(2), Some(5));
+        assert_eq!(distribution.weight(3), None);
+    }
+
+    #[test]
+    fn distribution_samples_in_proportion_to_weight() {
+        let instances = vec![
+            weighted(3, 1, &[]),
+            weighted(3, 1, &["critical"]),
+            weighted(5, 1, &[]),
+        ];
+        let distribution = WeightedDistribution::new(&instances);
+        let picks = (0..16)
+            .map(|value| distribution.sample(value).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 0, 0, 2, 2, 2, 2, 2, 0, 0, 0, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn distribution_without_weight_samples_nothing() {
+        let instances = vec![weighted(3, 1, &["critical"])];
+        assert_eq!(WeightedDistribution::new(&instances).sample(7), None);
+        assert_eq!(
+            WeightedDistribution::new::<ServiceEntry>(&[]).sample(7),
+            None
+        );
+        assert_eq!(Weighted::default().pick(&instances), None);
+    }
+
+    #[test]
+    fn weighted_never_picks_instances_without_weight() {
+        let instances = vec![
+            weighted(1, 1, &["critical"]),
+            weighted(1, 1, &[]),
+            weighted(1, 1, &["critical"]),
+        ];
+        let strategy = Weighted::default();
+        for _ in 0..100 {
+            assert_eq!(strategy.pick(&instances), Some(1));
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::runtime::{delay_for, Instant};
+
+/// Settings for the budget of watch restarts.
+#[derive(Clone, Debug)]
+pub(crate) struct RestartBudgetSettings {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+/// Limits the rate at which failed watches are restarted, across all of the watches of a client.
+///
+/// Individual backoff does not prevent a thundering herd when many watches fail at the same time,
+/// such as when the agent restarts, as they all back off by the same amount.  Up to `burst`
+/// restarts are allowed immediately, after which each restart is delayed so that they are spread
+/// out to one every `interval`.  Restarts are allowed in the order they were requested.
+#[derive(Debug)]
+pub(crate) struct RestartBudget {
+    settings: RestartBudgetSettings,
+    // The time at which the budget will be fully replenished, given the restarts so far.
+    replenished_at: Mutex<Instant>,
+}
+
+impl RestartBudget {
+    pub fn new(settings: RestartBudgetSettings) -> RestartBudget {
+        RestartBudget {
+            settings,
+            replenished_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until a watch is allowed to restart.
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if wait > Duration::from_secs(0) {
+            delay_for(wait).await;
+        }
+    }
+
+    /// Reserves the next restart, returning how long to wait before it is allowed.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut replenished_at = self
+            .replenished_at
+            .lock()
+            .expect("restart budget lock poisoned");
+        let next = std::cmp::max(*replenished_at, now) + self.settings.interval;
+        *replenished_at = next;
+
+        let tolerance = self.settings.interval * self.settings.burst;
+        match next.checked_sub(tolerance) {
+            Some(allowed_at) => allowed_at.saturating_duration_since(now),
+            None => Duration::from_secs(0),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Timers and task spawning, backed by Tokio, or by the event loop of the host when running on
+//! WebAssembly.
+//!
+//! Neither Tokio's timers nor `std::time::Instant` work on `wasm32-unknown-unknown`, so everything
+//! in the crate which waits, times out, spawns, or measures time goes through here instead.
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+mod imp {
+    use std::future::Future;
+    use std::time::SystemTime;
+
+    pub(crate) use tokio::time::{delay_for, delay_until, timeout, Instant};
+
+    /// Spawns the given future as a background task.
+    pub(crate) fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+
+    /// Gets the current system time.
+    pub(crate) fn system_now() -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod imp {
+    use std::future::Future;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use futures::future::{self, Either};
+    use send_wrapper::SendWrapper;
+
+    pub(crate) use web_time::Instant;
+
+    use crate::errors::Elapsed;
+
+    /// Waits until the given duration has elapsed.
+    ///
+    /// The timers of the host can only be used from the thread which created them, which is the
+    /// only thread there is, so they are safe to treat as `Send`.
+    pub(crate) fn delay_for(duration: Duration) -> SendWrapper<gloo_timers::future::TimeoutFuture> {
+        SendWrapper::new(gloo_timers::future::sleep(duration))
+    }
+
+    /// Waits until the given instant has been reached.
+    pub(crate) fn delay_until(at: Instant) -> SendWrapper<gloo_timers::future::TimeoutFuture> {
+        delay_for(at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Waits for the given future to complete, for up to the given duration.
+    pub(crate) async fn timeout<F>(duration: Duration, future: F) -> Result<F::Output, Elapsed>
+    where
+        F: Future,
+    {
+        futures::pin_mut!(future);
+        match future::select(future, delay_for(duration)).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed::new()),
+        }
+    }
+
+    /// Spawns the given future as a background task.
+    pub(crate) fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+
+    /// Gets the current system time.
+    pub(crate) fn system_now() -> SystemTime {
+        let since_epoch = web_time::SystemTime::now()
+            .duration_since(web_time::UNIX_EPOCH)
+            .unwrap_or_default();
+        UNIX_EPOCH + since_epoch
+    }
+}
+
+pub(crate) use self::imp::*;
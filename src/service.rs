@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::{Body, Request, Response};
+use tower_service::Service;
+
+use crate::common::QueryOptions;
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+
+/// Raw HTTP access to Consul as a [`Service`].
+///
+/// Requests are sent using the same connection pool, TLS configuration, and interceptors as the
+/// [`Client`](crate::Client) this was created from.  Requests whose URI only has a path (and
+/// optionally a query) are sent to the client's base URI.  The client's default datacenter,
+/// namespace and token are applied to requests which don't specify their own.
+///
+/// As this implements the standard `tower` service trait, it can be wrapped with any of the
+/// standard `tower` layers, such as timeouts, retries, load shedding, or concurrency limits.
+#[derive(Clone, Debug)]
+pub struct HttpService {
+    http_client: Arc<HttpClient>,
+}
+
+impl HttpService {
+    /// Creates a new [`HttpService`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> HttpService {
+        HttpService { http_client }
+    }
+}
+
+impl Service<Request<Body>> for HttpService {
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let http_client = self.http_client.clone();
+
+        Box::pin(async move {
+            if request.uri().scheme().is_none() {
+                let uri = http_client.resolve_uri(request.uri())?;
+                *request.uri_mut() = uri;
+            }
+
+            http_client
+                .run_request(request, None::<&QueryOptions>)
+                .await
+        })
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::agent::{
+    AgentServiceCheck, AgentServiceConnectProxyConfig, AgentServiceKind, AgentServiceRegistration,
+    AgentServiceUpstream, AgentServiceWeights, UpstreamDestinationType,
+};
+use crate::common::go_duration;
+use crate::config_entries::{MeshGatewayConfig, MeshGatewayMode};
+use crate::errors::Error;
+use crate::health::CheckStatus;
+
+/// A service definition file, as read by the Consul agent from its configuration directory.
+///
+/// Either a single `service` or a list of `services` may be given.
+#[derive(Deserialize)]
+struct DefinitionFile {
+    #[serde(default)]
+    service: Option<ServiceDefinition>,
+    #[serde(default)]
+    services: Vec<ServiceDefinition>,
+}
+
+#[derive(Deserialize)]
+struct ServiceDefinition {
+    #[serde(default)]
+    kind: AgentServiceKind,
+    #[serde(default)]
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    address: String,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    meta: HashMap<String, String>,
+    #[serde(default)]
+    enable_tag_override: bool,
+    #[serde(default)]
+    weights: Option<WeightsDefinition>,
+    #[serde(default)]
+    check: Option<CheckDefinition>,
+    #[serde(default)]
+    checks: Vec<CheckDefinition>,
+    #[serde(default)]
+    proxy: Option<ProxyDefinition>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WeightsDefinition {
+    #[serde(default = "default_weight")]
+    passing: u64,
+    #[serde(default = "default_weight")]
+    warning: u64,
+}
+
+fn default_weight() -> u64 {
+    1
+}
+
+#[derive(Deserialize)]
+struct CheckDefinition {
+    #[serde(default, alias = "check_id")]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    status: Option<CheckStatus>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    http: Option<String>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    header: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    tcp: Option<String>,
+    #[serde(default)]
+    grpc: Option<String>,
+    #[serde(default)]
+    grpc_use_tls: bool,
+    #[serde(default)]
+    tls_skip_verify: bool,
+    #[serde(with = "go_duration::option", default)]
+    interval: Option<Duration>,
+    #[serde(with = "go_duration::option", default)]
+    timeout: Option<Duration>,
+    #[serde(with = "go_duration::option", default)]
+    ttl: Option<Duration>,
+    #[serde(with = "go_duration::option", default)]
+    deregister_critical_service_after: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+struct ProxyDefinition {
+    #[serde(default)]
+    destination_service_name: String,
+    #[serde(default)]
+    destination_service_id: Option<String>,
+    #[serde(default)]
+    local_service_address: Option<String>,
+    #[serde(default)]
+    local_service_port: Option<u16>,
+    #[serde(default)]
+    config: HashMap<String, Value>,
+    #[serde(default)]
+    upstreams: Vec<UpstreamDefinition>,
+}
+
+#[derive(Deserialize)]
+struct UpstreamDefinition {
+    #[serde(default)]
+    destination_type: UpstreamDestinationType,
+    #[serde(default)]
+    destination_namespace: Option<String>,
+    #[serde(default)]
+    destination_partition: Option<String>,
+    #[serde(default)]
+    destination_peer: Option<String>,
+    destination_name: String,
+    #[serde(default)]
+    datacenter: Option<String>,
+    #[serde(default)]
+    local_bind_address: Option<String>,
+    #[serde(default)]
+    local_bind_port: Option<u16>,
+    #[serde(default)]
+    config: HashMap<String, Value>,
+    #[serde(default)]
+    mesh_gateway: Option<MeshGatewayDefinition>,
+}
+
+#[derive(Deserialize)]
+struct MeshGatewayDefinition {
+    #[serde(default)]
+    mode: MeshGatewayMode,
+}
+
+impl AgentServiceRegistration {
+    /// Parses the services in a Consul service definition, in JSON.
+    ///
+    /// This accepts the same format as the service definition files read by the Consul agent,
+    /// with either a single `service` block or a list of `services`, each of which may have a
+    /// `check` and/or a list of `checks`, `weights`, and a `proxy` block.  Keys are expected in
+    /// the `snake_case` form used in those files.  Fields which are not supported by
+    /// [`AgentServiceRegistration`], such as `connect`, are ignored.
+    ///
+    /// The configuration of a proxy, and of its upstreams, is passed through as-is, as the agent
+    /// accepts it in the same form when registering services.
+    pub fn parse_definitions(json: &str) -> Result<Vec<AgentServiceRegistration>, Error> {
+        let file: DefinitionFile = serde_json::from_str(json)
+            .map_err(|e| Error::InvalidConfigValue(format!("invalid service definition: {}", e)))?;
+
+        let registrations = file
+            .service
+            .into_iter()
+            .chain(file.services)
+            .map(ServiceDefinition::into_registration)
+            .collect();
+        Ok(registrations)
+    }
+
+    /// Loads the services in the Consul service definition file at the given path.
+    ///
+    /// See [`parse_definitions`](AgentServiceRegistration::parse_definitions) for the supported
+    /// format.
+    pub fn load_definitions<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Vec<AgentServiceRegistration>, Error> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfigFile(path.to_path_buf(), e))?;
+        AgentServiceRegistration::parse_definitions(&json).map_err(|e| match e {
+            Error::InvalidConfigValue(reason) => Error::InvalidConfigFile(
+                path.to_path_buf(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, reason),
+            ),
+            e => e,
+        })
+    }
+}
+
+impl ServiceDefinition {
+    fn into_registration(self) -> AgentServiceRegistration {
+        AgentServiceRegistration {
+            kind: self.kind,
+            id: self.id,
+            name: self.name,
+            tags: self.tags,
+            address: self.address,
+            port: self.port,
+            meta: self.meta,
+            enable_tag_override: self.enable_tag_override,
+            weights: self.weights.map(|weights| AgentServiceWeights {
+                passing: weights.passing,
+                warning: weights.warning,
+            }),
+            checks: self
+                .check
+                .into_iter()
+                .chain(self.checks)
+                .map(CheckDefinition::into_check)
+                .collect(),
+            proxy: self.proxy.map(|proxy| AgentServiceConnectProxyConfig {
+                destination_service_name: proxy.destination_service_name,
+                destination_service_id: proxy.destination_service_id,
+                local_service_address: proxy.local_service_address,
+                local_service_port: proxy.local_service_port,
+                config: proxy.config,
+                upstreams: proxy
+                    .upstreams
+                    .into_iter()
+                    .map(UpstreamDefinition::into_upstream)
+                    .collect(),
+                extra: HashMap::new(),
+            }),
+            namespace: self.namespace,
+        }
+    }
+}
+
+impl UpstreamDefinition {
+    fn into_upstream(self) -> AgentServiceUpstream {
+        AgentServiceUpstream {
+            destination_type: self.destination_type,
+            destination_namespace: self.destination_namespace,
+            destination_partition: self.destination_partition,
+            destination_peer: self.destination_peer,
+            destination_name: self.destination_name,
+            datacenter: self.datacenter,
+            local_bind_address: self.local_bind_address,
+            local_bind_port: self.local_bind_port,
+            config: self.config,
+            mesh_gateway: self
+                .mesh_gateway
+                .map(|gateway| MeshGatewayConfig { mode: gateway.mode }),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl CheckDefinition {
+    fn into_check(self) -> AgentServiceCheck {
+        AgentServiceCheck {
+            check_id: self.id,
+            name: self.name,
+            notes: self.notes,
+            status: self.status,
+            args: self.args,
+            http: self.http,
+            method: self.method,
+            header: self.header,
+            body: self.body,
+            tcp: self.tcp,
+            grpc: self.grpc,
+            grpc_use_tls: self.grpc_use_tls,
+            tls_skip_verify: self.tls_skip_verify,
+            interval: self.interval,
+            timeout: self.timeout,
+            ttl: self.ttl,
+            deregister_critical_service_after: self.deregister_critical_service_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_single_service_with_checks() {
+        let registrations = AgentServiceRegistration::parse_definitions(
+            r#"{
+                "service": {
+                    "id": "web-1",
+                    "name": "web",
+                    "tags": ["primary"],
+                    "port": 8080,
+                    "meta": { "version": "1" },
+                    "weights": { "passing": 10 },
+                    "check": { "id": "web-http", "http": "http://localhost:8080/health", "interval": "10s" },
+                    "checks": [{ "name": "web-ttl", "ttl": "1m30s", "status": "passing" }]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(registrations.len(), 1);
+
+        let web = &registrations[0];
+        assert_eq!(web.kind, AgentServiceKind::Default);
+        assert_eq!(web.id.as_deref(), Some("web-1"));
+        assert_eq!(web.name, "web");
+        assert_eq!(web.tags, vec!["primary".to_string()]);
+        assert_eq!(web.port, Some(8080));
+        assert_eq!(web.meta.get("version").map(String::as_str), Some("1"));
+        assert_eq!(
+            web.weights,
+            Some(AgentServiceWeights {
+                passing: 10,
+                warning: 1,
+            })
+        );
+
+        assert_eq!(web.checks.len(), 2);
+        assert_eq!(web.checks[0].check_id.as_deref(), Some("web-http"));
+        assert_eq!(
+            web.checks[0].http.as_deref(),
+            Some("http://localhost:8080/health")
+        );
+        assert_eq!(web.checks[0].interval, Some(Duration::from_secs(10)));
+        assert_eq!(web.checks[1].name, "web-ttl");
+        assert_eq!(web.checks[1].ttl, Some(Duration::from_secs(90)));
+        assert_eq!(web.checks[1].status, Some(CheckStatus::Passing));
+    }
+
+    #[test]
+    fn parses_a_list_of_services() {
+        let registrations = AgentServiceRegistration::parse_definitions(
+            r#"{ "services": [{ "name": "web" }, { "name": "api" }] }"#,
+        )
+        .unwrap();
+        let names = registrations
+            .iter()
+            .map(|registration| registration.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["web", "api"]);
+    }
+
+    #[test]
+    fn parses_a_proxy_with_upstreams() {
+        let registrations = AgentServiceRegistration::parse_definitions(
+            r#"{
+                "service": {
+                    "kind": "connect-proxy",
+                    "name": "web-proxy",
+                    "port": 21000,
+                    "proxy": {
+                        "destination_service_name": "web",
+                        "local_service_port": 8080,
+                        "config": { "protocol": "http" },
+                        "upstreams": [{
+                            "destination_name": "db",
+                            "local_bind_port": 9191,
+                            "mesh_gateway": { "mode": "local" }
+                        }]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let proxy = &registrations[0];
+        assert_eq!(proxy.kind, AgentServiceKind::ConnectProxy);
+        let config = proxy.proxy.as_ref().unwrap();
+        assert_eq!(config.destination_service_name, "web");
+        assert_eq!(config.local_service_port, Some(8080));
+        assert_eq!(config.config.get("protocol"), Some(&Value::from("http")));
+
+        assert_eq!(config.upstreams.len(), 1);
+        let upstream = &config.upstreams[0];
+        assert_eq!(upstream.destination_type, UpstreamDestinationType::Service);
+        assert_eq!(upstream.destination_name, "db");
+        assert_eq!(upstream.local_bind_port, Some(9191));
+        assert_eq!(
+            upstream.mesh_gateway,
+            Some(MeshGatewayConfig {
+                mode: MeshGatewayMode::Local,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        for json in &[
+            "not json",
+            r#"{ "service": { "port": 8080 } }"#,
+            r#"{ "service": { "name": "web", "check": { "interval": "soon" } } }"#,
+        ] {
+            match AgentServiceRegistration::parse_definitions(json) {
+                Err(Error::InvalidConfigValue(_)) => {}
+                other => panic!("unexpected result for {}: {:?}", json, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_the_path_of_invalid_files() {
+        let path = std::env::temp_dir().join(format!(
+            "async-consul-service-definition-{}.json",
+            std::process::id()
+        ));
+        fs::write(&path, r#"{ "service": {} }"#).unwrap();
+        let result = AgentServiceRegistration::load_definitions(&path);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::InvalidConfigFile(reported, _)) => assert_eq!(reported, path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
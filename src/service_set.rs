@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_stream::try_stream;
+use futures::future::{self, Either};
+use futures::stream::{self, AbortHandle, SelectAll, Stream, StreamExt};
+
+use crate::catalog::Catalog;
+use crate::common::{QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::health::{Health, ServiceEntry};
+use crate::http_client::HttpClient;
+
+type HealthUpdates =
+    Pin<Box<dyn Stream<Item = (String, Result<(Vec<ServiceEntry>, QueryMetadata), Error>)> + Send>>;
+
+/// Selects the services to watch with [`Health::watch_service_set`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceSelector {
+    /// Services with at least one instance carrying the given tag.
+    ///
+    /// Only the instances carrying the tag are included in the results.
+    Tag(String),
+    /// Services whose name starts with the given prefix.
+    NamePrefix(String),
+}
+
+impl ServiceSelector {
+    /// Whether or not a service with the given name and instance tags is selected.
+    pub fn matches(&self, name: &str, tags: &[String]) -> bool {
+        match self {
+            ServiceSelector::Tag(tag) => tags.contains(tag),
+            ServiceSelector::NamePrefix(prefix) => name.starts_with(prefix.as_str()),
+        }
+    }
+
+    /// Whether or not the given instance of a selected service should be included in the results.
+    fn includes(&self, entry: &ServiceEntry) -> bool {
+        match self {
+            ServiceSelector::Tag(tag) => entry.service.tags.contains(tag),
+            ServiceSelector::NamePrefix(_) => true,
+        }
+    }
+}
+
+/// A change to one of the services watched with [`Health::watch_service_set`].
+#[derive(Clone, Debug)]
+pub enum ServiceSetEvent {
+    /// The instances of the given service have changed, or the service has started matching.
+    Updated {
+        /// Name of the service.
+        service: String,
+        /// All instances of the service, along with their health checks.
+        entries: Vec<ServiceEntry>,
+        /// Metadata of the response the instances came from.
+        meta: QueryMetadata,
+    },
+    /// The given service no longer exists, or no longer matches, and is not watched anymore.
+    Removed {
+        /// Name of the service.
+        service: String,
+    },
+}
+
+pub(crate) fn watch_service_set(
+    http_client: Arc<HttpClient>,
+    selector: ServiceSelector,
+    passing_only: bool,
+    options: Option<QueryOptions>,
+) -> impl Stream<Item = Result<ServiceSetEvent, Error>> {
+    let health = Health::new(http_client.clone());
+    let mut services = Catalog::new(http_client).watch_services(options.clone());
+
+    try_stream! {
+        let mut watched: HashMap<String, AbortHandle> = HashMap::new();
+        let mut updates: SelectAll<HealthUpdates> = SelectAll::new();
+
+        loop {
+            // An empty set of updates is always ready, so only wait on it when it has watches.
+            let next = if updates.is_empty() {
+                Either::Left(services.next().await)
+            } else {
+                match future::select(services.next(), updates.next()).await {
+                    Either::Left((next, _)) => Either::Left(next),
+                    Either::Right((next, _)) => Either::Right(next),
+                }
+            };
+
+            match next {
+                Either::Left(Some(result)) => {
+                    let (catalog, _) = result?;
+                    let matching = catalog
+                        .iter()
+                        .filter(|(name, tags)| selector.matches(name, tags))
+                        .map(|(name, _)| name.clone())
+                        .collect::<HashSet<_>>();
+
+                    let removed = watched
+                        .keys()
+                        .filter(|service| !matching.contains(*service))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    for service in removed {
+                        if let Some(handle) = watched.remove(&service) {
+                            handle.abort();
+                        }
+                        yield ServiceSetEvent::Removed { service };
+                    }
+
+                    for service in matching {
+                        if watched.contains_key(&service) {
+                            continue;
+                        }
+                        let watch = health.watch_service_nodes(&service, passing_only, options.clone());
+                        let (watch, handle) = stream::abortable(watch);
+                        let name = service.clone();
+                        updates.push(Box::pin(watch.map(move |result| (name.clone(), result))));
+                        watched.insert(service, handle);
+                    }
+                }
+                Either::Left(None) => break,
+                Either::Right(Some((service, result))) => {
+                    let (mut entries, meta) = result?;
+                    entries.retain(|entry| selector.includes(entry));
+                    yield ServiceSetEvent::Updated { service, entries, meta };
+                }
+                // Aborted watches end without an item once they are polled again.
+                Either::Right(None) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::common::{go_duration, QueryMetadata, QueryOptions, WriteOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::payload;
+use crate::runtime::{delay_for, spawn};
+
+/// What happens to the locks held by a session when it is invalidated.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub enum SessionBehavior {
+    /// Locks are released, leaving the keys in place.
+    #[default]
+    #[serde(rename = "release")]
+    Release,
+    /// The keys locked by the session are deleted.
+    #[serde(rename = "delete")]
+    Delete,
+}
+
+/// A session to create.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct SessionRequest {
+    #[serde(rename = "Name", default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// The node to tie the session to.
+    ///
+    /// Defaults to the node of the agent being queried.
+    #[serde(rename = "Node", default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// How long locks held by the session are protected from being acquired by another session
+    /// once it is invalidated.
+    ///
+    /// Defaults to 15 seconds.
+    #[serde(
+        rename = "LockDelay",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub lock_delay: Option<Duration>,
+    #[serde(rename = "Behavior", default)]
+    pub behavior: SessionBehavior,
+    /// How long the session lives without being renewed, between 10 seconds and 24 hours.
+    ///
+    /// Sessions without a TTL live until destroyed, or until their node or checks fail.
+    #[serde(
+        rename = "TTL",
+        with = "go_duration::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttl: Option<Duration>,
+    /// The checks of the node which invalidate the session when they go critical.
+    ///
+    /// Defaults to the `serfHealth` check of the node.
+    #[serde(
+        rename = "NodeChecks",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub node_checks: Option<Vec<String>>,
+}
+
+/// A session, as returned by Consul.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SessionEntry {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Name", default)]
+    pub name: String,
+    #[serde(rename = "Node")]
+    pub node: String,
+    #[serde(rename = "LockDelay", default)]
+    pub lock_delay: u64,
+    #[serde(rename = "Behavior", default)]
+    pub behavior: SessionBehavior,
+    #[serde(rename = "TTL", default)]
+    pub ttl: String,
+    #[serde(rename = "NodeChecks", default)]
+    pub node_checks: Vec<String>,
+    #[serde(rename = "CreateIndex")]
+    pub create_index: u64,
+    #[serde(rename = "ModifyIndex")]
+    pub modify_index: u64,
+    /// Any fields returned by Consul which are not otherwise captured by this type.
+    #[serde(flatten, deserialize_with = "payload::extra")]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct SessionCreated {
+    #[serde(rename = "ID")]
+    id: String,
+}
+
+/// Session operations.
+///
+/// This type can be used to interact with the "Session" portion of the Consul API.  Sessions are
+/// used to hold locks on keys in the KV store, which are released, or the keys deleted, when the
+/// session is invalidated.
+#[derive(Clone, Debug)]
+pub struct Session {
+    http_client: Arc<HttpClient>,
+}
+
+impl Session {
+    /// Creates a new [`Session`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Session {
+        Session { http_client }
+    }
+
+    /// Creates a session, returning its ID.
+    pub async fn create(
+        &self,
+        session: &SessionRequest,
+        options: Option<WriteOptions>,
+    ) -> Result<String, Error> {
+        create(&self.http_client, session, options.as_ref()).await
+    }
+
+    /// Destroys the session with the given ID, invalidating it.
+    pub async fn destroy(&self, id: &str, options: Option<WriteOptions>) -> Result<bool, Error> {
+        destroy(&self.http_client, id, options.as_ref()).await
+    }
+
+    /// Renews the TTL of the session with the given ID.
+    ///
+    /// Returns `None` if the session no longer exists, such as when it has already expired.
+    pub async fn renew(
+        &self,
+        id: &str,
+        options: Option<WriteOptions>,
+    ) -> Result<Option<SessionEntry>, Error> {
+        renew(&self.http_client, id, options.as_ref()).await
+    }
+
+    /// Gets the session with the given ID.
+    ///
+    /// Returns `None` if the session does not exist.
+    pub async fn info(
+        &self,
+        id: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<SessionEntry>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "session", "info", id],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (entries, meta) = self
+            .http_client
+            .parse_list_query_response::<SessionEntry>(response)
+            .await?;
+        Ok((entries.into_iter().next(), meta))
+    }
+
+    /// Creates a session which deletes the keys it holds when invalidated, and keeps it renewed in
+    /// a background task.
+    ///
+    /// This is intended for presence or heartbeat keys, written with
+    /// [`Kv::put_ephemeral`](crate::Kv::put_ephemeral), which should disappear automatically when
+    /// the process dies: the session is renewed every half of `ttl`, so the keys are deleted at most
+    /// `ttl` after renewals stop.  The session is destroyed, deleting the keys immediately, when the
+    /// returned handle is dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub async fn create_ephemeral(
+        &self,
+        name: &str,
+        ttl: Duration,
+    ) -> Result<EphemeralSession, Error> {
+        let request = SessionRequest {
+            name: name.to_string(),
+            behavior: SessionBehavior::Delete,
+            ttl: Some(ttl),
+            // Deleting the keys should not be held up by the lock delay, as nothing locks them.
+            lock_delay: Some(Duration::from_secs(0)),
+            ..SessionRequest::default()
+        };
+        let id = create(&self.http_client, &request, None).await?;
+
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        let active = Arc::new(AtomicBool::new(true));
+        let task_active = active.clone();
+        let task_id = id.clone();
+        let http_client = self.http_client.clone();
+        spawn(async move {
+            loop {
+                match future::select(&mut stop_rx, delay_for(ttl / 2)).await {
+                    Either::Left(_) => {
+                        let _ = destroy(&http_client, &task_id, None).await;
+                        break;
+                    }
+                    Either::Right(_) => {}
+                }
+
+                let _result = renew(&http_client, &task_id, None).await;
+                #[cfg(feature = "tracing")]
+                if let Err(e) = &_result {
+                    tracing::debug!(error = %e, session = %task_id, "session renewal failed");
+                }
+                // Once the session is gone, it cannot be brought back, and neither can the keys.
+                // Failed renewals are retried on the next tick, unless the client is shutting down.
+                match &_result {
+                    Ok(None) => break,
+                    Err(e) if e.is_shutdown() => break,
+                    _ => {}
+                }
+            }
+            task_active.store(false, Ordering::Release);
+        });
+
+        Ok(EphemeralSession {
+            id,
+            active,
+            _stop: stop_tx,
+        })
+    }
+}
+
+/// Handle to a session which is kept renewed in a background task, as created by
+/// [`Session::create_ephemeral`].
+///
+/// The session is destroyed when this handle is dropped.
+pub struct EphemeralSession {
+    id: String,
+    active: Arc<AtomicBool>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl EphemeralSession {
+    /// Gets the ID of the session.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether or not the session is still being renewed.
+    ///
+    /// This becomes `false` if the session was invalidated, such as when renewals failed for longer
+    /// than its TTL, after which the keys it held have been deleted and a new session is needed.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for EphemeralSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EphemeralSession")
+            .field("id", &self.id)
+            .field("active", &self.is_active())
+            .finish()
+    }
+}
+
+async fn create(
+    http_client: &HttpClient,
+    session: &SessionRequest,
+    options: Option<&WriteOptions>,
+) -> Result<String, Error> {
+    let request =
+        http_client.build_request("PUT", &["v1", "session", "create"], options, session)?;
+    let response = http_client.run_request(request, options).await?;
+    let created: SessionCreated = http_client.parse_write_response(response).await?;
+    Ok(created.id)
+}
+
+async fn destroy(
+    http_client: &HttpClient,
+    id: &str,
+    options: Option<&WriteOptions>,
+) -> Result<bool, Error> {
+    let request =
+        http_client.build_request("PUT", &["v1", "session", "destroy", id], options, ())?;
+    let response = http_client.run_request(request, options).await?;
+    let destroyed = http_client.parse_write_response(response).await?;
+    Ok(destroyed)
+}
+
+async fn renew(
+    http_client: &HttpClient,
+    id: &str,
+    options: Option<&WriteOptions>,
+) -> Result<Option<SessionEntry>, Error> {
+    let request = http_client.build_request("PUT", &["v1", "session", "renew", id], options, ())?;
+    let response = http_client.run_request(request, options).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        let _ = hyper::body::to_bytes(response.into_body()).await;
+        return Ok(None);
+    }
+    let entries: Vec<SessionEntry> = http_client.parse_write_response(response).await?;
+    Ok(entries.into_iter().next())
+}
@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{watch, Notify};
+
+use crate::errors::Error;
+use crate::runtime::timeout;
+
+/// Tracks the requests in flight for a client, so that it can be shut down gracefully.
+#[derive(Debug)]
+pub(crate) struct Shutdown {
+    closed: AtomicBool,
+    in_flight: AtomicU64,
+    idle: Notify,
+    closed_tx: watch::Sender<bool>,
+    closed_rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Whether or not shutting down has started.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Registers a request as being in flight, for as long as the returned guard is held.
+    ///
+    /// Fails with [`Error::ClientShutdown`] if shutting down has already started.
+    pub fn request_started(&self) -> Result<RequestGuard<'_>, Error> {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let guard = RequestGuard(self);
+        if self.is_closed() {
+            return Err(Error::ClientShutdown);
+        }
+        Ok(guard)
+    }
+
+    /// Waits until shutting down has started.
+    pub async fn closed(&self) {
+        let mut rx = self.closed_rx.clone();
+        while let Some(closed) = rx.recv().await {
+            if closed {
+                return;
+            }
+        }
+    }
+
+    /// Stops accepting new requests, and waits for those in flight to finish, for up to the given
+    /// timeout.
+    ///
+    /// Returns `true` if all requests in flight finished in time.
+    pub async fn close(&self, wait: Duration) -> bool {
+        self.closed.store(true, Ordering::Release);
+        let _ = self.closed_tx.broadcast(true);
+
+        let idle = async {
+            while self.in_flight.load(Ordering::Acquire) > 0 {
+                self.idle.notified().await;
+            }
+        };
+        timeout(wait, idle).await.is_ok()
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Shutdown {
+        let (closed_tx, closed_rx) = watch::channel(false);
+        Shutdown {
+            closed: AtomicBool::new(false),
+            in_flight: AtomicU64::new(0),
+            idle: Notify::new(),
+            closed_tx,
+            closed_rx,
+        }
+    }
+}
+
+/// Marks a request as in flight until dropped.
+pub(crate) struct RequestGuard<'a>(&'a Shutdown);
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        let remaining = self.0.in_flight.fetch_sub(1, Ordering::AcqRel) - 1;
+        if remaining == 0 && self.0.is_closed() {
+            self.0.idle.notify();
+        }
+    }
+}
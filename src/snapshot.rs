@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tar::Archive;
+
+use crate::errors::Error;
+
+/// Name of the file holding the metadata within a snapshot archive.
+const META_FILE: &str = "meta.json";
+
+/// The metadata of a snapshot of the state of the Consul servers, as shown by
+/// `consul snapshot inspect`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SnapshotMeta {
+    #[serde(rename = "ID")]
+    pub id: String,
+    /// Version of the format of the snapshot.
+    #[serde(rename = "Version")]
+    pub version: u64,
+    /// Raft index of the last log entry included in the snapshot.
+    #[serde(rename = "Index")]
+    pub index: u64,
+    /// Raft term of the last log entry included in the snapshot.
+    #[serde(rename = "Term")]
+    pub term: u64,
+    /// Size of the state held in the snapshot, in bytes.
+    #[serde(rename = "Size", default)]
+    pub size: u64,
+    /// Raft index of the cluster configuration included in the snapshot.
+    #[serde(rename = "ConfigurationIndex", default)]
+    pub configuration_index: u64,
+    /// Any fields of the metadata which are not otherwise captured by this type.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl SnapshotMeta {
+    /// Reads the metadata of a snapshot, such as one saved with `consul snapshot save`.
+    ///
+    /// Snapshots are gzipped tar archives whose metadata comes first, so only as much of the given
+    /// reader is consumed as needed to get to it, and the state held in the snapshot is never
+    /// decoded.  This makes it cheap to check that a backup is a snapshot, and how recent it is,
+    /// but the state is not verified against the checksums in the snapshot.
+    pub fn read_from<R: Read>(reader: R) -> Result<SnapshotMeta, Error> {
+        read_meta(reader).map_err(Error::InvalidSnapshot)
+    }
+}
+
+fn read_meta<R: Read>(reader: R) -> io::Result<SnapshotMeta> {
+    let mut archive = Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()? == Path::new(META_FILE) {
+            return serde_json::from_reader(entry)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "snapshot has no metadata",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    const META: &str = r#"{"ID":"2-4567-1600000000000","Size":1234,"Index":4567,"Term":2,"Version":1,"ConfigurationIndex":12}"#;
+
+    /// Builds a gzipped tar archive holding the given files, in order.
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, data) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn reads_metadata() {
+        let state = vec![7u8; 10_000];
+        let data = archive(&[
+            ("meta.json", META.as_bytes()),
+            ("state.bin", &state),
+            ("SHA256SUMS", b"0000  meta.json\n"),
+        ]);
+
+        let meta = SnapshotMeta::read_from(&data[..]).unwrap();
+        assert_eq!(meta.id, "2-4567-1600000000000");
+        assert_eq!(meta.index, 4567);
+        assert_eq!(meta.term, 2);
+        assert_eq!(meta.version, 1);
+        assert_eq!(meta.size, 1234);
+        assert_eq!(meta.configuration_index, 12);
+        assert!(meta.extra.is_empty());
+    }
+
+    #[test]
+    fn reads_metadata_after_other_entries() {
+        let state = vec![1u8; 70_000];
+        let data = archive(&[("state.bin", &state), ("meta.json", META.as_bytes())]);
+        assert_eq!(SnapshotMeta::read_from(&data[..]).unwrap().index, 4567);
+    }
+
+    #[test]
+    fn rejects_snapshots_without_metadata() {
+        let data = archive(&[("state.bin", b"state")]);
+        let err = SnapshotMeta::read_from(&data[..]).unwrap_err();
+        assert!(matches!(err, Error::InvalidSnapshot(_)));
+        assert!(err.to_string().contains("snapshot has no metadata"));
+    }
+
+    #[test]
+    fn rejects_invalid_metadata() {
+        let data = archive(&[("meta.json", b"{\"ID\": 1}")]);
+        assert!(SnapshotMeta::read_from(&data[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        assert!(SnapshotMeta::read_from(&b"not a snapshot"[..]).is_err());
+        assert!(SnapshotMeta::read_from(&b""[..]).is_err());
+
+        // Cut off partway through the archive.
+        let data = archive(&[("meta.json", META.as_bytes())]);
+        assert!(SnapshotMeta::read_from(&data[..data.len() / 2]).is_err());
+
+        // Uncompressed tar archives are not snapshots.
+        let mut builder = Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_size(META.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "meta.json", META.as_bytes())
+            .unwrap();
+        let data = builder.into_inner().unwrap();
+        assert!(SnapshotMeta::read_from(&data[..]).is_err());
+    }
+}
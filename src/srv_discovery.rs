@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use url::Url;
+
+use crate::discovery::{DiscoveryFuture, ServerDiscovery};
+use crate::errors::Error;
+
+/// Default port for DNS resolvers.
+const DNS_PORT: u16 = 53;
+
+/// Default timeout for DNS queries.
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of a DNS response we advertise and accept via EDNS.
+const DNS_MAX_RESPONSE_LEN: usize = 4096;
+
+const DNS_TYPE_A: u16 = 1;
+const DNS_TYPE_AAAA: u16 = 28;
+const DNS_TYPE_SRV: u16 = 33;
+const DNS_TYPE_OPT: u16 = 41;
+const DNS_CLASS_IN: u16 = 1;
+
+/// Flag set on responses which were truncated to fit in a UDP datagram.
+const DNS_FLAG_TC: u16 = 0x0200;
+
+/// Discovers Consul endpoints via DNS SRV records.
+///
+/// Queries the SRV records for a name such as `consul.service.dc1.consul`, using either the
+/// system's configured resolver or an explicitly configured one, such as the DNS interface of a
+/// Consul agent at `127.0.0.1:8600`.
+///
+/// NOTE: The SRV records for the built-in `consul` service advertise the server RPC port, rather
+/// than the HTTP port, so callers using it will need to override the port via [`SrvDiscovery::port`].
+#[derive(Clone, Debug)]
+pub struct SrvDiscovery {
+    name: String,
+    resolver: Option<SocketAddr>,
+    scheme: String,
+    port: Option<u16>,
+}
+
+impl SrvDiscovery {
+    /// Creates a new [`SrvDiscovery`] for the given name.
+    pub fn new(name: &str) -> SrvDiscovery {
+        SrvDiscovery {
+            name: name.to_string(),
+            resolver: None,
+            scheme: "http".to_string(),
+            port: None,
+        }
+    }
+
+    /// Sets the DNS resolver to query.
+    ///
+    /// Defaults to the first nameserver in `/etc/resolv.conf`.
+    pub fn resolver(mut self, resolver: SocketAddr) -> SrvDiscovery {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Sets the scheme to use for the discovered endpoints.
+    ///
+    /// Defaults to `http`.
+    pub fn scheme(mut self, scheme: &str) -> SrvDiscovery {
+        self.scheme = scheme.to_string();
+        self
+    }
+
+    /// Overrides the port to use for the discovered endpoints.
+    ///
+    /// By default, the port from each SRV record is used.
+    pub fn port(mut self, port: u16) -> SrvDiscovery {
+        self.port = Some(port);
+        self
+    }
+
+    async fn resolve(&self) -> Result<Vec<Url>, Error> {
+        let resolver = match self.resolver {
+            Some(resolver) => resolver,
+            None => system_resolver().map_err(Error::DiscoveryFailed)?,
+        };
+
+        let records = timeout(DNS_TIMEOUT, query_srv(resolver, &self.name))
+            .await?
+            .map_err(Error::DiscoveryFailed)?;
+
+        records
+            .into_iter()
+            .map(|record| {
+                let host = match record.address {
+                    Some(IpAddr::V6(addr)) => format!("[{}]", addr),
+                    Some(IpAddr::V4(addr)) => addr.to_string(),
+                    None => record.target.trim_end_matches('.').to_string(),
+                };
+                let port = self.port.unwrap_or(record.port);
+                Ok(Url::parse(&format!(
+                    "{}://{}:{}/",
+                    self.scheme, host, port
+                ))?)
+            })
+            .collect()
+    }
+}
+
+impl ServerDiscovery for SrvDiscovery {
+    fn discover(&self) -> DiscoveryFuture<'_> {
+        Box::pin(self.resolve())
+    }
+}
+
+/// A resolved SRV record.
+#[derive(Debug)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+    address: Option<IpAddr>,
+}
+
+/// Gets the address of the system's DNS resolver.
+fn system_resolver() -> io::Result<SocketAddr> {
+    let resolv_conf = fs::read_to_string("/etc/resolv.conf")?;
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("nameserver") => parts.next(),
+                _ => None,
+            }
+        })
+        .filter_map(|addr| addr.parse::<IpAddr>().ok())
+        .map(|addr| SocketAddr::new(addr, DNS_PORT))
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no nameserver configured"))
+}
+
+/// Queries the given resolver for the SRV records of the given name.
+///
+/// Records are returned in order of priority, and then weight.  If the response included
+/// addresses for the record targets, as Consul does, they are included.  If the response over UDP
+/// is truncated, the query is retried over TCP.
+async fn query_srv(resolver: SocketAddr, name: &str) -> io::Result<Vec<SrvRecord>> {
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or(0);
+    let query = build_query(id, name)?;
+
+    let bind_addr: SocketAddr = if resolver.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let mut socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(resolver).await?;
+    socket.send(&query).await?;
+
+    let mut buf = vec![0u8; DNS_MAX_RESPONSE_LEN];
+    loop {
+        let len = socket.recv(&mut buf).await?;
+        let response = &buf[..len];
+        // Ignore any stray responses to other queries.
+        if response.len() >= 2 && u16::from_be_bytes([response[0], response[1]]) == id {
+            if is_truncated(response) {
+                return query_srv_tcp(resolver, &query).await;
+            }
+            return parse_response(response);
+        }
+    }
+}
+
+/// Queries the given resolver over TCP, for when the response does not fit in a UDP datagram.
+async fn query_srv_tcp(resolver: SocketAddr, query: &[u8]) -> io::Result<Vec<SrvRecord>> {
+    let mut stream = TcpStream::connect(resolver).await?;
+    // Messages over TCP are prefixed with their length.
+    let mut message = Vec::with_capacity(query.len() + 2);
+    message.extend_from_slice(&(query.len() as u16).to_be_bytes());
+    message.extend_from_slice(query);
+    stream.write_all(&message).await?;
+
+    let len = stream.read_u16().await? as usize;
+    let mut response = vec![0u8; len];
+    stream.read_exact(&mut response).await?;
+    parse_response(&response)
+}
+
+/// Whether or not the given response has the truncation (TC) flag set.
+fn is_truncated(response: &[u8]) -> bool {
+    response.len() >= 4 && u16::from_be_bytes([response[2], response[3]]) & DNS_FLAG_TC != 0
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn build_query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(64);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Standard query, recursion desired.
+    query.extend_from_slice(&0x0100u16.to_be_bytes());
+    // One question, no answers, no authority records, one additional record (EDNS).
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1]);
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid_data("invalid DNS name"));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&DNS_TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+
+    // EDNS OPT record, so that resolvers will send us more than 512 bytes.
+    query.push(0);
+    query.extend_from_slice(&DNS_TYPE_OPT.to_be_bytes());
+    query.extend_from_slice(&(DNS_MAX_RESPONSE_LEN as u16).to_be_bytes());
+    query.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+    Ok(query)
+}
+
+/// Minimal reader over a DNS message.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> io::Result<u8> {
+        let value = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| invalid_data("truncated DNS response"))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid_data("truncated DNS response"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn name(&mut self) -> io::Result<String> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+        // Bound the number of labels/pointers we follow, to avoid looping on malicious input.
+        for _ in 0..128 {
+            let len = *self
+                .data
+                .get(pos)
+                .ok_or_else(|| invalid_data("truncated DNS name"))? as usize;
+            if len == 0 {
+                if !jumped {
+                    self.pos = pos + 1;
+                }
+                return Ok(labels.join("."));
+            } else if len & 0xC0 == 0xC0 {
+                let low = *self
+                    .data
+                    .get(pos + 1)
+                    .ok_or_else(|| invalid_data("truncated DNS name"))?
+                    as usize;
+                if !jumped {
+                    self.pos = pos + 2;
+                }
+                pos = ((len & 0x3F) << 8) | low;
+                jumped = true;
+            } else {
+                let label = self
+                    .data
+                    .get(pos + 1..pos + 1 + len)
+                    .ok_or_else(|| invalid_data("truncated DNS name"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+        }
+
+        Err(invalid_data("DNS name too long"))
+    }
+}
+
+fn parse_response(data: &[u8]) -> io::Result<Vec<SrvRecord>> {
+    let mut reader = Reader { data, pos: 0 };
+    let _id = reader.u16()?;
+    let flags = reader.u16()?;
+    // A truncated response is missing records, and so would give us a partial list of servers.
+    if flags & DNS_FLAG_TC != 0 {
+        return Err(invalid_data("truncated DNS response"));
+    }
+    let rcode = flags & 0x000F;
+    if rcode != 0 {
+        return Err(invalid_data(&format!(
+            "DNS query failed with response code {}",
+            rcode
+        )));
+    }
+
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    let authorities = reader.u16()?;
+    let additionals = reader.u16()?;
+
+    for _ in 0..questions {
+        reader.name()?;
+        reader.bytes(4)?;
+    }
+
+    let mut records = Vec::new();
+    let mut addresses = HashMap::new();
+    let total = answers as usize + authorities as usize + additionals as usize;
+    for _ in 0..total {
+        let name = reader.name()?;
+        let rtype = reader.u16()?;
+        let _class = reader.u16()?;
+        let _ttl = reader.bytes(4)?;
+        let rdlen = reader.u16()? as usize;
+        let rdata_start = reader.pos;
+
+        match rtype {
+            DNS_TYPE_SRV => {
+                let priority = reader.u16()?;
+                let weight = reader.u16()?;
+                let port = reader.u16()?;
+                let target = reader.name()?;
+                records.push(SrvRecord {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                    address: None,
+                });
+            }
+            DNS_TYPE_A if rdlen == 4 => {
+                let octets = reader.bytes(4)?;
+                let addr = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
+                addresses.entry(name).or_insert_with(|| IpAddr::V4(addr));
+            }
+            DNS_TYPE_AAAA if rdlen == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(reader.bytes(16)?);
+                addresses
+                    .entry(name)
+                    .or_insert_with(|| IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+
+        reader.pos = rdata_start + rdlen;
+    }
+
+    for record in records.iter_mut() {
+        record.address = addresses.get(&record.target).cloned();
+    }
+
+    records.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.weight.cmp(&a.weight))
+    });
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a response to a query for `web.service.consul` with the given flags, answers and
+    /// additional records.
+    fn response(flags: u16, answers: &[Vec<u8>], additionals: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x1234u16.to_be_bytes());
+        data.extend_from_slice(&flags.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+        data.extend_from_slice(&(additionals.len() as u16).to_be_bytes());
+        // The question name starts at offset 12, so records can point back to it.
+        data.extend_from_slice(b"\x03web\x07service\x06consul\x00");
+        data.extend_from_slice(&DNS_TYPE_SRV.to_be_bytes());
+        data.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        for record in answers.iter().chain(additionals) {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    /// Builds a resource record whose name is a pointer to the given offset.
+    fn record(name_offset: u16, rtype: u16, rdata: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(0xC000 | name_offset).to_be_bytes());
+        data.extend_from_slice(&rtype.to_be_bytes());
+        data.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 60]);
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(rdata);
+        data
+    }
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&priority.to_be_bytes());
+        data.extend_from_slice(&weight.to_be_bytes());
+        data.extend_from_slice(&port.to_be_bytes());
+        data.extend_from_slice(target);
+        data
+    }
+
+    #[test]
+    fn build_query_encodes_name_and_edns() {
+        let query = build_query(0xBEEF, "consul.service.dc1.consul.").unwrap();
+        assert_eq!(&query[..4], &[0xBE, 0xEF, 0x01, 0x00]);
+        assert_eq!(&query[4..12], &[0, 1, 0, 0, 0, 0, 0, 1]);
+        let name = b"\x06consul\x07service\x03dc1\x06consul\x00";
+        assert_eq!(&query[12..12 + name.len()], &name[..]);
+
+        let rest = &query[12 + name.len()..];
+        assert_eq!(&rest[..4], &[0, 33, 0, 1]);
+        assert_eq!(&rest[4..7], &[0, 0, 41]);
+        assert_eq!(&rest[7..9], &(DNS_MAX_RESPONSE_LEN as u16).to_be_bytes());
+        assert_eq!(rest.len(), 15);
+    }
+
+    #[test]
+    fn build_query_rejects_invalid_names() {
+        assert!(build_query(1, "consul..service").is_err());
+        assert!(build_query(1, "").is_err());
+        assert!(build_query(1, &"a".repeat(64)).is_err());
+        assert!(build_query(1, &"a".repeat(63)).is_ok());
+    }
+
+    #[test]
+    fn parse_response_follows_compression_pointers() {
+        // The first target is spelled out, and the second points at the first.
+        let first = srv(1, 10, 8300, b"\x02n1\x04node\x06consul\x00");
+        let first_record = record(12, DNS_TYPE_SRV, &first);
+        let first_target = 12 + 24 + first_record.len() - first.len() + 6;
+        let second = srv(0, 5, 8301, &(0xC000 | first_target as u16).to_be_bytes());
+        let address = record(first_target as u16, DNS_TYPE_A, &[10, 0, 0, 1]);
+        let data = response(
+            0x8180,
+            &[first_record, record(12, DNS_TYPE_SRV, &second)],
+            &[address],
+        );
+
+        let records = parse_response(&data).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].priority, 0);
+        assert_eq!(records[0].port, 8301);
+        assert_eq!(records[0].target, "n1.node.consul");
+        assert_eq!(records[1].port, 8300);
+        assert_eq!(records[1].target, "n1.node.consul");
+        for record in &records {
+            assert_eq!(record.address, Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+        }
+    }
+
+    #[test]
+    fn parse_response_orders_by_priority_then_weight() {
+        let data = response(
+            0x8180,
+            &[
+                record(12, DNS_TYPE_SRV, &srv(2, 0, 1, b"\x01a\x00")),
+                record(12, DNS_TYPE_SRV, &srv(1, 1, 2, b"\x01b\x00")),
+                record(12, DNS_TYPE_SRV, &srv(1, 9, 3, b"\x01c\x00")),
+            ],
+            &[],
+        );
+        let ports = parse_response(&data)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.port)
+            .collect::<Vec<_>>();
+        assert_eq!(ports, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn parse_response_bounds_pointer_loops() {
+        // The target points at itself.
+        let answer = record(12, DNS_TYPE_SRV, &[0, 0, 0, 0, 0, 0, 0xC0, 0]);
+        let mut data = response(0x8180, &[answer], &[]);
+        let target = data.len() - 2;
+        data[target + 1] = target as u8;
+
+        let err = parse_response(&data).err().unwrap();
+        assert_eq!(err.to_string(), "DNS name too long");
+    }
+
+    #[test]
+    fn parse_response_rejects_short_buffers() {
+        let answer = record(12, DNS_TYPE_SRV, &srv(1, 1, 8300, b"\x01a\x00"));
+        let data = response(0x8180, &[answer], &[]);
+        for len in 0..data.len() {
+            assert!(parse_response(&data[..len]).is_err(), "length {}", len);
+        }
+        assert!(parse_response(&data).is_ok());
+    }
+
+    #[test]
+    fn parse_response_rejects_truncated_responses() {
+        let answer = record(12, DNS_TYPE_SRV, &srv(1, 1, 8300, b"\x01a\x00"));
+        let data = response(0x8380, &[answer], &[]);
+        assert!(is_truncated(&data));
+        assert!(parse_response(&data).is_err());
+        assert!(!is_truncated(&response(0x8180, &[], &[])));
+    }
+
+    #[test]
+    fn parse_response_rejects_error_codes() {
+        let err = parse_response(&response(0x8183, &[], &[])).err().unwrap();
+        assert_eq!(err.to_string(), "DNS query failed with response code 3");
+    }
+
+    #[tokio::test]
+    async fn query_srv_retries_truncated_responses_over_tcp() {
+        let answer = record(12, DNS_TYPE_SRV, &srv(1, 1, 8300, b"\x01a\x00"));
+        let full = response(0x8180, &[answer], &[]);
+
+        let mut udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let resolver = udp.local_addr().unwrap();
+        let mut tcp = tokio::net::TcpListener::bind(resolver).await.unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (_, from) = udp.recv_from(&mut buf).await.unwrap();
+            let mut truncated = response(0x8380, &[], &[]);
+            truncated[..2].copy_from_slice(&buf[..2]);
+            udp.send_to(&truncated, &from).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let (mut stream, _) = tcp.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap() as usize;
+            let mut query = vec![0u8; len];
+            stream.read_exact(&mut query).await.unwrap();
+            let mut full = full;
+            full[..2].copy_from_slice(&query[..2]);
+            stream.write_u16(full.len() as u16).await.unwrap();
+            stream.write_all(&full).await.unwrap();
+        });
+
+        let records = query_srv(resolver, "web.service.consul").await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].port, 8300);
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::{Body, Response, StatusCode};
+
+use crate::errors::Error;
+
+/// Counts of failed requests, by the class of failure.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ErrorCounts {
+    /// Requests which failed to connect to, or communicate with, Consul.
+    pub connection: u64,
+    /// Requests which timed out.
+    ///
+    /// Blocking queries whose wait expired without any changes are not counted.
+    pub timeout: u64,
+    /// Requests rejected by Consul for exceeding its rate limits.
+    pub rate_limited: u64,
+    /// Requests which Consul failed with a server error.
+    pub server: u64,
+    /// Requests which Consul rejected as invalid or unauthorized.
+    ///
+    /// Requests for resources which do not exist are not counted, as they are typical of many
+    /// operations.
+    pub client: u64,
+    /// Requests rejected without being sent, as the circuit breaker was open.
+    pub circuit_open: u64,
+}
+
+/// A snapshot of the statistics of a [`Client`](crate::Client).
+///
+/// Requests are only counted once sent to Consul, so those served from the response cache are not
+/// included.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientStats {
+    /// Number of requests currently waiting on a response from Consul.
+    pub requests_in_flight: u64,
+    /// Total number of requests sent to Consul.
+    pub total_requests: u64,
+    /// Counts of the requests which failed.
+    pub errors: ErrorCounts,
+    /// Number of watches currently running.
+    pub active_watches: u64,
+    /// Average latency of the requests which completed.
+    ///
+    /// Blocking queries are not included, as they are expected to wait until something changes.
+    /// This is `None` if no requests have completed yet.
+    pub average_latency: Option<Duration>,
+}
+
+/// A snapshot of the statistics of a [`Watch`](crate::Watch).
+///
+/// A watch which is healthy wakes up rarely, whenever the result of its query changes or its wait
+/// expires, while one which is spinning wakes up often without anything having changed, with a
+/// short average block duration.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WatchStats {
+    /// Number of times the query returned, whether or not the result changed.
+    pub wakeups: u64,
+    /// Number of wakeups where the result had not changed, which were not yielded by the watch.
+    ///
+    /// This includes blocking queries whose wait expired.
+    pub spurious_wakeups: u64,
+    /// Average amount of time the query blocked for before returning.
+    ///
+    /// This is `None` if the query has not returned yet.
+    pub average_block: Option<Duration>,
+    /// The error which stopped the watch, if any.
+    pub last_error: Option<String>,
+}
+
+/// Records the statistics of a watch, as its query returns.
+#[derive(Debug, Default)]
+pub(crate) struct WatchRecorder {
+    wakeups: AtomicU64,
+    spurious_wakeups: AtomicU64,
+    block_total_us: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl WatchRecorder {
+    /// Records that the query returned after blocking for the given duration.
+    pub fn wakeup(&self, blocked: Duration, spurious: bool) {
+        self.wakeups.fetch_add(1, Ordering::Relaxed);
+        if spurious {
+            self.spurious_wakeups.fetch_add(1, Ordering::Relaxed);
+        }
+        self.block_total_us
+            .fetch_add(blocked.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Records the error which stopped the watch.
+    pub fn error(&self, error: &Error) {
+        *self.last_error.lock().expect("watch stats lock poisoned") = Some(error.to_string());
+    }
+
+    /// Takes a snapshot of the current statistics.
+    pub fn snapshot(&self) -> WatchStats {
+        let wakeups = self.wakeups.load(Ordering::Relaxed);
+        let average_block = self
+            .block_total_us
+            .load(Ordering::Relaxed)
+            .checked_div(wakeups)
+            .map(Duration::from_micros);
+
+        WatchStats {
+            wakeups,
+            spurious_wakeups: self.spurious_wakeups.load(Ordering::Relaxed),
+            average_block,
+            last_error: self
+                .last_error
+                .lock()
+                .expect("watch stats lock poisoned")
+                .clone(),
+        }
+    }
+}
+
+/// Records the statistics of a client, as requests are made.
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder {
+    in_flight: AtomicU64,
+    total: AtomicU64,
+    connection_errors: AtomicU64,
+    timeout_errors: AtomicU64,
+    rate_limited_errors: AtomicU64,
+    server_errors: AtomicU64,
+    client_errors: AtomicU64,
+    circuit_open_errors: AtomicU64,
+    active_watches: AtomicU64,
+    latency_total_us: AtomicU64,
+    latency_count: AtomicU64,
+}
+
+impl StatsRecorder {
+    /// Records that a request has been sent, returning a guard which tracks it while in flight.
+    pub fn request_started(&self) -> InFlightGuard<'_> {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(self)
+    }
+
+    /// Records the outcome of a request which has completed.
+    pub fn request_finished(
+        &self,
+        result: &Result<Response<Body>, Error>,
+        latency: Duration,
+        blocking: bool,
+    ) {
+        let counter = match result {
+            Ok(response) => match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => Some(&self.rate_limited_errors),
+                StatusCode::NOT_FOUND => None,
+                status if status.is_server_error() => Some(&self.server_errors),
+                status if status.is_client_error() => Some(&self.client_errors),
+                _ => None,
+            },
+            Err(Error::BlockingWaitExpired) => None,
+            Err(Error::RequestTimedOut(_)) => Some(&self.timeout_errors),
+            Err(Error::RequestError(_)) | Err(Error::DiscoveryFailed(_)) => {
+                Some(&self.connection_errors)
+            }
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            Err(Error::FetchError(_)) => Some(&self.connection_errors),
+            Err(_) => None,
+        };
+        if let Some(counter) = counter {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if !blocking {
+            self.latency_total_us
+                .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+            self.latency_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records that a request was rejected by the circuit breaker.
+    pub fn circuit_open(&self) {
+        self.circuit_open_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the current statistics.
+    pub fn snapshot(&self) -> ClientStats {
+        let average_latency = self
+            .latency_total_us
+            .load(Ordering::Relaxed)
+            .checked_div(self.latency_count.load(Ordering::Relaxed))
+            .map(Duration::from_micros);
+
+        ClientStats {
+            requests_in_flight: self.in_flight.load(Ordering::Relaxed),
+            total_requests: self.total.load(Ordering::Relaxed),
+            errors: ErrorCounts {
+                connection: self.connection_errors.load(Ordering::Relaxed),
+                timeout: self.timeout_errors.load(Ordering::Relaxed),
+                rate_limited: self.rate_limited_errors.load(Ordering::Relaxed),
+                server: self.server_errors.load(Ordering::Relaxed),
+                client: self.client_errors.load(Ordering::Relaxed),
+                circuit_open: self.circuit_open_errors.load(Ordering::Relaxed),
+            },
+            active_watches: self.active_watches.load(Ordering::Relaxed),
+            average_latency,
+        }
+    }
+}
+
+/// Tracks a request as in flight until dropped.
+#[derive(Debug)]
+pub(crate) struct InFlightGuard<'a>(&'a StatsRecorder);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Tracks a watch as active until dropped.
+#[derive(Debug)]
+pub(crate) struct WatchGuard(Arc<StatsRecorder>);
+
+impl WatchGuard {
+    pub fn new(stats: Arc<StatsRecorder>) -> WatchGuard {
+        stats.active_watches.fetch_add(1, Ordering::Relaxed);
+        WatchGuard(stats)
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        self.0.active_watches.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::common::{QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::operator::AutopilotHealth;
+
+/// Status operations.
+///
+/// This type can be used to interact with the "Status" portion of the Consul API, which reports
+/// on the Raft cluster of the datacenter.
+#[derive(Clone, Debug)]
+pub struct Status {
+    http_client: Arc<HttpClient>,
+}
+
+impl Status {
+    /// Creates a new [`Status`].
+    pub(crate) fn new(http_client: Arc<HttpClient>) -> Status {
+        Status { http_client }
+    }
+
+    /// Gets the Raft address of the current leader of the cluster.
+    ///
+    /// Returns `None` if the cluster currently has no leader.
+    pub async fn leader(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Option<String>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "status", "leader"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (leader, meta) = self
+            .http_client
+            .parse_query_response::<String>(response)
+            .await?;
+        Ok((Some(leader).filter(|leader| !leader.is_empty()), meta))
+    }
+
+    /// Gets the Raft addresses of the voting servers in the cluster.
+    pub async fn peers(
+        &self,
+        options: Option<QueryOptions>,
+    ) -> Result<(Vec<String>, QueryMetadata), Error> {
+        let options = self.http_client.query_options(options);
+        let request = self.http_client.build_request(
+            "GET",
+            &["v1", "status", "peers"],
+            options.as_ref(),
+            (),
+        )?;
+        let response = self
+            .http_client
+            .run_request(request, options.as_ref())
+            .await?;
+        let (parsed, meta) = self.http_client.parse_query_response(response).await?;
+        Ok((parsed, meta))
+    }
+}
+
+/// A summary of the health of a Consul cluster, as returned by
+/// [`Client::cluster_health`](crate::Client::cluster_health).
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ClusterHealth {
+    /// The Raft address of the current leader, if there is one.
+    pub leader: Option<String>,
+    /// The Raft addresses of the voting servers.
+    pub peers: Vec<String>,
+    /// The health of the servers as tracked by Autopilot.
+    ///
+    /// This is `None` if it could not be read, such as when the token lacks `operator:read`
+    /// permissions, or when there is no leader to report it.
+    pub autopilot: Option<AutopilotHealth>,
+}
+
+impl ClusterHealth {
+    /// Whether or not the cluster is functional.
+    ///
+    /// The cluster is healthy if it has a leader and at least one voting server, and Autopilot, if
+    /// its health could be read, considers all servers healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.leader.is_some()
+            && !self.peers.is_empty()
+            && self.autopilot.as_ref().is_none_or(|health| health.healthy)
+    }
+
+    /// Gets how many servers can fail without the cluster losing its leader, if known.
+    pub fn failure_tolerance(&self) -> Option<u64> {
+        self.autopilot
+            .as_ref()
+            .map(|health| health.failure_tolerance)
+    }
+}
@@ -0,0 +1,885 @@
+//! Utilities for testing code which uses this crate, without a real Consul agent.
+//!
+//! [`MockConsul`] runs an in-process HTTP server which implements a subset of the Consul API on
+//! top of fixtures set up by the test: nodes, service instances and their health, and KV pairs.
+//! Every change to the fixtures advances the Consul index, just like a real cluster, which wakes
+//! up any blocking queries waiting on it, so watches can be tested end-to-end.
+//!
+//! The following endpoints are supported:
+//!
+//! - `/v1/status/leader` and `/v1/status/peers`
+//! - `/v1/catalog/datacenters`, `/v1/catalog/nodes`, `/v1/catalog/node/<node>`,
+//!   `/v1/catalog/services` and `/v1/catalog/service/<service>`
+//! - `/v1/coordinate/nodes` and `/v1/coordinate/node/<node>`
+//! - `/v1/health/service/<service>`
+//! - `/v1/kv/<key>`, for reads, writes and deletes, including acquiring keys with a session
+//! - `/v1/session/create`, `/v1/session/renew/<id>`, `/v1/session/destroy/<id>` and
+//!   `/v1/session/info/<id>`
+//!
+//! Any other request is answered with a 404.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::{oneshot, watch};
+use tokio::time::timeout;
+use url::form_urlencoded;
+
+use crate::common::go_duration;
+use crate::coordinate::NetworkCoordinate;
+use crate::errors::Error;
+use crate::health::CheckStatus;
+use crate::kv::KvPair;
+use crate::session::SessionBehavior;
+use crate::Client;
+
+/// Default wait time of blocking queries which don't specify one, matching Consul.
+const DEFAULT_WAIT: Duration = Duration::from_secs(300);
+
+const DATACENTER: &str = "dc1";
+
+/// A service instance to serve from a [`MockConsul`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockServiceInstance {
+    /// Name of the node the instance is registered on.
+    pub node: String,
+    /// ID of the instance, unique within the node.
+    pub id: String,
+    /// Name of the service.
+    pub name: String,
+    /// Address of the instance, or empty to use the address of the node.
+    pub address: String,
+    /// Port of the instance.
+    pub port: u16,
+    /// Tags of the instance.
+    pub tags: Vec<String>,
+    /// Metadata of the instance.
+    pub meta: HashMap<String, String>,
+    /// Status of the health check of the instance.
+    pub status: CheckStatus,
+}
+
+impl MockServiceInstance {
+    /// Creates a new [`MockServiceInstance`] for the given service, on the given node.
+    ///
+    /// The ID of the instance defaults to the name of the service, and its health check to
+    /// passing.
+    pub fn new(node: &str, name: &str) -> MockServiceInstance {
+        MockServiceInstance {
+            node: node.to_string(),
+            id: name.to_string(),
+            name: name.to_string(),
+            address: String::new(),
+            port: 0,
+            tags: Vec::new(),
+            meta: HashMap::new(),
+            status: CheckStatus::Passing,
+        }
+    }
+}
+
+struct MockNode {
+    address: String,
+    meta: HashMap<String, String>,
+    create_index: u64,
+    modify_index: u64,
+}
+
+struct MockService {
+    instance: MockServiceInstance,
+    create_index: u64,
+    modify_index: u64,
+}
+
+struct MockSession {
+    name: String,
+    behavior: SessionBehavior,
+    ttl: String,
+    create_index: u64,
+}
+
+#[derive(Default)]
+struct State {
+    index: u64,
+    nodes: BTreeMap<String, MockNode>,
+    services: BTreeMap<(String, String), MockService>,
+    kv: BTreeMap<String, KvPair>,
+    sessions: BTreeMap<String, MockSession>,
+    coordinates: BTreeMap<String, NetworkCoordinate>,
+    stale_last_contact: Option<Duration>,
+    stale_index_lag: u64,
+}
+
+impl State {
+    fn ensure_node(&mut self, node: &str, address: &str, meta: HashMap<String, String>) {
+        let index = self.index;
+        let entry = self
+            .nodes
+            .entry(node.to_string())
+            .or_insert_with(|| MockNode {
+                address: String::new(),
+                meta: HashMap::new(),
+                create_index: index,
+                modify_index: index,
+            });
+        entry.address = address.to_string();
+        entry.meta = meta;
+        entry.modify_index = index;
+    }
+
+    /// Invalidates the given session, deleting or releasing the keys it holds.
+    fn invalidate_session(&mut self, id: &str) -> bool {
+        let session = match self.sessions.remove(id) {
+            Some(session) => session,
+            None => return false,
+        };
+        match session.behavior {
+            SessionBehavior::Delete => self
+                .kv
+                .retain(|_, pair| pair.session.as_deref() != Some(id)),
+            SessionBehavior::Release => {
+                for pair in self.kv.values_mut() {
+                    if pair.session.as_deref() == Some(id) {
+                        pair.session = None;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+/// An in-process mock of the Consul HTTP API.
+///
+/// The server runs until the [`MockConsul`] is dropped.  All fixture methods can be called at any
+/// time, including while queries are running.
+pub struct MockConsul {
+    addr: SocketAddr,
+    state: SharedState,
+    index_tx: watch::Sender<u64>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockConsul {
+    /// Starts a new [`MockConsul`], listening on a random local port.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub async fn start() -> Result<MockConsul, Error> {
+        let state = Arc::new(Mutex::new(State {
+            index: 1,
+            ..State::default()
+        }));
+        let (index_tx, index_rx) = watch::channel(1);
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            let index_rx = index_rx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    let index_rx = index_rx.clone();
+                    async move { Ok::<_, Infallible>(handle(state, index_rx, request).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = shutdown_rx.await;
+        }));
+
+        Ok(MockConsul {
+            addr,
+            state,
+            index_tx,
+            _shutdown: shutdown_tx,
+        })
+    }
+
+    /// Gets the base URI of the mock server.
+    pub fn base_uri(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Creates a [`Client`] which talks to the mock server.
+    pub fn client(&self) -> Client {
+        Client::new(&self.base_uri()).expect("mock server URI must be valid")
+    }
+
+    /// Gets the current Consul index.
+    pub fn index(&self) -> u64 {
+        self.lock().index
+    }
+
+    /// Advances the Consul index without changing any fixtures, returning the new index.
+    ///
+    /// This wakes up every blocking query, as happens with a real cluster when unrelated data
+    /// changes, which is useful for testing how spurious wakeups are handled.
+    pub fn advance_index(&self) -> u64 {
+        self.update(|_, _| {})
+    }
+
+    /// Adds a node with the given address, or updates it if it already exists.
+    pub fn add_node(&self, node: &str, address: &str) {
+        self.add_node_with_meta(node, address, HashMap::new());
+    }
+
+    /// Adds a node with the given address and metadata, or updates it if it already exists.
+    pub fn add_node_with_meta(&self, node: &str, address: &str, meta: HashMap<String, String>) {
+        self.update(|state, _| state.ensure_node(node, address, meta));
+    }
+
+    /// Removes a node, along with every service instance registered on it.
+    pub fn remove_node(&self, node: &str) {
+        self.update(|state, _| {
+            state.nodes.remove(node);
+            state.coordinates.remove(node);
+            state.services.retain(|(n, _), _| n != node);
+        });
+    }
+
+    /// Adds a service instance, or updates it if it already exists.
+    ///
+    /// The node of the instance is added, with an address of `127.0.0.1`, if it does not exist.
+    pub fn add_service(&self, instance: MockServiceInstance) {
+        self.update(|state, index| {
+            if !state.nodes.contains_key(&instance.node) {
+                state.ensure_node(&instance.node, "127.0.0.1", HashMap::new());
+            }
+
+            let key = (instance.node.clone(), instance.id.clone());
+            let create_index = state
+                .services
+                .get(&key)
+                .map_or(index, |existing| existing.create_index);
+            state.services.insert(
+                key,
+                MockService {
+                    instance,
+                    create_index,
+                    modify_index: index,
+                },
+            );
+        });
+    }
+
+    /// Removes the service instance with the given ID from the given node.
+    pub fn remove_service(&self, node: &str, id: &str) {
+        self.update(|state, _| {
+            state.services.remove(&(node.to_string(), id.to_string()));
+        });
+    }
+
+    /// Sets the status of the health check of the given service instance.
+    ///
+    /// Does nothing, other than advancing the index, if the instance does not exist.
+    pub fn set_check_status(&self, node: &str, id: &str, status: CheckStatus) {
+        self.update(|state, index| {
+            if let Some(service) = state.services.get_mut(&(node.to_string(), id.to_string())) {
+                service.instance.status = status;
+                service.modify_index = index;
+            }
+        });
+    }
+
+    /// Sets the network coordinate of the given node, adding the node if it does not exist.
+    pub fn set_coordinate(&self, node: &str, coord: NetworkCoordinate) {
+        self.update(|state, _| {
+            if !state.nodes.contains_key(node) {
+                state.ensure_node(node, "127.0.0.1", HashMap::new());
+            }
+            state.coordinates.insert(node.to_string(), coord);
+        });
+    }
+
+    /// Sets the value of the given key.
+    pub fn put_kv<V>(&self, key: &str, value: V)
+    where
+        V: Into<Bytes>,
+    {
+        let value = value.into();
+        self.update(|state, index| put_kv(state, index, key, value, None));
+    }
+
+    /// Deletes the given key.
+    pub fn delete_kv(&self, key: &str) {
+        self.update(|state, _| {
+            state.kv.remove(key);
+        });
+    }
+
+    /// Invalidates the given session, as if its TTL ran out or its node failed.
+    ///
+    /// The keys held by the session are deleted or released, based on its behavior.
+    pub fn expire_session(&self, id: &str) {
+        self.update(|state, _| {
+            state.invalidate_session(id);
+        });
+    }
+
+    /// Sets how long ago reads which allow stale results report having contacted the leader.
+    ///
+    /// This simulates the read being serviced by a follower which is lagging behind the leader,
+    /// while other reads report contacting it just now.  Pass `None` to reset this.
+    pub fn set_stale_last_contact(&self, last_contact: Option<Duration>) {
+        self.lock().stale_last_contact = last_contact;
+    }
+
+    /// Sets how far behind the current index reads which allow stale results report being.
+    ///
+    /// This simulates the read being serviced by a follower which has not applied the latest
+    /// changes yet, such as after a failover.  Pass `0` to reset this.
+    pub fn set_stale_index_lag(&self, lag: u64) {
+        self.lock().stale_index_lag = lag;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("mock state lock poisoned")
+    }
+
+    /// Applies a change to the fixtures, advancing the index and waking up blocking queries.
+    fn update<F>(&self, f: F) -> u64
+    where
+        F: FnOnce(&mut State, u64),
+    {
+        let index = {
+            let mut state = self.lock();
+            state.index += 1;
+            let index = state.index;
+            f(&mut state, index);
+            index
+        };
+        let _ = self.index_tx.broadcast(index);
+        index
+    }
+}
+
+impl fmt::Debug for MockConsul {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockConsul")
+            .field("addr", &self.addr)
+            .field("index", &self.index())
+            .finish()
+    }
+}
+
+fn put_kv(state: &mut State, index: u64, key: &str, value: Bytes, flags: Option<u64>) {
+    let existing = state.kv.get(key);
+    let pair = KvPair {
+        key: key.to_string(),
+        create_index: existing.map_or(index, |pair| pair.create_index),
+        modify_index: index,
+        lock_index: existing.map_or(0, |pair| pair.lock_index),
+        flags: flags
+            .or_else(|| existing.map(|pair| pair.flags))
+            .unwrap_or(0),
+        value: Some(value),
+        session: existing.and_then(|pair| pair.session.clone()),
+    };
+    state.kv.insert(key.to_string(), pair);
+}
+
+async fn handle(
+    state: SharedState,
+    index_rx: watch::Receiver<u64>,
+    request: Request<Body>,
+) -> Response<Body> {
+    let stale = request
+        .uri()
+        .query()
+        .is_some_and(|q| form_urlencoded::parse(q.as_bytes()).any(|(k, _)| k == "stale"));
+    let mut response = route(state.clone(), index_rx, request).await;
+
+    // Only responses which carry the staleness headers to begin with are served by servers.
+    if !stale || !response.headers().contains_key("X-Consul-LastContact") {
+        return response;
+    }
+    let (last_contact, index_lag) = {
+        let state = state.lock().expect("mock state lock poisoned");
+        (state.stale_last_contact, state.stale_index_lag)
+    };
+    if let Some(last_contact) = last_contact {
+        response.headers_mut().insert(
+            "X-Consul-LastContact",
+            (last_contact.as_millis() as u64).into(),
+        );
+    }
+    let index = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if let Some(index) = index.filter(|_| index_lag > 0) {
+        let lagging = std::cmp::max(index.saturating_sub(index_lag), 1);
+        response
+            .headers_mut()
+            .insert("X-Consul-Index", lagging.into());
+    }
+    response
+}
+
+async fn route(
+    state: SharedState,
+    mut index_rx: watch::Receiver<u64>,
+    request: Request<Body>,
+) -> Response<Body> {
+    let params: HashMap<String, String> = request
+        .uri()
+        .query()
+        .map(|q| form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+        .unwrap_or_default();
+
+    // Block until the index moves past the one given, or until the wait runs out.
+    if let Some(index) = params.get("index").and_then(|i| i.parse::<u64>().ok()) {
+        let wait = params
+            .get("wait")
+            .and_then(|w| go_duration::parse(w).ok())
+            .unwrap_or(DEFAULT_WAIT);
+        let _ = timeout(wait, async {
+            while *index_rx.borrow() <= index {
+                if index_rx.recv().await.is_none() {
+                    break;
+                }
+            }
+        })
+        .await;
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return respond(StatusCode::BAD_REQUEST, 0, Value::Null),
+    };
+
+    let mut state = state.lock().expect("mock state lock poisoned");
+    let parts = path
+        .trim_start_matches('/')
+        .splitn(3, '/')
+        .collect::<Vec<_>>();
+    match (&method, parts.as_slice()) {
+        (&Method::GET, ["v1", "status", "leader"]) => {
+            respond(StatusCode::OK, state.index, json!("127.0.0.1:8300"))
+        }
+        (&Method::GET, ["v1", "status", "peers"]) => {
+            respond(StatusCode::OK, state.index, json!(["127.0.0.1:8300"]))
+        }
+        (&Method::GET, ["v1", "catalog", rest]) => catalog(&state, rest, &params),
+        (&Method::GET, ["v1", "health", rest]) => match rest.strip_prefix("service/") {
+            Some(service) => {
+                let passing = params.contains_key("passing");
+                let entries = service_instances(&state, service, &params)
+                    .filter(|s| !passing || s.instance.status == CheckStatus::Passing)
+                    .map(|s| health_entry(&state, s))
+                    .collect::<Vec<_>>();
+                respond(StatusCode::OK, state.index, Value::Array(entries))
+            }
+            None => not_found(),
+        },
+        (&Method::GET, ["v1", "coordinate", rest]) => coordinate(&state, rest),
+        (_, ["v1", "kv", key]) => kv(&mut state, &method, key, &params, body),
+        (_, ["v1", "session", rest]) => session(&mut state, &method, rest, body),
+        _ => not_found(),
+    }
+}
+
+fn catalog(state: &State, rest: &str, params: &HashMap<String, String>) -> Response<Body> {
+    if rest == "nodes" {
+        let nodes = state
+            .nodes
+            .iter()
+            .map(|(name, node)| node_json(name, node))
+            .collect::<Vec<_>>();
+        return respond(StatusCode::OK, state.index, Value::Array(nodes));
+    }
+
+    if rest == "datacenters" {
+        return respond(StatusCode::OK, state.index, json!([DATACENTER]));
+    }
+
+    if rest == "services" {
+        let mut services = BTreeMap::<&str, Vec<&str>>::new();
+        for service in state.services.values() {
+            let tags = services.entry(&service.instance.name).or_default();
+            for tag in &service.instance.tags {
+                if !tags.contains(&tag.as_str()) {
+                    tags.push(tag);
+                }
+            }
+        }
+        return respond(StatusCode::OK, state.index, json!(services));
+    }
+
+    if let Some(service) = rest.strip_prefix("service/") {
+        let entries = service_instances(state, service, params)
+            .map(|s| catalog_service_json(state, s))
+            .collect::<Vec<_>>();
+        return respond(StatusCode::OK, state.index, Value::Array(entries));
+    }
+
+    if let Some(name) = rest.strip_prefix("node/") {
+        let body = match state.nodes.get(name) {
+            Some(node) => {
+                let services = state
+                    .services
+                    .values()
+                    .filter(|s| s.instance.node == name)
+                    .map(|s| (s.instance.id.clone(), service_json(s)))
+                    .collect::<serde_json::Map<_, _>>();
+                json!({ "Node": node_json(name, node), "Services": services })
+            }
+            None => Value::Null,
+        };
+        return respond(StatusCode::OK, state.index, body);
+    }
+
+    not_found()
+}
+
+fn coordinate(state: &State, rest: &str) -> Response<Body> {
+    let entries = state
+        .coordinates
+        .iter()
+        .map(|(node, coord)| json!({ "Node": node, "Segment": "", "Coord": coord }));
+    match rest {
+        "nodes" => respond(StatusCode::OK, state.index, entries.collect()),
+        _ => match rest.strip_prefix("node/") {
+            Some(node) => {
+                let entries = entries
+                    .filter(|entry| entry["Node"] == node)
+                    .collect::<Vec<_>>();
+                if entries.is_empty() {
+                    return not_found();
+                }
+                respond(StatusCode::OK, state.index, Value::Array(entries))
+            }
+            None => not_found(),
+        },
+    }
+}
+
+fn kv(
+    state: &mut State,
+    method: &Method,
+    key: &str,
+    params: &HashMap<String, String>,
+    body: Bytes,
+) -> Response<Body> {
+    let recurse = params.contains_key("recurse");
+    match *method {
+        Method::GET => {
+            let pairs = state
+                .kv
+                .values()
+                .filter(|pair| {
+                    if recurse {
+                        pair.key.starts_with(key)
+                    } else {
+                        pair.key == key
+                    }
+                })
+                .collect::<Vec<_>>();
+            if pairs.is_empty() {
+                return respond(StatusCode::NOT_FOUND, state.index, Value::Null);
+            }
+
+            if params.contains_key("raw") {
+                let value = pairs[0].value.clone().unwrap_or_default();
+                let mut response = Response::new(Body::from(value));
+                add_headers(&mut response, state.index);
+                return response;
+            }
+            respond(StatusCode::OK, state.index, json!(pairs))
+        }
+        Method::PUT => {
+            if let Some(cas) = params.get("cas").and_then(|c| c.parse::<u64>().ok()) {
+                let current = state.kv.get(key).map_or(0, |pair| pair.modify_index);
+                if cas != current {
+                    return respond(StatusCode::OK, state.index, json!(false));
+                }
+            }
+
+            let acquire = params.get("acquire");
+            if let Some(session) = acquire {
+                if !state.sessions.contains_key(session) {
+                    let message = format!("invalid session \"{}\"", session);
+                    return respond(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        state.index,
+                        json!(message),
+                    );
+                }
+                let holder = state.kv.get(key).and_then(|pair| pair.session.as_ref());
+                if holder.is_some_and(|holder| holder != session) {
+                    return respond(StatusCode::OK, state.index, json!(false));
+                }
+            }
+
+            let flags = params.get("flags").and_then(|f| f.parse().ok());
+            state.index += 1;
+            let index = state.index;
+            put_kv(state, index, key, body, flags);
+            if let Some(session) = acquire {
+                let pair = state.kv.get_mut(key).expect("key was just written");
+                if pair.session.as_ref() != Some(session) {
+                    pair.session = Some(session.clone());
+                    pair.lock_index += 1;
+                }
+            }
+            respond(StatusCode::OK, index, json!(true))
+        }
+        Method::DELETE => {
+            state.index += 1;
+            if recurse {
+                state.kv.retain(|k, _| !k.starts_with(key));
+            } else {
+                state.kv.remove(key);
+            }
+            respond(StatusCode::OK, state.index, json!(true))
+        }
+        _ => not_found(),
+    }
+}
+
+fn session(state: &mut State, method: &Method, rest: &str, body: Bytes) -> Response<Body> {
+    if *method == Method::PUT && rest == "create" {
+        let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let behavior = match request["Behavior"].as_str() {
+            Some("delete") => SessionBehavior::Delete,
+            _ => SessionBehavior::Release,
+        };
+        state.index += 1;
+        let index = state.index;
+        let id = format!("00000000-0000-0000-0000-{:012}", index);
+        state.sessions.insert(
+            id.clone(),
+            MockSession {
+                name: request["Name"].as_str().unwrap_or_default().to_string(),
+                behavior,
+                ttl: request["TTL"].as_str().unwrap_or_default().to_string(),
+                create_index: index,
+            },
+        );
+        return respond(StatusCode::OK, index, json!({ "ID": id }));
+    }
+
+    if let Some(id) = rest.strip_prefix("destroy/") {
+        if *method != Method::PUT {
+            return not_found();
+        }
+        state.index += 1;
+        state.invalidate_session(id);
+        return respond(StatusCode::OK, state.index, json!(true));
+    }
+
+    let (id, renew) = match (
+        method,
+        rest.strip_prefix("renew/"),
+        rest.strip_prefix("info/"),
+    ) {
+        (&Method::PUT, Some(id), _) => (id, true),
+        (&Method::GET, _, Some(id)) => (id, false),
+        _ => return not_found(),
+    };
+    match state.sessions.get(id) {
+        Some(session) => {
+            let entry = session_json(id, session);
+            respond(StatusCode::OK, state.index, json!([entry]))
+        }
+        None if renew => {
+            let message = format!("Session id '{}' not found", id);
+            respond(StatusCode::NOT_FOUND, state.index, json!(message))
+        }
+        None => respond(StatusCode::OK, state.index, json!([])),
+    }
+}
+
+fn session_json(id: &str, session: &MockSession) -> Value {
+    json!({
+        "ID": id,
+        "Name": session.name,
+        "Node": "",
+        "LockDelay": 0,
+        "Behavior": session.behavior,
+        "TTL": session.ttl,
+        "NodeChecks": [],
+        "CreateIndex": session.create_index,
+        "ModifyIndex": session.create_index,
+    })
+}
+
+/// Gets the instances of the given service, filtered by the `tag` query parameter, if given.
+fn service_instances<'a>(
+    state: &'a State,
+    service: &'a str,
+    params: &'a HashMap<String, String>,
+) -> impl Iterator<Item = &'a MockService> {
+    let tag = params.get("tag");
+    state.services.values().filter(move |s| {
+        s.instance.name == service && tag.is_none_or(|tag| s.instance.tags.contains(tag))
+    })
+}
+
+fn node_json(name: &str, node: &MockNode) -> Value {
+    json!({
+        "ID": "",
+        "Node": name,
+        "Address": node.address,
+        "Datacenter": DATACENTER,
+        "TaggedAddresses": { "lan": node.address, "wan": node.address },
+        "Meta": node.meta,
+        "CreateIndex": node.create_index,
+        "ModifyIndex": node.modify_index,
+    })
+}
+
+fn service_json(service: &MockService) -> Value {
+    let instance = &service.instance;
+    json!({
+        "ID": instance.id,
+        "Service": instance.name,
+        "Tags": instance.tags,
+        "Meta": instance.meta,
+        "Port": instance.port,
+        "Address": instance.address,
+        "Weights": { "Passing": 1, "Warning": 1 },
+        "EnableTagOverride": false,
+        "CreateIndex": service.create_index,
+        "ModifyIndex": service.modify_index,
+    })
+}
+
+fn catalog_service_json(state: &State, service: &MockService) -> Value {
+    let instance = &service.instance;
+    let node = &state.nodes[&instance.node];
+    json!({
+        "ID": "",
+        "Node": instance.node,
+        "Address": node.address,
+        "Datacenter": DATACENTER,
+        "TaggedAddresses": { "lan": node.address, "wan": node.address },
+        "NodeMeta": node.meta,
+        "ServiceID": instance.id,
+        "ServiceName": instance.name,
+        "ServiceAddress": instance.address,
+        "ServiceTaggedAddresses": null,
+        "ServiceTags": instance.tags,
+        "ServiceMeta": instance.meta,
+        "ServicePort": instance.port,
+        "ServiceWeights": { "Passing": 1, "Warning": 1 },
+        "ServiceEnableTagOverride": false,
+        "CreateIndex": service.create_index,
+        "ModifyIndex": service.modify_index,
+    })
+}
+
+fn health_entry(state: &State, service: &MockService) -> Value {
+    let instance = &service.instance;
+    let node = &state.nodes[&instance.node];
+    let node_check = check_json(
+        &instance.node,
+        "serfHealth",
+        "Serf Health Status",
+        None,
+        node,
+    );
+    let service_check = check_json(
+        &instance.node,
+        &format!("service:{}", instance.id),
+        &format!("Service '{}' check", instance.name),
+        Some(service),
+        node,
+    );
+    json!({
+        "Node": node_json(&instance.node, node),
+        "Service": service_json(service),
+        "Checks": [node_check, service_check],
+    })
+}
+
+fn check_json(
+    node_name: &str,
+    check_id: &str,
+    name: &str,
+    service: Option<&MockService>,
+    node: &MockNode,
+) -> Value {
+    let (status, service_id, service_name, tags, create_index, modify_index) = match service {
+        Some(s) => (
+            s.instance.status,
+            s.instance.id.as_str(),
+            s.instance.name.as_str(),
+            s.instance.tags.clone(),
+            s.create_index,
+            s.modify_index,
+        ),
+        None => (
+            CheckStatus::Passing,
+            "",
+            "",
+            Vec::new(),
+            node.create_index,
+            node.modify_index,
+        ),
+    };
+    json!({
+        "Node": node_name,
+        "CheckID": check_id,
+        "Name": name,
+        "Status": status,
+        "Notes": "",
+        "Output": "",
+        "ServiceID": service_id,
+        "ServiceName": service_name,
+        "ServiceTags": tags,
+        "Type": "",
+        "Definition": {
+            "HTTP": "",
+            "Header": null,
+            "Method": "",
+            "Body": "",
+            "TLSSkipVerify": false,
+            "TCP": "",
+            "Interval": "0s",
+            "Timeout": "0s",
+            "DeregisterCriticalServiceAfter": "0s",
+        },
+        "CreateIndex": create_index,
+        "ModifyIndex": modify_index,
+    })
+}
+
+fn respond(status: StatusCode, index: u64, body: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    add_headers(&mut response, index);
+    response
+}
+
+fn not_found() -> Response<Body> {
+    let mut response = Response::new(Body::from("404 page not found"));
+    *response.status_mut() = StatusCode::NOT_FOUND;
+    response
+}
+
+fn add_headers(response: &mut Response<Body>, index: u64) {
+    let headers = response.headers_mut();
+    headers.insert("X-Consul-Index", index.into());
+    headers.insert(
+        "X-Consul-KnownLeader",
+        "true".parse().expect("valid header"),
+    );
+    headers.insert("X-Consul-LastContact", 0.into());
+}
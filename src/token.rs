@@ -0,0 +1,93 @@
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::errors::Error;
+
+/// Boxed future returned by [`TokenProvider::token`].
+pub type TokenFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<String>, Error>> + Send + 'a>>;
+
+/// A source of ACL tokens.
+///
+/// When configured on a client, the provider is asked for a token before every request that does
+/// not specify its own token, which allows tokens to be rotated or refreshed without rebuilding the
+/// client, such as for tokens issued by Vault or obtained via an auth method login.  Providers are
+/// expected to cache tokens themselves, as they are called frequently.
+pub trait TokenProvider: Send + Sync + 'static {
+    /// Gets the token to use for the next request, if any.
+    fn token(&self) -> TokenFuture<'_>;
+}
+
+impl<F> TokenProvider for F
+where
+    F: Fn() -> Result<Option<String>, Error> + Send + Sync + 'static,
+{
+    fn token(&self) -> TokenFuture<'_> {
+        let result = self();
+        Box::pin(async move { result })
+    }
+}
+
+/// Provides an ACL token read from a file.
+///
+/// The file is re-read whenever its modification time changes, so tokens written to disk by an
+/// external process, such as Vault Agent or `consul login`, are picked up automatically.
+#[derive(Debug)]
+pub struct FileTokenProvider {
+    path: PathBuf,
+    cached: Mutex<Option<(SystemTime, String)>>,
+}
+
+impl FileTokenProvider {
+    /// Creates a new [`FileTokenProvider`] for the given file.
+    pub fn new<P: AsRef<Path>>(path: P) -> FileTokenProvider {
+        FileTokenProvider {
+            path: path.as_ref().to_path_buf(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    fn read_token(&self) -> Result<Option<String>, Error> {
+        let to_error = |e| Error::InvalidConfigFile(self.path.clone(), e);
+        let modified = fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .map_err(to_error)?;
+
+        let mut cached = self.cached.lock().expect("token lock poisoned");
+        match cached.as_ref() {
+            Some((cached_at, token)) if *cached_at == modified => {}
+            _ => {
+                let contents = fs::read(&self.path).map_err(to_error)?;
+                let token = String::from_utf8_lossy(&contents).trim().to_string();
+                *cached = Some((modified, token));
+            }
+        }
+
+        Ok(cached
+            .as_ref()
+            .map(|(_, token)| token.clone())
+            .filter(|token| !token.is_empty()))
+    }
+}
+
+impl TokenProvider for FileTokenProvider {
+    fn token(&self) -> TokenFuture<'_> {
+        let result = self.read_token();
+        Box::pin(async move { result })
+    }
+}
+
+/// Shared handle to a [`TokenProvider`], to allow storing it in types which derive `Clone` and
+/// `Debug`.
+#[derive(Clone)]
+pub(crate) struct SharedTokenProvider(pub Arc<dyn TokenProvider>);
+
+impl fmt::Debug for SharedTokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("TokenProvider")
+    }
+}
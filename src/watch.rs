@@ -0,0 +1,535 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_stream::{stream, try_stream};
+use futures::future::{self, Either};
+use futures::stream::{Stream, StreamExt};
+use tokio::sync::{watch, Mutex as AsyncMutex};
+
+use crate::common::{Blocking, QueryMetadata, QueryOptions};
+use crate::errors::Error;
+use crate::http_client::HttpClient;
+use crate::runtime::{delay_for, delay_until, spawn, Instant};
+use crate::stats::{StatsRecorder, WatchGuard, WatchRecorder, WatchStats};
+
+/// Minimum amount of time between the start of consecutive queries when the index does not advance.
+///
+/// Prevents tight loops against an agent which keeps returning immediately without any changes.
+const MIN_REQUERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default delay before restarting a watch after it fails.
+pub(crate) const DEFAULT_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+type WatchStream<T> = Pin<Box<dyn Stream<Item = Result<(T, QueryMetadata), Error>> + Send>>;
+
+/// A stream of changes to the result of a blocking query.
+///
+/// Each item represents the full result of the query after a change has occurred.  The blocking
+/// parameters used for the next query can be inspected at any time, which allows persisting them
+/// so that a restarted process can resume watching where it left off, by passing them back via
+/// [`QueryOptions::blocking`].
+///
+/// Results are only yielded when they may have changed, with queries which return without the
+/// blocking parameters having advanced being suppressed.  How often this happens, along with other
+/// statistics which help telling whether a watch is healthy, can be inspected with
+/// [`Watch::stats`].
+///
+/// The stream will terminate if any error is hit during the background requests made to Consul,
+/// other than a blocking query timing out, in which case the query is simply run again.  It ends
+/// without an error once the client is [shut down](crate::Client::shutdown).
+pub struct Watch<T> {
+    inner: WatchStream<T>,
+    blocking: Arc<Mutex<Option<Blocking>>>,
+    stats: Arc<WatchRecorder>,
+    _guard: Option<WatchGuard>,
+}
+
+impl<T: Send + 'static> Watch<T> {
+    /// Creates a new [`Watch`] which repeatedly runs the given query.
+    ///
+    /// The blocking parameters of the given options, if any, are used for the first query, and
+    /// then replaced based on the metadata of each response.
+    pub(crate) fn new<F, Fut>(options: Option<QueryOptions>, mut query: F) -> Watch<T>
+    where
+        F: FnMut(QueryOptions) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(T, QueryMetadata), Error>> + Send,
+    {
+        let mut options = options.unwrap_or_default();
+        let blocking = Arc::new(Mutex::new(options.blocking.take()));
+        let state = blocking.clone();
+        let stats = Arc::new(WatchRecorder::default());
+        let recorder = stats.clone();
+
+        let inner = try_stream! {
+            let mut requery_at = None;
+            loop {
+                if let Some(at) = requery_at.take() {
+                    delay_until(at).await;
+                }
+
+                // Override the blocking settings, and pick a new jittered wait, before every request.
+                let previous = state.lock().expect("watch lock poisoned").clone();
+                options.blocking = previous.clone();
+
+                let started = Instant::now();
+                let (parsed, meta) = match query(options.clone().with_jittered_wait()).await {
+                    Ok(result) => result,
+                    // Nothing changed before the wait expired, so simply block again.
+                    Err(e) if e.is_blocking_wait_expired() => {
+                        recorder.wakeup(started.elapsed(), true);
+                        requery_at = Some(started + MIN_REQUERY_INTERVAL);
+                        continue;
+                    }
+                    // The client is shutting down, so there will be no more results.
+                    Err(e) if e.is_shutdown() => break,
+                    Err(e) => {
+                        recorder.error(&e);
+                        Err(e)?
+                    }
+                };
+
+                // Override our blocking configuration based on the metadata from this response,
+                // rate limiting the next query if nothing has changed.  Results without blocking
+                // parameters can never be known to be unchanged, so they are always yielded.
+                let next = sanitize_blocking(previous.as_ref(), meta.as_blocking());
+                let spurious = next.is_some() && next == previous;
+                recorder.wakeup(started.elapsed(), spurious);
+                if next == previous {
+                    requery_at = Some(started + MIN_REQUERY_INTERVAL);
+                }
+                *state.lock().expect("watch lock poisoned") = next;
+
+                if !spurious {
+                    yield (parsed, meta);
+                }
+            }
+        };
+
+        Watch {
+            inner: Box::pin(inner),
+            blocking,
+            stats,
+            _guard: None,
+        }
+    }
+
+    /// Counts this watch as active in the given statistics, for as long as it exists.
+    pub(crate) fn tracked(mut self, stats: &Arc<StatsRecorder>) -> Watch<T> {
+        self._guard = Some(WatchGuard::new(stats.clone()));
+        self
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Watch<T> {
+    /// Drives this watch in a background task, publishing the latest result into a [`watch`]
+    /// channel.
+    ///
+    /// The first result is awaited before returning, so the channel always holds a valid value.
+    /// The task stops once all receivers have been dropped, or if the watch fails, which closes the
+    /// channel.  The returned flag is cleared when the task stops.
+    pub(crate) async fn spawn_channel(
+        mut self,
+    ) -> Result<(watch::Receiver<T>, Arc<AtomicBool>), Error> {
+        let initial = match self.next().await {
+            Some(result) => result?.0,
+            None => return Err(Error::WatchStopped),
+        };
+
+        let (mut tx, rx) = watch::channel(initial);
+        let running = Arc::new(AtomicBool::new(true));
+        let task_running = running.clone();
+        spawn(async move {
+            loop {
+                let next = match future::select(self.next(), Box::pin(tx.closed())).await {
+                    Either::Left((next, _)) => next,
+                    Either::Right(_) => break,
+                };
+                match next {
+                    Some(Ok((value, _))) => {
+                        if tx.broadcast(value).is_err() {
+                            break;
+                        }
+                    }
+                    Some(Err(_e)) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(error = %_e, "watch failed");
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            task_running.store(false, Ordering::Release);
+        });
+
+        Ok((rx, running))
+    }
+}
+
+impl<T> Watch<T> {
+    /// Gets the blocking parameters that will be used for the next query.
+    ///
+    /// This is based on the metadata of the most recent response, or the initial blocking
+    /// parameters if no response has been received yet.
+    pub fn last_blocking(&self) -> Option<Blocking> {
+        self.blocking.lock().expect("watch lock poisoned").clone()
+    }
+
+    /// Gets the Consul index that will be used for the next query, if the query is index-based.
+    pub fn last_index(&self) -> Option<u64> {
+        match self.last_blocking() {
+            Some(Blocking::Index(idx)) => Some(idx),
+            _ => None,
+        }
+    }
+
+    /// Gets the statistics of this watch.
+    pub fn stats(&self) -> WatchStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<T> Stream for Watch<T> {
+    type Item = Result<(T, QueryMetadata), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl<T> fmt::Debug for Watch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("blocking", &self.last_blocking())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Applies the safeguards recommended for blocking queries to the blocking parameters for the next
+/// query.
+///
+/// If the index went backwards, such as after a Consul server was restored from a snapshot, it is
+/// reset to one so that the next query fetches a full snapshot rather than blocking on an index
+/// which may never be reached.  An index of zero, which should never be returned but has been seen
+/// with older Consul versions, is also replaced with one, as a query with an index of zero does not
+/// block and would otherwise end up in a tight loop.
+fn sanitize_blocking(previous: Option<&Blocking>, next: Option<Blocking>) -> Option<Blocking> {
+    match (previous, next) {
+        (Some(Blocking::Index(previous)), Some(Blocking::Index(next))) if next < *previous => {
+            Some(Blocking::Index(1))
+        }
+        (_, Some(Blocking::Index(next))) => Some(Blocking::Index(next.max(1))),
+        (_, next) => next,
+    }
+}
+
+/// How long to wait before restarting a failed watch.
+///
+/// The delay starts at `initial` and doubles with every consecutive failure, up to `max`, and goes
+/// back to `initial` once the watch produces a result again.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Always waits for the same delay.
+    pub fn fixed(delay: Duration) -> Backoff {
+        Backoff {
+            initial: delay,
+            max: delay,
+        }
+    }
+}
+
+/// An event from a watch run by [`restarting`].
+pub(crate) enum RestartEvent<T> {
+    /// The watch produced a new result.
+    Update(T, QueryMetadata),
+    /// The watch failed, and will be restarted after the given delay.
+    Failed(Error, Duration),
+}
+
+/// Runs the watch created by the given function, restarting it whenever it fails, until the client
+/// is shut down.
+///
+/// Restarted watches pick up where the previous one left off, by passing its last blocking
+/// parameters in the options given to `start`.  Restarts are delayed according to `backoff`, and
+/// then subject to the restart budget of the client, if any.
+pub(crate) fn restarting<T, F>(
+    http_client: Arc<HttpClient>,
+    mut options: Option<QueryOptions>,
+    backoff: Backoff,
+    start: F,
+) -> impl Stream<Item = RestartEvent<T>>
+where
+    F: Fn(Option<QueryOptions>) -> Watch<T>,
+{
+    stream! {
+        let mut delay = backoff.initial;
+        let mut watch = start(options.clone());
+        loop {
+            match watch.next().await {
+                Some(Ok((value, meta))) => {
+                    delay = backoff.initial;
+                    yield RestartEvent::Update(value, meta);
+                }
+                next => {
+                    // Watches end once the client is shut down, and can't be restarted.
+                    if http_client.is_shut_down() {
+                        break;
+                    }
+                    if let Some(Err(e)) = next {
+                        // Never retry sooner than Consul asked us to.
+                        if let Some(retry_after) = e.retry_after() {
+                            delay = delay.max(retry_after);
+                        }
+                        yield RestartEvent::Failed(e, delay);
+                    }
+
+                    let blocking = watch.last_blocking();
+                    delay_for(delay).await;
+                    http_client.acquire_restart().await;
+                    delay = std::cmp::min(delay * 2, backoff.max);
+                    options.get_or_insert_with(QueryOptions::default).blocking = blocking;
+                    watch = start(options.clone());
+                }
+            }
+        }
+    }
+}
+
+/// The underlying channel of a shared watch, along with whether or not it is still being updated.
+struct SharedChannel<T> {
+    rx: watch::Receiver<T>,
+    running: Arc<AtomicBool>,
+}
+
+/// A handle to a watch which is shared between all callers watching the same query.
+///
+/// Holds the latest result of the query.  The underlying watch stops once every handle to it has
+/// been dropped.
+pub struct SharedWatch<T> {
+    channel: Arc<SharedChannel<T>>,
+    rx: watch::Receiver<T>,
+}
+
+impl<T: Clone> SharedWatch<T> {
+    /// Gets a reference to the latest result.
+    pub fn borrow(&self) -> watch::Ref<'_, T> {
+        self.rx.borrow()
+    }
+
+    /// Waits for the next result, returning it.
+    ///
+    /// Returns the latest result immediately if it has not been seen yet by this handle, and
+    /// `None` if the underlying watch has stopped.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.rx.recv().await
+    }
+}
+
+impl<T> Clone for SharedWatch<T> {
+    fn clone(&self) -> SharedWatch<T> {
+        SharedWatch {
+            channel: self.channel.clone(),
+            rx: self.rx.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SharedWatch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedWatch")
+            .field("running", &self.channel.running.load(Ordering::Acquire))
+            .finish()
+    }
+}
+
+/// The slot for a shared watch in a [`WatchRegistry`], holding the watch once it has started.
+type WatchSlot = Arc<AsyncMutex<Option<Weak<dyn Any + Send + Sync>>>>;
+
+/// Registry of the shared watches running on a client, keyed by the query they run.
+#[derive(Clone, Default)]
+pub(crate) struct WatchRegistry(Arc<Mutex<HashMap<String, WatchSlot>>>);
+
+impl WatchRegistry {
+    /// Gets a handle to the running shared watch for the given key, starting it if necessary.
+    pub async fn get_or_start<T, F, Fut>(
+        &self,
+        key: String,
+        start: F,
+    ) -> Result<SharedWatch<T>, Error>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(watch::Receiver<T>, Arc<AtomicBool>), Error>>,
+    {
+        let slot = {
+            let mut watches = self.0.lock().expect("watch registry lock poisoned");
+            // Slots are only in use while someone holds onto them, so any others without a live
+            // watch can go.
+            watches.retain(|_, slot| {
+                Arc::strong_count(slot) > 1
+                    || slot.try_lock().map_or(true, |channel| {
+                        channel.as_ref().is_some_and(|c| c.strong_count() > 0)
+                    })
+            });
+            watches.entry(key).or_default().clone()
+        };
+
+        // The lock of the slot is held while starting the watch so that concurrent callers for the
+        // same key wait for it to start, rather than starting their own, without holding up
+        // callers for any other key.
+        let mut slot = slot.lock().await;
+        let existing = slot
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .and_then(|channel| channel.downcast::<SharedChannel<T>>().ok())
+            .filter(|channel| channel.running.load(Ordering::Acquire));
+        let channel = match existing {
+            Some(channel) => channel,
+            None => {
+                let (rx, running) = start().await?;
+                let channel = Arc::new(SharedChannel { rx, running });
+                let erased: Arc<dyn Any + Send + Sync> = channel.clone();
+                *slot = Some(Arc::downgrade(&erased));
+                channel
+            }
+        };
+
+        Ok(SharedWatch {
+            rx: channel.rx.clone(),
+            channel,
+        })
+    }
+}
+
+impl fmt::Debug for WatchRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("WatchRegistry")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::time::timeout;
+
+    #[test]
+    fn sanitize_blocking_keeps_increasing_indexes() {
+        assert_eq!(
+            sanitize_blocking(Some(&Blocking::Index(5)), Some(Blocking::Index(5))),
+            Some(Blocking::Index(5))
+        );
+        assert_eq!(
+            sanitize_blocking(Some(&Blocking::Index(5)), Some(Blocking::Index(9))),
+            Some(Blocking::Index(9))
+        );
+        assert_eq!(
+            sanitize_blocking(None, Some(Blocking::Index(3))),
+            Some(Blocking::Index(3))
+        );
+        assert_eq!(sanitize_blocking(Some(&Blocking::Index(5)), None), None);
+    }
+
+    #[test]
+    fn sanitize_blocking_resets_indexes_which_go_backwards_to_one() {
+        assert_eq!(
+            sanitize_blocking(Some(&Blocking::Index(10)), Some(Blocking::Index(4))),
+            Some(Blocking::Index(1))
+        );
+        assert_eq!(
+            sanitize_blocking(Some(&Blocking::Index(10)), Some(Blocking::Index(0))),
+            Some(Blocking::Index(1))
+        );
+    }
+
+    #[test]
+    fn sanitize_blocking_never_returns_an_index_of_zero() {
+        assert_eq!(
+            sanitize_blocking(None, Some(Blocking::Index(0))),
+            Some(Blocking::Index(1))
+        );
+        assert_eq!(
+            sanitize_blocking(Some(&Blocking::Index(0)), Some(Blocking::Index(0))),
+            Some(Blocking::Index(1))
+        );
+    }
+
+    fn started(value: u32) -> (watch::Receiver<u32>, Arc<AtomicBool>) {
+        let (_, rx) = watch::channel(value);
+        (rx, Arc::new(AtomicBool::new(true)))
+    }
+
+    #[tokio::test]
+    async fn registry_starts_each_key_once() {
+        let registry = WatchRegistry::default();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let start = || {
+            let starts = starts.clone();
+            async move {
+                starts.fetch_add(1, Ordering::SeqCst);
+                delay_for(Duration::from_millis(10)).await;
+                Ok(started(1))
+            }
+        };
+
+        let (first, second) = future::join(
+            registry.get_or_start("a".to_string(), start),
+            registry.get_or_start("a".to_string(), start),
+        )
+        .await;
+        assert_eq!(*first.unwrap().borrow(), 1);
+        assert_eq!(*second.unwrap().borrow(), 1);
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn registry_does_not_block_other_keys_while_starting() {
+        let registry = WatchRegistry::default();
+        let stuck = registry.clone();
+        tokio::spawn(async move {
+            let _ = stuck
+                .get_or_start::<u32, _, _>("slow".to_string(), future::pending)
+                .await;
+        });
+        // Let the slow watch start, and hold onto its slot.
+        delay_for(Duration::from_millis(10)).await;
+
+        let watch = timeout(
+            Duration::from_secs(1),
+            registry.get_or_start("fast".to_string(), || async { Ok(started(2)) }),
+        )
+        .await
+        .expect("starting an unrelated watch must not wait")
+        .unwrap();
+        assert_eq!(*watch.borrow(), 2);
+    }
+
+    #[tokio::test]
+    async fn registry_restarts_watches_which_stopped() {
+        let registry = WatchRegistry::default();
+        let first = registry
+            .get_or_start("a".to_string(), || async { Ok(started(1)) })
+            .await
+            .unwrap();
+        first.channel.running.store(false, Ordering::Release);
+
+        let second = registry
+            .get_or_start("a".to_string(), || async { Ok(started(2)) })
+            .await
+            .unwrap();
+        assert_eq!(*second.borrow(), 2);
+    }
+}
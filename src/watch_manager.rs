@@ -0,0 +1,171 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, Stream, StreamExt};
+
+use crate::common::QueryMetadata;
+use crate::errors::Error;
+use crate::runtime::delay_for;
+use crate::watch::{self, Backoff, RestartEvent, Watch};
+use crate::Client;
+
+/// Default delay between starting consecutive watches.
+const DEFAULT_STAGGER: Duration = Duration::from_millis(100);
+
+/// Default delay before restarting a failed watch for the first time.
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Default maximum delay before restarting a failed watch.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type WatchFactory<T> = Arc<dyn Fn(&Client) -> Watch<T> + Send + Sync>;
+
+/// An event from one of the watches owned by a [`WatchManager`].
+#[derive(Debug)]
+pub enum WatchEvent<T> {
+    /// The watch with the given name produced a new result.
+    Update {
+        /// Name of the watch.
+        name: String,
+        /// The new result.
+        value: T,
+        /// Metadata of the response the result came from.
+        meta: QueryMetadata,
+    },
+    /// The watch with the given name failed, and will be restarted after the given delay.
+    Failed {
+        /// Name of the watch.
+        name: String,
+        /// The error which caused the watch to fail.
+        error: Error,
+        /// Delay before the watch is restarted.
+        retry_in: Duration,
+    },
+}
+
+/// Manages a set of named watches sharing a single client.
+///
+/// Watches are started one after another with a small delay in between, to avoid a thundering
+/// herd of queries against the agent when a process starts.  Failed watches are restarted with
+/// exponential backoff, subject to the [restart budget](crate::ClientBuilder::watch_restart_budget)
+/// of the client, if any, and the results of all watches are merged into a single stream of
+/// [`WatchEvent`]s.
+///
+/// As the result type must be the same for all watches, watches of different types can be managed
+/// together by mapping their results into a common enum.
+pub struct WatchManager<T> {
+    client: Client,
+    watches: Vec<(String, WatchFactory<T>)>,
+    stagger: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl<T: Send + 'static> WatchManager<T> {
+    /// Creates a new [`WatchManager`] using the given client.
+    pub fn new(client: Client) -> WatchManager<T> {
+        WatchManager {
+            client,
+            watches: Vec::new(),
+            stagger: DEFAULT_STAGGER,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Adds a named watch.
+    ///
+    /// The given function is called to start the watch, and again every time it is restarted after
+    /// failing.
+    pub fn watch<F>(mut self, name: &str, factory: F) -> WatchManager<T>
+    where
+        F: Fn(&Client) -> Watch<T> + Send + Sync + 'static,
+    {
+        self.watches.push((name.to_string(), Arc::new(factory)));
+        self
+    }
+
+    /// Sets the delay between starting consecutive watches.
+    ///
+    /// Defaults to 100 milliseconds.
+    pub fn stagger(mut self, stagger: Duration) -> WatchManager<T> {
+        self.stagger = stagger;
+        self
+    }
+
+    /// Sets the backoff used when restarting failed watches.
+    ///
+    /// The delay starts at `initial` and doubles with every consecutive failure, up to `max`.
+    /// Defaults to 1 second, up to 60 seconds.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> WatchManager<T> {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Starts all watches, returning the merged stream of their events.
+    ///
+    /// Watches run for as long as the stream is polled, and are stopped when it is dropped.
+    pub fn run(self) -> impl Stream<Item = WatchEvent<T>> {
+        let WatchManager {
+            client,
+            watches,
+            stagger,
+            initial_backoff,
+            max_backoff,
+        } = self;
+
+        let streams = watches.into_iter().enumerate().map(|(i, (name, factory))| {
+            let client = client.clone();
+            let start_delay = stagger * i as u32;
+            let backoff = Backoff {
+                initial: initial_backoff,
+                max: max_backoff,
+            };
+
+            let watch = stream::once(delay_for(start_delay)).flat_map(move |_| {
+                let name = name.clone();
+                let factory = factory.clone();
+                let client = client.clone();
+                let http_client = client.http_client.clone();
+                // Factories always start over, rather than picking up where the failed watch left off.
+                watch::restarting(http_client, None, backoff, move |_| factory(&client)).map(
+                    move |event| match event {
+                        RestartEvent::Update(value, meta) => WatchEvent::Update {
+                            name: name.clone(),
+                            value,
+                            meta,
+                        },
+                        RestartEvent::Failed(error, retry_in) => WatchEvent::Failed {
+                            name: name.clone(),
+                            error,
+                            retry_in,
+                        },
+                    },
+                )
+            });
+            Box::pin(watch)
+        });
+
+        stream::select_all(streams)
+    }
+}
+
+impl<T> fmt::Debug for WatchManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchManager")
+            .field(
+                "watches",
+                &self
+                    .watches
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("stagger", &self.stagger)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .finish()
+    }
+}